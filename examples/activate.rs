@@ -23,7 +23,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         })
         .add_plugins(DefaultPlugins)
         .add_system(bevy::input::system::exit_on_esc_system)
-        .add_plugin(HanabiPlugin)
+        .add_plugin(HanabiPlugin::default())
         //.add_plugin(WorldInspectorPlugin::new())
         .add_startup_system(setup)
         .add_system(update)
@@ -101,7 +101,10 @@ fn setup(
         .render(SizeOverLifetimeModifier {
             gradient: Gradient::constant(Vec2::splat(0.02)),
         })
-        .render(ColorOverLifetimeModifier { gradient }),
+        .render(ColorOverLifetimeModifier {
+            gradient,
+            ..Default::default()
+        }),
     );
 
     ball.with_children(|node| {
@@ -26,7 +26,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         })
         .add_plugins(DefaultPlugins)
         .add_system(bevy::input::system::exit_on_esc_system)
-        .add_plugin(HanabiPlugin)
+        .add_plugin(HanabiPlugin::default())
         //.add_plugin(WorldInspectorPlugin::new())
         .add_startup_system(setup)
         .run();
@@ -66,12 +66,18 @@ fn setup(
             axis: Vec3::Y,
             radius: 0.4,
             speed: Value::Uniform((1.0, 1.5)),
+            tangent_speed: Value::Uniform((0.5, 1.0)),
             dimension: ShapeDimension::Surface,
+            ..Default::default()
         })
         .render(ParticleTextureModifier {
             texture: texture_handle.clone(),
+            ..Default::default()
+        })
+        .render(ColorOverLifetimeModifier {
+            gradient,
+            ..Default::default()
         })
-        .render(ColorOverLifetimeModifier { gradient })
         .render(SizeOverLifetimeModifier {
             gradient: Gradient::constant([0.2; 2].into()),
         }),
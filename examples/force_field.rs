@@ -29,7 +29,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .add_system(bevy::input::system::exit_on_esc_system)
         //.add_plugin(LookTransformPlugin)
         //.add_plugin(OrbitCameraPlugin::default())
-        .add_plugin(HanabiPlugin)
+        .add_plugin(HanabiPlugin::default())
         //.add_plugin(WorldInspectorPlugin::new())
         .add_startup_system(setup)
         .add_system(update)
@@ -146,7 +146,10 @@ fn setup(
         .render(SizeOverLifetimeModifier {
             gradient: Gradient::constant(Vec2::splat(0.05)),
         })
-        .render(ColorOverLifetimeModifier { gradient }),
+        .render(ColorOverLifetimeModifier {
+            gradient,
+            ..Default::default()
+        }),
     );
 
     commands.spawn_bundle(ParticleEffectBundle::new(effect).with_spawner(spawner));
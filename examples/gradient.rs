@@ -24,7 +24,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         })
         .add_plugins(DefaultPlugins)
         .add_system(bevy::input::system::exit_on_esc_system)
-        .add_plugin(HanabiPlugin)
+        .add_plugin(HanabiPlugin::default())
         //.add_plugin(WorldInspectorPlugin::new())
         .add_startup_system(setup)
         .add_system(update)
@@ -61,8 +61,12 @@ fn setup(
         }
         .render(ParticleTextureModifier {
             texture: texture_handle.clone(),
+            ..Default::default()
         })
-        .render(ColorOverLifetimeModifier { gradient }),
+        .render(ColorOverLifetimeModifier {
+            gradient,
+            ..Default::default()
+        }),
     );
 
     commands
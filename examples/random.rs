@@ -26,7 +26,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         })
         .add_plugins(DefaultPlugins)
         .add_system(bevy::input::system::exit_on_esc_system)
-        .add_plugin(HanabiPlugin)
+        .add_plugin(HanabiPlugin::default())
         //.add_plugin(WorldInspectorPlugin::new())
         .add_startup_system(setup)
         .run();
@@ -74,11 +74,15 @@ fn setup(
             radius: 5.,
             dimension: ShapeDimension::Volume,
             speed: 2.0.into(),
+            ..Default::default()
         })
         .update(AccelModifier {
             accel: Vec3::new(0., 5., 0.),
         })
-        .render(ColorOverLifetimeModifier { gradient }),
+        .render(ColorOverLifetimeModifier {
+            gradient,
+            ..Default::default()
+        }),
     );
 
     commands
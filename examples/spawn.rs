@@ -39,7 +39,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         })
         .add_plugins(DefaultPlugins)
         .add_system(bevy::input::system::exit_on_esc_system)
-        .add_plugin(HanabiPlugin)
+        .add_plugin(HanabiPlugin::default())
         //.add_plugin(WorldInspectorPlugin::new())
         .add_startup_system(setup)
         .run();
@@ -95,12 +95,14 @@ fn setup(
             radius: 2.,
             dimension: ShapeDimension::Surface,
             speed: 6.0.into(),
+            ..Default::default()
         })
         .update(AccelModifier {
             accel: Vec3::new(0., -3., 0.),
         })
         .render(ColorOverLifetimeModifier {
             gradient: color_gradient1,
+            ..Default::default()
         })
         .render(SizeOverLifetimeModifier {
             gradient: size_gradient1,
@@ -139,6 +141,7 @@ fn setup(
         }
         .render(ColorOverLifetimeModifier {
             gradient: gradient2,
+            ..Default::default()
         }),
     );
 
@@ -177,12 +180,14 @@ fn setup(
             radius: 5.,
             dimension: ShapeDimension::Volume,
             speed: 2.0.into(),
+            ..Default::default()
         })
         .update(AccelModifier {
             accel: Vec3::new(0., 5., 0.),
         })
         .render(ColorOverLifetimeModifier {
             gradient: gradient3,
+            ..Default::default()
         }),
     );
 
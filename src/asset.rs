@@ -1,21 +1,84 @@
 use bevy::{
     asset::{AssetLoader, Handle, LoadContext, LoadedAsset},
+    ecs::world::{FromWorld, World},
+    log::warn,
     math::{Vec2, Vec3, Vec4},
     reflect::TypeUuid,
-    render::texture::Image,
+    render::{mesh::Mesh, texture::Image},
     utils::BoxedFuture,
 };
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    modifiers::{ForceFieldParam, FFNUM},
-    Gradient, InitModifier, RenderModifier, Spawner, UpdateModifier,
+    modifiers::{
+        BuoyancyModifier, ColorBlendModifier, ColorInterpolation, DistanceFogModifier,
+        DragModifier, FlipbookModifier, FlowZoneModifier, ForceFieldParam, InheritEmitterModifier,
+        InitColorModifier, InitRotationModifier, InitSizeModifier, Integrator, ParticleGeometry,
+        PlaneCollisionModifier, PositionChainModifier, ShockwaveModifier, SimulationBoundsModifier,
+        TextureChannelMapping, UserBindGroupModifier, VortexModifier, WindModifier, FFNUM,
+    },
+    CompletionCondition, EffectVariants, ExtensionInstance, ExtensionModifier, ExtensionRegistry,
+    Gradient, InitModifier, RenderModifier, Spawner, StrengthMapping, UpdateModifier, Value,
 };
 
+/// Shader code initializing newly spawned particles, built by the [`InitModifier`]s of an
+/// effect.
+///
+/// The code is made of sections, which run in the order of the fields below, each one able to
+/// read and override the values written by the previous ones into `ret`:
+/// 1. `position_code` writes the spawn position `ret.pos`, velocity `ret.vel` and normal
+///    `ret.normal` of the particle, from the shape it spawns on;
+/// 2. `velocity_code` overrides the velocity `ret.vel`;
+/// 3. `velocity_spread_code` randomizes the direction of the velocity `ret.vel`;
+/// 4. `lifetime_code` writes the lifetime `ret.lifetime` of the particle, in seconds;
+/// 5. `custom_code` runs last, and can override any of the values.
+///
+/// The first four sections hold the code of a single modifier each, and a modifier setting a
+/// section replaces the code of any previous one. The custom section instead concatenates the
+/// code of all the modifiers appending to it, in the order they're added to the effect.
 #[derive(Default, Clone)]
 pub struct InitLayout {
+    /// Code setting the position, velocity and normal of the particle from a spawn shape.
     pub position_code: String,
+    /// Code overriding the velocity set by `position_code`, if any.
+    pub velocity_code: String,
+    /// Code randomizing the direction of the velocity, after `velocity_code`.
+    pub velocity_spread_code: String,
+    /// Code setting the lifetime of the particle.
+    pub lifetime_code: String,
+    /// Lifetime of the particles set by `lifetime_code`, if any, to validate the effect.
+    pub lifetime: Option<Value<f32>>,
+    /// Custom code appended by any number of modifiers, run after all the other sections.
+    pub custom_code: String,
+    /// Force field code, unused by the init pass; see `UpdateLayout` instead.
     pub force_field_code: String,
+    /// If set, spawn the particles as the links of a chain.
+    pub chain: Option<PositionChainModifier>,
+    /// If set, data uploaded to a storage buffer read by `position_code`.
+    pub init_data: Option<InitData>,
+}
+
+/// Source of the data of the storage buffer read by the init code of an effect.
+///
+/// The buffer is exposed to the code of the [`InitLayout`] as `init_data.values`, an array of
+/// `f32`. It's uploaded to the GPU once per effect asset, and again if the asset or the mesh
+/// it's built from changes. The effect doesn't spawn any particle until the data is available.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InitData {
+    /// Values uploaded as is.
+    Values(Vec<f32>),
+    /// The triangles of a mesh, in the layout of [`PositionMeshModifier::init_data()`].
+    ///
+    /// [`PositionMeshModifier::init_data()`]: crate::PositionMeshModifier::init_data
+    Mesh(Handle<Mesh>),
+}
+
+impl InitLayout {
+    /// Append code to the custom section, run after the code of all the other sections and
+    /// of the modifiers which appended to the custom section before.
+    pub fn append_custom_code(&mut self, code: &str) {
+        self.custom_code += code;
+    }
 }
 
 #[derive(Default, Clone, Copy)]
@@ -25,6 +88,22 @@ pub struct UpdateLayout {
     pub accel: Vec3,
     /// Array of force field components with a maximum number of components determined by [`FFNUM`].
     pub force_field: [ForceFieldParam; FFNUM],
+    /// Numerical scheme used to integrate the particle motion.
+    pub integrator: Integrator,
+    /// If set, accelerate the particles upward based on their temperature.
+    pub buoyancy: Option<BuoyancyModifier>,
+    /// If set, swirl the particles around an axis.
+    pub vortex: Option<VortexModifier>,
+    /// If set, push the particles along a gusting wind.
+    pub wind: Option<WindModifier>,
+    /// If set, push the particles inside a box toward a target velocity.
+    pub flow_zone: Option<FlowZoneModifier>,
+    /// If set, damp the velocity of the particles.
+    pub drag: Option<DragModifier>,
+    /// If set, collide the particles with an infinite plane.
+    pub plane_collision: Option<PlaneCollisionModifier>,
+    /// If set, keep the particles inside a box.
+    pub bounds: Option<SimulationBoundsModifier>,
 }
 
 #[derive(Default, Clone)]
@@ -33,42 +112,191 @@ pub struct RenderLayout {
     /// UV coordinates. Also make available the image as a 2D texture and sampler in the render
     /// shaders.
     pub particle_texture: Option<Handle<Image>>,
+    /// Mapping of the channels of the particle texture to the color modulating the particles.
+    pub particle_texture_mapping: TextureChannelMapping,
 
     pub lifetime_color_gradient: Option<Gradient<Vec4>>,
+    /// Color space in which the keys of the color gradient are interpolated.
+    pub lifetime_color_interpolation: ColorInterpolation,
+
+    /// If set, defines the COLOR_RAMP shader key, and sample the particle color over its
+    /// lifetime from this texture in the vertex shader.
+    pub color_ramp_texture: Option<Handle<Image>>,
+
+    /// If set, blend two color gradients over the lifetime of each particle with a
+    /// per-particle weight.
+    pub color_blend: Option<ColorBlendModifier>,
 
     pub size_color_gradient: Option<Gradient<Vec2>>,
+
+    /// If set, modulate the color of each particle by a random base color picked at spawn.
+    pub init_color: Option<InitColorModifier>,
+
+    /// If set, multiply the size of each particle by a random size picked at spawn.
+    pub init_size: Option<InitSizeModifier>,
+
+    /// If set, rotate the particle in its plane based on its lifetime, in radians.
+    pub lifetime_rotation_gradient: Option<Gradient<f32>>,
+
+    /// If set, rotate each particle by a random angle, spinning at a random angular velocity.
+    pub init_rotation: Option<InitRotationModifier>,
+
+    /// If set, tint and scale the particles by the properties of the emitter.
+    pub inherit_emitter: Option<InheritEmitterModifier>,
+
+    /// If set, animate the UV coordinates of the particle texture as a sprite sheet flipbook.
+    pub flipbook: Option<FlipbookModifier>,
+
+    /// If set, fade the particle color toward a fog color based on the distance to the camera.
+    pub distance_fog: Option<DistanceFogModifier>,
+
+    /// Custom code displacing the particle vertices, injected in the vertex shader once the
+    /// particle size has been applied.
+    pub vertex_deform_code: String,
+
+    /// Custom shader definitions added to the render pipeline.
+    pub shader_defs: Vec<String>,
+
+    /// If set, bind a custom user bind group after all the other bind groups of the render
+    /// pipeline, and inject its declarations into the render shader.
+    pub user_bind_group: Option<UserBindGroupModifier>,
+
+    /// Geometry used to render each particle.
+    pub geometry: ParticleGeometry,
+
+    /// If set, cut the particle geometry into a ring of varying width over the lifetime.
+    pub shockwave: Option<ShockwaveModifier>,
+
+    /// If set, orient the particle geometry along the normal of the spawn shape instead of
+    /// facing the +Z axis.
+    pub orient_along_normal: bool,
+
+    /// Offset of the particle geometry toward the camera, in view space.
+    pub depth_bias: f32,
 }
 
 /// Asset describing a visual effect.
 ///
 /// The effect can be instanciated with a [`ParticleEffect`] component, or a [`ParticleEffectBundle`].
 ///
+/// Effect assets loaded from `.effect` files authored with an older version of the crate are
+/// upgraded to the current [`FORMAT_VERSION`] on load. If the upgrade changes the content of
+/// the asset, a warning invites to save it again; files without a version are upgraded
+/// silently, since their format is the same as the current one.
+///
 /// [`ParticleEffect`]: crate::ParticleEffect
 /// [`ParticleEffectBundle`]: crate::ParticleEffectBundle
-#[derive(Default, Serialize, Deserialize, TypeUuid)]
+/// [`FORMAT_VERSION`]: EffectAsset::FORMAT_VERSION
+#[derive(Serialize, Deserialize, TypeUuid)]
 #[uuid = "249aefa4-9b8e-48d3-b167-3adf6c081c34"]
 pub struct EffectAsset {
+    /// Version of the serialized format of the asset.
+    ///
+    /// Files without a version predate the versioning of the format, and are loaded as
+    /// version `0`.
+    #[serde(default)]
+    pub version: u32,
     /// Display name of the effect.
     pub name: String,
     /// Maximum number of concurrent particles.
     pub capacity: u32,
     /// Spawner.
     pub spawner: Spawner,
+    /// Condition completing the instances of the effect, which are then despawned.
+    #[serde(default)]
+    pub completion: Option<CompletionCondition>,
+    /// Modifiers defined outside of this crate, in the order they were added.
+    ///
+    /// See [`ExtensionModifier`].
+    #[serde(default)]
+    pub extensions: Vec<ExtensionInstance>,
+    /// Mapping of the [`EffectStrength`] of an instance to the parameters it scales.
     ///
+    /// [`EffectStrength`]: crate::EffectStrength
+    #[serde(skip)] // TODO
+    pub strength_mapping: StrengthMapping,
+    /// Randomizable parameters resolved once per instance.
+    #[serde(default)]
+    pub variants: EffectVariants,
+    /// Layout of the init pass, built from the modifiers.
     #[serde(skip)] // TODO
     pub init_layout: InitLayout,
-    ///
+    /// Layout of the update pass, built from the modifiers.
     #[serde(skip)] // TODO
     pub update_layout: UpdateLayout,
-    ///
+    /// Layout of the render pass, built from the modifiers.
     #[serde(skip)] // TODO
     pub render_layout: RenderLayout,
 }
-///
+//
 //#[serde(skip)] // TODO
 //modifiers: Vec<Box<dyn Modifier + Send + Sync + 'static>>,
 
+impl Default for EffectAsset {
+    fn default() -> Self {
+        Self {
+            version: Self::FORMAT_VERSION,
+            name: Default::default(),
+            capacity: Default::default(),
+            spawner: Default::default(),
+            completion: None,
+            extensions: vec![],
+            strength_mapping: Default::default(),
+            variants: Default::default(),
+            init_layout: Default::default(),
+            update_layout: Default::default(),
+            render_layout: Default::default(),
+        }
+    }
+}
+
 impl EffectAsset {
+    /// Version of the serialized format written by this version of the crate.
+    pub const FORMAT_VERSION: u32 = 1;
+
+    /// Deserialize an effect asset from its RON representation, upgrading it to the current
+    /// [`FORMAT_VERSION`] if it was authored with an older version of the crate.
+    ///
+    /// Fails if the asset was authored with a newer version of the crate.
+    ///
+    /// [`FORMAT_VERSION`]: EffectAsset::FORMAT_VERSION
+    pub fn from_ron(bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        let mut asset = ron::de::from_bytes::<EffectAsset>(bytes)?;
+        asset.migrate()?;
+        Ok(asset)
+    }
+
+    /// Upgrade the asset to the current format version, one version at a time.
+    fn migrate(&mut self) -> Result<(), anyhow::Error> {
+        if self.version > Self::FORMAT_VERSION {
+            anyhow::bail!(
+                "Effect asset '{}' uses format version {}, which is newer than the version {} supported by this version of bevy_hanabi.",
+                self.name,
+                self.version,
+                Self::FORMAT_VERSION
+            );
+        }
+        let old_version = self.version;
+        let mut changed = false;
+        while self.version < Self::FORMAT_VERSION {
+            // Each step returns whether it changed the content of the asset
+            changed |= match self.version {
+                // Version 0 predates the versioning of the format, and has the same layout
+                // as version 1.
+                0 => false,
+                _ => unreachable!(),
+            };
+            self.version += 1;
+        }
+        if changed {
+            warn!(
+                "Effect asset '{}' uses the old format version {}, and was upgraded to version {}. Save it again to remove this warning.",
+                self.name, old_version, Self::FORMAT_VERSION
+            );
+        }
+        Ok(())
+    }
+
     /// Add an initialization modifier to the effect.
     pub fn init<M: InitModifier + Send + Sync + 'static>(mut self, modifier: M) -> Self {
         modifier.apply(&mut self.init_layout);
@@ -89,10 +317,44 @@ impl EffectAsset {
         //self.modifiers.push(Box::new(modifier));
         self
     }
+
+    /// Set the condition completing the instances of the effect.
+    pub fn complete_when(mut self, condition: CompletionCondition) -> Self {
+        self.completion = Some(condition);
+        self
+    }
+
+    /// Add a modifier defined outside of this crate to the effect.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the modifier reads a [`ParticleAttribute`] not available in its stage, or
+    /// if its parameters can't be serialized.
+    ///
+    /// [`ParticleAttribute`]: crate::ParticleAttribute
+    pub fn extend<M: ExtensionModifier>(mut self, modifier: M) -> Self {
+        let instance = ExtensionInstance::new(&modifier)
+            .unwrap_or_else(|err| panic!("Invalid extension modifier '{}': {}", M::TYPE_NAME, err));
+        self.extensions.push(instance);
+        self
+    }
+}
+
+/// Loader of the `.effect` files, resolving their extension modifiers with the
+/// [`ExtensionRegistry`].
+pub struct EffectAssetLoader {
+    registry: ExtensionRegistry,
 }
 
-#[derive(Default)]
-pub struct EffectAssetLoader;
+impl FromWorld for EffectAssetLoader {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            registry: world
+                .get_resource_or_insert_with(ExtensionRegistry::default)
+                .clone(),
+        }
+    }
+}
 
 impl AssetLoader for EffectAssetLoader {
     fn load<'a>(
@@ -101,7 +363,10 @@ impl AssetLoader for EffectAssetLoader {
         load_context: &'a mut LoadContext,
     ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
         Box::pin(async move {
-            let custom_asset = ron::de::from_bytes::<EffectAsset>(bytes)?;
+            let mut custom_asset = EffectAsset::from_ron(bytes)?;
+            for extension in &mut custom_asset.extensions {
+                self.registry.resolve(extension)?;
+            }
             load_context.set_default_asset(LoadedAsset::new(custom_asset));
             Ok(())
         })
@@ -111,3 +376,56 @@ impl AssetLoader for EffectAssetLoader {
         &["effect"]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Value;
+
+    #[test]
+    fn migrate() {
+        // Current version round-trips
+        let asset = EffectAsset {
+            name: "Effect".to_string(),
+            capacity: 256,
+            ..Default::default()
+        };
+        let ron = ron::ser::to_string(&asset).unwrap();
+        let loaded = EffectAsset::from_ron(ron.as_bytes()).unwrap();
+        assert_eq!(loaded.version, EffectAsset::FORMAT_VERSION);
+        assert_eq!(loaded.name, "Effect");
+        assert_eq!(loaded.capacity, 256);
+
+        // Unversioned assets are upgraded
+        let ron = ron.replace(&format!("version:{},", EffectAsset::FORMAT_VERSION), "");
+        assert!(!ron.contains("version"));
+        let loaded = EffectAsset::from_ron(ron.as_bytes()).unwrap();
+        assert_eq!(loaded.version, EffectAsset::FORMAT_VERSION);
+        assert_eq!(loaded.capacity, 256);
+
+        // Assets from the future are rejected
+        let asset = EffectAsset {
+            version: EffectAsset::FORMAT_VERSION + 1,
+            ..Default::default()
+        };
+        let ron = ron::ser::to_string(&asset).unwrap();
+        assert!(EffectAsset::from_ron(ron.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn serialize_variants() {
+        let mut gradient = Gradient::new();
+        gradient.add_key(0.0, Vec4::new(1.0, 0.0, 0.0, 1.0));
+        gradient.add_key(1.0, Vec4::ZERO);
+        let asset = EffectAsset {
+            variants: EffectVariants {
+                color_gradients: vec![gradient],
+                size_multiplier: Value::Uniform((0.5, 2.)),
+            },
+            ..Default::default()
+        };
+        let ron = ron::ser::to_string(&asset).unwrap();
+        let loaded = EffectAsset::from_ron(ron.as_bytes()).unwrap();
+        assert_eq!(loaded.variants, asset.variants);
+    }
+}
@@ -0,0 +1,80 @@
+use bevy::prelude::*;
+
+/// Attraction of the particles of an effect toward a point on screen.
+///
+/// Add this component to the entity of a [`ParticleEffect`] to accelerate all its particles
+/// toward a point specified on the screen of a camera, like an inventory icon for loot
+/// sparkles. Each frame the point is projected into the world at `distance` from the camera,
+/// using the current camera projection and transform, so the particles keep flying toward
+/// the same screen location as the camera moves.
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub struct ScreenSpaceAttractor {
+    /// Entity of the camera whose screen contains the point.
+    pub camera: Entity,
+    /// Position of the point on screen, in normalized viewport coordinates, from (0,0) at
+    /// the top left corner to (1,1) at the bottom right corner.
+    pub position: Vec2,
+    /// Distance of the point from the camera near plane, along the view ray.
+    pub distance: f32,
+    /// Magnitude of the acceleration toward the point.
+    pub accel: f32,
+}
+
+impl ScreenSpaceAttractor {
+    /// Project the point into the world with the given camera projection and transform.
+    pub fn world_position(&self, projection: &Mat4, camera_transform: &Mat4) -> Vec3 {
+        let (origin, dir) = view_ray(projection, camera_transform, self.position);
+        origin + dir * self.distance
+    }
+}
+
+/// Get the world-space ray through a point on the screen of a camera.
+///
+/// The point is in normalized viewport coordinates, from (0,0) at the top left corner to
+/// (1,1) at the bottom right corner. Returns the origin of the ray on the camera near plane,
+/// and its normalized direction.
+pub(crate) fn view_ray(projection: &Mat4, camera_transform: &Mat4, position: Vec2) -> (Vec3, Vec3) {
+    let ndc = Vec2::new(position.x * 2. - 1., 1. - position.y * 2.);
+    // Bevy uses a reversed depth, where the near plane is at z=1.
+    let inverse_projection = projection.inverse();
+    let near = inverse_projection.project_point3(ndc.extend(1.));
+    let far = inverse_projection.project_point3(ndc.extend(0.5));
+    let origin = camera_transform.transform_point3(near);
+    let dir = camera_transform.transform_vector3(far - near).normalize();
+    (origin, dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn world_position() {
+        let projection =
+            Mat4::perspective_infinite_reverse_rh(std::f32::consts::FRAC_PI_2, 1., 0.1);
+        let mut attractor = ScreenSpaceAttractor {
+            camera: Entity::from_raw(0),
+            position: Vec2::splat(0.5),
+            distance: 10.,
+            accel: 1.,
+        };
+
+        // Screen center is straight ahead, along -Z
+        let pos = attractor.world_position(&projection, &Mat4::IDENTITY);
+        assert!(pos.abs_diff_eq(Vec3::new(0., 0., -10.1), 1e-3));
+
+        // Camera transform is applied
+        let camera_transform = Mat4::from_translation(Vec3::new(1., 2., 3.));
+        let pos = attractor.world_position(&projection, &camera_transform);
+        assert!(pos.abs_diff_eq(Vec3::new(1., 2., -7.1), 1e-3));
+
+        // Top right corner, with a 90 degrees field of view
+        attractor.position = Vec2::new(1., 0.);
+        let pos = attractor.world_position(&projection, &Mat4::IDENTITY);
+        assert!(pos.x > 0. && pos.y > 0. && pos.z < 0.);
+        assert!((pos.x - pos.y).abs() < 1e-3);
+        assert!((pos.x + pos.z).abs() < 1e-3);
+    }
+}
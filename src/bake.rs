@@ -0,0 +1,463 @@
+use std::{
+    num::NonZeroU32,
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        render_asset::RenderAssets,
+        render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext},
+        render_resource::*,
+        renderer::{RenderContext, RenderDevice},
+        RenderApp, RenderStage,
+    },
+};
+
+use crate::render::AsyncReadback;
+
+/// Label of the render graph node copying the frames to bake back to the CPU.
+pub const FLIPBOOK_BAKE_PASS: &str = "hanabi_flipbook_bake_pass";
+
+/// Atlas of flipbook frames, stored as RGBA8 sRGB pixels.
+///
+/// The frames are laid out in row-major order, starting from the top left corner of the
+/// atlas, which matches the sprite order of a [`FlipbookModifier`].
+///
+/// [`FlipbookModifier`]: crate::FlipbookModifier
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlipbookAtlas {
+    grid_size: UVec2,
+    frame_size: UVec2,
+    frame_count: u32,
+    data: Vec<u8>,
+}
+
+impl FlipbookAtlas {
+    /// Create an empty atlas of `grid_size` columns and rows of frames, each frame being
+    /// `frame_size` pixels.
+    pub fn new(grid_size: UVec2, frame_size: UVec2) -> Self {
+        let size = grid_size * frame_size;
+        Self {
+            grid_size,
+            frame_size,
+            frame_count: 0,
+            data: vec![0; (size.x * size.y * 4) as usize],
+        }
+    }
+
+    /// The number of columns and rows of frames.
+    pub fn grid_size(&self) -> UVec2 {
+        self.grid_size
+    }
+
+    /// The size of a single frame, in pixels.
+    pub fn frame_size(&self) -> UVec2 {
+        self.frame_size
+    }
+
+    /// The number of frames added so far.
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    /// Check if all the frames of the atlas were added.
+    pub fn is_complete(&self) -> bool {
+        self.frame_count >= self.grid_size.x * self.grid_size.y
+    }
+
+    /// Add the next frame to the atlas.
+    ///
+    /// The frame is made of `frame_size` RGBA8 pixels, row by row. Returns `false` without
+    /// adding the frame if the atlas is already complete.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of pixels doesn't match the frame size.
+    pub fn add_frame(&mut self, pixels: &[u8]) -> bool {
+        if self.is_complete() {
+            return false;
+        }
+        let row_bytes = (self.frame_size.x * 4) as usize;
+        assert_eq!(pixels.len(), row_bytes * self.frame_size.y as usize);
+
+        let atlas_row_bytes = row_bytes * self.grid_size.x as usize;
+        let column = (self.frame_count % self.grid_size.x) as usize;
+        let row = (self.frame_count / self.grid_size.x) as usize;
+        let origin = row * self.frame_size.y as usize * atlas_row_bytes + column * row_bytes;
+        for (y, src) in pixels.chunks(row_bytes).enumerate() {
+            let start = origin + y * atlas_row_bytes;
+            self.data[start..start + row_bytes].copy_from_slice(src);
+        }
+        self.frame_count += 1;
+        true
+    }
+
+    /// The RGBA8 pixels of the entire atlas, row by row.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Create an image from the atlas, for example to save it or use it as a particle texture.
+    pub fn to_image(&self) -> Image {
+        let size = self.grid_size * self.frame_size;
+        Image::new(
+            Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            self.data.clone(),
+            TextureFormat::Rgba8UnormSrgb,
+        )
+    }
+}
+
+/// State of a [`FlipbookBaker`] shared with the render world.
+#[derive(Debug)]
+struct BakeState {
+    atlas: FlipbookAtlas,
+    frame_step: u32,
+    frames_rendered: u32,
+}
+
+/// Component baking the frames rendered by a camera into a [`FlipbookAtlas`].
+///
+/// Add this component to a camera rendering into an image, with a [`RenderTarget::Image`]
+/// target. The image must have the `Rgba8UnormSrgb` format, the size of a single frame, and
+/// the `COPY_SRC` usage in addition to the usual `RENDER_ATTACHMENT` usage. Each frame
+/// rendered by the camera, one every `frame_step` frames is copied back to the CPU and added
+/// to the atlas, until the atlas is complete.
+///
+/// Clear the camera target to a transparent black color. The particles are alpha blended
+/// over it, which accumulates their colors premultiplied by their alpha. The baked frames are
+/// converted back to straight alpha before being added to the atlas, so that they blend
+/// correctly once the baked flipbook is rendered as a particle texture, which is alpha
+/// blended too.
+///
+/// The baking requires the [`FlipbookBakePlugin`].
+#[derive(Debug, Clone, Component)]
+pub struct FlipbookBaker {
+    state: Arc<Mutex<BakeState>>,
+}
+
+impl FlipbookBaker {
+    /// Create a baker filling an atlas of `grid_size` columns and rows of frames, each
+    /// frame being `frame_size` pixels, with one frame every `frame_step` rendered frames.
+    pub fn new(grid_size: UVec2, frame_size: UVec2, frame_step: u32) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(BakeState {
+                atlas: FlipbookAtlas::new(grid_size, frame_size),
+                frame_step: frame_step.max(1),
+                frames_rendered: 0,
+            })),
+        }
+    }
+
+    /// Check if all the frames of the atlas were baked.
+    pub fn is_complete(&self) -> bool {
+        self.state.lock().unwrap().atlas.is_complete()
+    }
+
+    /// Get a copy of the atlas baked so far.
+    pub fn atlas(&self) -> FlipbookAtlas {
+        self.state.lock().unwrap().atlas.clone()
+    }
+}
+
+/// Plugin baking the frames of the cameras with a [`FlipbookBaker`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FlipbookBakePlugin;
+
+impl Plugin for FlipbookBakePlugin {
+    fn build(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .init_resource::<BakeReadbacks>()
+            .init_resource::<PendingBakeReadbacks>()
+            .add_system_to_stage(RenderStage::Extract, extract_flipbook_bakers)
+            .add_system_to_stage(RenderStage::Prepare, prepare_flipbook_bakers)
+            .add_system_to_stage(RenderStage::Cleanup, readback_flipbook_bakers);
+
+        // Copy the frames once all cameras have rendered
+        let mut graph = render_app.world.get_resource_mut::<RenderGraph>().unwrap();
+        graph.add_node(FLIPBOOK_BAKE_PASS, FlipbookBakeNode);
+        graph
+            .add_node_edge(
+                bevy::core_pipeline::node::MAIN_PASS_DRIVER,
+                FLIPBOOK_BAKE_PASS,
+            )
+            .unwrap();
+    }
+}
+
+/// Frame to copy back to the CPU for a [`FlipbookBaker`].
+struct ExtractedBaker {
+    image: Handle<Image>,
+    size: Extent3d,
+    state: Arc<Mutex<BakeState>>,
+}
+
+/// Frames to copy back to the CPU this frame.
+struct ExtractedBakers(Vec<ExtractedBaker>);
+
+/// Readback buffer of a frame to copy back to the CPU.
+struct BakeReadback {
+    image: Handle<Image>,
+    size: Extent3d,
+    /// Size in bytes of a row of pixels in the buffer, padded to the copy alignment.
+    padded_row_bytes: u32,
+    buffer: Buffer,
+    state: Arc<Mutex<BakeState>>,
+}
+
+/// Readback buffers of all the frames to copy back to the CPU this frame.
+#[derive(Default)]
+struct BakeReadbacks(Vec<BakeReadback>);
+
+/// Frame copied into a readback buffer being mapped, waiting to be added to its atlas.
+struct PendingBakeReadback {
+    size: Extent3d,
+    padded_row_bytes: u32,
+    readback: AsyncReadback,
+    state: Arc<Mutex<BakeState>>,
+}
+
+/// Frames being mapped, in the order they were copied.
+#[derive(Default)]
+struct PendingBakeReadbacks(Vec<PendingBakeReadback>);
+
+fn extract_flipbook_bakers(
+    mut commands: Commands,
+    images: Res<Assets<Image>>,
+    bakers: Query<(&Camera, &FlipbookBaker)>,
+) {
+    let mut extracted = vec![];
+    for (camera, baker) in bakers.iter() {
+        let image_handle = if let RenderTarget::Image(handle) = &camera.target {
+            handle
+        } else {
+            continue;
+        };
+        let image = if let Some(image) = images.get(image_handle) {
+            image
+        } else {
+            continue;
+        };
+
+        let mut state = baker.state.lock().unwrap();
+        if state.atlas.is_complete() {
+            continue;
+        }
+        let size = image.texture_descriptor.size;
+        let frame_size = state.atlas.frame_size();
+        if image.texture_descriptor.format != TextureFormat::Rgba8UnormSrgb
+            || size.width != frame_size.x
+            || size.height != frame_size.y
+        {
+            warn!(
+                "Flipbook baker target image must be Rgba8UnormSrgb and {}x{} pixels.",
+                frame_size.x, frame_size.y
+            );
+            continue;
+        }
+
+        // Only bake one frame every frame_step
+        let capture = state.frames_rendered % state.frame_step == 0;
+        state.frames_rendered += 1;
+        if capture {
+            extracted.push(ExtractedBaker {
+                image: image_handle.clone_weak(),
+                size,
+                state: baker.state.clone(),
+            });
+        }
+    }
+    commands.insert_resource(ExtractedBakers(extracted));
+}
+
+fn prepare_flipbook_bakers(
+    render_device: Res<RenderDevice>,
+    bakers: Res<ExtractedBakers>,
+    mut readbacks: ResMut<BakeReadbacks>,
+) {
+    readbacks.0 = bakers
+        .0
+        .iter()
+        .map(|baker| {
+            let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+            let padded_row_bytes = (baker.size.width * 4).next_multiple_of(align);
+            let buffer = render_device.create_buffer(&BufferDescriptor {
+                label: Some("hanabi:flipbook_bake_readback"),
+                size: padded_row_bytes as u64 * baker.size.height as u64,
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            });
+            BakeReadback {
+                image: baker.image.clone_weak(),
+                size: baker.size,
+                padded_row_bytes,
+                buffer,
+                state: baker.state.clone(),
+            }
+        })
+        .collect();
+}
+
+fn readback_flipbook_bakers(
+    render_device: Res<RenderDevice>,
+    gpu_images: Res<RenderAssets<Image>>,
+    mut readbacks: ResMut<BakeReadbacks>,
+    mut pending: ResMut<PendingBakeReadbacks>,
+) {
+    // Request the mapping of the frames copied this frame, which were submitted already
+    for readback in readbacks.0.drain(..) {
+        // The frame was not copied if the GPU image was not available
+        if gpu_images.get(&readback.image).is_none() {
+            continue;
+        }
+        pending.0.push(PendingBakeReadback {
+            size: readback.size,
+            padded_row_bytes: readback.padded_row_bytes,
+            readback: AsyncReadback::new(readback.buffer),
+            state: readback.state,
+        });
+    }
+    if pending.0.is_empty() {
+        return;
+    }
+
+    // Add the mapped frames to their atlas, without waiting for the others. Frames are added
+    // in order, so stop at the first frame still being mapped.
+    render_device.poll(wgpu::Maintain::Poll);
+    let mut frames = std::mem::take(&mut pending.0).into_iter();
+    for frame in frames.by_ref() {
+        let row_bytes = frame.size.width as usize * 4;
+        let padded_row_bytes = frame.padded_row_bytes as usize;
+        let height = frame.size.height as usize;
+        let result = frame.readback.try_read(|data| {
+            let mut pixels = Vec::with_capacity(row_bytes * height);
+            for row in data.chunks(padded_row_bytes) {
+                pixels.extend_from_slice(&row[..row_bytes]);
+            }
+            pixels
+        });
+        match result {
+            Ok(Some(mut pixels)) => {
+                unpremultiply_srgb(&mut pixels);
+                frame.state.lock().unwrap().atlas.add_frame(&pixels);
+            }
+            Ok(None) => warn!("Failed to read back a flipbook frame."),
+            Err(readback) => {
+                pending.0.push(PendingBakeReadback { readback, ..frame });
+                break;
+            }
+        }
+    }
+    pending.0.extend(frames);
+}
+
+/// Convert RGBA8 sRGB pixels from premultiplied alpha to straight alpha.
+///
+/// The colors are premultiplied in linear space by the blending, so are converted to linear
+/// space to be divided by the alpha, then back to sRGB. Fully transparent pixels are left
+/// black.
+fn unpremultiply_srgb(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        let alpha = pixel[3] as f32 / 255.;
+        if alpha <= 0. || alpha >= 1. {
+            continue;
+        }
+        let [r, g, b, _] = Color::rgb_u8(pixel[0], pixel[1], pixel[2]).as_linear_rgba_f32();
+        let [r, g, b, _] = Color::rgb_linear(
+            (r / alpha).min(1.),
+            (g / alpha).min(1.),
+            (b / alpha).min(1.),
+        )
+        .as_rgba_f32();
+        for (dst, src) in pixel.iter_mut().zip([r, g, b]) {
+            *dst = (src * 255.).round() as u8;
+        }
+    }
+}
+
+/// Render graph node copying the frames to bake into their readback buffer.
+struct FlipbookBakeNode;
+
+impl Node for FlipbookBakeNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let readbacks = world.get_resource::<BakeReadbacks>().unwrap();
+        let gpu_images = world.get_resource::<RenderAssets<Image>>().unwrap();
+        for readback in &readbacks.0 {
+            if let Some(gpu_image) = gpu_images.get(&readback.image) {
+                render_context.command_encoder.copy_texture_to_buffer(
+                    gpu_image.texture.as_image_copy(),
+                    ImageCopyBuffer {
+                        buffer: &readback.buffer,
+                        layout: ImageDataLayout {
+                            offset: 0,
+                            bytes_per_row: NonZeroU32::new(readback.padded_row_bytes),
+                            rows_per_image: None,
+                        },
+                    },
+                    readback.size,
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atlas_layout() {
+        let mut atlas = FlipbookAtlas::new(UVec2::new(2, 2), UVec2::new(1, 2));
+        assert_eq!(atlas.data().len(), 2 * 4 * 4);
+        for i in 0..4u8 {
+            assert!(!atlas.is_complete());
+            assert!(atlas.add_frame(&[i; 8]));
+        }
+        assert!(atlas.is_complete());
+        assert!(!atlas.add_frame(&[9; 8]));
+        assert_eq!(atlas.frame_count(), 4);
+
+        // Row-major frames, each 1 pixel wide and 2 pixels high
+        let pixel = |x: usize, y: usize| atlas.data()[(y * 2 + x) * 4];
+        assert_eq!(pixel(0, 0), 0);
+        assert_eq!(pixel(1, 0), 1);
+        assert_eq!(pixel(0, 1), 0);
+        assert_eq!(pixel(1, 1), 1);
+        assert_eq!(pixel(0, 2), 2);
+        assert_eq!(pixel(1, 3), 3);
+
+        let image = atlas.to_image();
+        assert_eq!(image.texture_descriptor.size.width, 2);
+        assert_eq!(image.texture_descriptor.size.height, 4);
+    }
+
+    #[test]
+    fn unpremultiply() {
+        // Opaque and fully transparent pixels are unchanged
+        let mut pixels = [10, 128, 250, 255, 0, 0, 0, 0];
+        unpremultiply_srgb(&mut pixels);
+        assert_eq!(pixels, [10, 128, 250, 255, 0, 0, 0, 0]);
+
+        // Linear white at half alpha is 188 in sRGB once premultiplied
+        let mut pixels = [188, 0, 94, 128];
+        unpremultiply_srgb(&mut pixels);
+        assert_eq!(pixels[0], 255);
+        assert_eq!(pixels[1], 0);
+        assert!((pixels[2] as i32 - 130).abs() <= 1);
+        assert_eq!(pixels[3], 128);
+    }
+}
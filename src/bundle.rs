@@ -1,3 +1,7 @@
+// The `Bundle` derive forgets the components after moving them, which clippy flags for the
+// components not implementing `Drop`.
+#![allow(clippy::forget_non_drop)]
+
 use crate::{EffectAsset, ParticleEffect, Spawner};
 use bevy::prelude::*;
 
@@ -0,0 +1,143 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// CPU inputs of a single [`ParticleEffect`] for a single frame.
+///
+/// Those are all the values which vary per frame and are sent to the GPU to simulate the
+/// effect. Replaying the same inputs reproduces the same particles.
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EffectFrameInputs {
+    /// Global transform of the effect, as a column-major matrix.
+    pub transform: [f32; 16],
+    /// Number of particles spawned this frame.
+    pub spawn_count: u32,
+    /// Seed of the GPU random generator used to initialize the spawned particles.
+    pub seed: u32,
+    /// Multiplier of the initial speed of the spawned particles.
+    pub speed_scale: f32,
+    /// Position of the last link of the chain before this frame.
+    pub chain_head: [f32; 3],
+    /// Offset between two links of the chain spawned this frame.
+    pub chain_step: [f32; 3],
+    /// Position the particles are attracted to.
+    pub attractor: [f32; 3],
+    /// Acceleration of the particles toward `attractor`, or zero if none.
+    pub attractor_accel: f32,
+    /// Range of burst progress swept by the particles spawned this frame.
+    pub sweep: [f32; 2],
+    /// Age of the first and last particle spawned this frame, in seconds.
+    #[serde(default)]
+    pub age: [f32; 2],
+}
+
+/// Recording of the per-frame inputs of a [`ParticleEffect`].
+///
+/// The recording can be serialized, for example to attach it to a bug report, and played
+/// back later with an [`EffectCapture`].
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EffectRecording {
+    /// Inputs of each recorded frame, in order.
+    pub frames: Vec<EffectFrameInputs>,
+}
+
+/// Component recording or playing back the per-frame inputs of a [`ParticleEffect`].
+///
+/// Add this component to the entity of a [`ParticleEffect`] to record its inputs each frame
+/// the effect is visible, or to replace them by those of a previous recording. During
+/// playback, the live inputs of the effect are ignored, and the effect stops spawning
+/// particles once all the recorded frames have been played.
+///
+/// The recording doesn't include the frame delta time, which is shared by all effects. To
+/// reproduce the exact same visual, record and play back with the same fixed delta time.
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+#[derive(Debug, Clone, PartialEq, Component)]
+pub enum EffectCapture {
+    /// Append the inputs of each frame to the recording.
+    Record(EffectRecording),
+    /// Replace the inputs of each frame by those of the recording.
+    Playback {
+        /// The recording to play back.
+        recording: EffectRecording,
+        /// The index of the next frame to play.
+        frame: usize,
+    },
+}
+
+impl EffectCapture {
+    /// Create a new capture in record mode, with an empty recording.
+    pub fn record() -> Self {
+        EffectCapture::Record(EffectRecording::default())
+    }
+
+    /// Create a new capture playing back a recording from its start.
+    pub fn playback(recording: EffectRecording) -> Self {
+        EffectCapture::Playback {
+            recording,
+            frame: 0,
+        }
+    }
+
+    /// Record the inputs of the current frame, or replace them during playback.
+    pub(crate) fn process(&mut self, inputs: &mut EffectFrameInputs) {
+        match self {
+            EffectCapture::Record(recording) => recording.frames.push(*inputs),
+            EffectCapture::Playback { recording, frame } => {
+                if let Some(recorded) = recording.frames.get(*frame) {
+                    *inputs = *recorded;
+                    *frame += 1;
+                } else {
+                    inputs.spawn_count = 0;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_playback() {
+        let mut capture = EffectCapture::record();
+        for i in 0..3 {
+            let mut inputs = EffectFrameInputs {
+                spawn_count: i,
+                seed: i * 10,
+                ..Default::default()
+            };
+            capture.process(&mut inputs);
+            assert_eq!(inputs.spawn_count, i);
+        }
+
+        let recording = match capture {
+            EffectCapture::Record(recording) => recording,
+            _ => unreachable!(),
+        };
+        assert_eq!(recording.frames.len(), 3);
+
+        let mut capture = EffectCapture::playback(recording);
+        for i in 0..3 {
+            let mut inputs = EffectFrameInputs {
+                spawn_count: 42,
+                ..Default::default()
+            };
+            capture.process(&mut inputs);
+            assert_eq!(inputs.spawn_count, i);
+            assert_eq!(inputs.seed, i * 10);
+        }
+
+        // Spawn nothing once the recording is over
+        let mut inputs = EffectFrameInputs {
+            spawn_count: 42,
+            ..Default::default()
+        };
+        capture.process(&mut inputs);
+        assert_eq!(inputs.spawn_count, 0);
+    }
+}
@@ -0,0 +1,45 @@
+//! Deprecated aliases of items renamed across releases.
+//!
+//! When an item of the public API is renamed, its old name is kept here for one release as a
+//! deprecated alias forwarding to the new one, so that large codebases can upgrade to a new
+//! version of the crate first, then migrate each use of the old name incrementally while
+//! following the deprecation warnings. Import the aliases with:
+//!
+//! ```
+//! #[allow(deprecated)]
+//! use bevy_hanabi::compat::*;
+//! ```
+//!
+//! The aliases are removed in the release following their deprecation.
+#![allow(deprecated)]
+
+use crate::ToWgslString;
+
+/// Deprecated name of [`ToWgslString`].
+#[deprecated(since = "0.2.0", note = "Use `ToWgslString` instead.")]
+pub trait ToWgslFloat {
+    /// Deprecated name of [`ToWgslString::to_wgsl_string()`].
+    #[deprecated(
+        since = "0.2.0",
+        note = "Use `ToWgslString::to_wgsl_string()` instead."
+    )]
+    fn to_float_string(&self) -> String;
+}
+
+impl<T: ToWgslString> ToWgslFloat for T {
+    fn to_float_string(&self) -> String {
+        self.to_wgsl_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::math::Vec2;
+
+    #[test]
+    fn to_wgsl_float() {
+        assert_eq!(2.0_f32.to_float_string(), 2.0_f32.to_wgsl_string());
+        assert_eq!(Vec2::ONE.to_float_string(), Vec2::ONE.to_wgsl_string());
+    }
+}
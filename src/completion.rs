@@ -0,0 +1,111 @@
+use std::sync::{Arc, Mutex};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{ParticleEffect, ToWgslString};
+
+/// Condition completing an effect instance, evaluated on the GPU.
+///
+/// Each particle alive after the update pass which doesn't satisfy the condition blocks the
+/// completion of the effect. Once no particle blocks it, the effect instance is complete:
+/// an [`EffectCompleted`] event is sent and its entity is despawned, with its children.
+///
+/// The condition is only evaluated once the effect spawned at least one particle, so that
+/// an effect is not completed before its first burst. The result is read back from the GPU
+/// every few frames, so the completion is detected with a latency of a few frames.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CompletionCondition {
+    /// Complete once no particle is alive.
+    NoneAlive,
+    /// Complete once no particle is above the plane passing through `point` with the given
+    /// `normal`, in simulation space.
+    NoneAbove {
+        /// A point of the plane.
+        point: Vec3,
+        /// Normal of the plane, pointing toward the blocking side.
+        normal: Vec3,
+    },
+    /// Complete once no particle moves faster than `speed`, in units per second.
+    NoneFasterThan {
+        /// Speed below which a particle is considered at rest.
+        speed: f32,
+    },
+}
+
+impl CompletionCondition {
+    /// Generate the update shader code counting the particles blocking the completion.
+    pub(crate) fn to_shader_code(self) -> String {
+        let blocking = match self {
+            CompletionCondition::NoneAlive => "true".to_string(),
+            CompletionCondition::NoneAbove { point, normal } => {
+                let normal = normal.normalize_or_zero();
+                format!(
+                    "dot(vPos - {}, {}) > 0.",
+                    point.to_wgsl_string(),
+                    normal.to_wgsl_string()
+                )
+            }
+            CompletionCondition::NoneFasterThan { speed } => {
+                format!("dot(vVel, vVel) > {}", (speed * speed).to_wgsl_string())
+            }
+        };
+        format!(
+            "    // Completion\n    if ({}) {{\n        let pending = atomicAdd(&completion.pending, 1);\n    }}\n",
+            blocking
+        )
+    }
+}
+
+/// Event sent when an effect instance completes, just before its entity is despawned.
+///
+/// See [`CompletionCondition`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EffectCompleted {
+    /// Entity of the completed effect.
+    pub entity: Entity,
+}
+
+/// Entities of the effects found complete by the render world, shared with the main world.
+#[derive(Default, Clone)]
+pub(crate) struct CompletedEffects(pub Arc<Mutex<Vec<Entity>>>);
+
+/// Despawn the effects found complete by the render world, sending an [`EffectCompleted`]
+/// event for each of them.
+pub(crate) fn complete_effects_system(
+    mut commands: Commands,
+    completed: Res<CompletedEffects>,
+    mut events: EventWriter<EffectCompleted>,
+    query: Query<(), With<ParticleEffect>>,
+) {
+    let entities = std::mem::take(&mut *completed.0.lock().unwrap());
+    for entity in entities {
+        // The readback may lag behind, so ignore effects already despawned
+        if query.get(entity).is_ok() {
+            events.send(EffectCompleted { entity });
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shader_code() {
+        let code = CompletionCondition::NoneAlive.to_shader_code();
+        assert!(code.contains("if (true)"));
+        assert!(code.contains("atomicAdd(&completion.pending, 1)"));
+
+        let code = CompletionCondition::NoneAbove {
+            point: Vec3::ZERO,
+            normal: Vec3::Y * 2.,
+        }
+        .to_shader_code();
+        assert!(code.contains("dot(vPos - vec3<f32>(0., 0., 0.), vec3<f32>(0., 1., 0.)) > 0."));
+
+        let code = CompletionCondition::NoneFasterThan { speed: 2. }.to_shader_code();
+        assert!(code.contains("dot(vVel, vVel) > 4."));
+    }
+}
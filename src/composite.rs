@@ -0,0 +1,190 @@
+use bevy::prelude::*;
+
+use crate::{EffectAsset, ParticleEffectBundle, Spawner};
+
+/// A single effect of a [`CompositeEffect`].
+#[derive(Debug, Clone)]
+struct CompositeLayer {
+    /// Handle of the effect asset to instantiate.
+    handle: Handle<EffectAsset>,
+    /// Transform of the effect relative to the composite effect.
+    transform: Transform,
+    /// Spawner overriding the one of the effect asset, if any.
+    spawner: Option<Spawner>,
+}
+
+/// Description of a group of particle effects spawned together, like the flash, sparks and
+/// smoke of an explosion.
+///
+/// Spawning a composite effect creates a root entity carrying a [`CompositeEffectInstance`]
+/// component, with one child entity per effect. All the effects follow the transform of the
+/// root entity, and are despawned together with it, for example with `despawn_recursive()`.
+/// If the composite effect has a lifetime, the root entity and all its effects are despawned
+/// automatically once it elapses.
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_hanabi::*;
+/// fn explode(
+///     mut commands: Commands,
+///     asset_server: Res<AssetServer>,
+/// ) {
+///     CompositeEffect::new()
+///         .with_effect(asset_server.load("flash.effect"))
+///         .with_effect(asset_server.load("sparks.effect"))
+///         .with_effect_at(
+///             asset_server.load("smoke.effect"),
+///             Transform::from_xyz(0., 0.5, 0.),
+///         )
+///         .with_lifetime(5.)
+///         .spawn(&mut commands, Transform::from_xyz(2., 0., 0.));
+/// }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct CompositeEffect {
+    /// Effects spawned by the composite effect.
+    layers: Vec<CompositeLayer>,
+    /// Time after which the composite effect is despawned, in seconds.
+    lifetime: Option<f32>,
+}
+
+impl CompositeEffect {
+    /// Create an empty composite effect.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an effect at the origin of the composite effect.
+    pub fn with_effect(self, handle: Handle<EffectAsset>) -> Self {
+        self.with_effect_at(handle, Transform::identity())
+    }
+
+    /// Add an effect with a transform relative to the composite effect.
+    pub fn with_effect_at(mut self, handle: Handle<EffectAsset>, transform: Transform) -> Self {
+        self.layers.push(CompositeLayer {
+            handle,
+            transform,
+            spawner: None,
+        });
+        self
+    }
+
+    /// Add an effect with a spawner overriding the one of its asset.
+    pub fn with_effect_spawner(
+        mut self,
+        handle: Handle<EffectAsset>,
+        transform: Transform,
+        spawner: Spawner,
+    ) -> Self {
+        self.layers.push(CompositeLayer {
+            handle,
+            transform,
+            spawner: Some(spawner),
+        });
+        self
+    }
+
+    /// Despawn the composite effect and all its effects after `lifetime` seconds.
+    pub fn with_lifetime(mut self, lifetime: f32) -> Self {
+        self.lifetime = Some(lifetime);
+        self
+    }
+
+    /// Get the number of effects of the composite effect.
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Check if the composite effect contains no effect.
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Spawn the composite effect with the given transform, and return its root entity.
+    pub fn spawn(&self, commands: &mut Commands, transform: Transform) -> Entity {
+        commands
+            .spawn_bundle((
+                transform,
+                GlobalTransform::default(),
+                CompositeEffectInstance {
+                    remaining: self.lifetime,
+                },
+            ))
+            .with_children(|parent| {
+                for layer in &self.layers {
+                    let mut bundle = ParticleEffectBundle::new(layer.handle.clone());
+                    bundle.transform = layer.transform;
+                    if let Some(spawner) = layer.spawner {
+                        bundle = bundle.with_spawner(spawner);
+                    }
+                    parent.spawn_bundle(bundle);
+                }
+            })
+            .id()
+    }
+}
+
+/// Root of a group of particle effects spawned by a [`CompositeEffect`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Component)]
+pub struct CompositeEffectInstance {
+    /// Time left before the composite effect is despawned, in seconds, if any.
+    pub remaining: Option<f32>,
+}
+
+/// Despawn the composite effects whose lifetime elapsed, with all their effects.
+pub(crate) fn composite_effect_lifetime_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut CompositeEffectInstance)>,
+) {
+    let dt = time.delta_seconds();
+    for (entity, mut instance) in query.iter_mut() {
+        if let Some(remaining) = instance.remaining.as_mut() {
+            *remaining -= dt;
+            if *remaining <= 0. {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::CommandQueue;
+
+    #[test]
+    fn spawn_children() {
+        let composite = CompositeEffect::new()
+            .with_effect(Handle::default())
+            .with_effect_spawner(
+                Handle::default(),
+                Transform::from_xyz(0., 1., 0.),
+                Spawner::rate(5.0.into()),
+            )
+            .with_lifetime(2.);
+        assert_eq!(composite.len(), 2);
+
+        let mut world = World::default();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        let root = composite.spawn(&mut commands, Transform::default());
+        queue.apply(&mut world);
+
+        assert_eq!(
+            world
+                .get::<CompositeEffectInstance>(root)
+                .unwrap()
+                .remaining,
+            Some(2.)
+        );
+        let children = world.get::<Children>(root).unwrap();
+        assert_eq!(children.len(), 2);
+        let child = children[1];
+        assert_eq!(
+            world.get::<Transform>(child).unwrap().translation,
+            Vec3::new(0., 1., 0.)
+        );
+        assert!(world.get::<crate::ParticleEffect>(child).is_some());
+    }
+}
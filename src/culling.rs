@@ -0,0 +1,107 @@
+use bevy::{
+    math::Vec3A,
+    prelude::*,
+    render::primitives::{Frustum, Sphere},
+};
+
+/// Pause the simulation of a [`ParticleEffect`] while it's outside the view of all cameras.
+///
+/// Add this component to the entity of a [`ParticleEffect`] to opt in. The effect is
+/// considered off-screen when a sphere of `radius` around its origin is outside the frustum
+/// of every camera. While off-screen, the particles neither age nor move, and no particle
+/// is spawned. The last simulated state is kept on the GPU, so the effect reappears exactly
+/// as it was when it left the screen, and resumes from there. This suits slow ambient
+/// effects like drifting fog, whose pause is not noticeable.
+///
+/// This differs from pausing an effect with an [`EffectTimeControl`], which keeps
+/// rendering the effect, and from hiding it with [`Visibility`], which also pauses the
+/// simulation but stops rendering even on-screen.
+///
+/// The component is ignored if [`HanabiConfig::culling`] is disabled.
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+/// [`EffectTimeControl`]: crate::EffectTimeControl
+/// [`HanabiConfig::culling`]: crate::HanabiConfig::culling
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub struct PauseWhenOffscreen {
+    /// Radius of the sphere around the effect origin bounding all its particles.
+    pub radius: f32,
+}
+
+impl PauseWhenOffscreen {
+    /// Check if the bounding sphere of an effect at `origin` intersects any of the frustums.
+    ///
+    /// Cameras without a frustum, which can't be culled against, always see the effect.
+    pub(crate) fn is_onscreen<'a>(
+        &self,
+        origin: Vec3,
+        mut frustums: impl Iterator<Item = Option<&'a Frustum>>,
+    ) -> bool {
+        let sphere = Sphere {
+            center: Vec3A::from(origin),
+            radius: self.radius,
+        };
+        frustums.any(|frustum| {
+            frustum
+                .map(|frustum| frustum.intersects_sphere(&sphere, true))
+                .unwrap_or(true)
+        })
+    }
+}
+
+/// Reason a [`ParticleEffect`] was neither simulated nor rendered in a frame.
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CullReason {
+    /// The effect is not visible, from its [`Visibility`] or that of one of its ancestors.
+    Hidden,
+    /// The effect is outside the view of all cameras, and has a [`PauseWhenOffscreen`].
+    Offscreen,
+    /// The maximum number of effects per frame set by [`HanabiConfig::max_effects`] was
+    /// reached by other effects.
+    ///
+    /// [`HanabiConfig::max_effects`]: crate::HanabiConfig::max_effects
+    Budget,
+    /// The [`EffectAsset`] of the effect is not loaded yet.
+    ///
+    /// [`EffectAsset`]: crate::EffectAsset
+    AssetNotLoaded,
+}
+
+/// Culling state of a [`ParticleEffect`] during the last frame, for debugging.
+///
+/// Add this component to the entity of a [`ParticleEffect`] to record each frame whether the
+/// effect was culled, and why, as well as the world-space bounds it was culled against. This
+/// helps diagnosing effects which unexpectedly don't show, and can drive a debug overlay, for
+/// example a marker drawn at the bounds of each culled effect.
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+#[derive(Debug, Default, Clone, Copy, PartialEq, Component)]
+pub struct EffectCullingDebug {
+    /// Reason the effect was culled during the last frame, if any.
+    culled: Option<CullReason>,
+    /// Center and radius of the bounding sphere of the effect, in world space.
+    bounds: Option<(Vec3, f32)>,
+}
+
+impl EffectCullingDebug {
+    /// Get the reason the effect was culled during the last frame, or `None` if the effect
+    /// was simulated and rendered.
+    pub fn culled(&self) -> Option<CullReason> {
+        self.culled
+    }
+
+    /// Get the center and radius of the world-space bounding sphere the effect was culled
+    /// against during the last frame, or `None` if the effect has no bounds because it has
+    /// no [`PauseWhenOffscreen`].
+    pub fn bounds(&self) -> Option<(Vec3, f32)> {
+        self.bounds
+    }
+
+    /// Record the culling state of the effect for the current frame.
+    pub(crate) fn record(&mut self, culled: Option<CullReason>, bounds: Option<(Vec3, f32)>) {
+        self.culled = culled;
+        self.bounds = bounds;
+    }
+}
@@ -0,0 +1,145 @@
+use bevy::{prelude::*, render::camera::RenderTarget};
+
+use crate::{attractor::view_ray, ExternalSpawnCount};
+
+/// Function returning the world position hit by a ray, if any.
+///
+/// The function receives the origin and the normalized direction of the ray.
+pub type RayHitProvider = Box<dyn Fn(Vec3, Vec3) -> Option<Vec3> + Send + Sync>;
+
+/// Component painting particles with the mouse cursor.
+///
+/// While `active`, each frame the cursor position over the window of `camera` is cast as a
+/// ray into the world, and the ray is passed to the `hit_provider` of the painter, which can
+/// for example raycast against the physics scene of the application. If the ray hits, the
+/// [`ParticleEffect`] on the same entity spawns `spawn_per_hit` particles at the hit position
+/// instead of at its emitter, whose [`Transform`] is left untouched. Since the particles are
+/// simulated in world space, the particles already spawned stay where they were painted.
+///
+/// The spawns are driven through an [`ExternalSpawnCount`] on the entity, which the painter
+/// inserts while painting, and removes once painting stops and the last particles painted
+/// are spawned. The spawner of the effect ticks as usual the rest of the time.
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+#[derive(Component)]
+pub struct CursorPainter {
+    /// Entity of the camera to cast the cursor ray from.
+    pub camera: Entity,
+    /// Function returning the world position hit by the cursor ray, if any.
+    pub hit_provider: RayHitProvider,
+    /// Number of particles to spawn each frame the cursor ray hits.
+    pub spawn_per_hit: u32,
+    /// Whether the painter is active, for example while a mouse button is pressed.
+    pub active: bool,
+    /// Position hit by the cursor ray this frame, if painting. This is updated by the painter.
+    pub hit: Option<Vec3>,
+}
+
+impl CursorPainter {
+    /// Create an active painter spawning particles where the cursor ray hits a plane.
+    ///
+    /// The plane contains `point` and is perpendicular to `normal`.
+    pub fn on_plane(camera: Entity, point: Vec3, normal: Vec3, spawn_per_hit: u32) -> Self {
+        CursorPainter {
+            camera,
+            hit_provider: Box::new(move |origin, dir| ray_plane_hit(origin, dir, point, normal)),
+            spawn_per_hit,
+            active: true,
+            hit: None,
+        }
+    }
+}
+
+/// Intersect a ray with a plane, returning the hit position in front of the ray origin.
+fn ray_plane_hit(origin: Vec3, dir: Vec3, point: Vec3, normal: Vec3) -> Option<Vec3> {
+    let denom = dir.dot(normal);
+    if denom.abs() < 1e-6 {
+        return None;
+    }
+    let t = (point - origin).dot(normal) / denom;
+    if t < 0. {
+        None
+    } else {
+        Some(origin + dir * t)
+    }
+}
+
+/// Cast the cursor ray of a painter, and return the world position it hits, if any.
+fn cursor_hit(
+    painter: &CursorPainter,
+    windows: &Windows,
+    cameras: &Query<(&Camera, &GlobalTransform)>,
+) -> Option<Vec3> {
+    let (camera, camera_transform) = cameras.get(painter.camera).ok()?;
+    let window = match camera.target {
+        RenderTarget::Window(id) => windows.get(id)?,
+        _ => return None,
+    };
+    // The window cursor origin is at the bottom left corner
+    let cursor = window
+        .cursor_position()
+        .map(|pos| Vec2::new(pos.x / window.width(), 1. - pos.y / window.height()))?;
+    let (origin, dir) = view_ray(
+        &camera.projection_matrix,
+        &camera_transform.compute_matrix(),
+        cursor,
+    );
+    (painter.hit_provider)(origin, dir)
+}
+
+/// System spawning particles at the cursor hit position of the effects with a
+/// [`CursorPainter`].
+pub(crate) fn cursor_painter_system(
+    mut commands: Commands,
+    windows: Res<Windows>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mut painters: Query<(Entity, &mut CursorPainter, Option<&mut ExternalSpawnCount>)>,
+) {
+    for (entity, mut painter, spawn_count) in painters.iter_mut() {
+        painter.hit = if painter.active {
+            cursor_hit(&painter, &windows, &cameras)
+        } else {
+            None
+        };
+
+        match (painter.hit, spawn_count) {
+            (Some(_), Some(mut spawn_count)) => spawn_count.0 += painter.spawn_per_hit,
+            (Some(_), None) => {
+                commands
+                    .entity(entity)
+                    .insert(ExternalSpawnCount(painter.spawn_per_hit));
+            }
+            // Let the spawner of the effect tick again once the last particles painted were
+            // spawned
+            (None, Some(spawn_count)) if spawn_count.0 == 0 => {
+                commands.entity(entity).remove::<ExternalSpawnCount>();
+            }
+            (None, _) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plane_hit() {
+        let hit = ray_plane_hit(Vec3::new(0., 5., 0.), -Vec3::Y, Vec3::ZERO, Vec3::Y);
+        assert_eq!(hit, Some(Vec3::ZERO));
+
+        let hit = ray_plane_hit(
+            Vec3::new(0., 5., 0.),
+            Vec3::new(1., -1., 0.).normalize(),
+            Vec3::ZERO,
+            Vec3::Y,
+        );
+        assert!(hit.unwrap().abs_diff_eq(Vec3::new(5., 0., 0.), 1e-5));
+
+        // Parallel to the plane
+        assert_eq!(ray_plane_hit(Vec3::Y, Vec3::X, Vec3::ZERO, Vec3::Y), None);
+
+        // Plane behind the ray
+        assert_eq!(ray_plane_hit(Vec3::Y, Vec3::Y, Vec3::ZERO, Vec3::Y), None);
+    }
+}
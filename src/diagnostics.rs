@@ -0,0 +1,265 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics},
+    prelude::*,
+};
+
+use crate::{render::PipelineRegistry, EffectAsset, ParticleEffect, ToWgslString};
+
+/// Adds particle effect statistics to [`Diagnostics`].
+///
+/// The statistics are sampled once per frame from the main app world, and can be displayed
+/// with any diagnostics consumer, like the `LogDiagnosticsPlugin` of Bevy.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EffectDiagnosticsPlugin;
+
+impl Plugin for EffectDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(Self::setup_system)
+            .add_system(Self::diagnostic_system);
+    }
+}
+
+impl EffectDiagnosticsPlugin {
+    /// Number of [`ParticleEffect`] instances in the world.
+    pub const EFFECT_COUNT: DiagnosticId =
+        DiagnosticId::from_u128(140917624327925846134498366315442573149);
+    /// Sum of the capacities of all [`ParticleEffect`] instances, in number of particles.
+    pub const TOTAL_CAPACITY: DiagnosticId =
+        DiagnosticId::from_u128(262043584905134780815785611036364101707);
+    /// Number of render shader variants generated for all the effects used so far.
+    pub const RENDER_SHADER_COUNT: DiagnosticId =
+        DiagnosticId::from_u128(56374981620187429064532734186548101391);
+
+    /// Register the effect diagnostics.
+    pub fn setup_system(mut diagnostics: ResMut<Diagnostics>) {
+        diagnostics.add(Diagnostic::new(Self::EFFECT_COUNT, "effect_count", 20));
+        diagnostics.add(Diagnostic::new(Self::TOTAL_CAPACITY, "total_capacity", 20));
+        diagnostics.add(Diagnostic::new(
+            Self::RENDER_SHADER_COUNT,
+            "render_shader_count",
+            20,
+        ));
+    }
+
+    /// Sample the effect statistics for the current frame.
+    pub fn diagnostic_system(
+        mut diagnostics: ResMut<Diagnostics>,
+        effects: Res<Assets<EffectAsset>>,
+        pipeline_registry: Res<PipelineRegistry>,
+        query: Query<&ParticleEffect>,
+    ) {
+        let mut effect_count = 0;
+        let mut total_capacity = 0;
+        for effect in query.iter() {
+            effect_count += 1;
+            if let Some(asset) = effects.get(&effect.handle) {
+                total_capacity += asset.capacity as u64;
+            }
+        }
+
+        diagnostics.add_measurement(Self::EFFECT_COUNT, effect_count as f64);
+        diagnostics.add_measurement(Self::TOTAL_CAPACITY, total_capacity as f64);
+        diagnostics.add_measurement(Self::RENDER_SHADER_COUNT, pipeline_registry.len() as f64);
+    }
+}
+
+/// Metrics of a single [`ParticleEffect`], for gameplay and scripting code.
+///
+/// Add this component to the entity of a [`ParticleEffect`] to estimate its number of alive
+/// particles, for example to throttle an effect from a script when it gets too dense. The
+/// metrics are refreshed every `update_period` seconds of effect time rather than every frame,
+/// so they can be polled cheaply and don't flicker. The component is reflected, so scripting
+/// integrations can read it like any other reflected component.
+///
+/// The particles are simulated on the GPU and never read back, so the metrics are estimated
+/// on the CPU from the spawned particles and the average lifetime of the effect.
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+#[derive(Debug, Clone, PartialEq, Component, Reflect)]
+#[reflect(Component)]
+pub struct EffectMetrics {
+    /// Interval between two refreshes of the metrics, in seconds.
+    pub update_period: f32,
+    /// Estimated number of alive particles at the last refresh.
+    alive: u32,
+    /// Highest estimated number of alive particles since the component was added.
+    high_watermark: u32,
+    /// Number of particles which could not be spawned because the effect was full, since the
+    /// component was added.
+    dropped: u64,
+    /// Age and count of the particles spawned each frame and still alive.
+    #[reflect(ignore)]
+    spawns: VecDeque<(f32, u32)>,
+    /// Current estimated number of alive particles.
+    #[reflect(ignore)]
+    live_alive: u32,
+    /// Current highest estimated number of alive particles.
+    #[reflect(ignore)]
+    live_high_watermark: u32,
+    /// Current number of dropped particles.
+    #[reflect(ignore)]
+    live_dropped: u64,
+    /// Effect time elapsed since the last refresh.
+    #[reflect(ignore)]
+    since_refresh: f32,
+}
+
+impl Default for EffectMetrics {
+    fn default() -> Self {
+        Self {
+            update_period: 0.25,
+            alive: 0,
+            high_watermark: 0,
+            dropped: 0,
+            spawns: VecDeque::new(),
+            live_alive: 0,
+            live_high_watermark: 0,
+            live_dropped: 0,
+            since_refresh: 0.,
+        }
+    }
+}
+
+impl EffectMetrics {
+    /// Create metrics refreshed every `update_period` seconds.
+    pub fn new(update_period: f32) -> Self {
+        Self {
+            update_period,
+            ..Default::default()
+        }
+    }
+
+    /// Get the estimated number of alive particles.
+    pub fn alive(&self) -> u32 {
+        self.alive
+    }
+
+    /// Get the highest estimated number of alive particles since the component was added.
+    pub fn high_watermark(&self) -> u32 {
+        self.high_watermark
+    }
+
+    /// Get the number of particles which could not be spawned because the effect was full,
+    /// since the component was added.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Record a frame of `dt` seconds of effect time, during which the spawner requested
+    /// `requested` particles, for an effect of `capacity` particles living `lifetime` seconds.
+    pub(crate) fn record(&mut self, dt: f32, requested: u32, capacity: u32, lifetime: f32) {
+        // Age the particles spawned during the previous frames, and retire the dead ones
+        for spawn in self.spawns.iter_mut() {
+            spawn.0 += dt;
+        }
+        while let Some(&(age, count)) = self.spawns.front() {
+            if age < lifetime {
+                break;
+            }
+            self.live_alive -= count;
+            self.spawns.pop_front();
+        }
+
+        // Particles only spawn in place of dead ones
+        let spawned = requested.min(capacity.saturating_sub(self.live_alive));
+        if spawned > 0 {
+            self.spawns.push_back((0., spawned));
+            self.live_alive += spawned;
+        }
+        self.live_dropped += (requested - spawned) as u64;
+        self.live_high_watermark = self.live_high_watermark.max(self.live_alive);
+
+        self.since_refresh += dt;
+        if self.since_refresh >= self.update_period {
+            self.since_refresh = 0.;
+            self.alive = self.live_alive;
+            self.high_watermark = self.live_high_watermark;
+            self.dropped = self.live_dropped;
+        }
+    }
+}
+
+/// Debug view coloring the particles of a [`ParticleEffect`] by one of their attributes.
+///
+/// Add this component to the entity of a [`ParticleEffect`] to replace the color of its
+/// particles by a false color, to diagnose the distribution or the lifetime of the particles.
+/// Scalar attributes are mapped from blue at their lowest value, through green, to red at
+/// their highest value. The particle texture still modulates the alpha of the particles, so
+/// their shape is unchanged. Remove the component to restore the normal colors.
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub enum EffectDebugView {
+    /// Color by the age of the particle relative to its lifetime.
+    Age,
+    /// Color by the speed of the particle, from zero to `max_speed`.
+    Speed {
+        /// The speed mapped to red.
+        max_speed: f32,
+    },
+    /// Color by the spawn index of the particle, with a different hue for each consecutive
+    /// index.
+    SpawnIndex,
+}
+
+impl EffectDebugView {
+    /// Generate the vertex shader code overriding the particle color.
+    pub(crate) fn to_shader_code(self) -> String {
+        let value = match self {
+            EffectDebugView::Age => "particle.age / particle.lifetime".to_string(),
+            EffectDebugView::Speed { max_speed } => format!(
+                "length(particle.vel) / {}",
+                max_speed.max(1e-5).to_wgsl_string()
+            ),
+            // Golden ratio sequence, to spread consecutive indices over the whole range
+            EffectDebugView::SpawnIndex => {
+                "fract(f32(particle.spawn_index) * 0.618034)".to_string()
+            }
+        };
+        format!(
+            r##"// Debug view
+let debug_t = clamp({}, 0., 1.);
+out.color = vec4<f32>(clamp(vec3<f32>(debug_t * 2. - 1., 1. - abs(debug_t * 2. - 1.), 1. - debug_t * 2.), vec3<f32>(0.), vec3<f32>(1.)), 1.);
+"##,
+            value
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics() {
+        let mut metrics = EffectMetrics::new(1.);
+        metrics.record(0.5, 10, 32, 2.);
+        // Not refreshed yet
+        assert_eq!(metrics.alive(), 0);
+        metrics.record(0.5, 10, 32, 2.);
+        assert_eq!(metrics.alive(), 20);
+        assert_eq!(metrics.high_watermark(), 20);
+        assert_eq!(metrics.dropped(), 0);
+
+        // Only 12 particles fit in the effect
+        metrics.record(0.5, 20, 32, 2.);
+        metrics.record(0.5, 0, 32, 2.);
+        assert_eq!(metrics.alive(), 32);
+        assert_eq!(metrics.dropped(), 8);
+
+        // The first particles die
+        metrics.record(0.5, 0, 32, 2.);
+        metrics.record(0.5, 0, 32, 2.);
+        assert_eq!(metrics.alive(), 12);
+        assert_eq!(metrics.high_watermark(), 32);
+    }
+
+    #[test]
+    fn debug_view_code() {
+        let code = EffectDebugView::Speed { max_speed: 2. }.to_shader_code();
+        assert!(code.contains("let debug_t = clamp(length(particle.vel) / 2., 0., 1.);"));
+        assert!(code.contains("out.color = "));
+    }
+}
@@ -0,0 +1,64 @@
+use bevy::prelude::*;
+
+/// Color of the emitter of a [`ParticleEffect`], inherited by its particles.
+///
+/// The color is exposed to the render shaders of the effect as `emitter.color`, and is used
+/// by the [`InheritEmitterModifier`] to tint the particles. Set it each frame to match the
+/// object the effect is attached to, or add an [`InheritMaterialColor`] component to have
+/// it copied from the `StandardMaterial` of the emitter, with the `pbr` feature.
+///
+/// Effects without this component have a white emitter color.
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+/// [`InheritEmitterModifier`]: crate::InheritEmitterModifier
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub struct EmitterColor(pub Color);
+
+impl Default for EmitterColor {
+    fn default() -> Self {
+        Self(Color::WHITE)
+    }
+}
+
+/// Copy the base color of the `StandardMaterial` of the emitter into its [`EmitterColor`]
+/// each frame.
+///
+/// The material is looked up on the entity of the [`ParticleEffect`] first, then on its
+/// parent, so that an effect spawned as a child of a mesh matches it. The [`EmitterColor`]
+/// component is inserted if the entity doesn't have one yet. This requires the `pbr`
+/// feature.
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Component)]
+pub struct InheritMaterialColor;
+
+/// Copy the base color of the `StandardMaterial` of the emitters with an
+/// [`InheritMaterialColor`] component into their [`EmitterColor`].
+#[cfg(feature = "pbr")]
+pub fn inherit_material_color_system(
+    mut commands: Commands,
+    materials: Res<Assets<StandardMaterial>>,
+    mut query: Query<
+        (Entity, Option<&Parent>, Option<&mut EmitterColor>),
+        With<InheritMaterialColor>,
+    >,
+    material_query: Query<&Handle<StandardMaterial>>,
+) {
+    for (entity, parent, emitter_color) in query.iter_mut() {
+        let material = material_query
+            .get(entity)
+            .or_else(|_| material_query.get(parent.map_or(entity, |p| p.0)))
+            .ok()
+            .and_then(|handle| materials.get(handle));
+        if let Some(material) = material {
+            let color = EmitterColor(material.base_color);
+            if let Some(mut emitter_color) = emitter_color {
+                if *emitter_color != color {
+                    *emitter_color = color;
+                }
+            } else {
+                commands.entity(entity).insert(color);
+            }
+        }
+    }
+}
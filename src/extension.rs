@@ -0,0 +1,405 @@
+use std::sync::{Arc, RwLock};
+
+use bevy::{prelude::*, utils::HashMap};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Stage of the particle pipeline the code of an [`ExtensionModifier`] runs in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ModifierStage {
+    /// Initialization of newly spawned particles, in the update compute shader. The code runs
+    /// after all the built-in init modifiers, and can read and override the values written
+    /// into `ret`.
+    Init,
+    /// Update of the particles each frame, in the update compute shader. The code runs after
+    /// the motion integration and all the built-in update modifiers, and can read and
+    /// override the `vPos`, `vVel`, `vAge`, `vLifetime` and `vNormal` variables.
+    Update,
+    /// Vertex stage of the render shader. The code runs after all the built-in vertex
+    /// modifiers, and can read and override the `out.color`, `size` and `rotation` variables.
+    Vertex,
+    /// Fragment stage of the render shader. The code runs after all the built-in fragment
+    /// modifiers, and can read and override the `color` variable.
+    Fragment,
+}
+
+/// Particle attribute read by the code of an [`ExtensionModifier`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ParticleAttribute {
+    /// Position of the particle.
+    Position,
+    /// Velocity of the particle.
+    Velocity,
+    /// Age of the particle, in seconds.
+    Age,
+    /// Lifetime of the particle, in seconds.
+    Lifetime,
+    /// Normal of the spawn shape at the spawn position of the particle.
+    Normal,
+    /// Index of the particle among all the particles spawned in the same frame.
+    SpawnIndex,
+}
+
+impl ParticleAttribute {
+    /// Get the WGSL expression of the attribute in the given stage, if available.
+    ///
+    /// Particles are not aged yet when they're initialized, and the fragment stage only
+    /// receives the interpolated color and UV coordinates of the particle vertices.
+    pub fn wgsl_expr(&self, stage: ModifierStage) -> Option<&'static str> {
+        match stage {
+            ModifierStage::Init => match self {
+                ParticleAttribute::Position => Some("ret.pos"),
+                ParticleAttribute::Velocity => Some("ret.vel"),
+                ParticleAttribute::Age => None,
+                ParticleAttribute::Lifetime => Some("ret.lifetime"),
+                ParticleAttribute::Normal => Some("ret.normal"),
+                ParticleAttribute::SpawnIndex => Some("spawn_index"),
+            },
+            ModifierStage::Update => match self {
+                ParticleAttribute::Position => Some("vPos"),
+                ParticleAttribute::Velocity => Some("vVel"),
+                ParticleAttribute::Age => Some("vAge"),
+                ParticleAttribute::Lifetime => Some("vLifetime"),
+                ParticleAttribute::Normal => Some("vNormal"),
+                ParticleAttribute::SpawnIndex => {
+                    Some("particle_buffer.particles[index].spawn_index")
+                }
+            },
+            ModifierStage::Vertex => match self {
+                ParticleAttribute::Position => Some("particle.pos"),
+                ParticleAttribute::Velocity => Some("particle.vel"),
+                ParticleAttribute::Age => Some("particle.age"),
+                ParticleAttribute::Lifetime => Some("particle.lifetime"),
+                ParticleAttribute::Normal => Some("particle.normal"),
+                ParticleAttribute::SpawnIndex => Some("particle.spawn_index"),
+            },
+            ModifierStage::Fragment => None,
+        }
+    }
+}
+
+/// WGSL declarations at module scope required by the code of an [`ExtensionModifier`], like
+/// helper functions or constants.
+///
+/// Includes are identified by their name, and declared once per shader even if several
+/// modifiers require them, so modifiers of the same crate can share them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ShaderInclude {
+    /// Unique name of the include. Prefix it with the crate name, like `"my_crate::noise"`.
+    pub name: String,
+    /// WGSL declarations.
+    pub code: String,
+}
+
+/// Shader code generated by an [`ExtensionModifier`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ModifierCode {
+    /// WGSL code injected in the function of the modifier stage.
+    pub code: String,
+    /// Declarations at module scope required by `code`.
+    pub includes: Vec<ShaderInclude>,
+    /// Particle attributes read by `code`, checked to be available in the modifier stage.
+    pub attributes: Vec<ParticleAttribute>,
+}
+
+impl ModifierCode {
+    /// Create the code of a modifier without includes nor attributes.
+    pub fn new(code: impl Into<String>) -> Self {
+        Self {
+            code: code.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Add a required include.
+    pub fn with_include(mut self, name: impl Into<String>, code: impl Into<String>) -> Self {
+        self.includes.push(ShaderInclude {
+            name: name.into(),
+            code: code.into(),
+        });
+        self
+    }
+
+    /// Add a required particle attribute.
+    pub fn with_attribute(mut self, attribute: ParticleAttribute) -> Self {
+        self.attributes.push(attribute);
+        self
+    }
+
+    /// Check that all the required attributes are available in the given stage.
+    pub fn validate(&self, stage: ModifierStage) -> Result<(), anyhow::Error> {
+        if let Some(attribute) = self
+            .attributes
+            .iter()
+            .find(|attribute| attribute.wgsl_expr(stage).is_none())
+        {
+            anyhow::bail!(
+                "Particle attribute {:?} is not available in the {:?} stage.",
+                attribute,
+                stage
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Trait for modifiers defined outside of this crate.
+///
+/// Unlike the [`InitModifier`], [`UpdateModifier`] and [`RenderModifier`] traits, which write
+/// into the layouts of the built-in modifiers, an extension modifier generates standalone
+/// [`ModifierCode`] for a single [`ModifierStage`], injected after the code of all the
+/// built-in modifiers of that stage. The modifier is stored in the [`EffectAsset`] with its
+/// parameters serialized, so it round-trips through `.effect` files, provided its type is
+/// registered with [`RegisterExtensionModifier::register_extension_modifier()`] before the
+/// asset is loaded.
+///
+/// ```
+/// # use bevy_hanabi::*;
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Serialize, Deserialize)]
+/// struct FadeModifier {
+///     duration: f32,
+/// }
+///
+/// impl ExtensionModifier for FadeModifier {
+///     const TYPE_NAME: &'static str = "my_crate::FadeModifier";
+///     const STAGE: ModifierStage = ModifierStage::Vertex;
+///
+///     fn to_shader_code(&self) -> ModifierCode {
+///         ModifierCode::new(format!(
+///             "out.color.a = out.color.a * fade(particle.age, {:.6});\n",
+///             self.duration
+///         ))
+///         .with_include(
+///             "my_crate::fade",
+///             "fn fade(age: f32, duration: f32) -> f32 { return clamp(age / duration, 0., 1.); }",
+///         )
+///         .with_attribute(ParticleAttribute::Age)
+///     }
+/// }
+///
+/// let asset = EffectAsset::default().extend(FadeModifier { duration: 0.5 });
+/// ```
+///
+/// [`InitModifier`]: crate::InitModifier
+/// [`UpdateModifier`]: crate::UpdateModifier
+/// [`RenderModifier`]: crate::RenderModifier
+/// [`EffectAsset`]: crate::EffectAsset
+pub trait ExtensionModifier: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Name identifying the modifier type in serialized effect assets. It must be unique
+    /// across all crates, so prefix it with the crate name, like `"my_crate::MyModifier"`.
+    const TYPE_NAME: &'static str;
+
+    /// Stage of the particle pipeline the modifier code runs in.
+    const STAGE: ModifierStage;
+
+    /// Generate the shader code of the modifier.
+    fn to_shader_code(&self) -> ModifierCode;
+}
+
+/// An [`ExtensionModifier`] added to an [`EffectAsset`], with its parameters serialized.
+///
+/// [`EffectAsset`]: crate::EffectAsset
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExtensionInstance {
+    /// The [`ExtensionModifier::TYPE_NAME`] of the modifier.
+    pub type_name: String,
+    /// The parameters of the modifier, serialized as RON.
+    pub params: String,
+    /// The stage and code of the modifier, once resolved.
+    #[serde(skip)]
+    resolved: Option<(ModifierStage, ModifierCode)>,
+}
+
+impl ExtensionInstance {
+    /// Create an instance of a modifier, generating its code.
+    pub fn new<M: ExtensionModifier>(modifier: &M) -> Result<Self, anyhow::Error> {
+        let code = modifier.to_shader_code();
+        code.validate(M::STAGE)?;
+        Ok(Self {
+            type_name: M::TYPE_NAME.to_string(),
+            params: ron::ser::to_string(modifier)?,
+            resolved: Some((M::STAGE, code)),
+        })
+    }
+
+    /// Get the stage and code of the modifier, if resolved.
+    ///
+    /// Instances deserialized on their own are only resolved once their asset is loaded, or
+    /// by [`ExtensionRegistry::resolve()`].
+    pub fn resolved(&self) -> Option<(ModifierStage, &ModifierCode)> {
+        self.resolved.as_ref().map(|(stage, code)| (*stage, code))
+    }
+}
+
+/// Function deserializing the parameters of a modifier type, and generating its code.
+type ResolveFn = fn(&str) -> Result<(ModifierStage, ModifierCode), anyhow::Error>;
+
+fn resolve<M: ExtensionModifier>(
+    params: &str,
+) -> Result<(ModifierStage, ModifierCode), anyhow::Error> {
+    let modifier: M = ron::de::from_str(params)?;
+    let code = modifier.to_shader_code();
+    code.validate(M::STAGE)?;
+    Ok((M::STAGE, code))
+}
+
+/// Registry of the [`ExtensionModifier`] types which can be loaded from effect assets.
+///
+/// The registry is inserted as a resource by the [`HanabiPlugin`], and shared with the asset
+/// loader of the effects. Register the modifier types with
+/// [`RegisterExtensionModifier::register_extension_modifier()`].
+///
+/// [`HanabiPlugin`]: crate::HanabiPlugin
+#[derive(Default, Clone)]
+pub struct ExtensionRegistry(Arc<RwLock<HashMap<String, ResolveFn>>>);
+
+impl ExtensionRegistry {
+    /// Register a modifier type.
+    pub fn register<M: ExtensionModifier>(&self) {
+        self.0
+            .write()
+            .unwrap()
+            .insert(M::TYPE_NAME.to_string(), resolve::<M>);
+    }
+
+    /// Check if a modifier type is registered.
+    pub fn contains(&self, type_name: &str) -> bool {
+        self.0.read().unwrap().contains_key(type_name)
+    }
+
+    /// Resolve the stage and code of a modifier instance from its serialized parameters.
+    ///
+    /// Fails if the modifier type is not registered, or its parameters are invalid.
+    pub fn resolve(&self, instance: &mut ExtensionInstance) -> Result<(), anyhow::Error> {
+        let resolve = *self
+            .0
+            .read()
+            .unwrap()
+            .get(&instance.type_name)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Extension modifier '{}' is not registered.",
+                    instance.type_name
+                )
+            })?;
+        instance.resolved = Some(resolve(&instance.params)?);
+        Ok(())
+    }
+}
+
+/// Extension trait to register [`ExtensionModifier`] types on an [`App`].
+pub trait RegisterExtensionModifier {
+    /// Register a modifier type, so that effect assets using it can be loaded.
+    fn register_extension_modifier<M: ExtensionModifier>(&mut self) -> &mut Self;
+}
+
+impl RegisterExtensionModifier for App {
+    fn register_extension_modifier<M: ExtensionModifier>(&mut self) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(ExtensionRegistry::default)
+            .register::<M>();
+        self
+    }
+}
+
+/// Shader code of all the extension modifiers of an effect, gathered by stage.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct ExtensionCode {
+    pub init: String,
+    pub update: String,
+    pub vertex: String,
+    pub fragment: String,
+    /// Includes of the init and update stages, declared in the update shader.
+    pub update_includes: String,
+    /// Includes of the vertex and fragment stages, declared in the render shader.
+    pub render_includes: String,
+}
+
+impl ExtensionCode {
+    /// Gather the code of the resolved extension modifiers. Unresolved ones are skipped.
+    pub fn new(extensions: &[ExtensionInstance]) -> Self {
+        let mut ext = Self::default();
+        let mut update_names = vec![];
+        let mut render_names = vec![];
+        for (stage, code) in extensions.iter().filter_map(|e| e.resolved()) {
+            let (target, includes, names) = match stage {
+                ModifierStage::Init => (&mut ext.init, &mut ext.update_includes, &mut update_names),
+                ModifierStage::Update => {
+                    (&mut ext.update, &mut ext.update_includes, &mut update_names)
+                }
+                ModifierStage::Vertex => {
+                    (&mut ext.vertex, &mut ext.render_includes, &mut render_names)
+                }
+                ModifierStage::Fragment => (
+                    &mut ext.fragment,
+                    &mut ext.render_includes,
+                    &mut render_names,
+                ),
+            };
+            *target += &code.code;
+            for include in &code.includes {
+                if !names.contains(&include.name) {
+                    names.push(include.name.clone());
+                    *includes += &include.code;
+                    *includes += "\n";
+                }
+            }
+        }
+        ext
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct TestModifier {
+        scale: f32,
+    }
+
+    impl ExtensionModifier for TestModifier {
+        const TYPE_NAME: &'static str = "test::TestModifier";
+        const STAGE: ModifierStage = ModifierStage::Update;
+
+        fn to_shader_code(&self) -> ModifierCode {
+            ModifierCode::new(format!("vVel = vVel * test_scale({:.1});\n", self.scale))
+                .with_include("test::scale", "fn test_scale(s: f32) -> f32 { return s; }")
+                .with_attribute(ParticleAttribute::Velocity)
+        }
+    }
+
+    #[test]
+    fn resolve() {
+        let instance = ExtensionInstance::new(&TestModifier { scale: 2. }).unwrap();
+        assert_eq!(instance.type_name, "test::TestModifier");
+
+        // Deserialized instances are resolved through the registry
+        let ron = ron::ser::to_string(&instance).unwrap();
+        let mut loaded: ExtensionInstance = ron::de::from_str(&ron).unwrap();
+        assert!(loaded.resolved().is_none());
+        let registry = ExtensionRegistry::default();
+        assert!(registry.resolve(&mut loaded).is_err());
+        registry.register::<TestModifier>();
+        assert!(registry.contains("test::TestModifier"));
+        registry.resolve(&mut loaded).unwrap();
+        assert_eq!(loaded.resolved(), instance.resolved());
+    }
+
+    #[test]
+    fn includes_once() {
+        let instance = ExtensionInstance::new(&TestModifier { scale: 2. }).unwrap();
+        let code = ExtensionCode::new(&[instance.clone(), instance]);
+        assert_eq!(code.update.matches("test_scale(2.0)").count(), 2);
+        assert_eq!(code.update_includes.matches("fn test_scale").count(), 1);
+        assert!(code.render_includes.is_empty());
+    }
+
+    #[test]
+    fn attributes() {
+        let code = ModifierCode::new("").with_attribute(ParticleAttribute::Age);
+        assert!(code.validate(ModifierStage::Update).is_ok());
+        assert!(code.validate(ModifierStage::Init).is_err());
+        assert!(code.validate(ModifierStage::Fragment).is_err());
+    }
+}
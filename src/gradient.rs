@@ -2,6 +2,7 @@ use bevy::{
     core::FloatOrd,
     math::{Quat, Vec2, Vec3, Vec3A, Vec4},
 };
+use serde::{Deserialize, Serialize};
 use std::vec::Vec;
 
 /// Describes a type that can be linearly interpolated between two keys.
@@ -52,8 +53,51 @@ impl Lerp for Quat {
     }
 }
 
+/// Describes a type whose values can be compared by a distance metric.
+///
+/// This trait is used to measure the error introduced when simplifying a gradient.
+pub trait Distance: Copy {
+    fn distance(self, other: Self) -> f32;
+}
+
+impl Distance for f32 {
+    #[inline]
+    fn distance(self, other: Self) -> f32 {
+        (self - other).abs()
+    }
+}
+
+impl Distance for f64 {
+    #[inline]
+    fn distance(self, other: Self) -> f32 {
+        (self - other).abs() as f32
+    }
+}
+
+macro_rules! impl_distance_vecn {
+    ($t:ty) => {
+        impl Distance for $t {
+            #[inline]
+            fn distance(self, other: Self) -> f32 {
+                <$t>::distance(self, other)
+            }
+        }
+    };
+}
+
+impl_distance_vecn!(Vec2);
+impl_distance_vecn!(Vec3);
+impl_distance_vecn!(Vec3A);
+impl_distance_vecn!(Vec4);
+
+impl Distance for Quat {
+    fn distance(self, other: Self) -> f32 {
+        self.angle_between(other)
+    }
+}
+
 /// A single key point for a [`Gradient`].
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct GradientKey<T: Lerp> {
     /// Ratio in \[0:1\] where the key is located.
     ratio: f32,
@@ -77,7 +121,7 @@ impl<T: Lerp> GradientKey<T> {
 /// The gradient can be sampled anywhere, and will return a linear interpolation
 /// of the values of its closest keys. Sampling before 0 or after 1 returns a
 /// constant value equal to the one of the closest bound.
-#[derive(Debug, Default, Clone, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Gradient<T: Lerp> {
     keys: Vec<GradientKey<T>>,
 }
@@ -98,6 +142,28 @@ impl<T: Default + Lerp> Gradient<T> {
 }
 
 impl<T: Lerp> Gradient<T> {
+    /// Create a gradient from a list of values evenly spaced over \[0:1\].
+    ///
+    /// The first sample is placed at ratio 0 and the last one at ratio 1. A single sample
+    /// produces a constant gradient with a key at ratio 0. This is typically used to import
+    /// a gradient from a texture row or a sampled curve.
+    pub fn from_samples(samples: &[T]) -> Self {
+        let inc = if samples.len() > 1 {
+            1. / (samples.len() - 1) as f32
+        } else {
+            0.
+        };
+        let keys = samples
+            .iter()
+            .enumerate()
+            .map(|(index, &value)| GradientKey {
+                ratio: (index as f32 * inc).min(1.),
+                value,
+            })
+            .collect();
+        Self { keys }
+    }
+
     /// Add a key point to the gradient.
     ///
     /// If one or more duplicate ratios already exist, append the new key after all
@@ -232,6 +298,71 @@ impl<T: Lerp> Gradient<T> {
             ratio += inc;
         }
     }
+
+    /// Resample the gradient into a new gradient with `count` keys evenly spaced over \[0:1\].
+    ///
+    /// Sharp transitions falling between two sample points are smoothed out, so this is
+    /// generally followed by a call to [`simplify()`] to obtain a compact set of keys.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the gradient is empty (has no key point).
+    ///
+    /// [`simplify()`]: Gradient::simplify
+    pub fn resample(&self, count: usize) -> Self {
+        assert!(!self.keys.is_empty());
+        let mut samples = vec![self.keys[0].value; count];
+        let inc = if count > 1 {
+            1. / (count - 1) as f32
+        } else {
+            0.
+        };
+        self.sample_by(0., inc, &mut samples[..]);
+        Self::from_samples(&samples[..])
+    }
+}
+
+impl<T: Lerp + Distance> Gradient<T> {
+    /// Simplify the gradient by removing the keys which can be approximated by a linear
+    /// interpolation of their neighbors.
+    ///
+    /// A key is removed if the value obtained by interpolating the kept keys around it is
+    /// within `tolerance` of its actual value. The first and last keys are always kept, as
+    /// are keys sharing their ratio with a neighbor and producing a discontinuity. This
+    /// reduces the amount of shader code generated for the gradient.
+    pub fn simplify(&self, tolerance: f32) -> Self {
+        let len = self.keys.len();
+        if len <= 2 {
+            return self.clone();
+        }
+
+        // Check if all keys strictly between `first` and `last` can be approximated by
+        // a linear interpolation of those two keys.
+        let can_skip = |first: usize, last: usize| -> bool {
+            let k0 = &self.keys[first];
+            let k1 = &self.keys[last];
+            let range = k1.ratio - k0.ratio;
+            if range <= 0. {
+                return false;
+            }
+            self.keys[first + 1..last].iter().all(|key| {
+                let t = (key.ratio - k0.ratio) / range;
+                k0.value.lerp(k1.value, t).distance(key.value) <= tolerance
+            })
+        };
+
+        let mut keys = vec![self.keys[0]];
+        let mut first = 0;
+        while first < len - 1 {
+            let mut last = first + 1;
+            while last + 1 < len && can_skip(first, last + 1) {
+                last += 1;
+            }
+            keys.push(self.keys[last]);
+            first = last;
+        }
+        Self { keys }
+    }
 }
 
 #[cfg(test)]
@@ -296,6 +427,67 @@ mod tests {
         assert_eq!(green, g.sample(1.0));
     }
 
+    #[test]
+    fn from_samples() {
+        let g = Gradient::from_samples(&[0., 2., 1.]);
+        let keys = g.keys();
+        assert_eq!(3, keys.len());
+        assert_eq!(0.0, keys[0].ratio());
+        assert_eq!(0.5, keys[1].ratio());
+        assert_eq!(1.0, keys[2].ratio());
+        assert_eq!(2., g.sample(0.5));
+        assert_eq!(1.5, g.sample(0.75));
+
+        let g = Gradient::from_samples(&[3.]);
+        assert_eq!(1, g.keys().len());
+        assert_eq!(0.0, g.keys()[0].ratio());
+
+        let g = Gradient::<f32>::from_samples(&[]);
+        assert!(g.keys().is_empty());
+    }
+
+    #[test]
+    fn resample() {
+        let mut g = Gradient::<f32>::new();
+        g.add_key(0.2, 0.);
+        g.add_key(0.6, 4.);
+        let r = g.resample(6);
+        let keys = r.keys();
+        assert_eq!(6, keys.len());
+        for (i, key) in keys.iter().enumerate() {
+            let ratio = i as f32 / 5.;
+            assert!((key.ratio() - ratio).abs() < 1e-5);
+            assert!((key.value - g.sample(ratio)).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn simplify() {
+        // Collinear keys are removed
+        let g = Gradient::from_samples(&[0., 1., 2., 3., 4.]);
+        let s = g.simplify(1e-5);
+        assert_eq!(2, s.keys().len());
+        assert_eq!(0., s.keys()[0].value);
+        assert_eq!(4., s.keys()[1].value);
+
+        // Keys outside the tolerance are kept
+        let g = Gradient::from_samples(&[0., 1., 2., 1., 0.]);
+        let s = g.simplify(0.1);
+        assert_eq!(3, s.keys().len());
+        assert_eq!(2., s.keys()[1].value);
+        let s = g.simplify(2.5);
+        assert_eq!(2, s.keys().len());
+
+        // Discontinuities are kept
+        let mut g = Gradient::new();
+        g.add_key(0.0, Vec2::ZERO);
+        g.add_key(0.5, Vec2::ZERO);
+        g.add_key(0.5, Vec2::ONE);
+        g.add_key(1.0, Vec2::ONE);
+        let s = g.simplify(0.1);
+        assert_eq!(4, s.keys().len());
+    }
+
     #[test]
     fn sample_by() {
         let red: Vec4 = Vec4::new(1., 0., 0., 1.);
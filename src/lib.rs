@@ -25,7 +25,7 @@
 //! # use bevy_hanabi::*;
 //! App::default()
 //!     .add_plugins(DefaultPlugins)
-//!     .add_plugin(HanabiPlugin)
+//!     .add_plugin(HanabiPlugin::default())
 //!     .run();
 //! ```
 //!
@@ -59,6 +59,7 @@
 //!         radius: 2.,
 //!         dimension: ShapeDimension::Surface,
 //!         speed: 6.0.into(),
+//!         ..Default::default()
 //!     })
 //!     // Every frame, add a gravity-like acceleration downward
 //!     .update(AccelModifier {
@@ -66,7 +67,10 @@
 //!     })
 //!     // Render the particles with a color gradient over their
 //!     // lifetime.
-//!     .render(ColorOverLifetimeModifier { gradient })
+//!     .render(ColorOverLifetimeModifier {
+//!         gradient,
+//!         ..Default::default()
+//!     })
 //!     );
 //!
 //!     commands
@@ -83,24 +87,82 @@
 use bevy::{prelude::*, reflect::TypeUuid};
 
 mod asset;
+mod attractor;
+mod bake;
 mod bundle;
+mod capture;
+pub mod compat;
+mod completion;
+mod composite;
+mod culling;
+mod cursor;
+mod diagnostics;
+mod emitter;
+mod extension;
 mod gradient;
+mod lint;
 mod modifiers;
 mod plugin;
+mod pulse;
+mod quality;
 mod render;
 mod spawn;
+mod strength;
+pub mod testing;
+mod time;
+mod variant;
 
-pub use asset::EffectAsset;
+pub use asset::{EffectAsset, InitData, InitLayout};
+pub use attractor::ScreenSpaceAttractor;
+pub use bake::{FlipbookAtlas, FlipbookBakePlugin, FlipbookBaker, FLIPBOOK_BAKE_PASS};
 pub use bundle::ParticleEffectBundle;
+pub use capture::{EffectCapture, EffectFrameInputs, EffectRecording};
+pub use completion::{CompletionCondition, EffectCompleted};
+pub use composite::{CompositeEffect, CompositeEffectInstance};
+pub use culling::{CullReason, EffectCullingDebug, PauseWhenOffscreen};
+pub use cursor::{CursorPainter, RayHitProvider};
+pub use diagnostics::{EffectDebugView, EffectDiagnosticsPlugin, EffectMetrics};
+#[cfg(feature = "pbr")]
+pub use emitter::inherit_material_color_system;
+pub use emitter::{EmitterColor, InheritMaterialColor};
+pub use extension::{
+    ExtensionInstance, ExtensionModifier, ExtensionRegistry, ModifierCode, ModifierStage,
+    ParticleAttribute, RegisterExtensionModifier, ShaderInclude,
+};
 pub use gradient::{Gradient, GradientKey};
+pub use lint::EffectLint;
 pub use modifiers::{
-    AccelModifier, ColorOverLifetimeModifier, ForceFieldModifier, ForceFieldParam, InitModifier,
-    ParticleTextureModifier, PositionCircleModifier, PositionSphereModifier, RenderModifier,
-    ShapeDimension, SizeOverLifetimeModifier, UpdateModifier, FFNUM,
+    AccelModifier, BoundaryCondition, BuoyancyModifier, ColorBlendModifier, ColorBlendWeight,
+    ColorInterpolation, ColorOverLifetimeModifier, ColorRampOverLifetimeModifier, ColorSelection,
+    CubeRegion, CylinderRegion, DepthBiasModifier, DistanceFogModifier, DragModifier,
+    FlipbookModifier, FlowZoneModifier, ForceFieldModifier, ForceFieldParam,
+    InheritEmitterModifier, InitColorModifier, InitModifier, InitRotationModifier,
+    InitSizeModifier, Integrator, IntegratorModifier, MaskChannel, OrientAlongNormalModifier,
+    ParticleGeometry, ParticleGeometryModifier, ParticleLifetimeModifier, ParticleTextureModifier,
+    PlaneCollisionModifier, PositionCapsuleModifier, PositionChainModifier, PositionCircleModifier,
+    PositionConeModifier, PositionCubeModifier, PositionCylinderModifier, PositionEllipseModifier,
+    PositionGridModifier, PositionLineModifier, PositionMaskModifier, PositionMeshModifier,
+    PositionPlaneModifier, PositionSphereModifier, PositionSpiralModifier, PositionSplineModifier,
+    PositionSweepModifier, PositionTorusModifier, RenderModifier, RotationOverLifetimeModifier,
+    ShaderDefsModifier, ShapeDimension, ShockwaveModifier, SimulationBoundsModifier,
+    SizeOverLifetimeModifier, SplineCurve, TextureChannel, TextureChannelMapping, UpdateModifier,
+    UserBindGroupModifier, VelocityCircleModifier, VelocityDistributionModifier,
+    VelocitySphereModifier, VelocitySpreadModifier, VelocityTangentModifier, VertexDeformModifier,
+    VortexModifier, WindModifier, FFNUM,
 };
-pub use plugin::HanabiPlugin;
-pub use render::EffectCacheId;
-pub use spawn::{Spawner, Value};
+pub use plugin::{draw_graph, HanabiConfig, HanabiPlugin, HanabiPluginBuilder};
+pub use pulse::{queue_spawn_pulses_system, SpawnPulse, SpawnPulseEvent, SpawnPulses};
+pub use quality::{AdaptiveQuality, EffectQuality};
+#[cfg(all(feature = "2d", feature = "3d"))]
+pub use render::PipelineMode;
+pub use render::{
+    EffectCacheId, EffectGpuBuffer, EffectGpuBuffers, ParticleRenderPipelineKey,
+    ParticleUpdatePipelineKey,
+};
+pub use spawn::{ExternalSpawnCount, Spawner, Value};
+pub use strength::{EffectStrength, StrengthMapping};
+pub use time::EffectTimeControl;
+pub use variant::{EffectVariant, EffectVariants};
 
 #[cfg(not(any(feature = "2d", feature = "3d")))]
 compile_error!("Enable either the '2d' or '3d' feature.");
@@ -180,10 +242,153 @@ impl ToWgslString for Value<f32> {
                 a.to_wgsl_string(),
                 b.to_wgsl_string(),
             ),
+            Self::Normal((mean, std_dev)) => format!(
+                "rand_normal() * {} + {}",
+                std_dev.to_wgsl_string(),
+                mean.to_wgsl_string(),
+            ),
+            Self::Curve(points) => format!(
+                "sample_curve(array<f32, 8>({}), rand())",
+                points
+                    .iter()
+                    .map(|p| p.to_wgsl_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+impl<T: Copy + ToWgslString> Value<T> {
+    /// Generate a shader expression of the value from the expressions `u` and `v` of two
+    /// independent random numbers in `[0:1]`.
+    ///
+    /// Unlike [`to_wgsl_string()`], the expression doesn't call `rand()`, so it can be used
+    /// in shaders other than the init one, with random numbers obtained by hashing. `u` can
+    /// be repeated in the expression, so must be free of side effects.
+    ///
+    /// [`to_wgsl_string()`]: ToWgslString::to_wgsl_string
+    pub(crate) fn to_wgsl_sampled(self, u: &str, v: &str) -> String {
+        match self {
+            Self::Single(x) => x.to_wgsl_string(),
+            Self::Uniform((a, b)) => {
+                format!("mix({}, {}, {})", a.to_wgsl_string(), b.to_wgsl_string(), u)
+            }
+            Self::Normal((mean, std_dev)) => format!(
+                "({} + {} * (sqrt(-2. * log(max({}, 0.0000001))) * cos(6.2831855 * {})))",
+                mean.to_wgsl_string(),
+                std_dev.to_wgsl_string(),
+                u,
+                v
+            ),
+            Self::Curve(points) => {
+                // Sum the contributions of the segments of the curve, to avoid indexing
+                let segments = points
+                    .windows(2)
+                    .enumerate()
+                    .map(|(index, p)| {
+                        format!(
+                            " + ({1} - {0}) * clamp({2} * 7. - {3}., 0., 1.)",
+                            p[0].to_wgsl_string(),
+                            p[1].to_wgsl_string(),
+                            u,
+                            index
+                        )
+                    })
+                    .collect::<String>();
+                format!("({}{})", points[0].to_wgsl_string(), segments)
+            }
+        }
+    }
+}
+
+/// Event sent when a [`ParticleEffect`] could not spawn all the particles requested by its
+/// spawner during a frame.
+///
+/// This happens when the spawner requests more particles in a single frame than the capacity
+/// of the effect. Gameplay code can react to the event, for example by falling back to a
+/// cheaper effect or a decal. Note that the particles still alive from the previous frames
+/// are not accounted for, so this only reports the deficit known on the CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EffectThrottled {
+    /// The entity holding the throttled [`ParticleEffect`].
+    pub entity: Entity,
+    /// The number of particles requested by the spawner this frame.
+    pub requested: u32,
+    /// The number of requested particles which could not be spawned.
+    pub deficit: u32,
+}
+
+/// Resource configuring the simulation of all particle effects.
+///
+/// The resource is inserted with its default value by the [`HanabiPlugin`], and can be
+/// modified at any time to change the settings of the next simulation steps.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct EffectSimulationSettings {
+    /// Maximum time step of a single simulation step, in seconds.
+    ///
+    /// If set, the frame delta time is clamped to this value before ticking the spawners and
+    /// updating the particles. This prevents a long frame, like a loading hitch, from spawning
+    /// all the particles accumulated during that time at once, or from moving particles over
+    /// large distances in a single step. The effects are slowed down during such frames
+    /// instead. If `None`, the frame delta time is used as is.
+    pub max_delta_time: Option<f32>,
+    /// Period after which the simulation time wraps back to zero, in seconds.
+    ///
+    /// If set, the simulation time available to the shaders as `sim_params.time` wraps
+    /// around every `loop_period` seconds, and the period is available as
+    /// `sim_params.loop_period`, or zero if the time doesn't loop. The gust and turbulence
+    /// noises of a [`WindModifier`] repeat over the loop period. Other time-driven shader
+    /// code, like some custom vertex deformation or a [`PositionSpiralModifier`], loops
+    /// seamlessly only if the period of its motion evenly divides the loop period. This
+    /// allows capturing tileable loops of an effect, for example to bake them into a
+    /// flipbook. If `None`, or if the period is not strictly positive, the simulation time
+    /// is the time since startup.
+    pub loop_period: Option<f32>,
+}
+
+impl EffectSimulationSettings {
+    /// Get the time step of the simulation for a given frame delta time, in seconds.
+    pub fn delta_time(&self, frame_delta_time: f32) -> f32 {
+        if let Some(max_delta_time) = self.max_delta_time {
+            frame_delta_time.min(max_delta_time)
+        } else {
+            frame_delta_time
+        }
+    }
+
+    /// Get the simulation time for a given time since startup, in seconds.
+    pub fn time(&self, seconds_since_startup: f64) -> f64 {
+        match self.loop_period {
+            Some(loop_period) if loop_period > 0. => {
+                seconds_since_startup.rem_euclid(loop_period as f64)
+            }
+            // Zero, negative, or NaN periods don't loop
+            _ => seconds_since_startup,
         }
     }
 }
 
+/// Resource holding global values shared by all particle effects.
+///
+/// The values are uploaded each frame to the GPU, where they're available to all the init,
+/// update, and render shaders as `sim_params.globals[i]`, an `array<vec4<f32>, 4>`. Custom
+/// shader code, for example injected with a [`VertexDeformModifier`], can use them to make
+/// all effects react to some world state, like the time of day or the intensity of a storm,
+/// without setting properties on each effect.
+///
+/// The resource is inserted with its default value, all zeros, by the [`HanabiPlugin`].
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct EffectGlobals {
+    /// The global values. Their meaning is defined by the application.
+    pub values: [Vec4; EffectGlobals::COUNT],
+}
+
+impl EffectGlobals {
+    /// Number of global `vec4` values.
+    pub const COUNT: usize = 4;
+}
+
 /// Visual effect made of particles.
 ///
 /// The particle effect component represent a single instance of a visual effect. The
@@ -199,6 +404,22 @@ pub struct ParticleEffect {
     effect: EffectCacheId,
     /// Particle spawning descriptor.
     spawner: Option<Spawner>,
+    /// Render shader configured for this instance, once extracted for rendering.
+    configured_render_shader: Option<Handle<Shader>>,
+    /// Key of the update pipeline specialized for this instance, once extracted for rendering.
+    configured_update_key: Option<ParticleUpdatePipelineKey>,
+    /// Position of the last link of the chain, for [`PositionChainModifier`].
+    chain_head: Vec3,
+    /// Number of links of the chain spawned since it last restarted.
+    chain_links: u32,
+    /// Position of the emitter when last extracted, to spawn particles over distance.
+    last_position: Option<Vec3>,
+    /// Position of the emitter when particles were last spawned, to space the spawns.
+    last_spawn_position: Option<Vec3>,
+    /// Variant of the effect asset picked by this instance, once extracted for rendering.
+    variant: Option<EffectVariant>,
+    /// Whether the effect spawned any particle, for its [`CompletionCondition`].
+    has_spawned: bool,
 }
 
 impl ParticleEffect {
@@ -208,6 +429,14 @@ impl ParticleEffect {
             handle,
             effect: EffectCacheId::INVALID,
             spawner: None,
+            configured_render_shader: None,
+            configured_update_key: None,
+            chain_head: Vec3::ZERO,
+            chain_links: 0,
+            last_position: None,
+            last_spawn_position: None,
+            variant: None,
+            has_spawned: false,
         }
     }
 
@@ -234,12 +463,72 @@ impl ParticleEffect {
     pub fn maybe_spawner(&mut self) -> Option<&mut Spawner> {
         self.spawner.as_mut()
     }
+
+    /// Get the render shader generated for this particle effect.
+    ///
+    /// The shader is the render shader template with the code of all render modifiers
+    /// injected, before any shader definition is preprocessed. The shader is shared by all
+    /// the instances of the same effect asset.
+    ///
+    /// Returns None if the effect has not rendered yet.
+    pub fn render_shader(&self) -> Option<&Handle<Shader>> {
+        self.configured_render_shader.as_ref()
+    }
+
+    /// Get the key used to specialize the update compute pipeline of this particle effect.
+    ///
+    /// Returns None if the effect has not rendered yet.
+    pub fn update_pipeline_key(&self) -> Option<&ParticleUpdatePipelineKey> {
+        self.configured_update_key.as_ref()
+    }
+
+    /// Get the variant of the effect asset picked by this particle effect.
+    ///
+    /// Returns None if the effect has not rendered yet.
+    pub fn variant(&self) -> Option<&EffectVariant> {
+        self.variant.as_ref()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn simulation_delta_time() {
+        let settings = EffectSimulationSettings::default();
+        assert_eq!(settings.delta_time(2.0), 2.0);
+
+        let settings = EffectSimulationSettings {
+            max_delta_time: Some(0.1),
+            ..Default::default()
+        };
+        assert_eq!(settings.delta_time(0.016), 0.016);
+        assert_eq!(settings.delta_time(2.0), 0.1);
+    }
+
+    #[test]
+    fn simulation_loop_time() {
+        let settings = EffectSimulationSettings::default();
+        assert_eq!(settings.time(12.5), 12.5);
+
+        let settings = EffectSimulationSettings {
+            loop_period: Some(4.0),
+            ..Default::default()
+        };
+        assert_eq!(settings.time(3.5), 3.5);
+        assert_eq!(settings.time(12.5), 0.5);
+
+        // Invalid periods are ignored
+        for loop_period in [0., -4., f32::NAN] {
+            let settings = EffectSimulationSettings {
+                loop_period: Some(loop_period),
+                ..Default::default()
+            };
+            assert_eq!(settings.time(12.5), 12.5);
+        }
+    }
+
     #[test]
     fn to_wgsl_f32() {
         let s = 1.0_f32.to_wgsl_string();
@@ -284,5 +573,28 @@ mod tests {
         assert_eq!(s, "1.");
         let s = Value::Uniform((1.0_f32, 2.0_f32)).to_wgsl_string();
         assert_eq!(s, "rand() * (2. - 1.) + 1.");
+        let s = Value::Normal((1.0_f32, 0.5_f32)).to_wgsl_string();
+        assert_eq!(s, "rand_normal() * 0.5 + 1.");
+        let s = Value::Curve([0., 1., 2., 3., 4., 5., 6., 7.]).to_wgsl_string();
+        assert_eq!(
+            s,
+            "sample_curve(array<f32, 8>(0., 1., 2., 3., 4., 5., 6., 7.), rand())"
+        );
+    }
+
+    #[test]
+    fn to_wgsl_value_sampled() {
+        let s = Value::Uniform((1.0_f32, 2.0_f32)).to_wgsl_sampled("u", "v");
+        assert_eq!(s, "mix(1., 2., u)");
+        let s = Value::Normal((1.0_f32, 0.5_f32)).to_wgsl_sampled("u", "v");
+        assert_eq!(
+            s,
+            "(1. + 0.5 * (sqrt(-2. * log(max(u, 0.0000001))) * cos(6.2831855 * v)))"
+        );
+        let s = Value::Curve([0., 1., 1., 1., 1., 1., 1., 2.]).to_wgsl_sampled("u", "v");
+        assert!(s.starts_with(
+            "(0. + (1. - 0.) * clamp(u * 7. - 0., 0., 1.) + (1. - 1.) * clamp(u * 7. - 1., 0., 1.)"
+        ));
+        assert!(s.ends_with(" + (2. - 1.) * clamp(u * 7. - 6., 0., 1.))"));
     }
 }
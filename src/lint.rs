@@ -0,0 +1,254 @@
+use bevy::prelude::*;
+use std::fmt;
+
+use crate::{
+    asset::EffectAsset,
+    gradient::{Gradient, Lerp},
+    Value,
+};
+
+/// Ratio of the capacity of an effect to the number of particles it can have alive at once,
+/// above which the capacity is considered wasted.
+const CAPACITY_WASTE_RATIO: f32 = 4.;
+
+/// A common mistake in the description of an [`EffectAsset`], found by [`EffectAsset::lint()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EffectLint {
+    /// The capacity is much larger than the number of particles the spawner can keep alive at
+    /// once, wasting GPU memory.
+    CapacityTooLarge {
+        /// Capacity of the effect.
+        capacity: u32,
+        /// Estimated maximum number of particles alive at once.
+        peak: u32,
+    },
+    /// A gradient contains a key which never affects the sampled values, because another
+    /// key has the same ratio and value, or because it's between two other keys with the
+    /// same ratio.
+    DuplicateGradientKey {
+        /// Name of the modifier owning the gradient.
+        gradient: &'static str,
+        /// Ratio of the duplicate key.
+        ratio: f32,
+    },
+    /// The particles can spawn with a zero lifetime, and die immediately.
+    ZeroLifetime,
+    /// The force field has components, but all with a zero mass, so it has no effect and
+    /// only costs GPU time.
+    ZeroForceField,
+}
+
+impl fmt::Display for EffectLint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EffectLint::CapacityTooLarge { capacity, peak } => write!(
+                f,
+                "capacity {} is much larger than the {} particles its spawner can keep alive at once; reduce the capacity to save GPU memory",
+                capacity, peak
+            ),
+            EffectLint::DuplicateGradientKey { gradient, ratio } => write!(
+                f,
+                "the gradient of {} has a redundant key at ratio {}; remove it",
+                gradient, ratio
+            ),
+            EffectLint::ZeroLifetime => write!(
+                f,
+                "particles can spawn with a zero lifetime and are never visible; set a positive lifetime with a ParticleLifetimeModifier"
+            ),
+            EffectLint::ZeroForceField => write!(
+                f,
+                "all the force field components have a zero mass and have no effect; set their mass or remove the ForceFieldModifier"
+            ),
+        }
+    }
+}
+
+/// Find the ratio of the first key of a gradient which never affects the sampled values.
+fn redundant_key<T: Lerp + PartialEq>(gradient: &Gradient<T>) -> Option<f32> {
+    let keys = gradient.keys();
+    keys.windows(2)
+        .find(|w| w[0].ratio() == w[1].ratio() && w[0].value == w[1].value)
+        .or_else(|| {
+            keys.windows(3)
+                .find(|w| w[0].ratio() == w[1].ratio() && w[1].ratio() == w[2].ratio())
+        })
+        .map(|w| w[0].ratio())
+}
+
+impl EffectAsset {
+    /// Check the effect for common mistakes wasting GPU resources or producing unexpected
+    /// results.
+    ///
+    /// The [`HanabiPlugin`] logs the result as warnings when an effect asset is added or
+    /// modified, unless disabled with [`HanabiConfig::lint`].
+    ///
+    /// [`HanabiPlugin`]: crate::HanabiPlugin
+    /// [`HanabiConfig::lint`]: crate::HanabiConfig::lint
+    pub fn lint(&self) -> Vec<EffectLint> {
+        let mut lints = vec![];
+
+        let lifetime = self
+            .init_layout
+            .lifetime
+            .unwrap_or(Value::Single(5.))
+            .range();
+        if lifetime[0] <= 0. {
+            lints.push(EffectLint::ZeroLifetime);
+        }
+
+        let peak = self.spawner.peak_count(lifetime[1]).ceil() as u32;
+        if peak > 0 && self.capacity as f32 > peak as f32 * CAPACITY_WASTE_RATIO {
+            lints.push(EffectLint::CapacityTooLarge {
+                capacity: self.capacity,
+                peak,
+            });
+        }
+
+        let force_field = &self.update_layout.force_field;
+        if force_field[0].force_exponent != 0. && force_field.iter().all(|p| p.mass == 0.) {
+            lints.push(EffectLint::ZeroForceField);
+        }
+
+        let render_layout = &self.render_layout;
+        let gradients = [
+            (
+                "ColorOverLifetimeModifier",
+                render_layout
+                    .lifetime_color_gradient
+                    .as_ref()
+                    .and_then(redundant_key),
+            ),
+            (
+                "SizeOverLifetimeModifier",
+                render_layout
+                    .size_color_gradient
+                    .as_ref()
+                    .and_then(redundant_key),
+            ),
+            (
+                "RotationOverLifetimeModifier",
+                render_layout
+                    .lifetime_rotation_gradient
+                    .as_ref()
+                    .and_then(redundant_key),
+            ),
+            (
+                "ShockwaveModifier",
+                render_layout
+                    .shockwave
+                    .as_ref()
+                    .and_then(|shockwave| redundant_key(&shockwave.width)),
+            ),
+        ];
+        for (gradient, ratio) in gradients {
+            if let Some(ratio) = ratio {
+                lints.push(EffectLint::DuplicateGradientKey { gradient, ratio });
+            }
+        }
+
+        lints
+    }
+}
+
+/// Log the lints of the effect assets added or modified this frame.
+pub(crate) fn lint_effect_assets_system(
+    mut events: EventReader<AssetEvent<EffectAsset>>,
+    effects: Res<Assets<EffectAsset>>,
+) {
+    for event in events.iter() {
+        let handle = match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => handle,
+            AssetEvent::Removed { .. } => continue,
+        };
+        if let Some(asset) = effects.get(handle) {
+            for lint in asset.lint() {
+                warn!("Effect asset '{}': {}", asset.name, lint);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ColorOverLifetimeModifier, ForceFieldModifier, ForceFieldParam, ParticleLifetimeModifier,
+        Spawner,
+    };
+
+    #[test]
+    fn lint_capacity() {
+        let asset = EffectAsset {
+            capacity: 1000,
+            spawner: Spawner::rate(10.0.into()),
+            ..Default::default()
+        }
+        .init(ParticleLifetimeModifier {
+            lifetime: Value::Single(2.),
+        });
+        assert_eq!(
+            asset.lint(),
+            vec![EffectLint::CapacityTooLarge {
+                capacity: 1000,
+                peak: 20
+            }]
+        );
+
+        let asset = EffectAsset {
+            capacity: 64,
+            ..asset
+        };
+        assert!(asset.lint().is_empty());
+    }
+
+    #[test]
+    fn lint_lifetime_and_force_field() {
+        let asset = EffectAsset {
+            capacity: 1,
+            ..Default::default()
+        }
+        .init(ParticleLifetimeModifier {
+            lifetime: Value::Uniform((0., 1.)),
+        })
+        .update(ForceFieldModifier::new(vec![ForceFieldParam {
+            mass: 0.,
+            force_exponent: 2.,
+            ..Default::default()
+        }]));
+        let lints = asset.lint();
+        assert!(lints.contains(&EffectLint::ZeroLifetime));
+        assert!(lints.contains(&EffectLint::ZeroForceField));
+    }
+
+    #[test]
+    fn lint_gradient() {
+        let mut gradient = Gradient::new();
+        gradient.add_key(0., Vec4::ONE);
+        gradient.add_key(0.5, Vec4::ONE);
+        gradient.add_key(0.5, Vec4::ZERO);
+        gradient.add_key(1., Vec4::ZERO);
+        let asset = EffectAsset {
+            capacity: 1,
+            ..Default::default()
+        }
+        .render(ColorOverLifetimeModifier {
+            gradient: gradient.clone(),
+            ..Default::default()
+        });
+        // A hard step is not redundant
+        assert!(asset.lint().is_empty());
+
+        gradient.add_key(0.5, Vec4::ZERO);
+        let asset = asset.render(ColorOverLifetimeModifier {
+            gradient,
+            ..Default::default()
+        });
+        assert_eq!(
+            asset.lint(),
+            vec![EffectLint::DuplicateGradientKey {
+                gradient: "ColorOverLifetimeModifier",
+                ratio: 0.5
+            }]
+        );
+    }
+}
@@ -1,7 +1,14 @@
-use bevy::prelude::*;
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, VertexAttributeValues},
+        render_resource::{PrimitiveTopology, TextureFormat},
+    },
+};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    asset::{InitLayout, RenderLayout, UpdateLayout},
+    asset::{InitData, InitLayout, RenderLayout, UpdateLayout},
     gradient::Gradient,
     ToWgslString, Value,
 };
@@ -10,6 +17,26 @@ use crate::{
 pub const FFNUM: usize = 16;
 
 /// Trait to customize the initializing of newly spawned particles.
+///
+/// Besides the `rand()` function, the position code of an init modifier can read the
+/// following values:
+/// - `spawn_index: u32`: the index of the particle being spawned among all the particles
+///   spawned in the same frame, which for an instantaneous burst is its index in the burst;
+/// - `spawn_count: u32`: the number of particles spawned in the same frame;
+/// - `spawner.dt: f32`: the time step of the effect this frame, in seconds, which differs from
+///   `sim_params.dt` for effects with an [`EffectTimeControl`].
+///
+/// [`EffectTimeControl`]: crate::EffectTimeControl
+///
+/// This allows patterns depending on the spawn order, like particles evenly spaced around
+/// a circle, or alternating between variants. The spawn index is also stored with each
+/// particle, and available to the render shaders as `particle.spawn_index`.
+///
+/// The code of each modifier goes into a section of the [`InitLayout`], and the sections run
+/// in a fixed order, so modifiers setting different sections combine regardless of the order
+/// they're added to the effect. For example, a position modifier combines with a
+/// [`VelocitySpreadModifier`] and a [`ParticleLifetimeModifier`]. Custom modifiers can append
+/// code run after all the built-in ones with [`InitLayout::append_custom_code()`].
 pub trait InitModifier {
     /// Apply the modifier to the init layout of the effect instance.
     fn apply(&self, init_layout: &mut InitLayout);
@@ -28,20 +55,15 @@ pub trait RenderModifier {
 }
 
 /// The dimension of a shape to consider.
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
 pub enum ShapeDimension {
     /// Consider the surface of the shape only.
+    #[default]
     Surface,
     /// Consider the entire shape volume.
     Volume,
 }
 
-impl Default for ShapeDimension {
-    fn default() -> Self {
-        ShapeDimension::Surface
-    }
-}
-
 /// An initialization modifier spawning particles on a circle/disc.
 #[derive(Clone, Copy)]
 pub struct PositionCircleModifier {
@@ -52,10 +74,47 @@ pub struct PositionCircleModifier {
     pub axis: Vec3,
     /// The circle radius.
     pub radius: f32,
+    /// If set, stretch the circle into an ellipse with independent radii, overriding
+    /// `radius`.
+    ///
+    /// The radii are along the two axes of the circle plane derived from `axis`, which are
+    /// the X and Z axes for a circle around `Vec3::Y`, and the X and Y axes for a circle
+    /// around `Vec3::Z`. The radial speed of the particles is then along the outward normal
+    /// of the ellipse.
+    pub radii: Option<Vec2>,
+    /// The inner radius of the ring to spawn from, when spawning in the
+    /// [`ShapeDimension::Volume`] of the disc, as a fraction of the radius in `[0:1]`.
+    ///
+    /// A value of `0.` spawns from the whole disc, while larger values spawn from an annulus
+    /// only, for example for ring-shaped ground effects.
+    pub inner_radius: f32,
     /// The radial speed of the particles on spawn.
     pub speed: Value<f32>,
+    /// The tangent speed of the particles on spawn, along the circle.
+    ///
+    /// Positive values launch the particles counterclockwise around `axis`, following the
+    /// right-hand rule, and negative values clockwise. Combined with the radial speed, this
+    /// produces swirling spawns like rings of fire or galaxy arms.
+    pub tangent_speed: Value<f32>,
     /// The shape dimension to spawn from.
     pub dimension: ShapeDimension,
+    /// Exponent biasing the spawn density along the disc radius, when spawning in the
+    /// [`ShapeDimension::Volume`] of the disc.
+    ///
+    /// A value of `1.` distributes the particles uniformly. Larger values concentrate the
+    /// particles toward the rim of the disc, for example for planetary rings, while smaller
+    /// positive values concentrate them toward the center.
+    pub density_exponent: f32,
+    /// The angle the arc of the circle to spawn from starts at, in radians, counterclockwise
+    /// around `axis`.
+    pub arc_start: Value<f32>,
+    /// The angular length of the arc of the circle to spawn from, in radians. Defaults to a
+    /// full turn, to spawn from the whole circle.
+    pub arc_length: Value<f32>,
+    /// If set, the particles spawn at an angle sweeping the arc sequentially over time at
+    /// this angular speed, in radians per second, instead of at a random angle. The angle
+    /// wraps back to the start of the arc after reaching its end.
+    pub sweep_speed: Option<f32>,
 }
 
 impl Default for PositionCircleModifier {
@@ -64,8 +123,15 @@ impl Default for PositionCircleModifier {
             center: Default::default(),
             axis: Vec3::Z,
             radius: Default::default(),
+            radii: None,
+            inner_radius: 0.,
             speed: Default::default(),
+            tangent_speed: Default::default(),
             dimension: Default::default(),
+            density_exponent: 1.,
+            arc_start: Value::Single(0.),
+            arc_length: Value::Single(std::f32::consts::TAU),
+            sweep_speed: None,
         }
     }
 }
@@ -74,229 +140,3585 @@ impl InitModifier for PositionCircleModifier {
     fn apply(&self, init_layout: &mut InitLayout) {
         let (tangent, bitangent) = self.axis.any_orthonormal_pair();
 
+        // The spawn point is at a relative radius `r` in [0:1], scaled by the radii
+        let radii = self.radii.unwrap_or_else(|| Vec2::splat(self.radius));
+        let inner_radius = self.inner_radius.clamp(0., 1.);
         let radius_code = match self.dimension {
             ShapeDimension::Surface => {
                 // Constant radius
-                format!("let r = {};", self.radius.to_wgsl_string())
+                "let r = 1.;".to_string()
+            }
+            ShapeDimension::Volume if self.density_exponent != 1. => {
+                // Radius uniformly distributed in [0:1], then raised to a power biasing the
+                // density toward the rim (power < 0.5) or the center (power > 0.5). The
+                // inner radius is mapped back through the same power to bound the ring.
+                format!(
+                    "let r = pow(mix({}, 1., rand()), {});",
+                    inner_radius
+                        .powf(2. * self.density_exponent)
+                        .to_wgsl_string(),
+                    (0.5 / self.density_exponent).to_wgsl_string(),
+                )
             }
             ShapeDimension::Volume => {
-                // Radius uniformly distributed in [0:1], then square-rooted
+                // Radius squared uniformly distributed in the ring, then square-rooted
                 // to account for the increased perimeter covered by increased radii.
-                format!("let r = sqrt(rand()) * {};", self.radius.to_wgsl_string())
+                format!(
+                    "let r = sqrt(mix({}, 1., rand()));",
+                    (inner_radius * inner_radius).to_wgsl_string()
+                )
             }
         };
 
+        // The outward normal of an ellipse differs from the radial direction
+        let dir_code = if self.radii.is_some() {
+            "let dir = normalize(tangent * ct * radii.y + bitangent * st * radii.x);"
+        } else {
+            "let dir = tangent * ct + bitangent * st;"
+        };
+
+        let theta_code = if let Some(sweep_speed) = self.sweep_speed {
+            // Spread the particles spawned this frame along the arc swept during the frame
+            format!(
+                r##"let frac = (f32(spawn_index) + 0.5) / f32(max(spawn_count, 1u));
+    let sweep = (sim_params.time - spawner.dt * (1. - frac)) * {};
+    // Guard against a zero arc length, which spawns all particles at the arc start
+    let sweep_arc = max(arc_length, 0.000001);
+    let theta = arc_start + sweep - floor(sweep / sweep_arc) * sweep_arc;"##,
+                sweep_speed.to_wgsl_string()
+            )
+        } else {
+            "let theta = arc_start + rand() * arc_length;".to_string()
+        };
+
         init_layout.position_code = format!(
             r##"
     // >>> [PositionCircleModifier]
     // Circle center
     let c = {};
     // Circle basis
+    let axis = {};
     let tangent = {};
     let bitangent = {};
-    // Circle radius
+    // Circle radii, and relative radius of the spawn point
+    let radii = {};
     {}
-    // Radial speed
+    // Radial and tangent speeds
     let speed = {};
-    // Spawn random point on/in circle
-    let theta = rand() * tau;
-    let dir = tangent * cos(theta) + bitangent * sin(theta);
-    ret.pos = c + r * dir;
-    // Velocity away from center
-    ret.vel = dir * speed;
+    let tangent_speed = {};
+    // Spawn point on/in the arc of the circle
+    let arc_start = {};
+    let arc_length = {};
+    {}
+    let ct = cos(theta);
+    let st = sin(theta);
+    ret.pos = c + r * (tangent * ct * radii.x + bitangent * st * radii.y);
+    {}
+    // Velocity away from center, and around the axis
+    ret.vel = dir * speed + cross(axis, dir) * tangent_speed;
+    // Normal of the circle plane
+    ret.normal = axis;
     // <<< [PositionCircleModifier]
             "##,
             self.center.to_wgsl_string(),
+            self.axis.to_wgsl_string(),
             tangent.to_wgsl_string(),
             bitangent.to_wgsl_string(),
+            radii.to_wgsl_string(),
             radius_code,
-            self.speed.to_wgsl_string()
+            self.speed.to_wgsl_string(),
+            self.tangent_speed.to_wgsl_string(),
+            self.arc_start.to_wgsl_string(),
+            self.arc_length.to_wgsl_string(),
+            theta_code,
+            dir_code,
         );
     }
 }
 
-/// An initialization modifier spawning particles on a sphere.
-#[derive(Default, Clone, Copy)]
-pub struct PositionSphereModifier {
-    /// The sphere center, relative to the emitter position.
+/// An initialization modifier spawning particles on an ellipse, or inside the elliptical
+/// disc it bounds.
+///
+/// The ellipse is a circle stretched by `radii.x` along `tangent` and `radii.y` along the
+/// other axis of its plane, which is useful for stylized effects where a circle seen at an
+/// angle would look wrong. The particles are spawned at a uniformly random parametric
+/// angle, so when spawning on the [`ShapeDimension::Surface`] of the ellipse they are
+/// slightly denser near the ends of its major axis. Spawning in the
+/// [`ShapeDimension::Volume`] of the disc is uniform.
+#[derive(Clone, Copy)]
+pub struct PositionEllipseModifier {
+    /// The ellipse center, relative to the emitter position.
     pub center: Vec3,
-    /// The sphere radius.
-    pub radius: f32,
-    /// The radial speed of the particles on spawn.
+    /// The ellipse axis, which is the normalized normal of the ellipse's plane.
+    /// Set this to `Vec3::Z` for a 2D game.
+    pub axis: Vec3,
+    /// The direction of the first radius of the ellipse. This is projected onto the plane
+    /// of the ellipse, so doesn't need to be exactly orthogonal to `axis`.
+    pub tangent: Vec3,
+    /// The ellipse radii, along `tangent` and along the other axis of the ellipse plane.
+    pub radii: Vec2,
+    /// The speed of the particles on spawn, along the outward normal of the ellipse.
     pub speed: Value<f32>,
     /// The shape dimension to spawn from.
     pub dimension: ShapeDimension,
 }
 
-impl InitModifier for PositionSphereModifier {
+impl Default for PositionEllipseModifier {
+    fn default() -> Self {
+        Self {
+            center: Default::default(),
+            axis: Vec3::Z,
+            tangent: Vec3::X,
+            radii: Vec2::ONE,
+            speed: Default::default(),
+            dimension: Default::default(),
+        }
+    }
+}
+
+impl InitModifier for PositionEllipseModifier {
     fn apply(&self, init_layout: &mut InitLayout) {
+        let axis = self.axis.normalize_or_zero();
+        let tangent = (self.tangent - axis * self.tangent.dot(axis)).normalize_or_zero();
+        let (tangent, bitangent) = if tangent == Vec3::ZERO {
+            axis.any_orthonormal_pair()
+        } else {
+            (tangent, axis.cross(tangent))
+        };
+
         let radius_code = match self.dimension {
-            ShapeDimension::Surface => {
-                // Constant radius
-                format!("let r = {};", self.radius.to_wgsl_string())
-            }
-            ShapeDimension::Volume => {
-                // Radius uniformly distributed in [0:1], then scaled by ^(1/3) in 3D
-                // to account for the increased surface covered by increased radii.
-                // https://stackoverflow.com/questions/54544971/how-to-generate-uniform-random-points-inside-d-dimension-ball-sphere
-                format!(
-                    "var r = pow(rand(), 1./3.) * {};",
-                    self.radius.to_wgsl_string()
-                )
-            }
+            ShapeDimension::Surface => "let r = 1.;",
+            // Stretching a disc is an affine transform, so preserves the uniform distribution
+            ShapeDimension::Volume => "let r = sqrt(rand());",
         };
+
         init_layout.position_code = format!(
             r##"
-    // >>> [PositionSphereModifier]
-    // Sphere center
-    let c = {0};
-    // Sphere radius
-    {1}
-    // Radial speed
-    let speed = {2};
-    // Spawn randomly along the sphere surface using Archimedes's theorem
-    var theta = rand() * tau;
-    var z = rand() * 2. - 1.;
-    var phi = acos(z);
-    var sinphi = sin(phi);
-    var x = sinphi * cos(theta);
-    var y = sinphi * sin(theta);
-    var dir = vec3<f32>(x, y, z);
-    ret.pos = c + r * dir;
-    // Radial velocity away from sphere center
-    ret.vel = dir * speed;
-    // <<< [PositionSphereModifier]
-"##,
+    // >>> [PositionEllipseModifier]
+    // Ellipse center
+    let c = {};
+    // Ellipse basis
+    let axis = {};
+    let tangent = {};
+    let bitangent = {};
+    // Ellipse radii
+    let radii = {};
+    // Spawn point on/in the unit circle, then stretched
+    {}
+    let theta = rand() * tau;
+    let ct = cos(theta);
+    let st = sin(theta);
+    ret.pos = c + r * (tangent * ct * radii.x + bitangent * st * radii.y);
+    // Velocity along the outward normal of the ellipse
+    let speed = {};
+    let n = normalize(tangent * ct * radii.y + bitangent * st * radii.x);
+    ret.vel = n * speed;
+    // Normal of the ellipse plane
+    ret.normal = axis;
+    // <<< [PositionEllipseModifier]
+            "##,
             self.center.to_wgsl_string(),
+            axis.to_wgsl_string(),
+            tangent.to_wgsl_string(),
+            bitangent.to_wgsl_string(),
+            self.radii.to_wgsl_string(),
             radius_code,
-            self.speed.to_wgsl_string()
+            self.speed.to_wgsl_string(),
         );
     }
 }
 
-/// A modifier modulating each particle's color by sampling a texture.
-#[derive(Default, Clone)]
-pub struct ParticleTextureModifier {
-    /// The texture image to modulate the particle color with.
-    pub texture: Handle<Image>,
-}
-
-impl RenderModifier for ParticleTextureModifier {
-    fn apply(&self, render_layout: &mut RenderLayout) {
-        render_layout.particle_texture = Some(self.texture.clone());
-    }
-}
-
-/// A modifier modulating each particle's color over its lifetime with a gradient curve.
-#[derive(Default, Clone)]
-pub struct ColorOverLifetimeModifier {
-    /// The color gradient defining the particle color based on its lifetime.
-    pub gradient: Gradient<Vec4>,
+/// An initialization modifier spawning particles sequentially along a helix.
+///
+/// The emission point travels along the helix over time at `angular_speed`, starting from
+/// the circle of `radius` around `center` in the plane normal to `axis`, and rising along
+/// `axis` by `pitch` for each full turn. After `turns` turns, the emission point wraps back
+/// to the start of the helix. The particles spawned during a single frame are spread along
+/// the arc traveled during that frame. A zero `pitch` produces a flat circle.
+#[derive(Clone, Copy)]
+pub struct PositionSpiralModifier {
+    /// The center of the start circle of the helix, relative to the emitter position.
+    pub center: Vec3,
+    /// The helix axis. Set this to `Vec3::Z` for a flat spiral in a 2D game.
+    pub axis: Vec3,
+    /// The helix radius.
+    pub radius: f32,
+    /// The distance traveled along the axis for each full turn.
+    pub pitch: f32,
+    /// The number of turns of the helix before the emission point wraps back to its start.
+    pub turns: f32,
+    /// The angular speed of the emission point along the helix, in radians per second.
+    pub angular_speed: f32,
+    /// The radial speed of the particles on spawn, away from the axis.
+    pub speed: Value<f32>,
 }
 
-impl RenderModifier for ColorOverLifetimeModifier {
-    fn apply(&self, render_layout: &mut RenderLayout) {
-        render_layout.lifetime_color_gradient = Some(self.gradient.clone());
+impl Default for PositionSpiralModifier {
+    fn default() -> Self {
+        Self {
+            center: Default::default(),
+            axis: Vec3::Y,
+            radius: 1.,
+            pitch: 0.,
+            turns: 1.,
+            angular_speed: std::f32::consts::TAU,
+            speed: Default::default(),
+        }
     }
 }
 
-/// A modifier modulating each particle's size over its lifetime with a gradient curve.
-#[derive(Default, Clone)]
-pub struct SizeOverLifetimeModifier {
-    /// The size gradient defining the particle size based on its lifetime.
-    pub gradient: Gradient<Vec2>,
-}
-
-impl RenderModifier for SizeOverLifetimeModifier {
-    fn apply(&self, render_layout: &mut RenderLayout) {
-        render_layout.size_color_gradient = Some(self.gradient.clone());
+impl InitModifier for PositionSpiralModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        let axis = self.axis.normalize();
+        let (tangent, bitangent) = axis.any_orthonormal_pair();
+        init_layout.position_code = format!(
+            r##"
+    // >>> [PositionSpiralModifier]
+    // Helix start center and basis
+    let c = {};
+    let axis = {};
+    let tangent = {};
+    let bitangent = {};
+    // Radial speed
+    let speed = {};
+    // Spread the particles spawned this frame along the arc traveled during the frame
+    let frac = (f32(spawn_index) + 0.5) / f32(max(spawn_count, 1u));
+    let t = sim_params.time - spawner.dt * (1. - frac);
+    // Angle along the helix, wrapped after the last turn
+    let max_angle = {} * tau;
+    let raw_angle = t * {};
+    let angle = raw_angle - floor(raw_angle / max_angle) * max_angle;
+    let dir = tangent * cos(angle) + bitangent * sin(angle);
+    ret.pos = c + {} * dir + axis * ({} * angle / tau);
+    // Velocity away from axis
+    ret.vel = dir * speed;
+    // Normal of the helix cylinder
+    ret.normal = dir;
+    // <<< [PositionSpiralModifier]
+"##,
+            self.center.to_wgsl_string(),
+            axis.to_wgsl_string(),
+            tangent.to_wgsl_string(),
+            bitangent.to_wgsl_string(),
+            self.speed.to_wgsl_string(),
+            self.turns.to_wgsl_string(),
+            self.angular_speed.to_wgsl_string(),
+            self.radius.to_wgsl_string(),
+            self.pitch.to_wgsl_string(),
+        );
     }
 }
 
-/// A modifier to apply a constant acceleration to all particles each frame.
+/// An initialization modifier spawning the particles of each burst in angular order over an
+/// arc of a circle.
 ///
-/// This is typically used to apply some kind of gravity.
-#[derive(Default, Clone, Copy)]
-pub struct AccelModifier {
-    /// The constant acceleration to apply to all particles in the effect each frame.
-    pub accel: Vec3,
-}
-
-impl UpdateModifier for AccelModifier {
-    fn apply(&self, layout: &mut UpdateLayout) {
-        layout.accel = self.accel;
-    }
-}
-
-/// Parameters for the components making the force field.
+/// Each particle spawns on the circle of `radius` around `center` in the plane normal to
+/// `axis`, at an angle proportional to the progress of the spawner burst when the particle is
+/// spawned, from `start_angle` at the start of the burst to `start_angle + sweep_angle` once
+/// the burst has spawned all its particles. Combined with a spawner emitting its bursts over
+/// some time, like `Spawner::new(count, 0.2.into(), period)`, this produces a radial wipe.
+/// With instantaneous bursts, the particles of each burst are spread evenly over the arc.
 #[derive(Clone, Copy)]
-pub struct ForceFieldParam {
-    /// Position of the source of the force field.
-    pub position: Vec3,
-    /// Maximum radius of the sphere of influence, outside of which
-    /// the force field is null.
-    pub max_radius: f32,
-    /// Minimum radius of the sphere of influence, inside of which
-    /// the force field is null, avoiding the singularity at the source position.
-    pub min_radius: f32,
-    /// The intensity of the force is proportional to mass. Note that the particles_update.wgsl shader will
-    /// ignore all subsequent force field components after it encounters a component with a mass of zero.
-    /// To change the force from an attracting one to a repulsive one, simply set the mass to a negative value.
-    pub mass: f32,
-    /// The force field is proportional to `1 / distance^force_exponent`.
-    pub force_exponent: f32,
-    /// If set to true, the particles that enter within the `min_radius` will conform to a sphere around the
-    /// source position, appearing like a recharging effect.
-    pub conform_to_sphere: bool,
+pub struct PositionSweepModifier {
+    /// The circle center, relative to the emitter position.
+    pub center: Vec3,
+    /// The circle axis. Set this to `Vec3::Z` for a 2D game.
+    pub axis: Vec3,
+    /// The circle radius.
+    pub radius: f32,
+    /// The angle of the first particle of each burst, in radians.
+    pub start_angle: f32,
+    /// The angle swept by each burst, in radians. Negative values sweep clockwise.
+    pub sweep_angle: f32,
+    /// The radial speed of the particles on spawn.
+    pub speed: Value<f32>,
 }
 
-impl Default for ForceFieldParam {
+impl Default for PositionSweepModifier {
     fn default() -> Self {
-        // defaults to no force field (a mass of 0)
-        ForceFieldParam {
-            position: Vec3::new(0., 0., 0.),
-            min_radius: 0.1,
-            max_radius: 0.0,
-            mass: 0.,
-            force_exponent: 0.0,
-            conform_to_sphere: false,
+        Self {
+            center: Default::default(),
+            axis: Vec3::Z,
+            radius: 1.,
+            start_angle: 0.,
+            sweep_angle: std::f32::consts::TAU,
+            speed: Default::default(),
         }
     }
 }
 
-/// A modifier to apply a force field to all particles each frame. The force field is made up of
-/// point sources, also called 'components'. The maximum number of components is set with [`FFNUM`].
-#[derive(Default, Clone, Copy)]
-pub struct ForceFieldModifier {
-    /// Array of force field components.
-    pub force_field: [ForceFieldParam; FFNUM],
+impl InitModifier for PositionSweepModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        let axis = self.axis.normalize();
+        let (tangent, bitangent) = axis.any_orthonormal_pair();
+        init_layout.position_code = format!(
+            r##"
+    // >>> [PositionSweepModifier]
+    // Circle center and basis
+    let c = {};
+    let axis = {};
+    let tangent = {};
+    let bitangent = {};
+    // Radial speed
+    let speed = {};
+    // Spread the particles spawned this frame over the burst progress made during the frame
+    let frac = (f32(spawn_index) + 0.5) / f32(max(spawn_count, 1u));
+    let progress = mix(spawner.sweep_start, spawner.sweep_end, frac);
+    let theta = {} + {} * progress;
+    let dir = tangent * cos(theta) + bitangent * sin(theta);
+    ret.pos = c + {} * dir;
+    // Velocity away from center
+    ret.vel = dir * speed;
+    ret.normal = axis;
+    // <<< [PositionSweepModifier]
+"##,
+            self.center.to_wgsl_string(),
+            axis.to_wgsl_string(),
+            tangent.to_wgsl_string(),
+            bitangent.to_wgsl_string(),
+            self.speed.to_wgsl_string(),
+            self.start_angle.to_wgsl_string(),
+            self.sweep_angle.to_wgsl_string(),
+            self.radius.to_wgsl_string(),
+        );
+    }
 }
 
-impl ForceFieldModifier {
-    /// Instantiate a ForceFieldModifier.
+/// An initialization modifier spawning particles over the covered area of a 2D mask.
+///
+/// The mask is typically the rasterized coverage of some text, to produce titles made of
+/// particles, but can be any grayscale image. It is converted on the CPU into a fixed set of
+/// sample points distributed according to the coverage, and each particle spawns at one of
+/// those points picked at random, jittered within its mask pixel. The mask lies in the XY
+/// plane of the effect space, centered on `center`. The sample points are uploaded to the GPU
+/// as the [`InitData`] of the effect, so their number doesn't affect the shader.
+///
+/// Use [`from_coverage()`] to build the modifier from the coverage values of the mask.
+///
+/// [`from_coverage()`]: PositionMaskModifier::from_coverage
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PositionMaskModifier {
+    /// The center of the mask, relative to the emitter position.
+    pub center: Vec3,
+    /// The sample points, as offsets from `center` in the XY plane.
+    pub points: Vec<Vec2>,
+    /// The size of a single mask pixel, to jitter the particles around the sample points.
+    pub cell_size: Vec2,
+    /// The speed of the particles on spawn, in a random direction.
+    pub speed: Value<f32>,
+}
+
+impl PositionMaskModifier {
+    /// Create a modifier from the coverage values of a mask.
+    ///
+    /// The `coverage` slice contains one value per pixel, row by row, with the first row at
+    /// the top of the mask. The mask is scaled to `size` in effect space, and converted into
+    /// `sample_count` sample points distributed proportionally to the coverage of each pixel.
     ///
     /// # Panics
     ///
-    /// Panics if the number of sources exceeds [`FFNUM`].
-    pub fn new<T>(point_attractors: T) -> Self
-    where
-        T: IntoIterator<Item = ForceFieldParam>,
-    {
-        let mut force_field = [ForceFieldParam::default(); FFNUM];
+    /// Panics if `coverage` contains less than `width * height` values.
+    pub fn from_coverage(
+        width: u32,
+        height: u32,
+        coverage: &[u8],
+        size: Vec2,
+        sample_count: usize,
+    ) -> Self {
+        let pixel_count = (width * height) as usize;
+        assert!(coverage.len() >= pixel_count);
+        let cell_size = size / Vec2::new(width as f32, height as f32);
 
-        for (i, p_attractor) in point_attractors.into_iter().enumerate() {
-            if i > FFNUM {
-                panic!("Too many point attractors");
+        // Build the cumulative distribution of the coverage
+        let mut cdf = Vec::with_capacity(pixel_count);
+        let mut total = 0u64;
+        for &value in &coverage[..pixel_count] {
+            total += value as u64;
+            cdf.push(total);
+        }
+
+        // Sample the distribution at regular intervals, which is deterministic and spreads
+        // the points better than random sampling.
+        let mut points = Vec::with_capacity(sample_count);
+        if total > 0 {
+            for i in 0..sample_count {
+                let u = ((i as f64 + 0.5) / sample_count as f64 * total as f64) as u64;
+                let index = cdf.partition_point(|&c| c <= u);
+                let x = (index as u32 % width) as f32 + 0.5;
+                let y = (index as u32 / width) as f32 + 0.5;
+                points.push(Vec2::new(x, height as f32 - y) * cell_size - size / 2.);
             }
-            force_field[i] = p_attractor;
         }
 
-        Self { force_field }
+        Self {
+            center: Vec3::ZERO,
+            points,
+            cell_size,
+            speed: Value::Single(0.),
+        }
     }
 
-    /// Perhaps will be deleted in the future.
-    // Delete me?
-    pub fn add_or_replace(&mut self, point_attractor: ForceFieldParam, index: usize) {
+    /// Create a modifier from a mask image, spawning particles only where the mask value
+    /// exceeds a threshold.
+    ///
+    /// The mask value of each pixel is read from the given `channel`. Pixels whose value is
+    /// strictly greater than `threshold` are fully covered, and the others are not covered
+    /// at all, so the particles are distributed uniformly over the covered area. See
+    /// [`from_coverage()`] for the meaning of `size` and `sample_count`.
+    ///
+    /// Returns `None` if the image format is not one of [`TextureFormat::R8Unorm`],
+    /// [`TextureFormat::Rgba8Unorm`] or [`TextureFormat::Rgba8UnormSrgb`], or if the image
+    /// has fewer pixels than its size.
+    ///
+    /// [`from_coverage()`]: PositionMaskModifier::from_coverage
+    pub fn from_image(
+        image: &Image,
+        channel: MaskChannel,
+        threshold: u8,
+        size: Vec2,
+        sample_count: usize,
+    ) -> Option<Self> {
+        let extent = image.texture_descriptor.size;
+        let (width, height) = (extent.width, extent.height);
+        let (stride, offset) = match (image.texture_descriptor.format, channel) {
+            (TextureFormat::R8Unorm, _) => (1, 0),
+            (TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb, MaskChannel::Red) => (4, 0),
+            (TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb, MaskChannel::Alpha) => {
+                (4, 3)
+            }
+            _ => return None,
+        };
+        let pixel_count = (width * height) as usize;
+        if image.data.len() < pixel_count * stride {
+            return None;
+        }
+        let coverage: Vec<u8> = image
+            .data
+            .chunks_exact(stride)
+            .take(pixel_count)
+            .map(|pixel| if pixel[offset] > threshold { 255 } else { 0 })
+            .collect();
+        Some(Self::from_coverage(
+            width,
+            height,
+            &coverage,
+            size,
+            sample_count,
+        ))
+    }
+
+    /// Get the init data uploaded for the sample points, as the number of points followed
+    /// by the XY coordinates of each point.
+    pub fn init_data(&self) -> Vec<f32> {
+        let mut values = Vec::with_capacity(1 + self.points.len() * 2);
+        values.push(self.points.len() as f32);
+        for p in &self.points {
+            values.extend_from_slice(&[p.x, p.y]);
+        }
+        values
+    }
+}
+
+/// The channel of a mask image holding the mask value, for
+/// [`PositionMaskModifier::from_image()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaskChannel {
+    /// The red channel, for grayscale masks.
+    Red,
+    /// The alpha channel, for masks with a transparent background.
+    Alpha,
+}
+
+impl InitModifier for PositionMaskModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        init_layout.init_data = Some(InitData::Values(self.init_data()));
+        init_layout.position_code = format!(
+            r##"
+    // >>> [PositionMaskModifier]
+    // Mask center
+    let c = {};
+    // Speed
+    let speed = {};
+    // Pick a random sample point of the mask
+    var p = vec2<f32>(0., 0.);
+    let point_count = u32(init_data.values[0]);
+    if (point_count > 0u) {{
+        let index = min(u32(rand() * f32(point_count)), point_count - 1u);
+        p = vec2<f32>(init_data.values[1u + index * 2u], init_data.values[2u + index * 2u]);
+        p = p + (rand2() - 0.5) * {};
+    }}
+    ret.pos = c + vec3<f32>(p.x, p.y, 0.);
+    // Velocity in a random direction
+    let dir = normalize(rand3() * 2. - 1.);
+    ret.vel = dir * speed;
+    // <<< [PositionMaskModifier]
+"##,
+            self.center.to_wgsl_string(),
+            self.speed.to_wgsl_string(),
+            self.cell_size.to_wgsl_string(),
+        );
+    }
+}
+
+/// An initialization modifier spawning particles uniformly over the surface of a mesh.
+///
+/// Each particle spawns on a triangle picked at random proportionally to its area, at a
+/// uniformly distributed position within that triangle, so the particle density is constant
+/// over the whole surface. The particles move along the vertex normals interpolated at their
+/// spawn position, which makes this modifier well suited to objects on fire or dissolving
+/// characters. The triangles are expressed relative to the emitter position.
+///
+/// The triangles of the mesh are read on the CPU and uploaded to a storage buffer once, when
+/// the mesh is loaded, and again each time it's modified. The effect doesn't spawn any
+/// particle until then. Only meshes with the [`PrimitiveTopology::TriangleList`] topology are
+/// supported; other meshes spawn all particles at the emitter position.
+///
+/// Skinned meshes are sampled in their bind pose, since the joint weights are ignored. To
+/// emit from an animated character, parent the effect to the joint closest to the emission
+/// area, or use one effect per body part.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PositionMeshModifier {
+    /// The mesh to spawn the particles on.
+    pub mesh: Handle<Mesh>,
+    /// The speed of the particles on spawn, along the interpolated normal.
+    pub speed: Value<f32>,
+}
+
+impl PositionMeshModifier {
+    /// Build the content of the storage buffer describing the triangles of a mesh.
+    ///
+    /// The buffer starts with the number of triangles `N`, followed by the cumulative
+    /// distribution of the triangle areas normalized to end at `1.` (`N` values), then by the
+    /// positions and normals of the three vertices of each triangle (18 values per triangle).
+    /// If the mesh has no vertex normals, the normal of each triangle is used for all its
+    /// vertices instead.
+    ///
+    /// Returns `None` if the mesh doesn't use the [`PrimitiveTopology::TriangleList`]
+    /// topology, or has no vertex positions.
+    pub fn init_data(mesh: &Mesh) -> Option<Vec<f32>> {
+        if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+            return None;
+        }
+        let vertices = match mesh.attribute(Mesh::ATTRIBUTE_POSITION)? {
+            VertexAttributeValues::Float32x3(vertices) => vertices,
+            _ => return None,
+        };
+        let vertex_normals = match mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
+            Some(VertexAttributeValues::Float32x3(normals)) if normals.len() == vertices.len() => {
+                Some(normals)
+            }
+            _ => None,
+        };
+        let indices: Vec<usize> = match mesh.indices() {
+            Some(Indices::U16(indices)) => indices.iter().map(|&i| i as usize).collect(),
+            Some(Indices::U32(indices)) => indices.iter().map(|&i| i as usize).collect(),
+            None => (0..vertices.len()).collect(),
+        };
+
+        let count = indices.len() / 3;
+        let mut cdf = Vec::with_capacity(count);
+        let mut triangles = Vec::with_capacity(count * 18);
+        let mut total = 0.;
+        for tri in indices.chunks_exact(3) {
+            let p = [
+                Vec3::from(vertices[tri[0]]),
+                Vec3::from(vertices[tri[1]]),
+                Vec3::from(vertices[tri[2]]),
+            ];
+            let n = if let Some(vertex_normals) = vertex_normals {
+                [
+                    Vec3::from(vertex_normals[tri[0]]),
+                    Vec3::from(vertex_normals[tri[1]]),
+                    Vec3::from(vertex_normals[tri[2]]),
+                ]
+            } else {
+                [(p[1] - p[0]).cross(p[2] - p[0]).normalize_or_zero(); 3]
+            };
+            total += (p[1] - p[0]).cross(p[2] - p[0]).length() / 2.;
+            cdf.push(total);
+            for v in p.iter().chain(n.iter()) {
+                triangles.extend_from_slice(&v.to_array());
+            }
+        }
+        if total > 0. {
+            for c in &mut cdf {
+                *c /= total;
+            }
+        } else {
+            // Degenerate mesh; spawn at the emitter position
+            cdf.clear();
+            triangles.clear();
+        }
+
+        let mut data = Vec::with_capacity(1 + cdf.len() + triangles.len());
+        data.push(cdf.len() as f32);
+        data.extend(cdf);
+        data.extend(triangles);
+        Some(data)
+    }
+}
+
+impl InitModifier for PositionMeshModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        init_layout.init_data = Some(InitData::Mesh(self.mesh.clone_weak()));
+        init_layout.position_code = format!(
+            r##"
+    // >>> [PositionMeshModifier]
+    let speed = {};
+    ret.pos = vec3<f32>(0., 0., 0.);
+    var n = vec3<f32>(0., 0., 1.);
+    let tri_count = u32(init_data.values[0]);
+    if (tri_count > 0u) {{
+        // Pick a triangle proportionally to its area, by binary search of the area distribution
+        let u = rand();
+        var lo = 0u;
+        var hi = tri_count - 1u;
+        loop {{
+            if (lo >= hi) {{
+                break;
+            }}
+            let mid = (lo + hi) / 2u;
+            if (init_data.values[1u + mid] < u) {{
+                lo = mid + 1u;
+            }} else {{
+                hi = mid;
+            }}
+        }}
+        // Pick a point uniformly distributed in the triangle
+        let s = sqrt(rand());
+        let b = rand();
+        let w = vec3<f32>(1. - s, s * (1. - b), s * b);
+        let i = 1u + tri_count + lo * 18u;
+        ret.pos = init_data_vec3(i) * w.x + init_data_vec3(i + 3u) * w.y + init_data_vec3(i + 6u) * w.z;
+        n = normalize(init_data_vec3(i + 9u) * w.x + init_data_vec3(i + 12u) * w.y + init_data_vec3(i + 15u) * w.z);
+    }}
+    // Velocity along the interpolated normal
+    ret.vel = n * speed;
+    ret.normal = n;
+    // <<< [PositionMeshModifier]
+"##,
+            self.speed.to_wgsl_string(),
+        );
+    }
+}
+
+/// An initialization modifier spawning each particle at the position of the previously
+/// spawned particle of the same effect instance, plus an offset.
+///
+/// The particles form a chain growing from the emitter, which allows creating vines or
+/// lightning bolts. The first link is spawned at `offset` from the emitter position. The
+/// chain position is tracked per effect instance, and the links spawned during a frame are
+/// placed on the GPU by the rank at which they were spawned.
+///
+/// A random vector with components in \[-`jitter`:`jitter`\] is added to the offset once per
+/// frame, so all the links spawned during the same frame are aligned. If the effect capacity
+/// is exhausted and fewer particles than requested are spawned, the chain continues after
+/// the missing links, leaving a gap.
+///
+/// Like any position modifier, this modifier replaces the other position modifiers of the
+/// effect. Newly spawned particles have a zero velocity, unless a velocity modifier like
+/// [`VelocitySphereModifier`] is added, which runs after this modifier and sets the velocity
+/// from the link position.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PositionChainModifier {
+    /// The offset between two consecutive links of the chain, in effect space.
+    pub offset: Vec3,
+    /// The magnitude of the random offset added to `offset` each frame.
+    pub jitter: f32,
+    /// The number of links after which the chain restarts from the emitter position, or zero
+    /// to grow the chain forever.
+    pub max_links: u32,
+}
+
+impl InitModifier for PositionChainModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        init_layout.position_code = r##"
+    // >>> [PositionChainModifier]
+    ret.pos = spawner.chain_head + f32(spawn_index + 1u) * spawner.chain_step;
+    ret.vel = vec3<f32>(0., 0., 0.);
+    // <<< [PositionChainModifier]
+            "##
+        .to_string();
+        init_layout.chain = Some(*self);
+    }
+}
+
+/// An initialization modifier spawning particles along a segment.
+///
+/// The particles spawn uniformly between `start` and `end`, within a cylinder of radius
+/// `thickness` around the segment, for example for sword slashes or electrical arcs. Use
+/// [`from_direction()`] to define the segment by a direction and a length instead.
+///
+/// The particles move away from the segment, perpendicularly to it, or in a random direction
+/// perpendicular to the segment if `thickness` is zero.
+///
+/// [`from_direction()`]: PositionLineModifier::from_direction
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PositionLineModifier {
+    /// The start point of the segment, relative to the emitter position.
+    pub start: Vec3,
+    /// The end point of the segment, relative to the emitter position.
+    pub end: Vec3,
+    /// The radius of the cylinder around the segment the particles spawn in.
+    pub thickness: f32,
+    /// The speed of the particles on spawn, away from the segment.
+    pub speed: Value<f32>,
+}
+
+impl PositionLineModifier {
+    /// Create a modifier spawning along a segment starting at `start` and extending by
+    /// `length` along `direction`.
+    pub fn from_direction(start: Vec3, direction: Vec3, length: f32) -> Self {
+        Self {
+            start,
+            end: start + direction.normalize_or_zero() * length,
+            ..Default::default()
+        }
+    }
+}
+
+impl InitModifier for PositionLineModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        let axis = (self.end - self.start).normalize_or_zero();
+        let axis = if axis == Vec3::ZERO { Vec3::X } else { axis };
+        let (tangent, bitangent) = axis.any_orthonormal_pair();
+        init_layout.position_code = format!(
+            r##"
+    // >>> [PositionLineModifier]
+    // Segment end points and basis perpendicular to it
+    let a = {};
+    let b = {};
+    let tangent = {};
+    let bitangent = {};
+    let speed = {};
+    // Radial distance to the segment, square-rooted to distribute uniformly in the cylinder
+    let r = sqrt(rand()) * {};
+    let theta = rand() * tau;
+    let dir = tangent * cos(theta) + bitangent * sin(theta);
+    ret.pos = mix(a, b, rand()) + dir * r;
+    // Velocity away from the segment
+    ret.vel = dir * speed;
+    ret.normal = dir;
+    // <<< [PositionLineModifier]
+"##,
+            self.start.to_wgsl_string(),
+            self.end.to_wgsl_string(),
+            tangent.to_wgsl_string(),
+            bitangent.to_wgsl_string(),
+            self.speed.to_wgsl_string(),
+            self.thickness.to_wgsl_string(),
+        );
+    }
+}
+
+/// An initialization modifier spawning particles on a rectangle.
+///
+/// The rectangle is centered on `center`, and extends by `half_extents.x` along its X axis
+/// and `half_extents.y` along its Z axis. Its normal is the +Y axis, and the whole rectangle
+/// is rotated by `rotation`. With [`ShapeDimension::Surface`] the particles spawn on the
+/// edges of the rectangle only, while with [`ShapeDimension::Volume`] they spawn over its
+/// entire area, for example for rain or snow sheets and ground fog.
+///
+/// The particles move along the rectangle normal.
+#[derive(Clone, Copy)]
+pub struct PositionPlaneModifier {
+    /// The rectangle center, relative to the emitter position.
+    pub center: Vec3,
+    /// The rotation of the rectangle, whose normal is the +Y axis when not rotated.
+    pub rotation: Quat,
+    /// Half the size of the rectangle along its X and Z axes.
+    pub half_extents: Vec2,
+    /// The speed of the particles on spawn, along the rectangle normal.
+    pub speed: Value<f32>,
+    /// The shape dimension to spawn from.
+    pub dimension: ShapeDimension,
+}
+
+impl Default for PositionPlaneModifier {
+    fn default() -> Self {
+        Self {
+            center: Default::default(),
+            rotation: Quat::IDENTITY,
+            half_extents: Vec2::ONE,
+            speed: Default::default(),
+            dimension: Default::default(),
+        }
+    }
+}
+
+impl InitModifier for PositionPlaneModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        let point_code = match self.dimension {
+            ShapeDimension::Surface => {
+                // Walk a random distance along the perimeter, starting from the (-X, +Z)
+                // corner, so the particles are distributed uniformly along the edges.
+                r##"let perimeter = 4. * (he.x + he.y);
+    let d = rand() * perimeter;
+    var p = vec2<f32>(0., 0.);
+    if (d < 2. * he.x) {
+        p = vec2<f32>(d - he.x, he.y);
+    } else if (d < 2. * he.x + 2. * he.y) {
+        p = vec2<f32>(he.x, he.y - (d - 2. * he.x));
+    } else if (d < 4. * he.x + 2. * he.y) {
+        p = vec2<f32>(he.x - (d - 2. * he.x - 2. * he.y), -he.y);
+    } else {
+        p = vec2<f32>(-he.x, (d - 4. * he.x - 2. * he.y) - he.y);
+    }"##
+            }
+            ShapeDimension::Volume => "let p = (rand2() * 2. - 1.) * he;",
+        };
+        init_layout.position_code = format!(
+            r##"
+    // >>> [PositionPlaneModifier]
+    // Rectangle center and basis
+    let c = {};
+    let x_axis = {};
+    let y_axis = {};
+    let z_axis = {};
+    // Rectangle half extents
+    let he = {};
+    let speed = {};
+    {}
+    ret.pos = c + x_axis * p.x + z_axis * p.y;
+    // Velocity along the rectangle normal
+    ret.vel = y_axis * speed;
+    ret.normal = y_axis;
+    // <<< [PositionPlaneModifier]
+"##,
+            self.center.to_wgsl_string(),
+            (self.rotation * Vec3::X).to_wgsl_string(),
+            (self.rotation * Vec3::Y).to_wgsl_string(),
+            (self.rotation * Vec3::Z).to_wgsl_string(),
+            self.half_extents.to_wgsl_string(),
+            self.speed.to_wgsl_string(),
+            point_code,
+        );
+    }
+}
+
+/// The kind of curve interpolating the control points of a [`PositionSplineModifier`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SplineCurve {
+    /// A Catmull-Rom spline, passing through all the control points.
+    #[default]
+    CatmullRom,
+    /// A chain of cubic Bézier curves. The control points are the start point followed by
+    /// groups of three points for each curve: two handles and the end point. Trailing
+    /// control points not forming a complete group are ignored.
+    Bezier,
+}
+
+/// An initialization modifier spawning particles along a curve.
+///
+/// The curve interpolating the control `points` is converted on the CPU into a polyline of
+/// `resolution` segments of equal length, so the particles are distributed uniformly along
+/// the curve whatever the spacing of the control points. The particles move along the curve
+/// tangent at their spawn position, for example for sparks following a racing line, or
+/// runes drawn along a circle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionSplineModifier {
+    /// The control points of the curve, relative to the emitter position.
+    pub points: Vec<Vec3>,
+    /// The kind of curve interpolating the control points.
+    pub curve: SplineCurve,
+    /// Close a Catmull-Rom curve by connecting its last control point to its first one.
+    /// Ignored for Bézier curves, which are closed by ending them on their start point.
+    pub closed: bool,
+    /// The number of segments of the polyline approximating the curve.
+    pub resolution: u32,
+    /// The speed of the particles on spawn, along the curve tangent.
+    pub speed: Value<f32>,
+}
+
+impl Default for PositionSplineModifier {
+    fn default() -> Self {
+        Self {
+            points: vec![],
+            curve: Default::default(),
+            closed: false,
+            resolution: 32,
+            speed: Default::default(),
+        }
+    }
+}
+
+impl PositionSplineModifier {
+    /// Number of curve pieces between control points.
+    fn piece_count(&self) -> usize {
+        let n = self.points.len();
+        match self.curve {
+            SplineCurve::CatmullRom if n < 2 => 0,
+            SplineCurve::CatmullRom if self.closed => n,
+            SplineCurve::CatmullRom => n - 1,
+            SplineCurve::Bezier => n.saturating_sub(1) / 3,
+        }
+    }
+
+    /// Evaluate the curve at the parameter `t` in \[0:`piece_count()`\].
+    fn evaluate(&self, t: f32) -> Vec3 {
+        let pieces = self.piece_count();
+        let i = (t.floor() as usize).min(pieces - 1);
+        let f = t - i as f32;
+        let p = &self.points;
+        match self.curve {
+            SplineCurve::CatmullRom => {
+                let n = p.len();
+                let at = |k: isize| {
+                    if self.closed {
+                        p[k.rem_euclid(n as isize) as usize]
+                    } else {
+                        p[k.clamp(0, n as isize - 1) as usize]
+                    }
+                };
+                let k = i as isize;
+                let (p0, p1, p2, p3) = (at(k - 1), at(k), at(k + 1), at(k + 2));
+                0.5 * (2. * p1
+                    + (p2 - p0) * f
+                    + (2. * p0 - 5. * p1 + 4. * p2 - p3) * f * f
+                    + (3. * p1 - p0 - 3. * p2 + p3) * f * f * f)
+            }
+            SplineCurve::Bezier => {
+                let (p0, p1, p2, p3) = (p[i * 3], p[i * 3 + 1], p[i * 3 + 2], p[i * 3 + 3]);
+                let g = 1. - f;
+                p0 * g * g * g + p1 * 3. * g * g * f + p2 * 3. * g * f * f + p3 * f * f * f
+            }
+        }
+    }
+
+    /// Sample `count` points distributed at equal distances along the curve, including both
+    /// ends of the curve.
+    ///
+    /// Returns an empty vector if the control points don't define any curve.
+    pub fn sample(&self, count: usize) -> Vec<Vec3> {
+        let pieces = self.piece_count();
+        if pieces == 0 || count < 2 {
+            return vec![];
+        }
+
+        // Approximate the arc length with a fine polyline
+        let steps = pieces * 64;
+        let mut fine = Vec::with_capacity(steps + 1);
+        let mut lengths = Vec::with_capacity(steps + 1);
+        let mut length = 0.;
+        for s in 0..=steps {
+            let p = self.evaluate(s as f32 / steps as f32 * pieces as f32);
+            if let Some(&prev) = fine.last() {
+                length += p.distance(prev);
+            }
+            fine.push(p);
+            lengths.push(length);
+        }
+
+        // Resample the polyline at regular distances
+        (0..count)
+            .map(|k| {
+                let d = k as f32 / (count - 1) as f32 * length;
+                let j = lengths.partition_point(|&l| l < d).clamp(1, steps);
+                let span = lengths[j] - lengths[j - 1];
+                let f = if span > 0. {
+                    (d - lengths[j - 1]) / span
+                } else {
+                    0.
+                };
+                fine[j - 1].lerp(fine[j], f)
+            })
+            .collect()
+    }
+}
+
+impl InitModifier for PositionSplineModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        let resolution = self.resolution.max(1) as usize;
+        let samples = self.sample(resolution + 1);
+        let pick_code = if samples.is_empty() {
+            "ret.pos = vec3<f32>(0., 0., 0.);
+    let tangent = vec3<f32>(0., 0., 0.);"
+                .to_string()
+        } else {
+            format!(
+                r##"var points = array<vec3<f32>, {0}>({1});
+    // Pick a random segment of the polyline, and a random point on it
+    let t = rand() * {2};
+    let i = min(u32(t), {3}u);
+    let a = points[i];
+    let b = points[i + 1u];
+    ret.pos = mix(a, b, t - f32(i));
+    let tangent = normalize(b - a);"##,
+                samples.len(),
+                samples
+                    .iter()
+                    .map(|p| p.to_wgsl_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                (resolution as f32).to_wgsl_string(),
+                resolution - 1,
+            )
+        };
+        init_layout.position_code = format!(
+            r##"
+    // >>> [PositionSplineModifier]
+    let speed = {};
+    {}
+    // Velocity along the curve tangent
+    ret.vel = tangent * speed;
+    // <<< [PositionSplineModifier]
+"##,
+            self.speed.to_wgsl_string(),
+            pick_code,
+        );
+    }
+}
+
+/// An initialization modifier spawning particles on the nodes of a regular grid.
+///
+/// The grid has `counts` nodes along each of its X, Y and Z axes, separated by `spacing`,
+/// and is centered on `center`. Use a count of 1 along an axis for a 2D grid. The particles
+/// are assigned to the grid nodes in order of their [spawn index], wrapping around after
+/// the last node, so a burst of as many particles as grid nodes fills each node exactly once.
+/// Each particle is then offset by a random vector with components in
+/// \[-`jitter`:`jitter`\], for a less regular look.
+///
+/// [spawn index]: InitModifier
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PositionGridModifier {
+    /// The grid center, relative to the emitter position.
+    pub center: Vec3,
+    /// The number of grid nodes along each axis. A zero count is treated as 1.
+    pub counts: UVec3,
+    /// The distance between two consecutive grid nodes along each axis.
+    pub spacing: Vec3,
+    /// The maximum random offset of the particles along each axis.
+    pub jitter: f32,
+    /// The speed of the particles on spawn, in a random direction.
+    pub speed: Value<f32>,
+}
+
+impl Default for PositionGridModifier {
+    fn default() -> Self {
+        Self {
+            center: Default::default(),
+            counts: UVec3::new(8, 8, 1),
+            spacing: Vec3::splat(0.1),
+            jitter: 0.,
+            speed: Default::default(),
+        }
+    }
+}
+
+impl InitModifier for PositionGridModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        let counts = self.counts.max(UVec3::ONE);
+        init_layout.position_code = format!(
+            r##"
+    // >>> [PositionGridModifier]
+    let c = {};
+    let spacing = {};
+    let speed = {};
+    // Grid node of the particle, in spawn order
+    let node = spawn_index % {}u;
+    let cell = vec3<u32>(node % {}u, (node / {}u) % {}u, node / {}u);
+    let origin = -0.5 * vec3<f32>(vec3<u32>({}u, {}u, {}u) - vec3<u32>(1u)) * spacing;
+    let jitter = (rand3() * 2. - 1.) * {};
+    ret.pos = c + origin + vec3<f32>(cell) * spacing + jitter;
+    // Velocity in a random direction
+    let dir = normalize(rand3() * 2. - 1.);
+    ret.vel = dir * speed;
+    // <<< [PositionGridModifier]
+"##,
+            self.center.to_wgsl_string(),
+            self.spacing.to_wgsl_string(),
+            self.speed.to_wgsl_string(),
+            counts.x * counts.y * counts.z,
+            counts.x,
+            counts.x,
+            counts.y,
+            counts.x * counts.y,
+            counts.x,
+            counts.y,
+            counts.z,
+            self.jitter.to_wgsl_string(),
+        );
+    }
+}
+
+/// An initialization modifier spawning particles on a sphere.
+///
+/// The particles can be restricted to a range of polar angles around the sphere `axis`, to
+/// spawn from a hemisphere or a spherical cap. For example, a polar range of `0.` to
+/// `FRAC_PI_2` around `Vec3::Y` spawns debris flying upward only from an explosion on the
+/// ground.
+#[derive(Clone, Copy)]
+pub struct PositionSphereModifier {
+    /// The sphere center, relative to the emitter position.
+    pub center: Vec3,
+    /// The sphere radius.
+    pub radius: f32,
+    /// The radial speed of the particles on spawn.
+    pub speed: Value<f32>,
+    /// The tangent speed of the particles on spawn, orbiting around the sphere axis.
+    ///
+    /// Positive values launch the particles counterclockwise around `axis`, following the
+    /// right-hand rule, and negative values clockwise. The particles spawned exactly on the
+    /// axis get no tangent velocity.
+    pub tangent_speed: Value<f32>,
+    /// The shape dimension to spawn from.
+    pub dimension: ShapeDimension,
+    /// Exponent biasing the spawn density along the sphere axis.
+    ///
+    /// A value of `1.` distributes the particles uniformly. Larger values concentrate the
+    /// particles toward the equator, for example for planetary rings, while smaller positive
+    /// values concentrate them toward the two poles. When the polar angles are restricted,
+    /// the equator and poles are the middle and the edges of the spawned band.
+    pub density_exponent: f32,
+    /// The polar axis of the sphere, from which the polar angles are measured.
+    pub axis: Vec3,
+    /// The minimum polar angle to spawn at, in radians, from `0.` at the pole in the
+    /// direction of `axis` to `PI` at the opposite pole.
+    pub polar_min: f32,
+    /// The maximum polar angle to spawn at, in radians.
+    pub polar_max: f32,
+}
+
+impl Default for PositionSphereModifier {
+    fn default() -> Self {
+        Self {
+            center: Default::default(),
+            radius: Default::default(),
+            speed: Default::default(),
+            tangent_speed: Default::default(),
+            dimension: Default::default(),
+            density_exponent: 1.,
+            axis: Vec3::Z,
+            polar_min: 0.,
+            polar_max: std::f32::consts::PI,
+        }
+    }
+}
+
+impl InitModifier for PositionSphereModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        let axis = self.axis.normalize_or_zero();
+        let (tangent, bitangent) = axis.any_orthonormal_pair();
+        // The height along the axis of an area-uniform sample is uniform (Archimedes), so
+        // the polar range maps to a range of heights.
+        let polar_min = self.polar_min.clamp(0., std::f32::consts::PI);
+        let polar_max = self.polar_max.clamp(polar_min, std::f32::consts::PI);
+        let (z_min, z_max) = (polar_max.cos(), polar_min.cos());
+
+        let radius_code = match self.dimension {
+            ShapeDimension::Surface => {
+                // Constant radius
+                format!("let r = {};", self.radius.to_wgsl_string())
+            }
+            ShapeDimension::Volume => {
+                // Radius uniformly distributed in [0:1], then scaled by ^(1/3) in 3D
+                // to account for the increased surface covered by increased radii.
+                // https://stackoverflow.com/questions/54544971/how-to-generate-uniform-random-points-inside-d-dimension-ball-sphere
+                format!(
+                    "var r = pow(rand(), 1./3.) * {};",
+                    self.radius.to_wgsl_string()
+                )
+            }
+        };
+        let density_code = if self.density_exponent != 1. {
+            // Bias the height along the polar axis, keeping the sampling symmetric
+            format!(
+                "u = sign(u) * pow(abs(u), {});",
+                self.density_exponent.to_wgsl_string()
+            )
+        } else {
+            String::new()
+        };
+        init_layout.position_code = format!(
+            r##"
+    // >>> [PositionSphereModifier]
+    // Sphere center
+    let c = {0};
+    // Sphere radius
+    {1}
+    // Radial speed
+    let speed = {2};
+    // Sphere basis
+    let axis = {4};
+    let tangent = {5};
+    let bitangent = {6};
+    // Spawn randomly along the sphere surface using Archimedes's theorem
+    var theta = rand() * tau;
+    var u = rand() * 2. - 1.;
+    {3}
+    var z = mix({7}, {8}, u * 0.5 + 0.5);
+    var phi = acos(z);
+    var sinphi = sin(phi);
+    var x = sinphi * cos(theta);
+    var y = sinphi * sin(theta);
+    var dir = tangent * x + bitangent * y + axis * z;
+    ret.pos = c + r * dir;
+    // Radial velocity away from sphere center
+    ret.vel = dir * speed;
+    // Tangent velocity around the sphere axis
+    let orbit = cross(axis, dir);
+    if (dot(orbit, orbit) > 0.00000001) {{
+        ret.vel = ret.vel + normalize(orbit) * {9};
+    }}
+    // Normal of the sphere surface
+    ret.normal = dir;
+    // <<< [PositionSphereModifier]
+"##,
+            self.center.to_wgsl_string(),
+            radius_code,
+            self.speed.to_wgsl_string(),
+            density_code,
+            axis.to_wgsl_string(),
+            tangent.to_wgsl_string(),
+            bitangent.to_wgsl_string(),
+            z_min.to_wgsl_string(),
+            z_max.to_wgsl_string(),
+            self.tangent_speed.to_wgsl_string(),
+        );
+    }
+}
+
+/// An initialization modifier spawning particles on a cone or a truncated cone.
+///
+/// The cone base is a disc of `base_radius` centered on `center`, and the cone extends
+/// from it by `height` along its axis, up to a disc of `top_radius`. A zero top radius
+/// produces a pointed cone, while a top radius larger than the base radius produces a
+/// widening cone, like the spray of a flamethrower. The cone axis is the +Y axis rotated by
+/// `rotation`.
+///
+/// The particles move away from the base along the cone generatrix passing through their
+/// spawn position, so they spread out with the cone flare.
+#[derive(Clone, Copy)]
+pub struct PositionConeModifier {
+    /// The center of the cone base, relative to the emitter position.
+    pub center: Vec3,
+    /// The rotation of the cone, whose axis is +Y before rotation.
+    pub rotation: Quat,
+    /// The radius of the cone base.
+    pub base_radius: f32,
+    /// The radius of the cone top, opposite to the base. Zero for a pointed cone.
+    pub top_radius: f32,
+    /// The cone height, from its base to its top.
+    pub height: f32,
+    /// The speed of the particles on spawn, along the cone generatrix.
+    pub speed: Value<f32>,
+    /// The shape dimension to spawn from.
+    pub dimension: ShapeDimension,
+}
+
+impl Default for PositionConeModifier {
+    fn default() -> Self {
+        Self {
+            center: Default::default(),
+            rotation: Quat::IDENTITY,
+            base_radius: 0.,
+            top_radius: 1.,
+            height: 1.,
+            speed: Default::default(),
+            dimension: Default::default(),
+        }
+    }
+}
+
+impl InitModifier for PositionConeModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        let (rb, rt) = (self.base_radius, self.top_radius);
+        // Sample the radius of the disc at the spawn height, distributed according to the
+        // area of the lateral surface (proportional to r) or the volume (proportional to r^2).
+        let height_code = if (rt - rb).abs() < 1e-5 {
+            "let t = rand();
+    let rh = rb;"
+                .to_string()
+        } else {
+            let rh = match self.dimension {
+                ShapeDimension::Surface => "sqrt(mix(rb * rb, rt * rt, rand()))",
+                ShapeDimension::Volume => "pow(mix(rb * rb * rb, rt * rt * rt, rand()), 1. / 3.)",
+            };
+            format!(
+                "let rh = {};
+    let t = (rh - rb) / (rt - rb);",
+                rh
+            )
+        };
+        let radius_code = match self.dimension {
+            ShapeDimension::Surface => "let rho = 1.;",
+            ShapeDimension::Volume => "let rho = sqrt(rand());",
+        };
+        init_layout.position_code = format!(
+            r##"
+    // >>> [PositionConeModifier]
+    // Cone base center and basis
+    let c = {};
+    let x_axis = {};
+    let y_axis = {};
+    let z_axis = {};
+    // Cone radii and height
+    let rb = {};
+    let rt = {};
+    let h = {};
+    // Speed along the generatrix
+    let speed = {};
+    // Spawn height fraction and radius of the cone at that height
+    {}
+    // Radial fraction
+    {}
+    let theta = rand() * tau;
+    let cost = cos(theta);
+    let sint = sin(theta);
+    let p = vec3<f32>(rh * rho * cost, t * h, rh * rho * sint);
+    ret.pos = c + x_axis * p.x + y_axis * p.y + z_axis * p.z;
+    // Velocity along the generatrix, away from the base
+    let g = normalize(vec3<f32>((rt - rb) * rho * cost, h, (rt - rb) * rho * sint));
+    ret.vel = (x_axis * g.x + y_axis * g.y + z_axis * g.z) * speed;
+    // Normal of the lateral surface
+    let n = normalize(vec3<f32>(h * cost, rb - rt, h * sint));
+    ret.normal = x_axis * n.x + y_axis * n.y + z_axis * n.z;
+    // <<< [PositionConeModifier]
+"##,
+            self.center.to_wgsl_string(),
+            (self.rotation * Vec3::X).to_wgsl_string(),
+            (self.rotation * Vec3::Y).to_wgsl_string(),
+            (self.rotation * Vec3::Z).to_wgsl_string(),
+            rb.to_wgsl_string(),
+            rt.to_wgsl_string(),
+            self.height.to_wgsl_string(),
+            self.speed.to_wgsl_string(),
+            height_code,
+            radius_code,
+        );
+    }
+}
+
+/// An initialization modifier spawning particles on a capsule.
+///
+/// The capsule is the set of points within `radius` of a segment centered on `center`, which
+/// extends by `half_length` on each side along the capsule axis. The capsule axis is the +Y
+/// axis rotated by `rotation`, so a capsule can wrap the collider of a character. The
+/// particles are distributed uniformly over the capsule surface or volume, and move away
+/// from the segment along the normal of the capsule surface.
+#[derive(Clone, Copy)]
+pub struct PositionCapsuleModifier {
+    /// The capsule center, relative to the emitter position.
+    pub center: Vec3,
+    /// The rotation of the capsule, whose axis is the +Y axis when not rotated.
+    pub rotation: Quat,
+    /// The capsule radius.
+    pub radius: f32,
+    /// Half the length of the capsule segment, excluding the hemispherical caps.
+    pub half_length: f32,
+    /// The speed of the particles on spawn, away from the capsule segment.
+    pub speed: Value<f32>,
+    /// The shape dimension to spawn from.
+    pub dimension: ShapeDimension,
+}
+
+impl Default for PositionCapsuleModifier {
+    fn default() -> Self {
+        Self {
+            center: Default::default(),
+            rotation: Quat::IDENTITY,
+            radius: 0.5,
+            half_length: 0.5,
+            speed: Default::default(),
+            dimension: Default::default(),
+        }
+    }
+}
+
+impl InitModifier for PositionCapsuleModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        let (r, h) = (self.radius, self.half_length);
+        // Fraction of the particles spawned on the cylinder part, proportional to its area or
+        // volume relative to the entire capsule.
+        let (cylinder_fraction, cylinder_rho, sphere_rho) = match self.dimension {
+            ShapeDimension::Surface => (h / (h + r), "r", "r"),
+            ShapeDimension::Volume => (
+                2. * h / (2. * h + 4. / 3. * r),
+                "sqrt(rand()) * r",
+                "pow(rand(), 1. / 3.) * r",
+            ),
+        };
+        init_layout.position_code = format!(
+            r##"
+    // >>> [PositionCapsuleModifier]
+    // Capsule center and basis
+    let c = {};
+    let x_axis = {};
+    let y_axis = {};
+    let z_axis = {};
+    // Capsule radius and half length
+    let r = {};
+    let hl = {};
+    let speed = {};
+    var n: vec3<f32>;
+    var p: vec3<f32>;
+    if (rand() < {}) {{
+        // Cylinder part
+        let theta = rand() * tau;
+        n = vec3<f32>(cos(theta), 0., sin(theta));
+        p = vec3<f32>(0., (rand() * 2. - 1.) * hl, 0.) + n * {};
+    }} else {{
+        // Hemispherical caps, using Archimedes's theorem
+        let theta = rand() * tau;
+        let z = rand() * 2. - 1.;
+        let sinphi = sqrt(1. - z * z);
+        n = vec3<f32>(sinphi * cos(theta), z, sinphi * sin(theta));
+        p = vec3<f32>(0., sign(z) * hl, 0.) + n * {};
+    }}
+    ret.pos = c + x_axis * p.x + y_axis * p.y + z_axis * p.z;
+    // Velocity away from the capsule segment
+    let world_n = x_axis * n.x + y_axis * n.y + z_axis * n.z;
+    ret.vel = world_n * speed;
+    // Normal of the capsule surface
+    ret.normal = world_n;
+    // <<< [PositionCapsuleModifier]
+"##,
+            self.center.to_wgsl_string(),
+            (self.rotation * Vec3::X).to_wgsl_string(),
+            (self.rotation * Vec3::Y).to_wgsl_string(),
+            (self.rotation * Vec3::Z).to_wgsl_string(),
+            r.to_wgsl_string(),
+            h.to_wgsl_string(),
+            self.speed.to_wgsl_string(),
+            cylinder_fraction.to_wgsl_string(),
+            cylinder_rho,
+            sphere_rho,
+        );
+    }
+}
+
+/// The region of a cube to spawn particles from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CubeRegion {
+    /// The six faces of the cube, weighted by [`PositionCubeModifier::face_weights`].
+    #[default]
+    Faces,
+    /// The twelve edges of the cube, like a wireframe.
+    Edges,
+    /// The entire cube volume.
+    Volume,
+}
+
+/// An initialization modifier spawning particles on a cube, or more generally a box.
+///
+/// The box is centered on `center`, extends by `half_size` on each side along each of its
+/// axes, and is rotated by `rotation`. The particles spawned on the faces move away from the
+/// box along the face normal, those spawned on the edges move away diagonally from both
+/// adjacent faces, and those spawned in the volume move radially away from the center.
+///
+/// The particles are distributed uniformly over the region, except for the faces which are
+/// weighted by `face_weights`. For example, weights of zero except for the +Y face spawn
+/// particles from the top face of a crate only.
+#[derive(Clone, Copy)]
+pub struct PositionCubeModifier {
+    /// The box center, relative to the emitter position.
+    pub center: Vec3,
+    /// The rotation of the box.
+    pub rotation: Quat,
+    /// Half the box size along each of its axes.
+    pub half_size: Vec3,
+    /// The speed of the particles on spawn.
+    pub speed: Value<f32>,
+    /// The region of the box to spawn from.
+    pub region: CubeRegion,
+    /// The relative weights of the faces when spawning from [`CubeRegion::Faces`], in the
+    /// order -X, +X, -Y, +Y, -Z, +Z. The weights multiply the face areas, so equal weights
+    /// distribute the particles uniformly over the box surface.
+    pub face_weights: [f32; 6],
+}
+
+impl Default for PositionCubeModifier {
+    fn default() -> Self {
+        Self {
+            center: Default::default(),
+            rotation: Quat::IDENTITY,
+            half_size: Vec3::splat(0.5),
+            speed: Default::default(),
+            region: Default::default(),
+            face_weights: [1.; 6],
+        }
+    }
+}
+
+impl PositionCubeModifier {
+    /// Cumulative distribution of the faces, by weighted area.
+    fn face_cdf(&self) -> [f32; 6] {
+        let h = self.half_size.abs();
+        let areas = [h.y * h.z, h.x * h.z, h.x * h.y];
+        let mut cdf = [0.; 6];
+        let mut sum = 0.;
+        for (face, c) in cdf.iter_mut().enumerate() {
+            sum += areas[face / 2] * self.face_weights[face].max(0.);
+            *c = sum;
+        }
+        if sum > 0. {
+            cdf.iter_mut().for_each(|c| *c /= sum);
+        } else {
+            // Fall back to uniform faces
+            cdf = [1. / 6., 2. / 6., 3. / 6., 4. / 6., 5. / 6., 1.];
+        }
+        cdf
+    }
+}
+
+impl InitModifier for PositionCubeModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        let region_code = match self.region {
+            CubeRegion::Faces => {
+                // Pick a face by weighted area, then a point on it
+                let mut face_code = String::new();
+                for (face, c) in self.face_cdf().iter().take(5).enumerate() {
+                    face_code += &format!(
+                        "if (u < {}) {{ face = {}u; }} else ",
+                        c.to_wgsl_string(),
+                        face
+                    );
+                }
+                format!(
+                    r##"let u = rand();
+    var face = 0u;
+    {}{{ face = 5u; }}
+    let a = face / 2u;
+    let s = f32(face % 2u) * 2. - 1.;
+    let m = vec3<f32>(select(0., 1., a == 0u), select(0., 1., a == 1u), select(0., 1., a == 2u));
+    let p = m * s + (1. - m) * (rand3() * 2. - 1.);
+    // Normal of the face
+    let n = m * s;"##,
+                    face_code
+                )
+            }
+            CubeRegion::Edges => {
+                // Pick an edge direction by length, then one of the 4 parallel edges
+                let h = self.half_size.abs();
+                let sum = (h.x + h.y + h.z).max(1e-6);
+                format!(
+                    r##"let u = rand();
+    let m = vec3<f32>(select(0., 1., u < {0}), select(0., 1., u >= {0} && u < {1}), select(0., 1., u >= {1}));
+    let signs = sign(rand3() - vec3<f32>(0.5, 0.5, 0.5));
+    let p = m * (rand() * 2. - 1.) + (1. - m) * signs;
+    // Normal halfway between the two adjacent faces
+    let n = normalize((1. - m) * signs);"##,
+                    (h.x / sum).to_wgsl_string(),
+                    ((h.x + h.y) / sum).to_wgsl_string(),
+                )
+            }
+            CubeRegion::Volume => r##"let p = rand3() * 2. - 1.;
+    // Radial direction away from the center
+    let n = normalize(p * hs + vec3<f32>(0., 0.000001, 0.));"##
+                .to_string(),
+        };
+        init_layout.position_code = format!(
+            r##"
+    // >>> [PositionCubeModifier]
+    // Box center and basis
+    let c = {};
+    let x_axis = {};
+    let y_axis = {};
+    let z_axis = {};
+    // Box half size
+    let hs = {};
+    let speed = {};
+    {}
+    ret.pos = c + x_axis * (p.x * hs.x) + y_axis * (p.y * hs.y) + z_axis * (p.z * hs.z);
+    let wn = x_axis * n.x + y_axis * n.y + z_axis * n.z;
+    ret.vel = wn * speed;
+    ret.normal = wn;
+    // <<< [PositionCubeModifier]
+"##,
+            self.center.to_wgsl_string(),
+            (self.rotation * Vec3::X).to_wgsl_string(),
+            (self.rotation * Vec3::Y).to_wgsl_string(),
+            (self.rotation * Vec3::Z).to_wgsl_string(),
+            self.half_size.to_wgsl_string(),
+            self.speed.to_wgsl_string(),
+            region_code,
+        );
+    }
+}
+
+/// The region of a cylinder to spawn particles from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CylinderRegion {
+    /// The lateral surface of the cylinder, excluding its caps.
+    #[default]
+    Lateral,
+    /// The two discs closing the ends of the cylinder.
+    Caps,
+    /// The entire cylinder volume.
+    Volume,
+}
+
+/// An initialization modifier spawning particles on a cylinder.
+///
+/// The cylinder is centered on `center`, and extends by `half_height` on each side along its
+/// axis, which is the +Y axis rotated by `rotation`. The particles spawned on the lateral
+/// surface or in the volume move radially away from the axis, like the smoke of a stack,
+/// while those spawned on the caps move along the axis away from the cylinder.
+#[derive(Clone, Copy)]
+pub struct PositionCylinderModifier {
+    /// The cylinder center, relative to the emitter position.
+    pub center: Vec3,
+    /// The rotation of the cylinder, whose axis is the +Y axis when not rotated.
+    pub rotation: Quat,
+    /// The cylinder radius.
+    pub radius: f32,
+    /// Half the cylinder height along its axis.
+    pub half_height: f32,
+    /// The speed of the particles on spawn.
+    pub speed: Value<f32>,
+    /// The region of the cylinder to spawn from.
+    pub region: CylinderRegion,
+}
+
+impl Default for PositionCylinderModifier {
+    fn default() -> Self {
+        Self {
+            center: Default::default(),
+            rotation: Quat::IDENTITY,
+            radius: 1.,
+            half_height: 1.,
+            speed: Default::default(),
+            region: Default::default(),
+        }
+    }
+}
+
+impl InitModifier for PositionCylinderModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        let region_code = match self.region {
+            CylinderRegion::Lateral => {
+                r##"let rho = r;
+    let y = (rand() * 2. - 1.) * hh;
+    // Normal of the lateral surface, and radial velocity away from the axis
+    let n = dir;"##
+            }
+            CylinderRegion::Caps => {
+                // Radius square-rooted to account for the increased perimeter covered by
+                // increased radii.
+                r##"let rho = sqrt(rand()) * r;
+    let side = select(-1., 1., rand() < 0.5);
+    let y = side * hh;
+    // Normal of the cap, and velocity away from the cylinder along the axis
+    let n = y_axis * side;"##
+            }
+            CylinderRegion::Volume => {
+                r##"let rho = sqrt(rand()) * r;
+    let y = (rand() * 2. - 1.) * hh;
+    // Radial velocity away from the axis
+    let n = dir;"##
+            }
+        };
+        init_layout.position_code = format!(
+            r##"
+    // >>> [PositionCylinderModifier]
+    // Cylinder center and basis
+    let c = {};
+    let x_axis = {};
+    let y_axis = {};
+    let z_axis = {};
+    // Cylinder radius and half height
+    let r = {};
+    let hh = {};
+    let speed = {};
+    let theta = rand() * tau;
+    let dir = x_axis * cos(theta) + z_axis * sin(theta);
+    {}
+    ret.pos = c + dir * rho + y_axis * y;
+    ret.vel = n * speed;
+    ret.normal = n;
+    // <<< [PositionCylinderModifier]
+"##,
+            self.center.to_wgsl_string(),
+            (self.rotation * Vec3::X).to_wgsl_string(),
+            (self.rotation * Vec3::Y).to_wgsl_string(),
+            (self.rotation * Vec3::Z).to_wgsl_string(),
+            self.radius.to_wgsl_string(),
+            self.half_height.to_wgsl_string(),
+            self.speed.to_wgsl_string(),
+            region_code,
+        );
+    }
+}
+
+/// An initialization modifier spawning particles on a torus.
+///
+/// The torus is the surface swept by a circle of `minor_radius`, called the tube, whose
+/// center travels along a circle of `major_radius` around the torus axis. The torus axis is
+/// the +Y axis rotated by `rotation`. The particles are distributed uniformly over the
+/// torus surface or volume, so the outer side of the ring receives more particles than the
+/// inner one.
+///
+/// The particles move away from the center of the tube, along the normal of the torus
+/// surface at their spawn position. Use a velocity modifier like [`VelocitySphereModifier`]
+/// or [`VelocityTangentModifier`] to move them in another direction.
+#[derive(Clone, Copy)]
+pub struct PositionTorusModifier {
+    /// The torus center, relative to the emitter position.
+    pub center: Vec3,
+    /// The rotation of the torus, whose axis is the +Y axis when not rotated.
+    pub rotation: Quat,
+    /// The radius of the circle traveled by the center of the tube.
+    pub major_radius: f32,
+    /// The radius of the tube.
+    pub minor_radius: f32,
+    /// The speed of the particles on spawn, away from the center of the tube.
+    pub speed: Value<f32>,
+    /// The shape dimension to spawn from.
+    pub dimension: ShapeDimension,
+}
+
+impl Default for PositionTorusModifier {
+    fn default() -> Self {
+        Self {
+            center: Default::default(),
+            rotation: Quat::IDENTITY,
+            major_radius: 1.,
+            minor_radius: 0.25,
+            speed: Default::default(),
+            dimension: Default::default(),
+        }
+    }
+}
+
+impl InitModifier for PositionTorusModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        let rho_code = match self.dimension {
+            ShapeDimension::Surface => "minor".to_string(),
+            // Distance to the tube center square-rooted to account for the increased
+            // perimeter covered by increased distances.
+            ShapeDimension::Volume => "sqrt(rand()) * minor".to_string(),
+        };
+        init_layout.position_code = format!(
+            r##"
+    // >>> [PositionTorusModifier]
+    // Torus center and basis
+    let c = {};
+    let x_axis = {};
+    let y_axis = {};
+    let z_axis = {};
+    // Torus radii
+    let major = {};
+    let minor = {};
+    // Speed away from the tube center
+    let speed = {};
+    // Sample the angle around the tube and the distance to its center, rejecting samples
+    // proportionally to the distance to the torus axis to get a uniform distribution.
+    var phi = 0.;
+    var rho = 0.;
+    for (var i: i32 = 0; i < 16; i = i + 1) {{
+        phi = rand() * tau;
+        rho = {};
+        if (rand() * (major + minor) <= major + rho * cos(phi)) {{
+            break;
+        }}
+    }}
+    let theta = rand() * tau;
+    let ring_dir = x_axis * cos(theta) + z_axis * sin(theta);
+    let n = ring_dir * cos(phi) + y_axis * sin(phi);
+    ret.pos = c + ring_dir * major + n * rho;
+    // Velocity away from the tube center
+    ret.vel = n * speed;
+    // Normal of the torus surface
+    ret.normal = n;
+    // <<< [PositionTorusModifier]
+"##,
+            self.center.to_wgsl_string(),
+            (self.rotation * Vec3::X).to_wgsl_string(),
+            (self.rotation * Vec3::Y).to_wgsl_string(),
+            (self.rotation * Vec3::Z).to_wgsl_string(),
+            self.major_radius.to_wgsl_string(),
+            self.minor_radius.to_wgsl_string(),
+            self.speed.to_wgsl_string(),
+            rho_code,
+        );
+    }
+}
+
+/// An initialization modifier setting the velocity of spawned particles radially away from
+/// a center point.
+///
+/// Velocity modifiers override the velocity set by the position modifier of the effect, if
+/// any, which allows combining the spawn position of one shape with the velocity direction of
+/// another. They run after the position modifier, and read the spawn position of the
+/// particle. Only one velocity modifier applies to an effect; the last one added replaces the
+/// others.
+#[derive(Default, Clone, Copy)]
+pub struct VelocitySphereModifier {
+    /// The center the particles move away from, relative to the emitter position.
+    pub center: Vec3,
+    /// The speed of the particles on spawn.
+    pub speed: Value<f32>,
+}
+
+impl InitModifier for VelocitySphereModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        init_layout.velocity_code = format!(
+            r##"
+    // >>> [VelocitySphereModifier]
+    {{
+        let vel_dir = ret.pos - {};
+        let vel_speed = {};
+        if (dot(vel_dir, vel_dir) > 0.00000001) {{
+            ret.vel = normalize(vel_dir) * vel_speed;
+        }} else {{
+            ret.vel = normalize(rand3() * 2. - 1.) * vel_speed;
+        }}
+    }}
+    // <<< [VelocitySphereModifier]
+"##,
+            self.center.to_wgsl_string(),
+            self.speed.to_wgsl_string(),
+        );
+    }
+}
+
+/// An initialization modifier setting the velocity of spawned particles radially away from
+/// an axis, in the plane normal to that axis.
+///
+/// See [`VelocitySphereModifier`] for how velocity modifiers combine with position modifiers.
+#[derive(Clone, Copy)]
+pub struct VelocityCircleModifier {
+    /// A point on the axis, relative to the emitter position.
+    pub center: Vec3,
+    /// The normalized axis the particles move away from.
+    pub axis: Vec3,
+    /// The speed of the particles on spawn.
+    pub speed: Value<f32>,
+}
+
+impl Default for VelocityCircleModifier {
+    fn default() -> Self {
+        Self {
+            center: Default::default(),
+            axis: Vec3::Z,
+            speed: Default::default(),
+        }
+    }
+}
+
+impl InitModifier for VelocityCircleModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        let (tangent, bitangent) = self.axis.any_orthonormal_pair();
+        init_layout.velocity_code = format!(
+            r##"
+    // >>> [VelocityCircleModifier]
+    {{
+        let vel_axis = {};
+        let vel_delta = ret.pos - {};
+        let vel_dir = vel_delta - dot(vel_delta, vel_axis) * vel_axis;
+        let vel_speed = {};
+        if (dot(vel_dir, vel_dir) > 0.00000001) {{
+            ret.vel = normalize(vel_dir) * vel_speed;
+        }} else {{
+            let vel_theta = rand() * tau;
+            ret.vel = ({} * cos(vel_theta) + {} * sin(vel_theta)) * vel_speed;
+        }}
+    }}
+    // <<< [VelocityCircleModifier]
+"##,
+            self.axis.to_wgsl_string(),
+            self.center.to_wgsl_string(),
+            self.speed.to_wgsl_string(),
+            tangent.to_wgsl_string(),
+            bitangent.to_wgsl_string(),
+        );
+    }
+}
+
+/// An initialization modifier setting the velocity of spawned particles tangent to a circle
+/// around an axis, making the particles swirl around that axis.
+///
+/// The direction of rotation follows the right-hand rule around `axis`. Particles spawned on
+/// the axis itself have no tangent direction, and are spawned with a zero velocity.
+///
+/// See [`VelocitySphereModifier`] for how velocity modifiers combine with position modifiers.
+#[derive(Clone, Copy)]
+pub struct VelocityTangentModifier {
+    /// A point on the rotation axis, relative to the emitter position.
+    pub origin: Vec3,
+    /// The normalized rotation axis.
+    pub axis: Vec3,
+    /// The speed of the particles on spawn.
+    pub speed: Value<f32>,
+}
+
+impl Default for VelocityTangentModifier {
+    fn default() -> Self {
+        Self {
+            origin: Default::default(),
+            axis: Vec3::Z,
+            speed: Default::default(),
+        }
+    }
+}
+
+impl InitModifier for VelocityTangentModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        init_layout.velocity_code = format!(
+            r##"
+    // >>> [VelocityTangentModifier]
+    {{
+        let vel_dir = cross({}, ret.pos - {});
+        if (dot(vel_dir, vel_dir) > 0.00000001) {{
+            ret.vel = normalize(vel_dir) * {};
+        }} else {{
+            ret.vel = vec3<f32>(0., 0., 0.);
+        }}
+    }}
+    // <<< [VelocityTangentModifier]
+"##,
+            self.axis.to_wgsl_string(),
+            self.origin.to_wgsl_string(),
+            self.speed.to_wgsl_string(),
+        );
+    }
+}
+
+/// An initialization modifier setting the velocity of spawned particles in a random direction
+/// picked from a painted distribution.
+///
+/// The probability of each direction is defined by a small latitude-longitude grid of
+/// weights, like an environment map, with `size.x` columns of longitude and `size.y` rows of
+/// latitude, stored row by row. The first row is around the +Y axis and the last one around
+/// the -Y axis; the longitude starts on the +X axis and turns toward +Z. The weights are
+/// relative, and account for the solid angle of their cell, so a uniform grid spawns the
+/// particles uniformly in all directions. This lets artists paint the directionality of a
+/// burst, for example mostly upward, a bit sideways, and never down.
+///
+/// The distribution is baked into the shader, so keep the grid small, like 16 by 8 cells.
+/// See [`VelocitySphereModifier`] for how velocity modifiers combine with position modifiers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VelocityDistributionModifier {
+    /// The weights of the cells of the grid, row by row, from +Y to -Y.
+    pub weights: Vec<f32>,
+    /// The number of columns and rows of the grid.
+    pub size: UVec2,
+    /// The speed of the particles on spawn.
+    pub speed: Value<f32>,
+}
+
+impl VelocityDistributionModifier {
+    /// Create a modifier from a grid of weights, stored row by row.
+    pub fn new(weights: Vec<f32>, size: UVec2, speed: Value<f32>) -> Self {
+        Self {
+            weights,
+            size,
+            speed,
+        }
+    }
+
+    /// Create a modifier from the red channel of a latitude-longitude image.
+    ///
+    /// The image must have been loaded, and have an 8-bit `R8Unorm`, `Rgba8Unorm` or
+    /// `Rgba8UnormSrgb` format, otherwise this returns `None`.
+    pub fn from_image(image: &Image, speed: Value<f32>) -> Option<Self> {
+        let stride = match image.texture_descriptor.format {
+            TextureFormat::R8Unorm => 1,
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => 4,
+            _ => return None,
+        };
+        let size = image.texture_descriptor.size;
+        let count = (size.width * size.height) as usize;
+        if image.data.len() < count * stride {
+            return None;
+        }
+        let weights = image
+            .data
+            .iter()
+            .step_by(stride)
+            .take(count)
+            .map(|&r| r as f32 / 255.)
+            .collect();
+        Some(Self::new(
+            weights,
+            UVec2::new(size.width, size.height),
+            speed,
+        ))
+    }
+
+    /// Build the cumulative distributions of the rows, and of the columns of each row.
+    ///
+    /// Cells missing from `weights` have no weight. If all the weights are zero, the
+    /// directions are picked uniformly instead.
+    fn cumulative_distributions(&self) -> (Vec<f32>, Vec<f32>) {
+        let cols = self.size.x.max(1) as usize;
+        let rows = self.size.y.max(1) as usize;
+        let weight = |index: usize| self.weights.get(index).map_or(0., |w| w.max(0.));
+        let uniform = (0..cols * rows).all(|index| weight(index) <= 0.);
+        let weight = |index: usize| if uniform { 1. } else { weight(index) };
+        let mut row_cdf = Vec::with_capacity(rows);
+        let mut col_cdf = Vec::with_capacity(cols * rows);
+        let polar = |row: usize| row as f32 * std::f32::consts::PI / rows as f32;
+        let mut total = 0.;
+        for row in 0..rows {
+            // Solid angle of the cells of the row, up to a constant factor
+            let solid_angle = polar(row).cos() - polar(row + 1).cos();
+            let row_weights: Vec<f32> = (0..cols).map(|col| weight(row * cols + col)).collect();
+            let row_total: f32 = row_weights.iter().sum();
+            let mut sum = 0.;
+            for w in row_weights {
+                sum += if row_total > 0. {
+                    w / row_total
+                } else {
+                    1. / cols as f32
+                };
+                col_cdf.push(sum);
+            }
+            total += row_total * solid_angle;
+            row_cdf.push(total);
+        }
+        for cdf in &mut row_cdf {
+            *cdf /= total;
+        }
+        (row_cdf, col_cdf)
+    }
+}
+
+impl InitModifier for VelocityDistributionModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        let cols = self.size.x.max(1);
+        let rows = self.size.y.max(1);
+        let (row_cdf, col_cdf) = self.cumulative_distributions();
+        let to_array = |cdf: Vec<f32>| {
+            cdf.iter()
+                .map(|c| c.to_wgsl_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        init_layout.velocity_code = format!(
+            r##"
+    // >>> [VelocityDistributionModifier]
+    {{
+        var vel_rows = array<f32, {1}>({2});
+        var vel_cols = array<f32, {3}>({4});
+        // Pick a cell by inverting the cumulative distributions of the rows, then of the
+        // columns of the row
+        let vel_u = rand();
+        var vel_row = 0u;
+        loop {{
+            if (vel_row + 1u >= {1}u || vel_u < vel_rows[vel_row]) {{ break; }}
+            vel_row = vel_row + 1u;
+        }}
+        let vel_v = rand();
+        var vel_col = 0u;
+        loop {{
+            if (vel_col + 1u >= {0}u || vel_v < vel_cols[vel_row * {0}u + vel_col]) {{ break; }}
+            vel_col = vel_col + 1u;
+        }}
+        // Pick a direction uniformly over the solid angle of the cell
+        let vel_cell = rand2();
+        let vel_cos = mix(cos(f32(vel_row) * tau / {5}), cos(f32(vel_row + 1u) * tau / {5}), vel_cell.x);
+        let vel_sin = sqrt(max(1. - vel_cos * vel_cos, 0.));
+        let vel_lon = (f32(vel_col) + vel_cell.y) * tau / {6};
+        ret.vel = vec3<f32>(cos(vel_lon) * vel_sin, vel_cos, sin(vel_lon) * vel_sin) * {7};
+    }}
+    // <<< [VelocityDistributionModifier]
+"##,
+            cols,
+            rows,
+            to_array(row_cdf),
+            cols * rows,
+            to_array(col_cdf),
+            (rows as f32 * 2.).to_wgsl_string(),
+            (cols as f32).to_wgsl_string(),
+            self.speed.to_wgsl_string(),
+        );
+    }
+}
+
+/// An initialization modifier randomizing the direction of the initial velocity of spawned
+/// particles within a cone.
+///
+/// The velocity set by the position and velocity modifiers of the effect is rotated to a
+/// random direction within a cone of half-angle `spread` around it, keeping its magnitude.
+/// The directions are distributed uniformly over the spherical cap of the cone. This applies
+/// after, and independently of, the other modifiers, so it combines with any of them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VelocitySpreadModifier {
+    /// The half-angle of the cone, in radians.
+    pub spread: Value<f32>,
+}
+
+impl Default for VelocitySpreadModifier {
+    fn default() -> Self {
+        Self {
+            spread: Value::Single(0.),
+        }
+    }
+}
+
+impl InitModifier for VelocitySpreadModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        init_layout.velocity_spread_code = format!(
+            r##"
+    // >>> [VelocitySpreadModifier]
+    {{
+        let speed = length(ret.vel);
+        if (speed > 0.00000001) {{
+            let d = ret.vel / speed;
+            // Basis orthogonal to the velocity
+            let up = select(vec3<f32>(0., 0., 1.), vec3<f32>(1., 0., 0.), abs(d.z) > 0.9);
+            let t = normalize(cross(up, d));
+            let b = cross(d, t);
+            // Direction uniformly distributed on the spherical cap of the cone
+            let cz = mix(cos({}), 1., rand());
+            let sz = sqrt(max(1. - cz * cz, 0.));
+            let phi = rand() * tau;
+            ret.vel = speed * (d * cz + (t * cos(phi) + b * sin(phi)) * sz);
+        }}
+    }}
+    // <<< [VelocitySpreadModifier]
+"##,
+            self.spread.to_wgsl_string(),
+        );
+    }
+}
+
+/// An initialization modifier setting the lifetime of spawned particles.
+///
+/// A [`Value::Uniform`] lifetime is sampled for each particle independently, which gives
+/// effects like smoke puffs a more natural look than a single lifetime for all particles.
+/// Without this modifier, the particles live for 5 seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParticleLifetimeModifier {
+    /// The lifetime of the particles, in seconds.
+    pub lifetime: Value<f32>,
+}
+
+impl Default for ParticleLifetimeModifier {
+    fn default() -> Self {
+        Self {
+            lifetime: Value::Single(5.),
+        }
+    }
+}
+
+impl InitModifier for ParticleLifetimeModifier {
+    fn apply(&self, init_layout: &mut InitLayout) {
+        init_layout.lifetime_code = format!(
+            "\n    // [ParticleLifetimeModifier]\n    ret.lifetime = {};\n",
+            self.lifetime.to_wgsl_string()
+        );
+        init_layout.lifetime = Some(self.lifetime);
+    }
+}
+
+/// A channel of a texture sample, or a constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureChannel {
+    /// The red channel of the texture.
+    Red,
+    /// The green channel of the texture.
+    Green,
+    /// The blue channel of the texture.
+    Blue,
+    /// The alpha channel of the texture.
+    Alpha,
+    /// The constant zero, ignoring the texture.
+    Zero,
+    /// The constant one, ignoring the texture.
+    One,
+}
+
+impl ToWgslString for TextureChannel {
+    fn to_wgsl_string(&self) -> String {
+        match self {
+            TextureChannel::Red => "texel.r",
+            TextureChannel::Green => "texel.g",
+            TextureChannel::Blue => "texel.b",
+            TextureChannel::Alpha => "texel.a",
+            TextureChannel::Zero => "0.",
+            TextureChannel::One => "1.",
+        }
+        .to_string()
+    }
+}
+
+/// Mapping of the channels of a particle texture to the color the particle is modulated with.
+///
+/// Each component of the modulating color is read from a channel of the texture, or is a
+/// constant. This allows using single-channel and packed textures without authoring separate
+/// RGBA images, for example a grayscale mask as the particle alpha.
+///
+/// The default mapping uses the red channel as alpha and keeps the particle color, which suits
+/// grayscale textures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureChannelMapping {
+    /// The channel modulating the red component of the particle color.
+    pub red: TextureChannel,
+    /// The channel modulating the green component of the particle color.
+    pub green: TextureChannel,
+    /// The channel modulating the blue component of the particle color.
+    pub blue: TextureChannel,
+    /// The channel modulating the alpha component of the particle color.
+    pub alpha: TextureChannel,
+}
+
+impl Default for TextureChannelMapping {
+    fn default() -> Self {
+        Self::ALPHA_FROM_RED
+    }
+}
+
+impl TextureChannelMapping {
+    /// Mapping modulating the particle color with the RGBA texture color, unchanged.
+    pub const RGBA: TextureChannelMapping = TextureChannelMapping {
+        red: TextureChannel::Red,
+        green: TextureChannel::Green,
+        blue: TextureChannel::Blue,
+        alpha: TextureChannel::Alpha,
+    };
+
+    /// Mapping modulating the particle alpha only, with the red channel of the texture.
+    pub const ALPHA_FROM_RED: TextureChannelMapping = TextureChannelMapping {
+        red: TextureChannel::One,
+        green: TextureChannel::One,
+        blue: TextureChannel::One,
+        alpha: TextureChannel::Red,
+    };
+
+    /// Mapping modulating the particle alpha only, with the given channel of the texture.
+    pub fn alpha_from(channel: TextureChannel) -> Self {
+        Self {
+            alpha: channel,
+            ..Self::ALPHA_FROM_RED
+        }
+    }
+
+    /// Generate the WGSL expression of the modulating color, from the texture sample `texel`.
+    pub(crate) fn to_shader_code(self) -> String {
+        format!(
+            "vec4<f32>({}, {}, {}, {})",
+            self.red.to_wgsl_string(),
+            self.green.to_wgsl_string(),
+            self.blue.to_wgsl_string(),
+            self.alpha.to_wgsl_string()
+        )
+    }
+}
+
+/// A modifier modulating each particle's color by sampling a texture.
+///
+/// The channels of the texture are mapped to the modulating color according to `mapping`.
+#[derive(Default, Clone)]
+pub struct ParticleTextureModifier {
+    /// The texture image to modulate the particle color with.
+    pub texture: Handle<Image>,
+    /// The mapping of the texture channels to the modulating color.
+    pub mapping: TextureChannelMapping,
+}
+
+impl RenderModifier for ParticleTextureModifier {
+    fn apply(&self, render_layout: &mut RenderLayout) {
+        render_layout.particle_texture = Some(self.texture.clone());
+        render_layout.particle_texture_mapping = self.mapping;
+    }
+}
+
+/// Color space in which the keys of a color gradient are interpolated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+pub enum ColorInterpolation {
+    /// Interpolate the RGBA components of the keys independently.
+    #[default]
+    Rgb,
+    /// Interpolate the keys in the HSV color space, blending the hue over the shortest path
+    /// around the color wheel.
+    ///
+    /// This keeps the intermediate colors saturated, for example to cycle through a rainbow
+    /// with a few keys, where the RGB interpolation would produce dull or muddy colors. The
+    /// hue of a key without saturation, like white or black, is taken from its neighbor keys.
+    Hsv,
+}
+
+/// A modifier modulating each particle's color over its lifetime with a gradient curve.
+#[derive(Default, Clone)]
+pub struct ColorOverLifetimeModifier {
+    /// The color gradient defining the particle color based on its lifetime.
+    pub gradient: Gradient<Vec4>,
+    /// The color space in which the gradient keys are interpolated.
+    pub interpolation: ColorInterpolation,
+}
+
+impl ColorOverLifetimeModifier {
+    /// Create a modifier interpolating the gradient keys in the HSV color space.
+    ///
+    /// The gradient keys are RGBA colors, like for the default RGB interpolation.
+    pub fn hsv(gradient: Gradient<Vec4>) -> Self {
+        Self {
+            gradient,
+            interpolation: ColorInterpolation::Hsv,
+        }
+    }
+}
+
+impl RenderModifier for ColorOverLifetimeModifier {
+    fn apply(&self, render_layout: &mut RenderLayout) {
+        render_layout.lifetime_color_gradient = Some(self.gradient.clone());
+        render_layout.lifetime_color_interpolation = self.interpolation;
+        render_layout.color_ramp_texture = None;
+        render_layout.color_blend = None;
+    }
+}
+
+/// A modifier setting each particle's color over its lifetime by sampling a ramp texture.
+///
+/// This is an alternative to [`ColorOverLifetimeModifier`] for color ramps authored in image
+/// tools. The texture is sampled horizontally along its vertical center, from its left edge
+/// at the particle birth to its right edge at the particle death, so a texture one pixel high
+/// is enough. The same texture can be shared by several effects.
+///
+/// This modifier, [`ColorOverLifetimeModifier`] and [`ColorBlendModifier`] are mutually
+/// exclusive; the last one added to an effect replaces the others.
+#[derive(Default, Clone)]
+pub struct ColorRampOverLifetimeModifier {
+    /// The ramp texture defining the particle color based on its lifetime.
+    pub texture: Handle<Image>,
+}
+
+impl RenderModifier for ColorRampOverLifetimeModifier {
+    fn apply(&self, render_layout: &mut RenderLayout) {
+        render_layout.color_ramp_texture = Some(self.texture.clone());
+        render_layout.lifetime_color_gradient = None;
+        render_layout.color_blend = None;
+    }
+}
+
+/// Weight blending the two gradients of a [`ColorBlendModifier`], in `[0:1]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorBlendWeight {
+    /// Random weight picked uniformly between two bounds for each particle, which the
+    /// particle keeps for its whole lifetime.
+    Random((f32, f32)),
+    /// Weight mapped linearly from the speed of the particle, from `0` at the first speed to
+    /// `1` at the second one, clamped outside.
+    Speed((f32, f32)),
+    /// Weight mapped linearly from the lifetime of the particle, from `0` at the first
+    /// lifetime to `1` at the second one, clamped outside.
+    Lifetime((f32, f32)),
+}
+
+impl Default for ColorBlendWeight {
+    fn default() -> Self {
+        ColorBlendWeight::Random((0., 1.))
+    }
+}
+
+/// A modifier setting each particle's color over its lifetime by blending two gradient curves.
+///
+/// Both gradients are sampled over the particle lifetime like for a
+/// [`ColorOverLifetimeModifier`], then blended with a per-particle weight, from `gradient_a`
+/// at a weight of `0` to `gradient_b` at a weight of `1`. This mixes two palettes across the
+/// particle population, for example fire and smoke, without splitting the effect in two.
+///
+/// Like for the [`InitColorModifier`], the random weight is derived from a hash of the
+/// particle slot in the particle buffer, so no weight is stored with the particles. The
+/// gradients are interpolated in the RGB color space.
+///
+/// This modifier, [`ColorOverLifetimeModifier`] and [`ColorRampOverLifetimeModifier`] are
+/// mutually exclusive; the last one added to an effect replaces the others.
+#[derive(Default, Clone)]
+pub struct ColorBlendModifier {
+    /// The color gradient of the particles with a weight of `0`.
+    pub gradient_a: Gradient<Vec4>,
+    /// The color gradient of the particles with a weight of `1`.
+    pub gradient_b: Gradient<Vec4>,
+    /// The weight blending the two gradients for each particle.
+    pub weight: ColorBlendWeight,
+}
+
+impl ColorBlendModifier {
+    /// Create a modifier blending two gradients with a random weight in `[0:1]`.
+    pub fn random(gradient_a: Gradient<Vec4>, gradient_b: Gradient<Vec4>) -> Self {
+        Self {
+            gradient_a,
+            gradient_b,
+            weight: ColorBlendWeight::Random((0., 1.)),
+        }
+    }
+}
+
+impl RenderModifier for ColorBlendModifier {
+    fn apply(&self, render_layout: &mut RenderLayout) {
+        render_layout.color_blend = Some(self.clone());
+        render_layout.lifetime_color_gradient = None;
+        render_layout.color_ramp_texture = None;
+    }
+}
+
+/// A modifier modulating each particle's size over its lifetime with a gradient curve.
+#[derive(Default, Clone)]
+pub struct SizeOverLifetimeModifier {
+    /// The size gradient defining the particle size based on its lifetime.
+    pub gradient: Gradient<Vec2>,
+}
+
+impl RenderModifier for SizeOverLifetimeModifier {
+    fn apply(&self, render_layout: &mut RenderLayout) {
+        render_layout.size_color_gradient = Some(self.gradient.clone());
+    }
+}
+
+/// A modifier randomizing the size of each particle.
+///
+/// Each particle picks a size at spawn, which multiplies the size set by the
+/// [`SizeOverLifetimeModifier`] gradient, if any, for the whole particle lifetime. This breaks
+/// the uniform look of particles all following the exact same size curve. A
+/// [`Value::Uniform`] size is interpolated between its two bounds with a single random factor,
+/// so the particles keep the aspect ratio of the bounds.
+///
+/// The size is derived from a hash of the particle slot in the particle buffer, which the
+/// particle keeps until it dies, so no size is stored with the particles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InitSizeModifier {
+    /// The size of the particles, or size multiplier if the effect also has a
+    /// [`SizeOverLifetimeModifier`].
+    pub size: Value<Vec2>,
+}
+
+impl Default for InitSizeModifier {
+    fn default() -> Self {
+        Self {
+            size: Value::Single(Vec2::ONE),
+        }
+    }
+}
+
+impl InitSizeModifier {
+    /// Create a modifier scaling the particles uniformly by a random factor.
+    pub fn uniform(scale: Value<f32>) -> Self {
+        let size = match scale {
+            Value::Single(s) => Value::Single(Vec2::splat(s)),
+            Value::Uniform((a, b)) => Value::Uniform((Vec2::splat(a), Vec2::splat(b))),
+            Value::Normal((mean, std_dev)) => {
+                Value::Normal((Vec2::splat(mean), Vec2::splat(std_dev)))
+            }
+            Value::Curve(points) => Value::Curve(points.map(Vec2::splat)),
+        };
+        Self { size }
+    }
+}
+
+impl RenderModifier for InitSizeModifier {
+    fn apply(&self, render_layout: &mut RenderLayout) {
+        render_layout.init_size = Some(*self);
+    }
+}
+
+/// Random selection of the base color of a particle by an [`InitColorModifier`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColorSelection {
+    /// Pick a color uniformly between two RGBA colors, interpolating all components with the
+    /// same random factor.
+    Uniform((Vec4, Vec4)),
+    /// Pick one of a set of discrete RGBA colors, with equal probability.
+    Palette(Vec<Vec4>),
+    /// Sample a color gradient at a random position in `[0:1]`.
+    Gradient(Gradient<Vec4>),
+}
+
+/// A modifier randomizing the base color of each particle.
+///
+/// Each particle picks a base color at spawn, which it keeps for its whole lifetime. If the
+/// effect also has a [`ColorOverLifetimeModifier`] or a [`ColorRampOverLifetimeModifier`], the
+/// base color modulates the color over lifetime instead of being replaced by it, so a white
+/// gradient fading to transparent keeps the base color of each particle.
+///
+/// Like for the [`InitSizeModifier`], the color is derived from a hash of the particle slot in
+/// the particle buffer, so no color is stored with the particles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InitColorModifier {
+    /// The random selection of the base color.
+    pub color: ColorSelection,
+}
+
+impl InitColorModifier {
+    /// Create a modifier picking a color uniformly between two colors.
+    pub fn uniform(min: Vec4, max: Vec4) -> Self {
+        Self {
+            color: ColorSelection::Uniform((min, max)),
+        }
+    }
+
+    /// Create a modifier picking one of a set of discrete colors.
+    pub fn palette(colors: Vec<Vec4>) -> Self {
+        Self {
+            color: ColorSelection::Palette(colors),
+        }
+    }
+
+    /// Create a modifier sampling a color gradient at a random position.
+    pub fn gradient(gradient: Gradient<Vec4>) -> Self {
+        Self {
+            color: ColorSelection::Gradient(gradient),
+        }
+    }
+}
+
+impl RenderModifier for InitColorModifier {
+    fn apply(&self, render_layout: &mut RenderLayout) {
+        render_layout.init_color = Some(self.clone());
+    }
+}
+
+/// A modifier animating each particle's texture as a flipbook over its lifetime.
+///
+/// The particle texture set with a [`ParticleTextureModifier`] is treated as a sprite sheet
+/// made of a grid of `sprite_grid_size.x` columns by `sprite_grid_size.y` rows of sprites of
+/// equal size. By default the animation plays all the sprites in row-major order, from the
+/// first one at birth to the last one at death.
+///
+/// If `random_row` is set, each particle instead picks a random row of the sprite sheet at
+/// spawn, and only animates through the columns of that row. This allows a single sprite sheet
+/// to hold multiple variants of the same animation, one per row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlipbookModifier {
+    /// The number of columns and rows of sprites in the sprite sheet.
+    pub sprite_grid_size: UVec2,
+    /// Pick a random row per particle, and animate through its columns only.
+    pub random_row: bool,
+}
+
+impl Default for FlipbookModifier {
+    fn default() -> Self {
+        Self {
+            sprite_grid_size: UVec2::ONE,
+            random_row: false,
+        }
+    }
+}
+
+impl RenderModifier for FlipbookModifier {
+    fn apply(&self, render_layout: &mut RenderLayout) {
+        render_layout.flipbook = Some(*self);
+    }
+}
+
+/// A modifier rotating each particle in its plane over its lifetime with a gradient curve.
+///
+/// The gradient defines the rotation angle in radians, counter-clockwise, based on the
+/// particle lifetime. Easing the rotation in or out is obtained by adding more keys to
+/// the gradient, for example to make a smoke puff slow down its spin as it fades out.
+#[derive(Default, Clone)]
+pub struct RotationOverLifetimeModifier {
+    /// The rotation gradient defining the particle rotation angle based on its lifetime.
+    pub gradient: Gradient<f32>,
+}
+
+impl RenderModifier for RotationOverLifetimeModifier {
+    fn apply(&self, render_layout: &mut RenderLayout) {
+        render_layout.lifetime_rotation_gradient = Some(self.gradient.clone());
+    }
+}
+
+/// A modifier giving each particle a random initial rotation and angular velocity.
+///
+/// The particles are rotated in their plane by `angle` at spawn, then spin at
+/// `angular_velocity` over their lifetime, both in radians, counter-clockwise. Use a range
+/// crossing zero for the angular velocity to spin the particles in both directions, like
+/// tumbling debris or snowflakes. The rotation adds to the one of a
+/// [`RotationOverLifetimeModifier`], if any.
+///
+/// Like for the [`InitSizeModifier`], the rotation is derived from a hash of the particle slot
+/// in the particle buffer and from the particle age, so no rotation is stored with the
+/// particles.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct InitRotationModifier {
+    /// The rotation angle of the particles on spawn, in radians.
+    pub angle: Value<f32>,
+    /// The angular velocity of the particles, in radians per second.
+    pub angular_velocity: Value<f32>,
+}
+
+impl RenderModifier for InitRotationModifier {
+    fn apply(&self, render_layout: &mut RenderLayout) {
+        render_layout.init_rotation = Some(*self);
+    }
+}
+
+/// A modifier matching the particles to the emitter of each effect instance.
+///
+/// The color of the particles is multiplied by the [`EmitterColor`] of the instance, or
+/// replaced by it if the particles have no other color. Their size is multiplied by the
+/// largest scale of the emitter transform. Both are read each frame, so the particles follow
+/// any change of the emitter, like a pickup changing color.
+///
+/// Instances with different emitter properties can't be drawn together, so this modifier
+/// splits the draw calls of the instances of the effect.
+///
+/// [`EmitterColor`]: crate::EmitterColor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InheritEmitterModifier {
+    /// Inherit the emitter color.
+    pub color: bool,
+    /// Inherit the emitter scale.
+    pub size: bool,
+}
+
+impl Default for InheritEmitterModifier {
+    fn default() -> Self {
+        Self {
+            color: true,
+            size: false,
+        }
+    }
+}
+
+impl InheritEmitterModifier {
+    /// Generate the vertex shader code applying the emitter properties. If `modulate` is
+    /// true, the particles already have a color which the emitter color modulates.
+    pub(crate) fn to_shader_code(self, modulate: bool) -> String {
+        let mut code = String::new();
+        if self.color {
+            code += if modulate {
+                "out.color = out.color * emitter.color;\n"
+            } else {
+                "out.color = emitter.color;\n"
+            };
+        }
+        if self.size {
+            code += "size = size * emitter.scale;\n";
+        }
+        code
+    }
+}
+
+impl RenderModifier for InheritEmitterModifier {
+    fn apply(&self, render_layout: &mut RenderLayout) {
+        render_layout.inherit_emitter = Some(*self);
+    }
+}
+
+/// The geometry used to render each particle of an effect.
+///
+/// All geometries are unit-sized, centered on the particle position, and are later scaled by
+/// the particle size. They all cover the same \[0:1\] UV range, which allows swapping one for
+/// another without changing the particle texture. Geometries with fewer vertices and a smaller
+/// area trade vertex processing cost for fill rate, or conversely.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ParticleGeometry {
+    /// A quad made of two triangles. This is the default geometry.
+    #[default]
+    Quad,
+    /// A single equilateral triangle inscribed in the unit quad. This is the cheapest
+    /// geometry, both in vertex count and in fill rate, but cuts the corners of the texture.
+    Triangle,
+    /// A hexagon inscribed in the unit quad. This is a good fit for round particles, as it
+    /// reduces the fill rate spent on the transparent corners of a quad, for 6 triangles.
+    Hexagon,
+    /// A quad shaded like a lit sphere facing the camera, discarding the pixels outside the
+    /// sphere's silhouette.
+    SphereImpostor,
+}
+
+/// A modifier selecting the geometry used to render each particle.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ParticleGeometryModifier {
+    /// The geometry of a single particle.
+    pub geometry: ParticleGeometry,
+}
+
+impl RenderModifier for ParticleGeometryModifier {
+    fn apply(&self, render_layout: &mut RenderLayout) {
+        render_layout.geometry = self.geometry;
+    }
+}
+
+/// A modifier adding custom shader definitions to the render pipeline of an effect.
+///
+/// The definitions are passed to the shader preprocessor of the render shader, and are part
+/// of the key specializing the render pipeline, so effects with different definitions get
+/// distinct pipelines. This allows code injected with a [`VertexDeformModifier`], or custom
+/// shader code of a downstream crate, to be conditionally compiled with `#ifdef`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ShaderDefsModifier {
+    /// The shader definitions to add.
+    pub defs: Vec<String>,
+}
+
+impl RenderModifier for ShaderDefsModifier {
+    fn apply(&self, render_layout: &mut RenderLayout) {
+        render_layout.shader_defs.extend(self.defs.iter().cloned());
+    }
+}
+
+/// A modifier attaching a custom bind group with user data to the render and update pipelines
+/// of an effect.
+///
+/// The bind group contains the `data` bytes as a read-only storage buffer at binding 0 and,
+/// if `texture` is set, the texture and its sampler at bindings 1 and 2. It is bound after
+/// all the other bind groups of each pipeline. The WGSL `declarations` of the bindings are
+/// injected as is into the render and update shaders, where the `{{GROUP}}` placeholder is
+/// replaced by the actual index of the bind group in each of them. The bindings are then
+/// accessible from custom code injected with a [`VertexDeformModifier`], or from the code of
+/// an [`ExtensionModifier`](crate::ExtensionModifier). The update shader is a compute shader, so it can only sample the
+/// texture with an explicit level.
+///
+/// The buffer is created once per effect asset, and its content is written to GPU each frame,
+/// so changes to the `data` of the render layout of the effect asset are visible on the
+/// next frame. The buffer is only recreated if the size of the data changes. The buffer size is
+/// rounded up to a multiple of 4 bytes. Only a single custom bind group can be attached to an
+/// effect; adding another modifier replaces the previous one.
+///
+/// # Example
+///
+/// ```
+/// # use bevy_hanabi::*;
+/// // Displace all particles by a wind vector controlled by the application.
+/// let wind: [f32; 4] = [0.5, 0., 0., 0.];
+/// let user_bind_group = UserBindGroupModifier {
+///     declarations: "struct Wind { dir: vec4<f32>; };\n\
+///         [[group({{GROUP}}), binding(0)]] var<storage, read> wind: Wind;"
+///         .to_string(),
+///     data: wind.iter().flat_map(|f| f.to_le_bytes()).collect(),
+///     texture: None,
+/// };
+/// let deform = VertexDeformModifier {
+///     code: "vpos = vpos + wind.dir.xyz * vertex_position.y;".to_string(),
+/// };
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct UserBindGroupModifier {
+    /// The WGSL declarations of the bindings of the bind group.
+    pub declarations: String,
+    /// The content of the storage buffer at binding 0.
+    pub data: Vec<u8>,
+    /// An optional texture at binding 1, with its sampler at binding 2.
+    pub texture: Option<Handle<Image>>,
+}
+
+impl RenderModifier for UserBindGroupModifier {
+    fn apply(&self, render_layout: &mut RenderLayout) {
+        render_layout.user_bind_group = Some(self.clone());
+    }
+}
+
+/// A modifier rendering each particle as a ring, to build shockwaves from a single particle.
+///
+/// The particle quad is cut into a ring whose outer radius is the particle size, and whose
+/// width evolves over the particle lifetime. A shockwave is a single particle combining this
+/// modifier with a [`SizeOverLifetimeModifier`] to expand the ring and a
+/// [`ColorOverLifetimeModifier`] to fade it out. Add an [`OrientAlongNormalModifier`] with a
+/// zero-radius [`PositionCircleModifier`] around the `Vec3::Y` axis to lay it on the ground.
+///
+/// This modifier is designed for the default [`ParticleGeometry::Quad`] geometry.
+///
+/// # Example
+///
+/// ```
+/// # use bevy::math::Vec2;
+/// # use bevy_hanabi::*;
+/// let mut width = Gradient::new();
+/// width.add_key(0.0, 0.5);
+/// width.add_key(1.0, 0.05);
+/// let mut size = Gradient::new();
+/// size.add_key(0.0, Vec2::splat(0.1));
+/// size.add_key(1.0, Vec2::splat(10.));
+/// let asset = EffectAsset {
+///     capacity: 1,
+///     spawner: Spawner::once(1.0.into(), true),
+///     ..Default::default()
+/// }
+/// .render(ShockwaveModifier { width, softness: 0.02 })
+/// .render(SizeOverLifetimeModifier { gradient: size });
+/// ```
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ShockwaveModifier {
+    /// The ring width over the particle lifetime, as a fraction of the ring radius in \[0:1\].
+    pub width: Gradient<f32>,
+    /// The width of the smooth transition at the ring edges, as a fraction of the ring radius.
+    pub softness: f32,
+}
+
+impl RenderModifier for ShockwaveModifier {
+    fn apply(&self, render_layout: &mut RenderLayout) {
+        render_layout.shockwave = Some(self.clone());
+    }
+}
+
+/// A modifier orienting each particle along the normal of the spawn shape.
+///
+/// The particle normal is stored when the particle spawns, from the shape it spawns on: the
+/// axis of a [`PositionCircleModifier`], the surface normal of a [`PositionSphereModifier`],
+/// or the radial direction of a [`PositionSpiralModifier`]. Other shapes store the +Z axis.
+/// With this modifier, the particle geometry faces its normal instead of facing the +Z axis,
+/// which allows for example spawning ground-aligned dust rings with a circle on the XZ plane.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OrientAlongNormalModifier;
+
+impl RenderModifier for OrientAlongNormalModifier {
+    fn apply(&self, render_layout: &mut RenderLayout) {
+        render_layout.orient_along_normal = true;
+    }
+}
+
+/// A modifier offsetting each particle toward or away from the camera.
+///
+/// The particle vertices are moved along the view axis by `bias` units in view space, after
+/// any other vertex modifier. A positive bias pushes the particles toward the camera, which
+/// prevents particles spawned on a surface, like dust on the ground, from z-fighting with
+/// that surface. The visible size of the particles is unchanged with an orthographic camera,
+/// and changes slightly with a perspective one.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DepthBiasModifier {
+    /// The offset toward the camera, in view space units. Negative values push the particles
+    /// away from the camera.
+    pub bias: f32,
+}
+
+impl RenderModifier for DepthBiasModifier {
+    fn apply(&self, render_layout: &mut RenderLayout) {
+        render_layout.depth_bias = self.bias;
+    }
+}
+
+/// A modifier displacing the vertices of each particle with some custom WGSL code.
+///
+/// The code is injected as is into the vertex shader of the render pass, once the particle
+/// size has been applied and before the vertex is projected. It can read and write the
+/// `vpos: vec3<f32>` variable, the offset of the current vertex relative to the particle
+/// position, and read the following values:
+/// - `particle.pos`, `particle.vel`, `particle.age`, `particle.lifetime`,
+///   `particle.spawn_index`: the attributes of the particle being rendered;
+/// - `particle.seed: u32`: a random seed sampled when the particle spawned, which
+///   `particle_hash(particle.seed, salt)` hashes into a random `u32` stable over the particle
+///   lifetime;
+/// - `vertex_position: vec3<f32>`: the unscaled position of the vertex in the particle mesh;
+/// - `sim_params.time`, `sim_params.dt`: the simulation time and timestep, in seconds.
+///
+/// Multiple modifiers can be added to the same effect; their code is executed in the order
+/// the modifiers were added.
+///
+/// # Example
+///
+/// ```
+/// # use bevy_hanabi::*;
+/// // Sway the top vertices of each quad sideways, like leaves in the wind.
+/// let modifier = VertexDeformModifier {
+///     code: "vpos.x = vpos.x + max(vertex_position.y, 0.) * 0.1 * sin(sim_params.time * 3.);"
+///         .to_string(),
+/// };
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct VertexDeformModifier {
+    /// The WGSL code to inject into the vertex shader.
+    pub code: String,
+}
+
+impl RenderModifier for VertexDeformModifier {
+    fn apply(&self, render_layout: &mut RenderLayout) {
+        render_layout.vertex_deform_code += &self.code;
+        render_layout.vertex_deform_code += "\n";
+    }
+}
+
+/// A modifier fading each particle toward a fog color based on its distance to the camera.
+///
+/// The fog factor increases linearly from zero at `start` to `color.w` at `end`, where
+/// distances are measured in world units from the camera rendering the particle. Only the
+/// RGB color of the particle is affected; its alpha is left untouched.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DistanceFogModifier {
+    /// The fog color. The alpha component is the maximum fog intensity, in \[0:1\].
+    pub color: Vec4,
+    /// The distance from the camera where the fog starts.
+    pub start: f32,
+    /// The distance from the camera where the fog reaches its maximum intensity.
+    pub end: f32,
+}
+
+impl RenderModifier for DistanceFogModifier {
+    fn apply(&self, render_layout: &mut RenderLayout) {
+        render_layout.distance_fog = Some(*self);
+    }
+}
+
+/// A modifier to apply a constant acceleration to all particles each frame.
+///
+/// This is typically used to apply some kind of gravity.
+#[derive(Default, Clone, Copy)]
+pub struct AccelModifier {
+    /// The constant acceleration to apply to all particles in the effect each frame.
+    pub accel: Vec3,
+}
+
+impl UpdateModifier for AccelModifier {
+    fn apply(&self, layout: &mut UpdateLayout) {
+        layout.accel = self.accel;
+    }
+}
+
+/// A modifier applying an upward acceleration to the particles, proportional to their
+/// temperature, which cools down over their lifetime.
+///
+/// Each particle spawns with an initial `temperature`, which decays exponentially toward the
+/// `ambient_temperature` at `cooling_rate`. The particle is accelerated along `up` by
+/// `strength` times the difference between its temperature and the ambient one, so hot
+/// particles rise fast then stall as they cool, like smoke. Particles colder than the ambient
+/// temperature sink.
+///
+/// The temperature is not stored with the particles, but derived from their age. When
+/// `temperature` is a range, the initial temperature of each particle is a deterministic
+/// pseudo-random value derived from its index in the particle buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BuoyancyModifier {
+    /// The upward direction, opposite to gravity.
+    pub up: Vec3,
+    /// The acceleration applied per unit of temperature above the ambient one.
+    pub strength: f32,
+    /// The temperature of the particles on spawn.
+    pub temperature: Value<f32>,
+    /// The ambient temperature the particles cool down toward.
+    pub ambient_temperature: f32,
+    /// The rate at which the particles cool down, in inverse seconds. The temperature
+    /// difference with the ambient is divided by `e` every `1 / cooling_rate` seconds.
+    pub cooling_rate: f32,
+}
+
+impl Default for BuoyancyModifier {
+    fn default() -> Self {
+        Self {
+            up: Vec3::Y,
+            strength: 1.,
+            temperature: Value::Single(1.),
+            ambient_temperature: 0.,
+            cooling_rate: 1.,
+        }
+    }
+}
+
+impl BuoyancyModifier {
+    /// Generate the code declaring the buoyancy acceleration `buoyancy_accel` of the particle.
+    pub(crate) fn to_shader_code(self) -> String {
+        let initial_temperature = self.temperature.to_wgsl_sampled(
+            "to_float01(pcg_hash(index * 0x9e3779b9u))",
+            "to_float01(pcg_hash(index * 0x85ebca6bu))",
+        );
+        format!(
+            r##"
+    // Buoyancy from the temperature of the particle, cooling down with its age
+    let initial_temperature = {};
+    let ambient_temperature = {};
+    let temperature = ambient_temperature + (initial_temperature - ambient_temperature) * exp({} * vAge);
+    let buoyancy_accel = {} * ({} * (temperature - ambient_temperature));
+"##,
+            initial_temperature,
+            self.ambient_temperature.to_wgsl_string(),
+            (-self.cooling_rate).to_wgsl_string(),
+            self.up.normalize_or_zero().to_wgsl_string(),
+            self.strength.to_wgsl_string(),
+        )
+    }
+}
+
+impl UpdateModifier for BuoyancyModifier {
+    fn apply(&self, layout: &mut UpdateLayout) {
+        layout.buoyancy = Some(*self);
+    }
+}
+
+/// A modifier damping the velocity of the particles, to simulate air resistance.
+///
+/// The velocity is multiplied by `exp(-drag * dt)` each frame, after the particles moved, so
+/// it decays exponentially and is divided by `e` every `1 / drag` seconds independently of
+/// the frame rate. When `drag` is random, the drag of each particle is a deterministic
+/// pseudo-random value derived from its index in the particle buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DragModifier {
+    /// The drag coefficient, in inverse seconds.
+    pub drag: Value<f32>,
+}
+
+impl Default for DragModifier {
+    fn default() -> Self {
+        Self {
+            drag: Value::Single(1.),
+        }
+    }
+}
+
+impl DragModifier {
+    /// Create a modifier with a constant drag coefficient.
+    pub fn new(drag: f32) -> Self {
+        Self {
+            drag: Value::Single(drag),
+        }
+    }
+
+    /// Generate the code damping the velocity `vVel` of the particle.
+    pub(crate) fn to_shader_code(self) -> String {
+        let drag = self.drag.to_wgsl_sampled(
+            "to_float01(pcg_hash(index * 0x27d4eb2du))",
+            "to_float01(pcg_hash(index * 0x165667b1u))",
+        );
+        format!(
+            "    // Drag\n    let drag = {};\n    vVel = vVel * exp(-drag * spawner.dt);\n",
+            drag
+        )
+    }
+}
+
+impl UpdateModifier for DragModifier {
+    fn apply(&self, layout: &mut UpdateLayout) {
+        layout.drag = Some(*self);
+    }
+}
+
+/// A modifier swirling the particles around an axis, like a tornado or a whirlpool.
+///
+/// The particles are accelerated around the axis passing through `origin` along `axis` by
+/// `strength`, counterclockwise around `axis` for positive values, and toward the axis by
+/// `pull`. Both accelerations fade out linearly with the distance to the axis, reaching zero
+/// at `radius`, unless `radius` is zero, in which case they apply everywhere.
+///
+/// The acceleration is applied to the velocity after the motion integration, like the
+/// [`DragModifier`], so it is independent of the [`Integrator`]. Combine with a drag to keep
+/// the particles from spinning ever faster.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VortexModifier {
+    /// A point of the axis, in simulation space.
+    pub origin: Vec3,
+    /// The direction of the axis.
+    pub axis: Vec3,
+    /// The acceleration around the axis.
+    pub strength: f32,
+    /// The acceleration toward the axis. Negative values push the particles away.
+    pub pull: f32,
+    /// The distance to the axis where the accelerations fade out, or zero for no falloff.
+    pub radius: f32,
+}
+
+impl Default for VortexModifier {
+    fn default() -> Self {
+        Self {
+            origin: Vec3::ZERO,
+            axis: Vec3::Y,
+            strength: 1.,
+            pull: 0.,
+            radius: 0.,
+        }
+    }
+}
+
+impl VortexModifier {
+    /// Generate the code accelerating the velocity `vVel` of the particle around the axis.
+    pub(crate) fn to_shader_code(self) -> String {
+        let falloff = if self.radius > 0. {
+            format!(
+                "clamp(1. - vortex_dist / {}, 0., 1.)",
+                self.radius.to_wgsl_string()
+            )
+        } else {
+            "1.".to_string()
+        };
+        format!(
+            r##"
+    // Vortex
+    let vortex_axis = {};
+    let vortex_offset = vPos - {};
+    let vortex_radial = vortex_offset - vortex_axis * dot(vortex_offset, vortex_axis);
+    let vortex_dist = length(vortex_radial);
+    let vortex_dir = vortex_radial / max(vortex_dist, 0.0001);
+    let vortex_accel = (cross(vortex_axis, vortex_dir) * {} - vortex_dir * {}) * {};
+    vVel = vVel + vortex_accel * spawner.dt;
+"##,
+            self.axis.normalize_or_zero().to_wgsl_string(),
+            self.origin.to_wgsl_string(),
+            self.strength.to_wgsl_string(),
+            self.pull.to_wgsl_string(),
+            falloff,
+        )
+    }
+}
+
+impl UpdateModifier for VortexModifier {
+    fn apply(&self, layout: &mut UpdateLayout) {
+        layout.vortex = Some(*self);
+    }
+}
+
+/// A modifier pushing the particles along a wind blowing in gusts, like leaves or snow
+/// drifting in the air.
+///
+/// The particles are accelerated along `direction` by `strength`, plus gusts adding up to
+/// `gust_amplitude` with a smooth noise over time, varying about `gust_frequency` times per
+/// second. Gusts sweep through the effect along the wind direction, reaching particles
+/// `gust_scale` units apart with a phase shift of one noise period, unless `gust_scale` is
+/// zero, in which case all the particles feel the same gust. On top of that, each particle
+/// is shaken in all directions by a noise of magnitude `turbulence`, at the same frequency.
+///
+/// When the simulation time loops every [`EffectSimulationSettings::loop_period`] seconds,
+/// the noise repeats over the loop period, so the gusts and turbulence loop seamlessly. To
+/// that end, the frequency is adjusted to the nearest whole number of noise periods per loop.
+///
+/// The acceleration is applied to the velocity after the motion integration, like the
+/// [`VortexModifier`]. Combine with a [`DragModifier`] to keep the particles from speeding up
+/// forever, so they drift at the speed of the wind.
+///
+/// [`EffectSimulationSettings::loop_period`]: crate::EffectSimulationSettings::loop_period
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindModifier {
+    /// The direction the wind blows toward.
+    pub direction: Vec3,
+    /// The base acceleration along the wind direction.
+    pub strength: f32,
+    /// The maximum acceleration added by the gusts along the wind direction.
+    pub gust_amplitude: f32,
+    /// The number of gust noise periods per second.
+    pub gust_frequency: f32,
+    /// The distance along the wind direction between particles feeling gusts one noise period
+    /// apart, or zero for uniform gusts.
+    pub gust_scale: f32,
+    /// The maximum acceleration of the noise shaking each particle in all directions.
+    pub turbulence: f32,
+}
+
+impl Default for WindModifier {
+    fn default() -> Self {
+        Self {
+            direction: Vec3::X,
+            strength: 1.,
+            gust_amplitude: 0.,
+            gust_frequency: 0.5,
+            gust_scale: 0.,
+            turbulence: 0.,
+        }
+    }
+}
+
+impl WindModifier {
+    /// Create a modifier blowing a constant wind along a direction.
+    pub fn new(direction: Vec3, strength: f32) -> Self {
+        Self {
+            direction,
+            strength,
+            ..Default::default()
+        }
+    }
+
+    /// Generate the code accelerating the velocity `vVel` of the particle along the wind.
+    pub(crate) fn to_shader_code(self) -> String {
+        let direction = self.direction.normalize_or_zero();
+        let mut s = format!(
+            "\n    // Wind\n    let wind_dir = {};\n    var wind_accel = wind_dir * {};\n",
+            direction.to_wgsl_string(),
+            self.strength.to_wgsl_string()
+        );
+        if self.gust_amplitude != 0. || self.turbulence != 0. {
+            let frequency = self.gust_frequency.to_wgsl_string();
+            s += &format!(
+                "    let wind_t = sim_params.time * {};\n    let wind_period = sim_params.loop_period * {};\n",
+                frequency, frequency
+            );
+        }
+        if self.gust_amplitude != 0. {
+            let phase = if self.gust_scale > 0. {
+                format!(
+                    "wind_t - dot(vPos, wind_dir) / {}",
+                    self.gust_scale.to_wgsl_string()
+                )
+            } else {
+                "wind_t".to_string()
+            };
+            s += &format!(
+                "    wind_accel = wind_accel + wind_dir * noise1({}, wind_period, 0u) * {};\n",
+                phase,
+                self.gust_amplitude.to_wgsl_string()
+            );
+        }
+        if self.turbulence != 0. {
+            // Offset the noise of each particle by its slot, so they don't all shake together
+            s += &format!(
+                r##"    let wind_p = wind_t + f32(index & 0xffffu) * 0.618034;
+    let wind_noise = vec3<f32>(
+        noise1(wind_p, wind_period, 1u),
+        noise1(wind_p, wind_period, 2u),
+        noise1(wind_p, wind_period, 3u)
+    );
+    wind_accel = wind_accel + (wind_noise * 2. - 1.) * {};
+"##,
+                self.turbulence.to_wgsl_string()
+            );
+        }
+        s + "    vVel = vVel + wind_accel * spawner.dt;\n"
+    }
+}
+
+impl UpdateModifier for WindModifier {
+    fn apply(&self, layout: &mut UpdateLayout) {
+        layout.wind = Some(*self);
+    }
+}
+
+/// A modifier pushing the particles inside an oriented box toward a target velocity, like the
+/// air flow through a vent, a chimney or a wind tunnel.
+///
+/// The box is centered on `center`, rotated by `rotation`, and extends by `half_size` along
+/// each of its local axes, in simulation space. The velocity of the particles inside the box
+/// converges exponentially toward `velocity` at a rate of `strength` per second, so that it
+/// is reached in about `3 / strength` seconds, whatever the frame rate. Particles outside the
+/// box are not affected.
+///
+/// Unlike the point-based force fields of the [`ForceFieldModifier`], the flow is uniform in
+/// the box, so it can direct the particles along a path made of several effects, or of boxes
+/// placed along a conveyor. The flow is applied to the velocity after the motion integration,
+/// like the [`DragModifier`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlowZoneModifier {
+    /// The center of the box, in simulation space.
+    pub center: Vec3,
+    /// The half size of the box along each of its local axes.
+    pub half_size: Vec3,
+    /// The rotation of the box.
+    pub rotation: Quat,
+    /// The target velocity of the particles in the box, in simulation space.
+    pub velocity: Vec3,
+    /// The rate at which the velocity of the particles converges toward the target velocity,
+    /// per second.
+    pub strength: f32,
+}
+
+impl Default for FlowZoneModifier {
+    fn default() -> Self {
+        Self {
+            center: Vec3::ZERO,
+            half_size: Vec3::ONE,
+            rotation: Quat::IDENTITY,
+            velocity: Vec3::Y,
+            strength: 1.,
+        }
+    }
+}
+
+impl FlowZoneModifier {
+    /// Create a modifier pushing the particles in an axis-aligned box toward a velocity.
+    pub fn new(center: Vec3, half_size: Vec3, velocity: Vec3) -> Self {
+        Self {
+            center,
+            half_size,
+            velocity,
+            ..Default::default()
+        }
+    }
+
+    /// Generate the code pushing the velocity `vVel` of the particle toward the target.
+    pub(crate) fn to_shader_code(self) -> String {
+        let rotation = self.rotation.normalize();
+        format!(
+            r##"
+    // Flow zone
+    let flow_offset = vPos - {};
+    let flow_local = vec3<f32>(dot(flow_offset, {}), dot(flow_offset, {}), dot(flow_offset, {}));
+    if (all(abs(flow_local) <= {})) {{
+        vVel = mix({}, vVel, vec3<f32>(exp(-{} * spawner.dt)));
+    }}
+"##,
+            self.center.to_wgsl_string(),
+            (rotation * Vec3::X).to_wgsl_string(),
+            (rotation * Vec3::Y).to_wgsl_string(),
+            (rotation * Vec3::Z).to_wgsl_string(),
+            self.half_size.abs().to_wgsl_string(),
+            self.velocity.to_wgsl_string(),
+            self.strength.max(0.).to_wgsl_string(),
+        )
+    }
+}
+
+impl UpdateModifier for FlowZoneModifier {
+    fn apply(&self, layout: &mut UpdateLayout) {
+        layout.flow_zone = Some(*self);
+    }
+}
+
+/// The numerical scheme used to integrate the motion of the particles each frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Integrator {
+    /// Explicit (forward) Euler: the position is advanced with the velocity at the start of
+    /// the frame, then the velocity is advanced with the acceleration. This is the cheapest
+    /// scheme, but it gains energy over time, and orbits spiral outward.
+    ExplicitEuler,
+    /// Semi-implicit (symplectic) Euler: the velocity is advanced first, then the position is
+    /// advanced with the new velocity. This is the default scheme.
+    #[default]
+    SemiImplicitEuler,
+    /// Velocity Verlet: the position is advanced with the velocity and acceleration at the
+    /// start of the frame, then the velocity is advanced with the average of the accelerations
+    /// at the start and end of the frame. This evaluates the force field twice per frame, but
+    /// keeps orbits stable under strong force fields.
+    VelocityVerlet,
+}
+
+/// A modifier selecting the [`Integrator`] used to update the particle motion each frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IntegratorModifier {
+    /// The integration scheme.
+    pub integrator: Integrator,
+}
+
+impl UpdateModifier for IntegratorModifier {
+    fn apply(&self, layout: &mut UpdateLayout) {
+        layout.integrator = self.integrator;
+    }
+}
+
+/// Behavior of the particles reaching the boundary of a [`SimulationBoundsModifier`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BoundaryCondition {
+    /// Kill the particles leaving the bounds.
+    #[default]
+    Kill,
+    /// Stop the particles at the boundary, absorbing the velocity component pointing outward.
+    /// The particles can still slide along the boundary.
+    Clamp,
+    /// Bounce the particles back into the bounds. The velocity component normal to the
+    /// boundary is reversed and multiplied by `restitution`, between 0 and 1.
+    Reflect {
+        /// Fraction of the normal velocity kept after a bounce.
+        restitution: f32,
+    },
+    /// Teleport the particles leaving the bounds to the opposite side, as if the bounds were
+    /// tiled infinitely. This suits ambient effects like snow or dust around the camera.
+    Wrap,
+}
+
+/// A modifier keeping the particles inside an axis-aligned box.
+///
+/// The box is centered on `center` relative to the effect origin, so it follows the effect
+/// when it moves. The boundary condition is applied each frame after the particle motion is
+/// integrated, so particles never render outside the box, except for the size of their
+/// geometry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimulationBoundsModifier {
+    /// The center of the box, relative to the effect origin.
+    pub center: Vec3,
+    /// The half size of the box along each axis.
+    pub half_size: Vec3,
+    /// The behavior of the particles reaching the boundary.
+    pub boundary: BoundaryCondition,
+}
+
+impl Default for SimulationBoundsModifier {
+    fn default() -> Self {
+        Self {
+            center: Vec3::ZERO,
+            half_size: Vec3::ONE,
+            boundary: BoundaryCondition::default(),
+        }
+    }
+}
+
+impl SimulationBoundsModifier {
+    /// Generate the code applying the boundary condition to the particle.
+    pub(crate) fn to_shader_code(self) -> String {
+        let mut s = format!(
+            r##"
+    // Simulation bounds
+    let bounds_center = spawner.origin + {};
+    let bounds_min = bounds_center - {1};
+    let bounds_max = bounds_center + {1};
+"##,
+            self.center.to_wgsl_string(),
+            self.half_size.abs().to_wgsl_string()
+        );
+        s += &match self.boundary {
+            BoundaryCondition::Kill => {
+                r##"    if (any(vPos < bounds_min) || any(vPos > bounds_max)) {
+        particle_buffer.particles[index].age = vLifetime;
+        return;
+    }
+"##
+                .to_string()
+            }
+            BoundaryCondition::Clamp => {
+                r##"    let bounds_pos = clamp(vPos, bounds_min, bounds_max);
+    vVel = select(vVel, vec3<f32>(0.), bounds_pos != vPos);
+    vPos = bounds_pos;
+"##
+                .to_string()
+            }
+            BoundaryCondition::Reflect { restitution } => format!(
+                r##"    let bounds_below = vPos < bounds_min;
+    let bounds_above = vPos > bounds_max;
+    vPos = select(vPos, 2. * bounds_min - vPos, bounds_below);
+    vPos = select(vPos, 2. * bounds_max - vPos, bounds_above);
+    vPos = clamp(vPos, bounds_min, bounds_max);
+    vVel = select(vVel, abs(vVel) * {0}, bounds_below);
+    vVel = select(vVel, -abs(vVel) * {0}, bounds_above);
+"##,
+                restitution.clamp(0., 1.).to_wgsl_string()
+            ),
+            BoundaryCondition::Wrap => {
+                r##"    let bounds_size = max(bounds_max - bounds_min, vec3<f32>(0.0001));
+    vPos = vPos - bounds_size * floor((vPos - bounds_min) / bounds_size);
+"##
+                .to_string()
+            }
+        };
+        s
+    }
+}
+
+impl UpdateModifier for SimulationBoundsModifier {
+    fn apply(&self, layout: &mut UpdateLayout) {
+        layout.bounds = Some(*self);
+    }
+}
+
+/// A modifier colliding the particles with an infinite plane, like a ground.
+///
+/// The plane passes through `point` with the given `normal`, in simulation space. Particles
+/// crossing the plane toward its back side during the frame are moved back to its front side
+/// and bounce: the velocity component along the normal is reversed and multiplied by
+/// `restitution`, and the tangential component is multiplied by `1 - friction`, both between
+/// 0 and 1. With `kill_on_contact`, the particles are killed instead, for example for rain
+/// drops hitting the ground.
+///
+/// The collision is resolved each frame after the particle motion is integrated, before the
+/// [`SimulationBoundsModifier`], so fast particles can't tunnel through the plane.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaneCollisionModifier {
+    /// A point of the plane, in simulation space.
+    pub point: Vec3,
+    /// The normal of the plane, pointing toward the side the particles stay on.
+    pub normal: Vec3,
+    /// Fraction of the normal velocity kept after a bounce.
+    pub restitution: f32,
+    /// Fraction of the tangential velocity lost on each bounce.
+    pub friction: f32,
+    /// Kill the particles hitting the plane instead of bouncing them.
+    pub kill_on_contact: bool,
+}
+
+impl Default for PlaneCollisionModifier {
+    fn default() -> Self {
+        Self {
+            point: Vec3::ZERO,
+            normal: Vec3::Y,
+            restitution: 0.5,
+            friction: 0.,
+            kill_on_contact: false,
+        }
+    }
+}
+
+impl PlaneCollisionModifier {
+    /// Create a modifier bouncing the particles on a horizontal ground at the given height.
+    pub fn ground(height: f32) -> Self {
+        Self {
+            point: Vec3::new(0., height, 0.),
+            ..Default::default()
+        }
+    }
+
+    /// Generate the code colliding the particle with the plane.
+    pub(crate) fn to_shader_code(self) -> String {
+        let response = if self.kill_on_contact {
+            r##"        particle_buffer.particles[index].age = vLifetime;
+        return;
+"##
+            .to_string()
+        } else {
+            let restitution = self.restitution.clamp(0., 1.);
+            format!(
+                r##"        vPos = vPos - plane_normal * plane_dist * (1. + {0});
+        let plane_vn = dot(vVel, plane_normal);
+        if (plane_vn < 0.) {{
+            vVel = (vVel - plane_normal * plane_vn) * {1} - plane_normal * plane_vn * {0};
+        }}
+"##,
+                restitution.to_wgsl_string(),
+                (1. - self.friction.clamp(0., 1.)).to_wgsl_string()
+            )
+        };
+        format!(
+            r##"
+    // Plane collision
+    let plane_normal = {};
+    let plane_dist = dot(vPos - {}, plane_normal);
+    if (plane_dist < 0.) {{
+{}    }}
+"##,
+            self.normal.normalize_or_zero().to_wgsl_string(),
+            self.point.to_wgsl_string(),
+            response
+        )
+    }
+}
+
+impl UpdateModifier for PlaneCollisionModifier {
+    fn apply(&self, layout: &mut UpdateLayout) {
+        layout.plane_collision = Some(*self);
+    }
+}
+
+/// Parameters for the components making the force field.
+#[derive(Clone, Copy)]
+pub struct ForceFieldParam {
+    /// Position of the source of the force field.
+    pub position: Vec3,
+    /// Maximum radius of the sphere of influence, outside of which
+    /// the force field is null.
+    pub max_radius: f32,
+    /// Minimum radius of the sphere of influence, inside of which
+    /// the force field is null, avoiding the singularity at the source position.
+    pub min_radius: f32,
+    /// The intensity of the force is proportional to mass. Note that the particles_update.wgsl shader will
+    /// ignore all subsequent force field components after it encounters a component with a mass of zero.
+    /// To change the force from an attracting one to a repulsive one, simply set the mass to a negative value.
+    pub mass: f32,
+    /// The force field is proportional to `1 / distance^force_exponent`.
+    pub force_exponent: f32,
+    /// If set to true, the particles that enter within the `min_radius` will conform to a sphere around the
+    /// source position, appearing like a recharging effect.
+    pub conform_to_sphere: bool,
+}
+
+impl Default for ForceFieldParam {
+    fn default() -> Self {
+        // defaults to no force field (a mass of 0)
+        ForceFieldParam {
+            position: Vec3::new(0., 0., 0.),
+            min_radius: 0.1,
+            max_radius: 0.0,
+            mass: 0.,
+            force_exponent: 0.0,
+            conform_to_sphere: false,
+        }
+    }
+}
+
+/// A modifier to apply a force field to all particles each frame. The force field is made up of
+/// point sources, also called 'components'. The maximum number of components is set with [`FFNUM`].
+#[derive(Default, Clone, Copy)]
+pub struct ForceFieldModifier {
+    /// Array of force field components.
+    pub force_field: [ForceFieldParam; FFNUM],
+}
+
+impl ForceFieldModifier {
+    /// Instantiate a ForceFieldModifier.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of sources exceeds [`FFNUM`].
+    pub fn new<T>(point_attractors: T) -> Self
+    where
+        T: IntoIterator<Item = ForceFieldParam>,
+    {
+        let mut force_field = [ForceFieldParam::default(); FFNUM];
+
+        for (i, p_attractor) in point_attractors.into_iter().enumerate() {
+            if i > FFNUM {
+                panic!("Too many point attractors");
+            }
+            force_field[i] = p_attractor;
+        }
+
+        Self { force_field }
+    }
+
+    /// Perhaps will be deleted in the future.
+    // Delete me?
+    pub fn add_or_replace(&mut self, point_attractor: ForceFieldParam, index: usize) {
         self.force_field[index] = point_attractor;
     }
 }
@@ -306,3 +3728,466 @@ impl UpdateModifier for ForceFieldModifier {
         layout.force_field = self.force_field;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::render_resource::{Extent3d, TextureDimension};
+
+    #[test]
+    fn mask_from_coverage() {
+        // 2x2 mask with only the top-right pixel covered
+        let mask = PositionMaskModifier::from_coverage(2, 2, &[0, 255, 0, 0], Vec2::ONE, 4);
+        assert_eq!(mask.cell_size, Vec2::splat(0.5));
+        assert_eq!(mask.points.len(), 4);
+        for p in &mask.points {
+            assert_eq!(*p, Vec2::new(0.25, 0.25));
+        }
+
+        // Points are distributed proportionally to the coverage
+        let mask = PositionMaskModifier::from_coverage(2, 1, &[64, 192], Vec2::new(2., 1.), 4);
+        let left = mask.points.iter().filter(|p| p.x < 0.).count();
+        assert_eq!(left, 1);
+
+        // Empty mask
+        let mask = PositionMaskModifier::from_coverage(2, 2, &[0; 4], Vec2::ONE, 4);
+        assert!(mask.points.is_empty());
+        assert_eq!(mask.init_data(), vec![0.]);
+
+        // Mask image thresholded on its alpha channel
+        let image = Image::new(
+            Extent3d {
+                width: 2,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            vec![255, 255, 255, 100, 0, 0, 0, 200],
+            TextureFormat::Rgba8Unorm,
+        );
+        let mask =
+            PositionMaskModifier::from_image(&image, MaskChannel::Alpha, 150, Vec2::new(2., 1.), 4)
+                .unwrap();
+        assert!(mask.points.iter().all(|p| p.x > 0.));
+        let mask =
+            PositionMaskModifier::from_image(&image, MaskChannel::Red, 150, Vec2::new(2., 1.), 4)
+                .unwrap();
+        assert!(mask.points.iter().all(|p| p.x < 0.));
+
+        // Sample points are uploaded as init data
+        let mask = PositionMaskModifier {
+            points: vec![Vec2::new(1., 2.), Vec2::new(-3., 4.)],
+            ..Default::default()
+        };
+        let mut init_layout = InitLayout::default();
+        mask.apply(&mut init_layout);
+        assert_eq!(
+            init_layout.init_data,
+            Some(InitData::Values(vec![2., 1., 2., -3., 4.]))
+        );
+    }
+
+    #[test]
+    fn cone_sampling_code() {
+        let mut init_layout = InitLayout::default();
+        let cone = PositionConeModifier {
+            dimension: ShapeDimension::Volume,
+            ..Default::default()
+        };
+        cone.apply(&mut init_layout);
+        assert!(init_layout
+            .position_code
+            .contains("pow(mix(rb * rb * rb, rt * rt * rt"));
+        assert!(init_layout
+            .position_code
+            .contains("let rho = sqrt(rand());"));
+
+        // Cylinder
+        let cone = PositionConeModifier {
+            base_radius: 1.,
+            top_radius: 1.,
+            ..Default::default()
+        };
+        cone.apply(&mut init_layout);
+        assert!(init_layout.position_code.contains("let rh = rb;"));
+        assert!(init_layout.position_code.contains("let rho = 1.;"));
+    }
+
+    #[test]
+    fn mesh_triangles() {
+        let mesh = Mesh::from(shape::Quad::new(Vec2::new(2., 1.)));
+        let data = PositionMeshModifier::init_data(&mesh).unwrap();
+        assert_eq!(data.len(), 1 + 2 + 2 * 18);
+        assert_eq!(data[0], 2.);
+
+        // Both halves of the quad have the same area
+        assert_eq!(&data[1..3], &[0.5, 1.]);
+
+        // The first vertex normal of the first triangle
+        assert_eq!(&data[3 + 9..3 + 12], &[0., 0., 1.]);
+
+        // Same data from 16-bit indices
+        let mut mesh16 = mesh.clone();
+        if let Some(Indices::U32(indices)) = mesh.indices() {
+            mesh16.set_indices(Some(Indices::U16(
+                indices.iter().map(|&i| i as u16).collect(),
+            )));
+        }
+        assert!(matches!(mesh16.indices(), Some(Indices::U16(_))));
+        assert_eq!(PositionMeshModifier::init_data(&mesh16).unwrap(), data);
+
+        let modifier = PositionMeshModifier::default();
+        let mut init_layout = InitLayout::default();
+        modifier.apply(&mut init_layout);
+        assert_eq!(
+            init_layout.init_data,
+            Some(InitData::Mesh(Handle::default()))
+        );
+        assert!(init_layout
+            .position_code
+            .contains("let tri_count = u32(init_data.values[0]);"));
+
+        // Only triangle lists are supported
+        let mesh = Mesh::new(PrimitiveTopology::LineList);
+        assert!(PositionMeshModifier::init_data(&mesh).is_none());
+    }
+
+    #[test]
+    fn spline_sample() {
+        // A straight Catmull-Rom spline with uneven control points is sampled evenly
+        let spline = PositionSplineModifier {
+            points: vec![Vec3::ZERO, Vec3::X, Vec3::X * 4.],
+            ..Default::default()
+        };
+        let samples = spline.sample(5);
+        assert_eq!(samples.len(), 5);
+        for (k, p) in samples.iter().enumerate() {
+            assert!((p.x - k as f32).abs() < 0.05);
+            assert!(p.y.abs() < 1e-5 && p.z.abs() < 1e-5);
+        }
+
+        // A single Bézier curve starts and ends on its end points
+        let spline = PositionSplineModifier {
+            points: vec![Vec3::ZERO, Vec3::Y, Vec3::new(1., 1., 0.), Vec3::X],
+            curve: SplineCurve::Bezier,
+            ..Default::default()
+        };
+        let samples = spline.sample(3);
+        assert!(samples[0].distance(Vec3::ZERO) < 1e-5);
+        assert!(samples[2].distance(Vec3::X) < 1e-5);
+        assert!((samples[1].x - 0.5).abs() < 1e-3);
+
+        // Not enough control points
+        let spline = PositionSplineModifier {
+            points: vec![Vec3::ZERO],
+            ..Default::default()
+        };
+        assert!(spline.sample(4).is_empty());
+    }
+
+    #[test]
+    fn sphere_polar_range() {
+        let mut init_layout = InitLayout::default();
+        PositionSphereModifier::default().apply(&mut init_layout);
+        assert!(init_layout
+            .position_code
+            .contains("var z = mix(-1., 1., u * 0.5 + 0.5);"));
+
+        // Upper hemisphere
+        PositionSphereModifier {
+            axis: Vec3::Y,
+            polar_max: std::f32::consts::FRAC_PI_2,
+            ..Default::default()
+        }
+        .apply(&mut init_layout);
+        let z_min = std::f32::consts::FRAC_PI_2.cos();
+        assert!(init_layout.position_code.contains(&format!(
+            "var z = mix({}, 1., u * 0.5 + 0.5);",
+            z_min.to_wgsl_string()
+        )));
+        assert!(init_layout
+            .position_code
+            .contains(&format!("let axis = {};", Vec3::Y.to_wgsl_string())));
+    }
+
+    #[test]
+    fn texture_channel_mapping() {
+        assert_eq!(
+            TextureChannelMapping::default().to_shader_code(),
+            "vec4<f32>(1., 1., 1., texel.r)"
+        );
+        assert_eq!(
+            TextureChannelMapping::RGBA.to_shader_code(),
+            "vec4<f32>(texel.r, texel.g, texel.b, texel.a)"
+        );
+
+        let mut render_layout = RenderLayout::default();
+        ParticleTextureModifier {
+            mapping: TextureChannelMapping::alpha_from(TextureChannel::Green),
+            ..Default::default()
+        }
+        .apply(&mut render_layout);
+        assert_eq!(
+            render_layout.particle_texture_mapping.alpha,
+            TextureChannel::Green
+        );
+    }
+
+    #[test]
+    fn cube_face_weights() {
+        let cube = PositionCubeModifier {
+            half_size: Vec3::new(1., 2., 1.),
+            ..Default::default()
+        };
+        // The +-X and +-Z faces are twice as large as the +-Y ones
+        assert_eq!(cube.face_cdf(), [0.2, 0.4, 0.5, 0.6, 0.8, 1.]);
+
+        // Top face only
+        let cube = PositionCubeModifier {
+            face_weights: [0., 0., 0., 1., 0., 0.],
+            ..cube
+        };
+        assert_eq!(cube.face_cdf(), [0., 0., 0., 1., 1., 1.]);
+
+        // No weight falls back to uniform faces
+        let cube = PositionCubeModifier {
+            face_weights: [0.; 6],
+            ..cube
+        };
+        assert_eq!(cube.face_cdf()[5], 1.);
+        assert!(cube.face_cdf()[0] > 0.);
+    }
+
+    #[test]
+    fn torus_sampling_code() {
+        let mut init_layout = InitLayout::default();
+        PositionTorusModifier::default().apply(&mut init_layout);
+        assert!(init_layout.position_code.contains("rho = minor;"));
+        assert!(init_layout.position_code.contains("let major = 1.;"));
+
+        PositionTorusModifier {
+            dimension: ShapeDimension::Volume,
+            ..Default::default()
+        }
+        .apply(&mut init_layout);
+        assert!(init_layout
+            .position_code
+            .contains("rho = sqrt(rand()) * minor;"));
+    }
+
+    #[test]
+    fn ellipse_basis() {
+        let mut init_layout = InitLayout::default();
+        PositionEllipseModifier {
+            axis: Vec3::Y,
+            tangent: Vec3::new(1., 1., 0.),
+            radii: Vec2::new(2., 0.5),
+            ..Default::default()
+        }
+        .apply(&mut init_layout);
+        // The tangent is projected onto the ellipse plane
+        assert!(init_layout
+            .position_code
+            .contains(&format!("let tangent = {};", Vec3::X.to_wgsl_string())));
+        assert!(init_layout.position_code.contains(&format!(
+            "let radii = {};",
+            Vec2::new(2., 0.5).to_wgsl_string()
+        )));
+    }
+
+    #[test]
+    fn circle_ring() {
+        let mut init_layout = InitLayout::default();
+        PositionCircleModifier {
+            radius: 2.,
+            dimension: ShapeDimension::Volume,
+            ..Default::default()
+        }
+        .apply(&mut init_layout);
+        let code = &init_layout.position_code;
+        assert!(code.contains(&format!(
+            "let radii = {};",
+            Vec2::splat(2.).to_wgsl_string()
+        )));
+        assert!(code.contains("let r = sqrt(mix(0., 1., rand()));"));
+        assert!(code.contains("let dir = tangent * ct + bitangent * st;"));
+
+        // Elliptical annulus
+        PositionCircleModifier {
+            axis: Vec3::Y,
+            radii: Some(Vec2::new(2., 1.)),
+            inner_radius: 0.5,
+            dimension: ShapeDimension::Volume,
+            ..Default::default()
+        }
+        .apply(&mut init_layout);
+        let code = &init_layout.position_code;
+        assert!(code.contains(&format!(
+            "let radii = {};",
+            Vec2::new(2., 1.).to_wgsl_string()
+        )));
+        assert!(code.contains("let r = sqrt(mix(0.25, 1., rand()));"));
+        assert!(code.contains("let dir = normalize("));
+
+        // The inner radius is bounded through the density bias
+        PositionCircleModifier {
+            inner_radius: 0.5,
+            density_exponent: 0.5,
+            dimension: ShapeDimension::Volume,
+            ..Default::default()
+        }
+        .apply(&mut init_layout);
+        assert!(init_layout
+            .position_code
+            .contains("let r = pow(mix(0.5, 1., rand()), 1.);"));
+
+        // The sequential sweep is guarded against a zero arc length
+        PositionCircleModifier {
+            arc_length: Value::Single(0.),
+            sweep_speed: Some(1.),
+            ..Default::default()
+        }
+        .apply(&mut init_layout);
+        assert!(init_layout
+            .position_code
+            .contains("floor(sweep / sweep_arc) * sweep_arc;"));
+    }
+
+    #[test]
+    fn inherit_emitter_code() {
+        let inherit = InheritEmitterModifier::default();
+        assert_eq!(
+            inherit.to_shader_code(false),
+            "out.color = emitter.color;\n"
+        );
+        assert_eq!(
+            inherit.to_shader_code(true),
+            "out.color = out.color * emitter.color;\n"
+        );
+
+        let inherit = InheritEmitterModifier {
+            color: false,
+            size: true,
+        };
+        assert_eq!(
+            inherit.to_shader_code(true),
+            "size = size * emitter.scale;\n"
+        );
+    }
+
+    #[test]
+    fn velocity_spread_applies_last() {
+        let mut init_layout = InitLayout::default();
+        VelocitySpreadModifier {
+            spread: Value::Single(0.5),
+        }
+        .apply(&mut init_layout);
+        VelocitySphereModifier::default().apply(&mut init_layout);
+        assert!(init_layout
+            .velocity_spread_code
+            .contains("let cz = mix(cos(0.5), 1., rand());"));
+        let code = crate::render::init_position_code(&init_layout);
+        assert!(
+            code.find("[VelocitySphereModifier]").unwrap()
+                < code.find("[VelocitySpreadModifier]").unwrap()
+        );
+    }
+
+    #[test]
+    fn lifetime_per_particle() {
+        let mut init_layout = InitLayout::default();
+        ParticleLifetimeModifier {
+            lifetime: Value::Uniform((1., 3.)),
+        }
+        .apply(&mut init_layout);
+        assert!(init_layout
+            .lifetime_code
+            .contains("ret.lifetime = rand() * (3. - 1.) + 1.;"));
+        assert_eq!(init_layout.lifetime, Some(Value::Uniform((1., 3.))));
+    }
+
+    #[test]
+    fn init_sections_compose() {
+        let mut init_layout = InitLayout::default();
+        init_layout.append_custom_code("\n    // custom 1\n");
+        ParticleLifetimeModifier {
+            lifetime: Value::Single(2.),
+        }
+        .apply(&mut init_layout);
+        PositionCircleModifier::default().apply(&mut init_layout);
+        init_layout.append_custom_code("\n    // custom 2\n");
+
+        let code = crate::render::init_position_code(&init_layout);
+        let position = code.find("[PositionCircleModifier]").unwrap();
+        let lifetime = code.find("ret.lifetime = 2.;").unwrap();
+        let custom1 = code.find("// custom 1").unwrap();
+        let custom2 = code.find("// custom 2").unwrap();
+        assert!(position < lifetime);
+        assert!(lifetime < custom1);
+        assert!(custom1 < custom2);
+    }
+
+    #[test]
+    fn velocity_overrides_position() {
+        let mut init_layout = InitLayout::default();
+        VelocityTangentModifier {
+            speed: Value::Single(2.),
+            ..Default::default()
+        }
+        .apply(&mut init_layout);
+        PositionCircleModifier::default().apply(&mut init_layout);
+
+        // Position modifiers don't touch the velocity code, whatever the order they're added
+        assert!(init_layout
+            .position_code
+            .contains("[PositionCircleModifier]"));
+        assert!(init_layout
+            .velocity_code
+            .contains("[VelocityTangentModifier]"));
+        assert!(init_layout.velocity_code.contains("* 2.;"));
+
+        // The last velocity modifier replaces the others
+        VelocitySphereModifier::default().apply(&mut init_layout);
+        assert!(!init_layout
+            .velocity_code
+            .contains("[VelocityTangentModifier]"));
+    }
+
+    #[test]
+    fn velocity_distribution() {
+        // Only upward, twice as likely along +X than -X
+        let modifier = VelocityDistributionModifier::new(
+            vec![2., 1., 0., 0.],
+            UVec2::new(2, 2),
+            Value::Single(1.),
+        );
+        let (row_cdf, col_cdf) = modifier.cumulative_distributions();
+        assert_eq!(row_cdf, vec![1., 1.]);
+        assert!((col_cdf[0] - 2. / 3.).abs() < 1e-6);
+        assert!((col_cdf[1] - 1.).abs() < 1e-6);
+        assert_eq!(&col_cdf[2..], &[0.5, 1.]);
+
+        // Uniform weights account for the solid angle of the rows
+        let modifier =
+            VelocityDistributionModifier::new(vec![1.; 3], UVec2::new(1, 3), Value::Single(1.));
+        let (row_cdf, _) = modifier.cumulative_distributions();
+        assert!((row_cdf[0] - 0.25).abs() < 1e-6);
+        assert!((row_cdf[1] - 0.75).abs() < 1e-6);
+
+        // Without any weight, the directions are uniform
+        let modifier =
+            VelocityDistributionModifier::new(vec![], UVec2::new(2, 2), Value::Single(1.));
+        let (row_cdf, col_cdf) = modifier.cumulative_distributions();
+        assert_eq!(row_cdf, vec![0.5, 1.]);
+        assert_eq!(col_cdf, vec![0.5, 1., 0.5, 1.]);
+
+        let mut init_layout = InitLayout::default();
+        modifier.apply(&mut init_layout);
+        assert!(init_layout
+            .velocity_code
+            .contains("var vel_rows = array<f32, 2>(0.5, 1.);"));
+        assert!(init_layout
+            .velocity_code
+            .contains("var vel_cols = array<f32, 4>(0.5, 1., 0.5, 1.);"));
+    }
+}
@@ -5,42 +5,276 @@ use bevy::core_pipeline::Transparent3d;
 use bevy::{
     prelude::*,
     render::{
-        render_graph::RenderGraph, render_phase::DrawFunctions,
-        render_resource::SpecializedRenderPipelines, renderer::RenderDevice, RenderApp,
-        RenderStage,
+        render_graph::RenderGraph,
+        render_phase::DrawFunctions,
+        render_resource::{FilterMode, SpecializedRenderPipelines},
+        renderer::RenderDevice,
+        RenderApp, RenderStage,
     },
 };
 
 use crate::{
     asset::{EffectAsset, EffectAssetLoader},
+    completion::{complete_effects_system, CompletedEffects},
+    composite::composite_effect_lifetime_system,
+    cursor::cursor_painter_system,
+    lint::lint_effect_assets_system,
+    pulse::queue_spawn_pulses_system,
+    quality::adaptive_quality_system,
     render::{
-        extract_effect_events, extract_effects, prepare_effects, queue_effects, ComputeCache,
-        DrawEffects, EffectAssetEvents, EffectBindGroups, EffectSystems, EffectsMeta,
-        ExtractedEffects, ParticleUpdateNode, ParticlesRenderPipeline, ParticlesUpdatePipeline,
-        PipelineRegistry, SimParams, PARTICLES_RENDER_SHADER_HANDLE,
-        PARTICLES_UPDATE_SHADER_HANDLE,
+        extract_effect_events, extract_effects, prepare_effects, queue_effects,
+        readback_completion, CompletionReadback, ComputeCache, DrawEffects, EffectAssetEvents,
+        EffectBindGroups, EffectGpuBuffers, EffectSystems, EffectsMeta, ExtractedEffects,
+        ParticleUpdateNode, ParticlesRenderPipeline, ParticlesUpdatePipeline, PipelineRegistry,
+        SimParams, PARTICLES_RENDER_SHADER_HANDLE, PARTICLES_UPDATE_SHADER_HANDLE,
     },
     spawn::{self, Random},
+    EffectCompleted, EffectGlobals, EffectMetrics, EffectQuality, EffectSimulationSettings,
+    EffectThrottled, ExtensionRegistry, SpawnPulseEvent,
 };
 
+/// Render graph labels of Hanabi.
 pub mod draw_graph {
+    /// Labels of the render graph nodes.
     pub mod node {
         /// Label for the particle update compute node.
         pub const PARTICLE_UPDATE_PASS: &str = "particle_update_pass";
     }
 }
 
+/// Global configuration of Hanabi.
+///
+/// The configuration is set when building the [`HanabiPlugin`], and is inserted as a
+/// resource in both the main and render worlds. The texture filtering and the enabled
+/// systems are applied once when the plugin is built, while the other values are read from
+/// the resources each frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HanabiConfig {
+    /// Filtering mode of the particle textures, overriding the sampler of each texture image.
+    ///
+    /// If `None`, the default, each texture is sampled with its own image sampler.
+    pub texture_filter: Option<FilterMode>,
+    /// Maximum number of effects simulated and rendered each frame.
+    ///
+    /// Visible effects in excess of this number are skipped for the frame. If `None`, the
+    /// default, the number of effects is unlimited.
+    pub max_effects: Option<usize>,
+    /// Maximum capacity of any effect, in number of particles.
+    ///
+    /// The capacity of effect assets larger than this is clamped to it when allocating the
+    /// effect instances. If `None`, the default, the asset capacity is used as is.
+    pub max_capacity: Option<u32>,
+    /// Enable the [`CursorPainter`] system. Defaults to `true`.
+    ///
+    /// [`CursorPainter`]: crate::CursorPainter
+    pub cursor_painting: bool,
+    /// Sort the effects back to front relative to each camera when drawing them. Defaults
+    /// to `true`.
+    ///
+    /// The effects are sorted by their origin, which is approximate for effects drawn
+    /// together in a single batch. If disabled, the effects are drawn in an unspecified
+    /// order, which saves the sorting cost when it doesn't matter, for example with additive
+    /// blending.
+    pub sorting: bool,
+    /// Skip the simulation of offscreen effects with a [`PauseWhenOffscreen`] component.
+    /// Defaults to `true`.
+    ///
+    /// If disabled, those effects are always simulated, as if they didn't have the
+    /// component.
+    ///
+    /// [`PauseWhenOffscreen`]: crate::PauseWhenOffscreen
+    pub culling: bool,
+    /// Read back from the GPU the completion state of the effects with a
+    /// [`CompletionCondition`]. Defaults to `true`.
+    ///
+    /// If disabled, those effects are never found complete, so are never despawned
+    /// automatically and no [`EffectCompleted`] event is sent.
+    ///
+    /// [`CompletionCondition`]: crate::CompletionCondition
+    /// [`EffectCompleted`]: crate::EffectCompleted
+    pub readback: bool,
+    /// Enable the [`AdaptiveQuality`] system. Defaults to `true`.
+    ///
+    /// [`AdaptiveQuality`]: crate::AdaptiveQuality
+    pub adaptive_quality: bool,
+    /// Enable the [`EffectLint`] checks of the effect assets, logged as warnings. Defaults
+    /// to `true`.
+    ///
+    /// [`EffectLint`]: crate::EffectLint
+    pub lint: bool,
+}
+
+impl Default for HanabiConfig {
+    fn default() -> Self {
+        HanabiConfig {
+            texture_filter: None,
+            max_effects: None,
+            max_capacity: None,
+            cursor_painting: true,
+            sorting: true,
+            culling: true,
+            readback: true,
+            adaptive_quality: true,
+            lint: true,
+        }
+    }
+}
+
+impl HanabiConfig {
+    /// Get the capacity allocated for an effect asset with the given capacity.
+    pub fn effect_capacity(&self, asset_capacity: u32) -> u32 {
+        if let Some(max_capacity) = self.max_capacity {
+            asset_capacity.min(max_capacity)
+        } else {
+            asset_capacity
+        }
+    }
+}
+
+/// Builder for a [`HanabiPlugin`] with a custom [`HanabiConfig`].
+///
+/// # Example
+///
+/// ```
+/// # use bevy::render::render_resource::FilterMode;
+/// # use bevy_hanabi::HanabiPlugin;
+/// let plugin = HanabiPlugin::builder()
+///     .texture_filter(FilterMode::Nearest)
+///     .max_effects(256)
+///     .build();
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HanabiPluginBuilder {
+    config: HanabiConfig,
+}
+
+impl HanabiPluginBuilder {
+    /// Set the filtering mode of all particle textures.
+    pub fn texture_filter(mut self, filter: FilterMode) -> Self {
+        self.config.texture_filter = Some(filter);
+        self
+    }
+
+    /// Set the maximum number of effects simulated and rendered each frame.
+    pub fn max_effects(mut self, max_effects: usize) -> Self {
+        self.config.max_effects = Some(max_effects);
+        self
+    }
+
+    /// Set the maximum capacity of any effect, in number of particles.
+    pub fn max_capacity(mut self, max_capacity: u32) -> Self {
+        self.config.max_capacity = Some(max_capacity);
+        self
+    }
+
+    /// Enable or disable the [`CursorPainter`] system.
+    ///
+    /// [`CursorPainter`]: crate::CursorPainter
+    pub fn cursor_painting(mut self, enabled: bool) -> Self {
+        self.config.cursor_painting = enabled;
+        self
+    }
+
+    /// Enable or disable the sorting of the effects when drawing them.
+    pub fn sorting(mut self, enabled: bool) -> Self {
+        self.config.sorting = enabled;
+        self
+    }
+
+    /// Enable or disable skipping the simulation of offscreen effects.
+    pub fn culling(mut self, enabled: bool) -> Self {
+        self.config.culling = enabled;
+        self
+    }
+
+    /// Enable or disable the readback of the completion state of the effects.
+    pub fn readback(mut self, enabled: bool) -> Self {
+        self.config.readback = enabled;
+        self
+    }
+
+    /// Enable or disable the [`AdaptiveQuality`] system.
+    ///
+    /// [`AdaptiveQuality`]: crate::AdaptiveQuality
+    pub fn adaptive_quality(mut self, enabled: bool) -> Self {
+        self.config.adaptive_quality = enabled;
+        self
+    }
+
+    /// Enable or disable the [`EffectLint`] checks of the effect assets.
+    ///
+    /// [`EffectLint`]: crate::EffectLint
+    pub fn lint(mut self, enabled: bool) -> Self {
+        self.config.lint = enabled;
+        self
+    }
+
+    /// Build the plugin.
+    pub fn build(self) -> HanabiPlugin {
+        HanabiPlugin {
+            config: self.config,
+        }
+    }
+}
+
 /// Plugin to add systems related to Hanabi.
-#[derive(Debug, Clone, Copy)]
-pub struct HanabiPlugin;
+///
+/// Use [`HanabiPlugin::default()`] for the default configuration, or
+/// [`HanabiPlugin::builder()`] to customize it.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HanabiPlugin {
+    config: HanabiConfig,
+}
+
+impl HanabiPlugin {
+    /// Create a builder to customize the configuration of the plugin.
+    pub fn builder() -> HanabiPluginBuilder {
+        HanabiPluginBuilder::default()
+    }
+
+    /// Get the configuration of the plugin.
+    pub fn config(&self) -> &HanabiConfig {
+        &self.config
+    }
+}
 
 impl Plugin for HanabiPlugin {
     fn build(&self, app: &mut App) {
+        // Effects found complete by the render world, despawned in the main world
+        let completed_effects = CompletedEffects::default();
+
         // Register asset
         app.add_asset::<EffectAsset>()
+            .insert_resource(self.config)
             .insert_resource(Random(spawn::new_rng()))
             .init_resource::<PipelineRegistry>()
-            .init_asset_loader::<EffectAssetLoader>();
+            .init_resource::<ExtensionRegistry>()
+            .init_asset_loader::<EffectAssetLoader>()
+            .init_resource::<EffectSimulationSettings>()
+            .init_resource::<EffectGlobals>()
+            .init_resource::<EffectQuality>()
+            .add_event::<EffectThrottled>()
+            .add_event::<SpawnPulseEvent>()
+            .add_event::<EffectCompleted>()
+            .insert_resource(completed_effects.clone())
+            .add_system(composite_effect_lifetime_system)
+            .add_system(queue_spawn_pulses_system);
+
+        if self.config.cursor_painting {
+            app.add_system(cursor_painter_system);
+        }
+        if self.config.readback {
+            app.add_system(complete_effects_system);
+        }
+        if self.config.adaptive_quality {
+            app.add_system(adaptive_quality_system);
+        }
+        if self.config.lint {
+            app.add_system(lint_effect_assets_system);
+        }
+
+        #[cfg(feature = "pbr")]
+        app.add_system(crate::inherit_material_color_system);
 
         // Register the spawn and update systems
         // app.add_system(hanabi_spawn.system())
@@ -55,15 +289,18 @@ impl Plugin for HanabiPlugin {
 
         // Register the component reflection
         //app.register_type::<ParticleEffect>();
+        app.register_type::<EffectMetrics>();
 
         let render_device = app.world.get_resource::<RenderDevice>().unwrap();
-        let effects_meta = EffectsMeta::new(render_device.clone());
+        let effects_meta = EffectsMeta::new(render_device.clone(), self.config.texture_filter);
 
         // Register the custom render pipeline
         let render_app = app.sub_app_mut(RenderApp);
         render_app
+            .insert_resource(self.config)
             .insert_resource(effects_meta)
             .init_resource::<EffectBindGroups>()
+            .init_resource::<EffectGpuBuffers>()
             .init_resource::<ParticlesUpdatePipeline>()
             .init_resource::<ComputeCache<ParticlesUpdatePipeline>>()
             .init_resource::<ParticlesRenderPipeline>()
@@ -71,6 +308,8 @@ impl Plugin for HanabiPlugin {
             .init_resource::<ExtractedEffects>()
             .init_resource::<EffectAssetEvents>()
             .init_resource::<SimParams>()
+            .init_resource::<CompletionReadback>()
+            .insert_resource(completed_effects)
             .add_system_to_stage(
                 RenderStage::Extract,
                 extract_effects.label(EffectSystems::ExtractEffects),
@@ -87,6 +326,9 @@ impl Plugin for HanabiPlugin {
                 RenderStage::Queue,
                 queue_effects.label(EffectSystems::QueueEffects),
             );
+        if self.config.readback {
+            render_app.add_system_to_stage(RenderStage::Cleanup, readback_completion);
+        }
 
         // Register the draw function for drawing the particles. This will be called during
         // the main 2D/3D pass, at the Transparent2d/3d phase, after the opaque objects have been
@@ -187,3 +429,37 @@ impl Plugin for HanabiPlugin {
 //         effect.updater.update(motion, time.delta_seconds());
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder() {
+        let plugin = HanabiPlugin::default();
+        assert_eq!(*plugin.config(), HanabiConfig::default());
+
+        let plugin = HanabiPlugin::builder()
+            .texture_filter(FilterMode::Nearest)
+            .max_effects(16)
+            .max_capacity(1024)
+            .cursor_painting(false)
+            .sorting(false)
+            .culling(false)
+            .readback(false)
+            .adaptive_quality(false)
+            .lint(false)
+            .build();
+        let config = plugin.config();
+        assert_eq!(config.texture_filter, Some(FilterMode::Nearest));
+        assert_eq!(config.max_effects, Some(16));
+        assert!(!config.cursor_painting);
+        assert!(!config.sorting);
+        assert!(!config.culling);
+        assert!(!config.readback);
+        assert!(!config.adaptive_quality);
+        assert!(!config.lint);
+        assert_eq!(config.effect_capacity(256), 256);
+        assert_eq!(config.effect_capacity(4096), 1024);
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::VecDeque;
+
+use bevy::{prelude::*, utils::HashMap};
+
+/// A timestamped request to spawn a number of particles.
+///
+/// Pulses let external systems pace the emission of an effect, for example to emit on the
+/// beats of a music track or on the notifies of an animation. Queue them into the
+/// [`SpawnPulses`] component of the effect, or send them as [`SpawnPulseEvent`]s.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SpawnPulse {
+    /// Time at which the particles spawn, in seconds, in the time base of the effect.
+    ///
+    /// This is the [`EffectTimeControl::time()`] of the effect if it has one, so that pulses
+    /// follow its rate and pauses, or [`Time::seconds_since_startup()`] otherwise. The time
+    /// is never wrapped by the [`EffectSimulationSettings::loop_period`]; queue the pulses
+    /// again each period to loop them.
+    ///
+    /// [`EffectTimeControl::time()`]: crate::EffectTimeControl::time
+    /// [`EffectSimulationSettings::loop_period`]: crate::EffectSimulationSettings::loop_period
+    pub time: f64,
+    /// Number of particles to spawn.
+    pub count: u32,
+    /// Offset of the spawn origin from the effect origin, in the local space of the effect.
+    pub offset: Vec3,
+}
+
+impl SpawnPulse {
+    /// Create a pulse spawning `count` particles at the effect origin at the given time.
+    pub fn new(time: f64, count: u32) -> Self {
+        Self {
+            time,
+            count,
+            offset: Vec3::ZERO,
+        }
+    }
+
+    /// Set the offset of the spawn origin from the effect origin.
+    pub fn with_offset(mut self, offset: Vec3) -> Self {
+        self.offset = offset;
+        self
+    }
+}
+
+/// Spawn pulses of a single frame, merged together.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PulseBatch {
+    /// Total number of particles to spawn.
+    pub count: u32,
+    /// Offset of the spawn origin, shared by all the pulses of the batch.
+    pub offset: Vec3,
+    /// Age of the first and last particle spawned, in seconds. The particles in between are
+    /// aged linearly, which only approximates the ages of their own pulses.
+    pub age: [f32; 2],
+}
+
+/// Queue of the [`SpawnPulse`]s of a [`ParticleEffect`].
+///
+/// When this component is present on the entity of a [`ParticleEffect`], the spawner of the
+/// effect is not ticked. Instead, the effect spawns the particles of each pulse at the frame
+/// its time is reached. Particles are aged by the time elapsed since their pulse, so pulses
+/// keep their sub-frame timing even at a low frame rate. Pulses can be queued ahead of time
+/// and in any order. See [`SpawnPulse::time`] for the time base of the pulses, which is
+/// rewound when an [`EffectTimeControl`] restarts the effect, without restoring the pulses
+/// already consumed.
+///
+/// When several pulses are due in the same frame, their particles are spawned together,
+/// aged linearly from the age of the first pulse to the age of the last one. This is exact
+/// for a single pulse, or for evenly spaced pulses of a single particle each, and otherwise
+/// approximates the age of each particle within the frame duration.
+///
+/// All the particles spawned during a frame share the same origin, so pulses with a
+/// different offset than the first pulse of the frame are deferred to the next frame, still
+/// aged by their actual time. An [`ExternalSpawnCount`] takes precedence over the pulses.
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+/// [`ExternalSpawnCount`]: crate::ExternalSpawnCount
+/// [`EffectTimeControl`]: crate::EffectTimeControl
+#[derive(Debug, Default, Clone, Component)]
+pub struct SpawnPulses {
+    /// Queued pulses, sorted by time.
+    queue: VecDeque<SpawnPulse>,
+}
+
+impl SpawnPulses {
+    /// Create an empty queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a pulse.
+    pub fn push(&mut self, pulse: SpawnPulse) {
+        let index = self.queue.partition_point(|p| p.time <= pulse.time);
+        self.queue.insert(index, pulse);
+    }
+
+    /// Number of pulses in the queue.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Check if the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Remove all the queued pulses.
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+
+    /// Consume the pulses due at the effect time `now`, for a frame of `dt` seconds of effect
+    /// time.
+    ///
+    /// The age of the particles is clamped to the frame duration, so that pulses queued too
+    /// late don't spawn particles already half-way through their lifetime.
+    pub(crate) fn consume(&mut self, now: f64, dt: f32) -> Option<PulseBatch> {
+        let first = *self.queue.front().filter(|p| p.time <= now)?;
+        let age = |pulse: &SpawnPulse| ((now - pulse.time) as f32).min(dt);
+        let mut batch = PulseBatch {
+            count: 0,
+            offset: first.offset,
+            age: [age(&first); 2],
+        };
+        while let Some(pulse) = self
+            .queue
+            .front()
+            .copied()
+            .filter(|p| p.time <= now && p.offset == first.offset)
+        {
+            batch.count += pulse.count;
+            batch.age[1] = age(&pulse);
+            self.queue.pop_front();
+        }
+        Some(batch)
+    }
+}
+
+/// Event queuing a [`SpawnPulse`] for the [`ParticleEffect`] of an entity.
+///
+/// This is an alternative to [`SpawnPulses::push()`] for systems which don't have access to
+/// the effect entities, for example an audio system. The [`SpawnPulses`] component is
+/// inserted if the entity doesn't have one yet.
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpawnPulseEvent {
+    /// Entity of the effect to spawn the particles of.
+    pub entity: Entity,
+    /// Pulse to queue.
+    pub pulse: SpawnPulse,
+}
+
+/// Queue the [`SpawnPulseEvent`]s into the [`SpawnPulses`] component of their entity.
+pub fn queue_spawn_pulses_system(
+    mut commands: Commands,
+    mut events: EventReader<SpawnPulseEvent>,
+    mut query: Query<&mut SpawnPulses>,
+) {
+    let mut added: HashMap<Entity, SpawnPulses> = HashMap::default();
+    for event in events.iter() {
+        if let Ok(mut pulses) = query.get_mut(event.entity) {
+            pulses.push(event.pulse);
+        } else {
+            added.entry(event.entity).or_default().push(event.pulse);
+        }
+    }
+    for (entity, pulses) in added {
+        commands.entity(entity).insert(pulses);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_sorted() {
+        let mut pulses = SpawnPulses::new();
+        pulses.push(SpawnPulse::new(2., 1));
+        pulses.push(SpawnPulse::new(1., 2));
+        pulses.push(SpawnPulse::new(3., 3));
+        assert_eq!(pulses.len(), 3);
+        let times: Vec<f64> = pulses.queue.iter().map(|p| p.time).collect();
+        assert_eq!(times, vec![1., 2., 3.]);
+    }
+
+    #[test]
+    fn consume() {
+        let mut pulses = SpawnPulses::new();
+        pulses.push(SpawnPulse::new(1., 4));
+        pulses.push(SpawnPulse::new(1.0625, 2));
+        pulses.push(SpawnPulse::new(2., 8));
+
+        // Nothing due yet
+        assert_eq!(pulses.consume(0.5, 0.25), None);
+
+        // Pulses due this frame are merged, and aged since their time
+        let batch = pulses.consume(1.125, 0.25).unwrap();
+        assert_eq!(batch.count, 6);
+        assert_eq!(batch.offset, Vec3::ZERO);
+        assert_eq!(batch.age, [0.125, 0.0625]);
+        assert_eq!(pulses.len(), 1);
+
+        // The age of late pulses is clamped to the frame duration
+        let batch = pulses.consume(3., 0.25).unwrap();
+        assert_eq!(batch.count, 8);
+        assert_eq!(batch.age, [0.25, 0.25]);
+        assert!(pulses.is_empty());
+    }
+
+    #[test]
+    fn consume_offsets() {
+        let mut pulses = SpawnPulses::new();
+        pulses.push(SpawnPulse::new(1., 1).with_offset(Vec3::X));
+        pulses.push(SpawnPulse::new(1.25, 2).with_offset(Vec3::Y));
+
+        // Pulses with a different offset are deferred to the next frame
+        let batch = pulses.consume(1.5, 1.).unwrap();
+        assert_eq!(batch.count, 1);
+        assert_eq!(batch.offset, Vec3::X);
+        let batch = pulses.consume(1.5, 1.).unwrap();
+        assert_eq!(batch.count, 2);
+        assert_eq!(batch.offset, Vec3::Y);
+        assert_eq!(batch.age, [0.25, 0.25]);
+    }
+}
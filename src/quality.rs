@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+
+/// Global quality factor of all particle effects.
+///
+/// The quality multiplies the spawn rate, or count for bursts, of all the effects, on top of
+/// the [`EffectStrength`] of each instance. A quality of 1 is the nominal quality. The
+/// quality can be set manually, for example from a graphics settings menu, or driven
+/// automatically from the frame time by inserting an [`AdaptiveQuality`] resource.
+///
+/// [`EffectStrength`]: crate::EffectStrength
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EffectQuality(pub f32);
+
+impl Default for EffectQuality {
+    fn default() -> Self {
+        EffectQuality(1.0)
+    }
+}
+
+/// Controller adjusting the [`EffectQuality`] to keep the frame time under a target.
+///
+/// Insert this resource to enable the controller. Each frame, the controller averages the
+/// frame time, and lowers the quality while the average is above the target, or raises it
+/// back while the average is below, at a bounded rate so effects degrade and recover
+/// smoothly. Within `tolerance` of the target the quality is left unchanged, to avoid
+/// oscillating. The resource is ignored if [`HanabiConfig::adaptive_quality`] is disabled.
+///
+/// [`HanabiConfig::adaptive_quality`]: crate::HanabiConfig::adaptive_quality
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveQuality {
+    /// Target frame time, in seconds.
+    pub target_frame_time: f32,
+    /// Relative tolerance around the target frame time within which the quality is kept.
+    pub tolerance: f32,
+    /// Minimum quality the controller can lower the quality to.
+    pub min_quality: f32,
+    /// Maximum quality the controller can raise the quality to.
+    pub max_quality: f32,
+    /// Maximum change of the quality per second.
+    pub rate: f32,
+    /// Time constant of the frame time average, in seconds.
+    pub smoothing: f32,
+    /// Current average frame time, in seconds.
+    average_frame_time: Option<f32>,
+}
+
+impl Default for AdaptiveQuality {
+    fn default() -> Self {
+        Self {
+            target_frame_time: 1. / 60.,
+            tolerance: 0.1,
+            min_quality: 0.25,
+            max_quality: 1.,
+            rate: 0.5,
+            smoothing: 0.5,
+            average_frame_time: None,
+        }
+    }
+}
+
+impl AdaptiveQuality {
+    /// Create a controller targeting the given frame rate, in frames per second.
+    pub fn with_target_fps(fps: f32) -> Self {
+        Self {
+            target_frame_time: 1. / fps,
+            ..Default::default()
+        }
+    }
+
+    /// Get the current average frame time, in seconds.
+    pub fn average_frame_time(&self) -> Option<f32> {
+        self.average_frame_time
+    }
+
+    /// Account for a frame of `dt` seconds, and return the new value of `quality`.
+    pub(crate) fn update(&mut self, dt: f32, quality: f32) -> f32 {
+        if dt <= 0. {
+            return quality;
+        }
+        let average = match self.average_frame_time {
+            Some(average) => {
+                let alpha = 1. - (-dt / self.smoothing.max(1e-3)).exp();
+                average + (dt - average) * alpha
+            }
+            None => dt,
+        };
+        self.average_frame_time = Some(average);
+
+        let ratio = average / self.target_frame_time;
+        let quality = if ratio > 1. + self.tolerance {
+            quality - self.rate * dt
+        } else if ratio < 1. - self.tolerance {
+            quality + self.rate * dt
+        } else {
+            quality
+        };
+        quality.clamp(self.min_quality, self.max_quality)
+    }
+}
+
+/// Update the [`EffectQuality`] from the frame time, if an [`AdaptiveQuality`] exists.
+pub(crate) fn adaptive_quality_system(
+    time: Res<Time>,
+    adaptive: Option<ResMut<AdaptiveQuality>>,
+    mut quality: ResMut<EffectQuality>,
+) {
+    if let Some(mut adaptive) = adaptive {
+        let value = adaptive.update(time.delta_seconds(), quality.0);
+        if value != quality.0 {
+            quality.0 = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adaptive() {
+        let mut adaptive = AdaptiveQuality {
+            target_frame_time: 0.01,
+            smoothing: 0.001,
+            rate: 1.,
+            ..Default::default()
+        };
+
+        // Slow frames lower the quality down to the minimum
+        let mut quality = 1.;
+        for _ in 0..10 {
+            quality = adaptive.update(0.1, quality);
+        }
+        assert_eq!(quality, adaptive.min_quality);
+
+        // Frames at the target keep the quality
+        let kept = adaptive.update(0.01, 0.5);
+        assert_eq!(kept, 0.5);
+
+        // Fast frames raise the quality back up to the maximum
+        for _ in 0..200 {
+            quality = adaptive.update(0.005, quality);
+        }
+        assert_eq!(quality, adaptive.max_quality);
+    }
+}
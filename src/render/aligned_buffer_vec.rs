@@ -11,8 +11,7 @@ use copyless::VecHelper;
 
 // TODO - filler for usize.next_multiple_of()
 fn next_multiple_of(value: usize, align: usize) -> usize {
-    let count = (value + align - 1) / align;
-    count * align
+    value.div_ceil(align) * align
 }
 
 /// Like Bevy's [`BufferVec`], but with an explicit item alignment.
@@ -36,8 +35,8 @@ impl<T: Pod> Default for AlignedBufferVec<T> {
             buffer: None,
             capacity: 0,
             buffer_usage: BufferUsages::all(),
-            item_size: std::mem::size_of::<T>(),
-            aligned_size: std::mem::size_of::<T>(),
+            item_size: size_of::<T>(),
+            aligned_size: size_of::<T>(),
             label: None,
         }
     }
@@ -45,11 +44,11 @@ impl<T: Pod> Default for AlignedBufferVec<T> {
 
 impl<T: Pod> AlignedBufferVec<T> {
     pub fn new(buffer_usage: BufferUsages, item_align: usize, label: Option<String>) -> Self {
-        let item_size = std::mem::size_of::<T>();
-        //let aligned_size = item_size.next_multiple_of(item_align);
-        let aligned_size = next_multiple_of(item_size, item_align);
+        let item_size = size_of::<T>();
+        //let aligned_size = next_multiple_of(item_size, item_align);
+        let aligned_size = item_size.next_multiple_of(item_align);
         assert!(aligned_size >= item_size);
-        assert!(aligned_size % item_align == 0);
+        assert!(aligned_size.is_multiple_of(item_align));
         Self {
             buffer_usage,
             aligned_size,
@@ -116,8 +115,7 @@ impl<T: Pod> AlignedBufferVec<T> {
         if let Some(buffer) = &self.buffer {
             let aligned_size = self.aligned_size * self.values.len();
             trace!("aligned_buffer: size={}", aligned_size);
-            let mut aligned_buffer: Vec<u8> = Vec::with_capacity(aligned_size);
-            aligned_buffer.resize(aligned_size, 0);
+            let mut aligned_buffer: Vec<u8> = vec![0; aligned_size];
             for i in 0..self.values.len() {
                 let src: &[u8] = cast_slice(std::slice::from_ref(&self.values[i]));
                 let dst_offset = i * self.aligned_size;
@@ -19,27 +19,38 @@ pub trait SpecializedComputePipeline {
 }
 
 /// Cache for specialized (preprocessed) compute pipelines.
+///
+/// Each pipeline is identified by its index in the cache, which is stable for the lifetime
+/// of the cache, and cheap to compare and sort.
 pub struct ComputeCache<S: SpecializedComputePipeline> {
-    cache: HashMap<S::Key, ComputePipeline>,
+    indices: HashMap<S::Key, usize>,
+    pipelines: Vec<ComputePipeline>,
 }
 
 impl<S: SpecializedComputePipeline> Default for ComputeCache<S> {
     fn default() -> Self {
         Self {
-            cache: Default::default(),
+            indices: Default::default(),
+            pipelines: Default::default(),
         }
     }
 }
 
 impl<S: SpecializedComputePipeline> ComputeCache<S> {
-    pub fn specialize(
-        &mut self,
-        pipeline: &S,
-        key: S::Key,
-        render_device: &RenderDevice,
-    ) -> &ComputePipeline {
-        self.cache
-            .entry(key.clone())
-            .or_insert_with(|| pipeline.specialize(key, render_device))
+    /// Get the index of the pipeline specialized with the given key, specializing it if it's
+    /// not in the cache yet.
+    pub fn specialize(&mut self, pipeline: &S, key: S::Key, render_device: &RenderDevice) -> usize {
+        let pipelines = &mut self.pipelines;
+        *self.indices.entry(key.clone()).or_insert_with(|| {
+            pipelines.push(pipeline.specialize(key, render_device));
+            pipelines.len() - 1
+        })
+    }
+
+    /// Get a pipeline by the index returned by [`specialize()`].
+    ///
+    /// [`specialize()`]: ComputeCache::specialize
+    pub fn get(&self, index: usize) -> &ComputePipeline {
+        &self.pipelines[index]
     }
 }
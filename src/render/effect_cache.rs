@@ -97,12 +97,6 @@ pub struct EffectBuffer {
     asset: Handle<EffectAsset>,
 }
 
-struct BestRange {
-    range: Range<u32>,
-    capacity: u32,
-    index: usize,
-}
-
 impl EffectBuffer {
     /// Minimum buffer capacity to allocate, in number of particles.
     pub const MIN_CAPACITY: u32 = 65536; // at least 64k particles
@@ -126,7 +120,7 @@ impl EffectBuffer {
         let particle_buffer = render_device.create_buffer(&BufferDescriptor {
             label,
             size: particle_capacity_bytes,
-            usage: BufferUsages::COPY_DST | BufferUsages::STORAGE,
+            usage: BufferUsages::COPY_DST | BufferUsages::COPY_SRC | BufferUsages::STORAGE,
             mapped_at_creation: false,
         });
         let indirect_label = if let Some(label) = label {
@@ -134,12 +128,11 @@ impl EffectBuffer {
         } else {
             "vfx_indirect_buffer".to_owned()
         };
-        let indirect_capacity_bytes: BufferAddress =
-            capacity as u64 * std::mem::size_of::<u32>() as u64;
+        let indirect_capacity_bytes: BufferAddress = capacity as u64 * size_of::<u32>() as u64;
         let indirect_buffer = render_device.create_buffer(&BufferDescriptor {
             label: Some(&indirect_label),
             size: indirect_capacity_bytes,
-            usage: BufferUsages::COPY_DST | BufferUsages::STORAGE,
+            usage: BufferUsages::COPY_DST | BufferUsages::COPY_SRC | BufferUsages::STORAGE,
             mapped_at_creation: false,
         });
         EffectBuffer {
@@ -169,7 +162,7 @@ impl EffectBuffer {
     }
 
     /// Return a binding for the entire buffer.
-    pub fn max_binding(&self) -> BindingResource {
+    pub fn max_binding(&self) -> BindingResource<'_> {
         let capacity_bytes = self.capacity as u64 * self.item_size as u64;
         BindingResource::Buffer(BufferBinding {
             buffer: &self.particle_buffer,
@@ -179,7 +172,7 @@ impl EffectBuffer {
     }
 
     /// Return a binding of the buffer for a starting range of a given size (in bytes).
-    pub fn binding(&self, size: u32) -> BindingResource {
+    pub fn binding(&self, size: u32) -> BindingResource<'_> {
         BindingResource::Buffer(BufferBinding {
             buffer: &self.particle_buffer,
             offset: 0,
@@ -188,8 +181,8 @@ impl EffectBuffer {
     }
 
     /// Return a binding for the entire indirect buffer associated with the current effect buffer.
-    pub fn indirect_max_binding(&self) -> BindingResource {
-        let capacity_bytes = self.capacity as u64 * std::mem::size_of::<u32>() as u64;
+    pub fn indirect_max_binding(&self) -> BindingResource<'_> {
+        let capacity_bytes = self.capacity as u64 * size_of::<u32>() as u64;
         BindingResource::Buffer(BufferBinding {
             buffer: &self.indirect_buffer,
             offset: 0,
@@ -197,31 +190,22 @@ impl EffectBuffer {
         })
     }
 
+    /// Take from the free list the smallest free range fitting `size` particles, returning
+    /// its unused tail, if any, to the free list.
     fn pop_free_slice(&mut self, size: u32) -> Option<Range<u32>> {
-        if self.free_slices.is_empty() {
-            return None;
-        }
-        let slice0 = &self.free_slices[0];
-        let mut result = BestRange {
-            range: slice0.clone(),
-            capacity: (slice0.end - slice0.start),
-            index: 0,
-        };
-        for (index, slice) in self.free_slices.iter().skip(1).enumerate() {
-            let capacity = slice.end - slice.start;
-            if size > capacity {
-                continue;
-            }
-            if capacity < result.capacity {
-                result = BestRange {
-                    range: slice.clone(),
-                    capacity,
-                    index,
-                };
-            }
+        let (index, range) = self
+            .free_slices
+            .iter()
+            .enumerate()
+            .filter(|(_, slice)| slice.end - slice.start >= size)
+            .min_by_key(|(_, slice)| slice.end - slice.start)
+            .map(|(index, slice)| (index, slice.clone()))?;
+        self.free_slices.swap_remove(index);
+        let end = range.start + size;
+        if end < range.end {
+            self.free_slices.push(end..range.end);
         }
-        self.free_slices.swap_remove(result.index);
-        Some(result.range)
+        Some(range.start..end)
     }
 
     /// Allocate a new slice in the buffer to store the particles of a single effect.
@@ -255,6 +239,26 @@ impl EffectBuffer {
         Some(SliceRef { range, item_size })
     }
 
+    /// Return a slice allocated with [`allocate_slice()`] to the buffer, for reuse by a later
+    /// allocation.
+    ///
+    /// [`allocate_slice()`]: EffectBuffer::allocate_slice
+    pub fn free_slice(&mut self, range: Range<u32>) {
+        if range.end == self.used_size {
+            // Last slice; shrink the used size, along with any free range now at the end
+            self.used_size = range.start;
+            while let Some(index) = self
+                .free_slices
+                .iter()
+                .position(|slice| slice.end == self.used_size)
+            {
+                self.used_size = self.free_slices.swap_remove(index).start;
+            }
+        } else {
+            self.free_slices.push(range);
+        }
+    }
+
     // pub fn write_slice(&mut self, slice: &SliceRef, data: &[u8], queue: &RenderQueue) {
     //     assert!(data.len() <= slice.byte_size());
     //     let bytes: &[u8] = cast_slice(data);
@@ -311,7 +315,7 @@ impl EffectCache {
         capacity: u32,
         item_size: u32,
         //pipeline: ComputePipeline,
-        _queue: &RenderQueue,
+        queue: &RenderQueue,
     ) -> EffectCacheId {
         let (buffer_index, slice) = self
             .buffers
@@ -359,6 +363,13 @@ impl EffectCache {
                 ))
             })
             .unwrap();
+        // The slice may have been used by a removed effect; clear it so that its particles are
+        // all dead (zero lifetime) instead of simulating the particles of the previous effect.
+        queue.write_buffer(
+            &self.buffers[buffer_index].particle_buffer,
+            slice.range.start as u64 * item_size as u64,
+            &vec![0; slice.byte_size()],
+        );
         let id = EffectCacheId::new();
         trace!(
             "Insert effect id={:?} buffer_index={} slice={:?}x{}B",
@@ -381,4 +392,26 @@ impl EffectCache {
             })
             .unwrap()
     }
+
+    /// Remove an effect from the cache, returning its slice to the effect buffer it was
+    /// allocated from. Empty buffers are kept for later compatible effects, as the index of
+    /// a buffer is the group index of the effects allocated in it.
+    ///
+    /// Returns the slice the effect was using, or `None` if the effect is not in the cache.
+    pub fn remove(&mut self, id: EffectCacheId) -> Option<EffectSlice> {
+        let (buffer_index, slice_ref) = self.effects.remove(&id)?;
+        trace!(
+            "Remove effect id={:?} buffer_index={} slice={:?}x{}B",
+            id,
+            buffer_index,
+            slice_ref.range,
+            slice_ref.item_size
+        );
+        self.buffers[buffer_index].free_slice(slice_ref.range.clone());
+        Some(EffectSlice {
+            slice: slice_ref.range,
+            group_index: buffer_index as u32,
+            item_size: slice_ref.item_size,
+        })
+    }
 }
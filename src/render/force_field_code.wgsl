@@ -1,6 +1,6 @@
     // force field acceleration: note that the particles do not have a mass as of yet,
     // or we could say that the particles all have a mass of one, which means F = 1 * a.
-    var ff_acceleration: vec3<f32> = vec3<f32>(0.0); 
+    let ff_acceleration = force_field_accel(vPos);
     var not_conformed_to_sphere: f32 = 1.0;
 
     var unit_p2p_conformed: vec3<f32> = vec3<f32>(0.0);
@@ -22,7 +22,6 @@
         let unit_p2p = normalize(particle_to_point_source) ;
 
         let min_dist_check = step(spawner.force_field[kk].min_radius, distance);
-        let max_dist_check = 1.0 - step(spawner.force_field[kk].max_radius, distance);
 
         // this turns into 0 when the field is an attractor and the particle is inside the min_radius and the source
         // is an attractor.
@@ -47,16 +46,6 @@
                 * spawner.force_field[kk].min_radius / 1.2
                 * (1.0 - min_dist_check);
         }
-
-        let point_source_force =             
-            - unit_p2p
-            * min_dist_check * max_dist_check
-            * spawner.force_field[kk].mass / 
-                (0.0000001 + pow(distance, spawner.force_field[kk].force_exponent));
-        
-        // if the particle is within the min_radius of a source, then forget about
-        // the other sources and only use the conformed field, thus the "* min_dist_check"
-        ff_acceleration =  ff_acceleration * min_dist_check + point_source_force;
     }
 
     // conform to a sphere of radius min_radius/2 by projecting the velocity vector
@@ -66,12 +55,9 @@
     let conformed_field = 
         (1.0 - not_conformed_to_sphere) * normalize(projected_on_sphere) * length(vVel);
 
-    // Euler integration
-    vVel = (vVel + (spawner.accel  + ff_acceleration) * sim_params.dt) 
-        * not_conformed_to_sphere + conformed_field;
-
-    // let temp_vPos = vPos;
-    vPos = (vPos + (vVel * sim_params.dt));
+    // Integration
+    let accel = spawner.accel + attractor_accel(vPos) + ff_acceleration + buoyancy_accel;
+{{INTEGRATION_CODE}}
     
     // project on the sphere if within conforming distance
     let pos_to_source = conforming_source - vPos ;
@@ -7,18 +7,23 @@ use bevy::{
         prelude::*,
         system::{lifetimeless::*, SystemState},
     },
-    log::trace,
+    log::{trace, warn},
     math::{const_vec3, Mat4, Rect, Vec2, Vec3, Vec4, Vec4Swizzles},
     reflect::TypeUuid,
     render::{
+        camera::Camera,
         color::Color,
+        mesh::Mesh,
+        primitives::Frustum,
         render_asset::RenderAssets,
         render_graph::{Node, NodeRunError, RenderGraphContext, SlotInfo, SlotType},
         render_phase::{Draw, DrawFunctions, RenderPhase, TrackedRenderPass},
         render_resource::{std140::AsStd140, std430::AsStd430, *},
         renderer::{RenderContext, RenderDevice, RenderQueue},
         texture::{BevyDefault, Image},
-        view::{ComputedVisibility, ExtractedView, ViewUniform, ViewUniformOffset, ViewUniforms},
+        view::{
+            ComputedVisibility, ExtractedView, Msaa, ViewUniform, ViewUniformOffset, ViewUniforms,
+        },
         RenderWorld,
     },
     transform::components::GlobalTransform,
@@ -26,9 +31,8 @@ use bevy::{
 };
 use bitflags::bitflags;
 use bytemuck::cast_slice_mut;
-use rand::random;
 use rand::Rng;
-use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering as AtomicOrdering};
 use std::{borrow::Cow, cmp::Ordering, num::NonZeroU64, ops::Range};
 
 #[cfg(feature = "2d")]
@@ -37,18 +41,33 @@ use bevy::core_pipeline::Transparent2d;
 use bevy::core_pipeline::Transparent3d;
 
 use crate::{
-    asset::EffectAsset,
-    modifiers::{ForceFieldParam, FFNUM},
-    spawn::{new_rng, Random},
-    Gradient, ParticleEffect, ToWgslString,
+    asset::{EffectAsset, InitData, InitLayout, UpdateLayout},
+    capture::{EffectCapture, EffectFrameInputs},
+    completion::CompletedEffects,
+    culling::{CullReason, EffectCullingDebug},
+    cursor::CursorPainter,
+    extension::ExtensionCode,
+    modifiers::{
+        ColorBlendModifier, ColorBlendWeight, ColorInterpolation, ColorSelection,
+        DistanceFogModifier, FlipbookModifier, ForceFieldParam, InitColorModifier,
+        InitRotationModifier, InitSizeModifier, Integrator, ParticleGeometry, PositionMeshModifier,
+        ShockwaveModifier, TextureChannelMapping, FFNUM,
+    },
+    spawn::{burst_sweep, new_rng, Random},
+    EffectDebugView, EffectGlobals, EffectMetrics, EffectQuality, EffectSimulationSettings,
+    EffectStrength, EffectThrottled, EffectTimeControl, EmitterColor, ExternalSpawnCount, Gradient,
+    HanabiConfig, ParticleEffect, PauseWhenOffscreen, ScreenSpaceAttractor, SpawnPulses,
+    ToWgslString, Value,
 };
 
 mod aligned_buffer_vec;
 mod compute_cache;
 mod effect_cache;
 mod pipeline_template;
+mod readback;
 
 use aligned_buffer_vec::AlignedBufferVec;
+pub(crate) use readback::AsyncReadback;
 
 pub use compute_cache::{ComputeCache, SpecializedComputePipeline};
 pub use effect_cache::{EffectBuffer, EffectCache, EffectCacheId, EffectSlice};
@@ -69,15 +88,156 @@ const DEFAULT_POSITION_CODE: &str = r##"
     dir = normalize(dir);
     var speed = 2.;
     ret.vel = dir * speed;
+    ret.normal = dir;
+"##;
+
+const VERTEX_ORIENT_CODE: &str = r##"
+    // Orient the particle geometry along the particle normal
+    var up = vec3<f32>(0., 1., 0.);
+    if (abs(particle.normal.y) > 0.999) {
+        up = vec3<f32>(1., 0., 0.);
+    }
+    let orient_tangent = normalize(cross(up, particle.normal));
+    let orient_bitangent = cross(particle.normal, orient_tangent);
+    vpos = orient_tangent * vpos.x + orient_bitangent * vpos.y + particle.normal * vpos.z;
+"##;
+
+const DEFAULT_BUOYANCY_CODE: &str = r##"
+    let buoyancy_accel = vec3<f32>(0.0);
 "##;
 
 const DEFAULT_FORCE_FIELD_CODE: &str = r##"
-    vVel = vVel + (spawner.accel * sim_params.dt);
-    vPos = vPos + vVel * sim_params.dt;
+    let accel = spawner.accel + attractor_accel(vPos) + buoyancy_accel;
+{{INTEGRATION_CODE}}
 "##;
 
 const FORCE_FIELD_CODE: &str = include_str!("force_field_code.wgsl");
 
+/// Declarations of the storage buffer of the [`InitData`] of an effect, read by its init code.
+const INIT_BINDINGS: &str = r##"struct InitData {
+    values: [[stride(4)]] array<f32>;
+};
+
+[[group(4), binding(0)]] var<storage, read> init_data : InitData;
+
+// Read 3 consecutive values of the init data as a vector
+fn init_data_vec3(i: u32) -> vec3<f32> {
+    return vec3<f32>(init_data.values[i], init_data.values[i + 1u], init_data.values[i + 2u]);
+}
+"##;
+
+/// Generate the shader code initializing the position and velocity of newly emitted particles.
+pub(crate) fn init_position_code(init_layout: &InitLayout) -> String {
+    let mut position_code = if init_layout.position_code.is_empty() {
+        DEFAULT_POSITION_CODE.to_owned()
+    } else {
+        init_layout.position_code.clone()
+    };
+    // Override the velocity after the position code, which the velocity code can read
+    position_code += &init_layout.velocity_code;
+    position_code += &init_layout.velocity_spread_code;
+    position_code += &init_layout.lifetime_code;
+    position_code += &init_layout.custom_code;
+    position_code
+}
+
+/// Generate the shader code integrating the motion of the particles, including the force field.
+pub(crate) fn update_force_field_code(update_layout: &UpdateLayout) -> String {
+    let has_force_field = 0.0 != update_layout.force_field[0].force_exponent;
+    let force_field_code = if has_force_field {
+        FORCE_FIELD_CODE
+    } else {
+        DEFAULT_FORCE_FIELD_CODE
+    };
+    let buoyancy_code = if let Some(buoyancy) = &update_layout.buoyancy {
+        buoyancy.to_shader_code()
+    } else {
+        DEFAULT_BUOYANCY_CODE.to_owned()
+    };
+    let vortex_code = if let Some(vortex) = &update_layout.vortex {
+        vortex.to_shader_code()
+    } else {
+        String::new()
+    };
+    let wind_code = if let Some(wind) = &update_layout.wind {
+        wind.to_shader_code()
+    } else {
+        String::new()
+    };
+    let flow_zone_code = if let Some(flow_zone) = &update_layout.flow_zone {
+        flow_zone.to_shader_code()
+    } else {
+        String::new()
+    };
+    let drag_code = if let Some(drag) = &update_layout.drag {
+        drag.to_shader_code()
+    } else {
+        String::new()
+    };
+    let plane_collision_code = if let Some(plane_collision) = &update_layout.plane_collision {
+        plane_collision.to_shader_code()
+    } else {
+        String::new()
+    };
+    let bounds_code = if let Some(bounds) = &update_layout.bounds {
+        bounds.to_shader_code()
+    } else {
+        String::new()
+    };
+    buoyancy_code
+        + &force_field_code.replace(
+            "{{INTEGRATION_CODE}}",
+            &integration_code(update_layout.integrator, has_force_field),
+        )
+        + &vortex_code
+        + &wind_code
+        + &flow_zone_code
+        + &drag_code
+        + &plane_collision_code
+        + &bounds_code
+}
+
+/// Generate the code integrating the particle motion over one frame.
+///
+/// The code expects the acceleration at the start of the frame in `accel`. If `force_field`
+/// is true, the velocity is also conformed to the sphere of the force field components with
+/// `conform_to_sphere` set.
+fn integration_code(integrator: Integrator, force_field: bool) -> String {
+    let velocity = |accel: &str| {
+        if force_field {
+            format!(
+                "vVel = (vVel + {} * spawner.dt) * not_conformed_to_sphere + conformed_field;",
+                accel
+            )
+        } else {
+            format!("vVel = vVel + {} * spawner.dt;", accel)
+        }
+    };
+    match integrator {
+        Integrator::ExplicitEuler => format!(
+            "    vPos = vPos + vVel * spawner.dt;\n    {}\n",
+            velocity("accel")
+        ),
+        Integrator::SemiImplicitEuler => format!(
+            "    {}\n    vPos = vPos + vVel * spawner.dt;\n",
+            velocity("accel")
+        ),
+        Integrator::VelocityVerlet => {
+            if force_field {
+                format!(
+                    "    vPos = vPos + (vVel + 0.5 * accel * spawner.dt) * spawner.dt;\n    let accel_next = spawner.accel + attractor_accel(vPos) + force_field_accel(vPos) + buoyancy_accel;\n    {}\n",
+                    velocity("(0.5 * (accel + accel_next))")
+                )
+            } else {
+                format!(
+                    "    vPos = vPos + (vVel + 0.5 * accel * spawner.dt) * spawner.dt;\n    let accel_next = spawner.accel + attractor_accel(vPos) + buoyancy_accel;\n    {}\n",
+                    velocity("(0.5 * (accel + accel_next))")
+                )
+            }
+        }
+    }
+}
+
 /// Labels for the Hanabi systems.
 #[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
 pub enum EffectSystems {
@@ -139,6 +299,78 @@ impl ShaderCode for Gradient<Vec2> {
     }
 }
 
+impl ShaderCode for Gradient<f32> {
+    fn to_shader_code(&self) -> String {
+        scalar_gradient_code(self, "rotation")
+    }
+}
+
+/// Generate the shader code assigning the value of a scalar gradient over the particle
+/// lifetime to the `target` shader variable.
+fn scalar_gradient_code(gradient: &Gradient<f32>, target: &str) -> String {
+    if gradient.keys().is_empty() {
+        return String::new();
+    }
+    // Scope the keys in a block to avoid conflicting with the other gradients.
+    let mut s: String = gradient
+        .keys()
+        .iter()
+        .enumerate()
+        .map(|(index, key)| {
+            format!(
+                "let t{0} = {1};\nlet r{0} = {2};",
+                index,
+                key.ratio().to_wgsl_string(),
+                key.value.to_wgsl_string()
+            )
+        })
+        .fold("// Gradient\n{\n".into(), |s, key| s + &key + "\n");
+    if gradient.keys().len() == 1 {
+        s + &format!("{} = r0;\n}}\n", target)
+    } else {
+        s += &format!(
+            "let life = particle.age / particle.lifetime;\nif (life <= t0) {{ {} = r0; }}\n",
+            target
+        );
+        let mut s = gradient
+            .keys()
+            .iter()
+            .skip(1)
+            .enumerate()
+            .map(|(index, _key)| {
+                format!(
+                    "else if (life <= t{1}) {{ {2} = mix(r{0}, r{1}, (life - t{0}) / (t{1} - t{0})); }}\n",
+                    index,
+                    index + 1,
+                    target
+                )
+            })
+            .fold(s, |s, key| s + &key);
+        s += &format!(
+            "else {{ {} = r{}; }}\n}}\n",
+            target,
+            gradient.keys().len() - 1
+        );
+        s
+    }
+}
+
+impl ShaderCode for ShockwaveModifier {
+    fn to_shader_code(&self) -> String {
+        format!(
+            r##"// Shockwave ring
+let ring_r = length(in.uv * 2. - 1.);
+if (ring_r > 1.) {{ discard; }}
+let ring_inner = 1. - in.ring_width;
+let ring_soft = {0};
+let ring_alpha = smoothstep(ring_inner - ring_soft, ring_inner, ring_r) * (1. - smoothstep(1. - ring_soft, 1., ring_r));
+color = vec4<f32>(color.rgb, color.a * ring_alpha);
+"##,
+            self.softness.max(0.0001).to_wgsl_string()
+        )
+    }
+}
+
 impl ShaderCode for Gradient<Vec4> {
     fn to_shader_code(&self) -> String {
         if self.keys().is_empty() {
@@ -181,6 +413,317 @@ impl ShaderCode for Gradient<Vec4> {
     }
 }
 
+/// Generate the shader code assigning the value of a color gradient over the particle
+/// lifetime to the `target` shader variable, leaving it unchanged if the gradient is empty.
+fn color_gradient_code(gradient: &Gradient<Vec4>, target: &str) -> String {
+    let keys = gradient.keys();
+    if keys.is_empty() {
+        return String::new();
+    }
+    // Scope the keys in a block to avoid conflicting with the other gradients.
+    let mut s: String = keys
+        .iter()
+        .enumerate()
+        .map(|(index, key)| {
+            format!(
+                "let t{0} = {1};\nlet c{0} = {2};",
+                index,
+                key.ratio().to_wgsl_string(),
+                key.value.to_wgsl_string()
+            )
+        })
+        .fold("{\n".into(), |s, key| s + &key + "\n");
+    if keys.len() == 1 {
+        return s + &format!("{} = c0;\n}}\n", target);
+    }
+    s += &format!(
+        "let life = particle.age / particle.lifetime;\nif (life <= t0) {{ {} = c0; }}\n",
+        target
+    );
+    for index in 0..keys.len() - 1 {
+        s += &format!(
+            "else if (life <= t{1}) {{ {2} = mix(c{0}, c{1}, (life - t{0}) / (t{1} - t{0})); }}\n",
+            index,
+            index + 1,
+            target
+        );
+    }
+    s + &format!("else {{ {} = c{}; }}\n}}\n", target, keys.len() - 1)
+}
+
+impl ShaderCode for ColorBlendModifier {
+    fn to_shader_code(&self) -> String {
+        // Map an attribute of the particle linearly to [0:1] between two bounds.
+        let attribute_weight = |attribute: &str, (min, max): (f32, f32)| {
+            let scale = if max != min { 1. / (max - min) } else { 1e6 };
+            format!(
+                "let blend_w = clamp(({} - {}) * {}, 0., 1.);\n",
+                attribute,
+                min.to_wgsl_string(),
+                scale.to_wgsl_string()
+            )
+        };
+        let weight = match self.weight {
+            // Hash the seed of the particle into a weight, which is stable for the particle
+            // lifetime.
+            ColorBlendWeight::Random((min, max)) => format!(
+                r##"let blend_seed = particle_hash(particle.seed, 0x165667b1u);
+let blend_w = mix({}, {}, f32(blend_seed & 0xffffu) / 65535.);
+"##,
+                min.to_wgsl_string(),
+                max.to_wgsl_string()
+            ),
+            ColorBlendWeight::Speed(range) => attribute_weight("length(particle.vel)", range),
+            ColorBlendWeight::Lifetime(range) => attribute_weight("particle.lifetime", range),
+        };
+        format!(
+            "// Color blend\nvar blend_a = vec4<f32>(1.);\nvar blend_b = vec4<f32>(1.);\n{}{}{}out.color = mix(blend_a, blend_b, vec4<f32>(blend_w));\n",
+            color_gradient_code(&self.gradient_a, "blend_a"),
+            color_gradient_code(&self.gradient_b, "blend_b"),
+            weight
+        )
+    }
+}
+
+/// Convert an RGBA color into an HSVA color, with all components in `[0:1]`.
+fn rgb_to_hsv(color: Vec4) -> Vec4 {
+    let max = color.x.max(color.y).max(color.z);
+    let min = color.x.min(color.y).min(color.z);
+    let delta = max - min;
+    let hue = if delta <= 0. {
+        0.
+    } else if max == color.x {
+        ((color.y - color.z) / delta).rem_euclid(6.)
+    } else if max == color.y {
+        (color.z - color.x) / delta + 2.
+    } else {
+        (color.x - color.y) / delta + 4.
+    };
+    let saturation = if max > 0. { delta / max } else { 0. };
+    Vec4::new(hue / 6., saturation, max, color.w)
+}
+
+/// Generate the shader code of a color gradient interpolated in the HSV color space.
+///
+/// The keys are converted to HSV on the CPU, and the hue is blended over the shortest path
+/// around the color wheel. Keys without saturation have no meaningful hue, so they take the
+/// hue of the previous saturated key, or else of the next one, to avoid sweeping through
+/// unrelated hues when fading from or to white or black.
+fn hsv_gradient_shader_code(gradient: &Gradient<Vec4>) -> String {
+    let keys = gradient.keys();
+    if keys.len() <= 1 {
+        return gradient.to_shader_code();
+    }
+    let mut hsv: Vec<Vec4> = keys.iter().map(|key| rgb_to_hsv(key.value)).collect();
+    for index in 0..hsv.len() {
+        if hsv[index].y > 0. {
+            continue;
+        }
+        let neighbor = hsv[..index]
+            .iter()
+            .rev()
+            .chain(hsv[index + 1..].iter())
+            .find(|c| c.y > 0.);
+        if let Some(neighbor) = neighbor {
+            hsv[index].x = neighbor.x;
+        }
+    }
+    let mut s: String = keys
+        .iter()
+        .zip(hsv.iter())
+        .enumerate()
+        .map(|(index, (key, hsv))| {
+            format!(
+                "let t{0} = {1};\nlet c{0} = {2};",
+                index,
+                key.ratio().to_wgsl_string(),
+                hsv.to_wgsl_string()
+            )
+        })
+        .fold("// HSV gradient\n".into(), |s, key| s + &key + "\n");
+    s += "var hsv = c0;\nlet life = particle.age / particle.lifetime;\nif (life <= t0) { hsv = c0; }\n";
+    for index in 0..keys.len() - 1 {
+        s += &format!(
+            "else if (life <= t{1}) {{ let f = (life - t{0}) / (t{1} - t{0}); let dh = c{1}.x - c{0}.x; hsv = vec4<f32>(fract(c{0}.x + (dh - round(dh)) * f), mix(c{0}.yzw, c{1}.yzw, vec3<f32>(f))); }}\n",
+            index,
+            index + 1
+        );
+    }
+    s += &format!("else {{ hsv = c{}; }}\n", keys.len() - 1);
+    s + r##"let hsv_p = abs(fract(hsv.xxx + vec3<f32>(1., 2. / 3., 1. / 3.)) * 6. - vec3<f32>(3.));
+out.color = vec4<f32>(hsv.z * mix(vec3<f32>(1.), clamp(hsv_p - vec3<f32>(1.), vec3<f32>(0.), vec3<f32>(1.)), vec3<f32>(hsv.y)), hsv.w);
+"##
+}
+
+impl ShaderCode for DistanceFogModifier {
+    fn to_shader_code(&self) -> String {
+        format!(
+            r##"// Distance fog
+let fog_color = {0};
+let fog_dist = length(particle.pos - view.world_position);
+let fog = clamp((fog_dist - {1}) / max({2} - {1}, 0.0001), 0., 1.) * fog_color.a;
+out.color = vec4<f32>(mix(out.color.rgb, fog_color.rgb, vec3<f32>(fog)), out.color.a);
+"##,
+            self.color.to_wgsl_string(),
+            self.start.to_wgsl_string(),
+            self.end.to_wgsl_string()
+        )
+    }
+}
+
+impl ShaderCode for InitSizeModifier {
+    fn to_shader_code(&self) -> String {
+        if let Value::Single(size) = self.size {
+            return format!("// Init size\nsize = size * {};\n", size.to_wgsl_string());
+        }
+        // Hash the seed of the particle into two factors, which are stable for the particle
+        // lifetime.
+        format!(
+            r##"// Init size
+let size_seed = particle_hash(particle.seed, 0x5bd1e995u);
+size = size * {};
+"##,
+            self.size.to_wgsl_sampled(
+                "f32(size_seed & 0xffffu) / 65535.",
+                "f32(size_seed >> 16u) / 65535."
+            )
+        )
+    }
+}
+
+impl ShaderCode for InitRotationModifier {
+    fn to_shader_code(&self) -> String {
+        // Hash the seed of the particle into two factors, which are stable for the particle
+        // lifetime.
+        let mut s = "// Init rotation\n".to_string();
+        if self.angle.is_random() || self.angular_velocity.is_random() {
+            s += "let rotation_seed = particle_hash(particle.seed, 0x68e31da4u);\n";
+        }
+        let low = "f32(rotation_seed & 0xffffu) / 65535.";
+        let high = "f32(rotation_seed >> 16u) / 65535.";
+        s += &format!(
+            "rotation = rotation + {} + {} * particle.age;\n",
+            self.angle.to_wgsl_sampled(low, high),
+            self.angular_velocity.to_wgsl_sampled(high, low)
+        );
+        s
+    }
+}
+
+impl ShaderCode for InitColorModifier {
+    fn to_shader_code(&self) -> String {
+        // Hash the seed of the particle into a factor, which is stable for the particle
+        // lifetime.
+        let mut s = r##"// Init color
+let color_seed = particle_hash(particle.seed, 0x27d4eb2du);
+let color_t = f32(color_seed & 0xffffu) / 65536.;
+"##
+        .to_string();
+        match &self.color {
+            ColorSelection::Uniform((a, b)) => {
+                s += &format!(
+                    "let init_color = mix({}, {}, vec4<f32>(color_t));\n",
+                    a.to_wgsl_string(),
+                    b.to_wgsl_string()
+                );
+            }
+            ColorSelection::Palette(colors) if colors.is_empty() => {
+                s += "let init_color = vec4<f32>(1.);\n";
+            }
+            ColorSelection::Palette(colors) => {
+                let count = colors.len();
+                let colors = colors
+                    .iter()
+                    .map(|c| c.to_wgsl_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                s += &format!(
+                    "var init_palette = array<vec4<f32>, {0}>({1});\nlet init_color = init_palette[min(u32(color_t * {0}.), {0}u - 1u)];\n",
+                    count,
+                    colors
+                );
+            }
+            ColorSelection::Gradient(gradient) => {
+                let keys = gradient.keys();
+                if keys.is_empty() {
+                    s += "let init_color = vec4<f32>(1.);\n";
+                    return s;
+                }
+                for (index, key) in keys.iter().enumerate() {
+                    s += &format!(
+                        "let ct{0} = {1};\nlet cc{0} = {2};\n",
+                        index,
+                        key.ratio().to_wgsl_string(),
+                        key.value.to_wgsl_string()
+                    );
+                }
+                s += "var init_color = cc0;\n";
+                if keys.len() > 1 {
+                    s += "if (color_t <= ct0) { init_color = cc0; }\n";
+                    for index in 0..keys.len() - 1 {
+                        s += &format!(
+                            "else if (color_t <= ct{1}) {{ init_color = mix(cc{0}, cc{1}, (color_t - ct{0}) / (ct{1} - ct{0})); }}\n",
+                            index,
+                            index + 1
+                        );
+                    }
+                    s += &format!("else {{ init_color = cc{}; }}\n", keys.len() - 1);
+                }
+            }
+        }
+        s
+    }
+}
+
+impl ShaderCode for FlipbookModifier {
+    fn to_shader_code(&self) -> String {
+        let cols = self.sprite_grid_size.x.max(1);
+        let rows = self.sprite_grid_size.y.max(1);
+        let frame_code = if self.random_row {
+            // Hash the seed of the particle into a row, which is stable for the particle
+            // lifetime.
+            format!(
+                r##"let flipbook_row = particle_hash(particle.seed, 0x7feb352du) % {1}u;
+let flipbook_col = min(u32(flipbook_life * {0}.), {0}u - 1u);"##,
+                cols, rows
+            )
+        } else {
+            format!(
+                r##"let flipbook_frame = min(u32(flipbook_life * {0}.), {0}u - 1u);
+let flipbook_row = flipbook_frame / {1}u;
+let flipbook_col = flipbook_frame % {1}u;"##,
+                cols * rows,
+                cols
+            )
+        };
+        format!(
+            r##"// Flipbook
+let flipbook_life = clamp(particle.age / particle.lifetime, 0., 1.);
+{0}
+out.uv = (vec2<f32>(f32(flipbook_col), f32(flipbook_row)) + vertex_uv) / vec2<f32>({1}., {2}.);
+"##,
+            frame_code, cols, rows
+        )
+    }
+}
+
+impl ShaderCode for ParticleGeometry {
+    fn to_shader_code(&self) -> String {
+        match self {
+            ParticleGeometry::SphereImpostor => r##"// Sphere impostor
+let sphere_xy = in.uv * 2. - 1.;
+let sphere_r2 = dot(sphere_xy, sphere_xy);
+if (sphere_r2 > 1.) { discard; }
+let sphere_normal = vec3<f32>(sphere_xy, sqrt(1. - sphere_r2));
+let sphere_light = max(dot(sphere_normal, normalize(vec3<f32>(-0.4, 0.6, 0.7))), 0.);
+color = vec4<f32>(color.rgb * (0.3 + 0.7 * sphere_light), color.a);
+"##
+            .to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
 /// Simulation parameters.
 #[derive(Debug, Default, Clone, Copy)]
 pub(crate) struct SimParams {
@@ -188,6 +731,10 @@ pub(crate) struct SimParams {
     time: f64,
     /// Frame timestep.
     dt: f32,
+    /// Period after which the simulation time wraps back to zero, or zero if it doesn't.
+    loop_period: f32,
+    /// Global user values.
+    globals: [Vec4; EffectGlobals::COUNT],
 }
 
 /// GPU representation of [`SimParams`].
@@ -196,6 +743,14 @@ pub(crate) struct SimParams {
 struct SimParamsUniform {
     dt: f32,
     time: f32,
+    loop_period: f32,
+    // Explicit padding to align the globals to 16 bytes, as in the shaders
+    __pad1: f32,
+    // Global user values, declared as an array in the shaders
+    global0: Vec4,
+    global1: Vec4,
+    global2: Vec4,
+    global3: Vec4,
 }
 
 impl Default for SimParamsUniform {
@@ -203,15 +758,28 @@ impl Default for SimParamsUniform {
         SimParamsUniform {
             dt: 0.04,
             time: 0.0,
+            loop_period: 0.0,
+            __pad1: 0.0,
+            global0: Vec4::ZERO,
+            global1: Vec4::ZERO,
+            global2: Vec4::ZERO,
+            global3: Vec4::ZERO,
         }
     }
 }
 
 impl From<SimParams> for SimParamsUniform {
     fn from(src: SimParams) -> Self {
+        let [global0, global1, global2, global3] = src.globals;
         SimParamsUniform {
             dt: src.dt,
             time: src.time as f32,
+            loop_period: src.loop_period,
+            __pad1: 0.0,
+            global0,
+            global1,
+            global2,
+            global3,
         }
     }
 }
@@ -257,14 +825,87 @@ struct SpawnerParams {
 
     /// Force field components. One PullingForceFieldParam takes up 32 bytes.
     force_field: [ForceFieldStd430; FFNUM],
-    ///
+    /// Padding.
     __pad0: Vec3,
     /// Spawn seed, for randomized modifiers.
     seed: u32,
+    /// Position of the last link of the chain before this frame, for [`PositionChainModifier`].
+    ///
+    /// [`PositionChainModifier`]: crate::PositionChainModifier
+    chain_head: Vec3,
+    /// Multiplier of the initial speed of newly spawned particles.
+    speed_scale: f32,
+    /// Offset between two links of the chain spawned this frame.
+    chain_step: Vec3,
+    /// Total number of particles to spawn this frame. Unlike `spawn`, this is not modified
+    /// by the update shader.
+    spawn_total: i32,
+    /// Position the particles are attracted to, for [`ScreenSpaceAttractor`].
+    attractor: Vec3,
+    /// Acceleration of the particles toward `attractor`, or zero if none.
+    attractor_accel: f32,
+    /// Burst progress at the first particle spawned this frame, for [`PositionSweepModifier`].
+    ///
+    /// [`PositionSweepModifier`]: crate::PositionSweepModifier
+    sweep_start: f32,
+    /// Burst progress at the last particle spawned this frame.
+    sweep_end: f32,
+    /// Time step of the effect this frame, which differs from the simulation time step for
+    /// effects with an [`EffectTimeControl`].
+    ///
+    /// [`EffectTimeControl`]: crate::EffectTimeControl
+    dt: f32,
+    /// Non-zero to kill all particles of the effect this frame, when it restarts.
+    reset: u32,
+    /// Age of the first particle spawned this frame, for [`SpawnPulses`].
+    ///
+    /// [`SpawnPulses`]: crate::SpawnPulses
+    age_start: f32,
+    /// Age of the last particle spawned this frame.
+    age_end: f32,
+}
+
+/// Arguments of the indirect draw call of a batch, as read by `draw_indirect()`.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Pod, Zeroable)]
+struct DrawIndirectArgs {
+    vertex_count: u32,
+    instance_count: u32,
+    first_vertex: u32,
+    first_instance: u32,
+}
+
+/// Properties of the emitter of a batch of effect instances, exposed to the render shader.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+struct EmitterParams {
+    /// Color of the emitter, in linear RGBA.
+    color: [f32; 4],
+    /// Largest scale of the emitter transform.
+    scale: f32,
+    /// Multiplier of the particle size from the [`EffectVariant`] and [`EffectStrength`] of
+    /// the effect.
     ///
-    __pad1: Vec3,
+    /// [`EffectVariant`]: crate::EffectVariant
+    /// [`EffectStrength`]: crate::EffectStrength
+    size_multiplier: f32,
+    /// Index of the color gradient of the [`EffectVariant`] of the effect, if any.
     ///
-    __pad2: f32,
+    /// [`EffectVariant`]: crate::EffectVariant
+    variant: u32,
+    __pad: f32,
+}
+
+impl Default for EmitterParams {
+    fn default() -> Self {
+        Self {
+            color: [1.; 4],
+            scale: 1.,
+            size_multiplier: 1.,
+            variant: 0,
+            __pad: 0.,
+        }
+    }
 }
 
 pub struct ParticlesUpdatePipeline {
@@ -272,7 +913,9 @@ pub struct ParticlesUpdatePipeline {
     particles_buffer_layout: BindGroupLayout,
     spawner_buffer_layout: BindGroupLayout,
     indirect_buffer_layout: BindGroupLayout,
-    pipeline_layout: PipelineLayout,
+    init_data_layout: BindGroupLayout,
+    user_data_layout: BindGroupLayout,
+    user_data_texture_layout: BindGroupLayout,
 }
 
 impl FromWorld for ParticlesUpdatePipeline {
@@ -333,18 +976,30 @@ impl FromWorld for ParticlesUpdatePipeline {
         );
         let spawner_buffer_layout =
             render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-                entries: &[BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStages::COMPUTE,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: true,
-                        min_binding_size: BufferSize::new(
-                            SpawnerParams::std430_size_static() as u64
-                        ),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: true,
+                            min_binding_size: BufferSize::new(
+                                SpawnerParams::std430_size_static() as u64
+                            ),
+                        },
+                        count: None,
                     },
-                    count: None,
-                }],
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: true,
+                            min_binding_size: BufferSize::new(size_of::<i32>() as u64),
+                        },
+                        count: None,
+                    },
+                ],
                 label: Some("particles_update_spawner_buffer_layout"),
             });
 
@@ -356,38 +1011,100 @@ impl FromWorld for ParticlesUpdatePipeline {
                     ty: BindingType::Buffer {
                         ty: BufferBindingType::Storage { read_only: false },
                         has_dynamic_offset: true,
-                        min_binding_size: BufferSize::new(std::mem::size_of::<u32>() as u64),
+                        min_binding_size: BufferSize::new(size_of::<u32>() as u64),
                     },
                     count: None,
                 }],
                 label: Some("particles_update_indirect_buffer_layout"),
             });
 
-        let pipeline_layout = render_device.create_pipeline_layout(&PipelineLayoutDescriptor {
-            label: Some("particles_update_pipeline_layout"),
-            bind_group_layouts: &[
-                &sim_params_layout,
-                &particles_buffer_layout,
-                &spawner_buffer_layout,
-                &indirect_buffer_layout,
-            ],
-            push_constant_ranges: &[],
+        let init_data_layout = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: BufferSize::new(size_of::<f32>() as u64),
+                },
+                count: None,
+            }],
+            label: Some("particles_update_init_data_layout"),
         });
 
+        let (user_data_layout, user_data_texture_layout) =
+            create_user_data_layouts(&render_device, ShaderStages::COMPUTE, "update");
+
         ParticlesUpdatePipeline {
             sim_params_layout,
             particles_buffer_layout,
             spawner_buffer_layout,
             indirect_buffer_layout,
-            pipeline_layout,
+            init_data_layout,
+            user_data_layout,
+            user_data_texture_layout,
         }
     }
 }
 
+/// Create the layouts of the custom user bind group of a [`UserBindGroupModifier`], without and
+/// with a texture, visible to the given shader stages.
+///
+/// [`UserBindGroupModifier`]: crate::UserBindGroupModifier
+fn create_user_data_layouts(
+    render_device: &RenderDevice,
+    visibility: ShaderStages,
+    stage: &str,
+) -> (BindGroupLayout, BindGroupLayout) {
+    let user_data_entry = BindGroupLayoutEntry {
+        binding: 0,
+        visibility,
+        ty: BindingType::Buffer {
+            ty: BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+
+    let user_data_layout = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        entries: &[user_data_entry],
+        label: Some(&format!("particles_user_data_layout_{}", stage)),
+    });
+
+    let user_data_texture_layout =
+        render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[
+                user_data_entry,
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some(&format!("particles_user_data_texture_layout_{}", stage)),
+        });
+
+    (user_data_layout, user_data_texture_layout)
+}
+
 pub struct ParticlesRenderPipeline {
     view_layout: BindGroupLayout,
     particles_buffer_layout: BindGroupLayout,
     material_layout: BindGroupLayout,
+    user_data_layout: BindGroupLayout,
+    user_data_texture_layout: BindGroupLayout,
 }
 
 impl FromWorld for ParticlesRenderPipeline {
@@ -396,16 +1113,40 @@ impl FromWorld for ParticlesRenderPipeline {
         let render_device = world.get_resource::<RenderDevice>().unwrap();
 
         let view_layout = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-            entries: &[BindGroupLayoutEntry {
-                binding: 0,
-                visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
-                ty: BindingType::Buffer {
-                    ty: BufferBindingType::Uniform,
-                    has_dynamic_offset: true,
-                    min_binding_size: BufferSize::new(ViewUniform::std140_size_static() as u64),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: BufferSize::new(ViewUniform::std140_size_static() as u64),
+                    },
+                    count: None,
                 },
-                count: None,
-            }],
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(
+                            SimParamsUniform::std140_size_static() as u64
+                        ),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: BufferSize::new(size_of::<EmitterParams>() as u64),
+                    },
+                    count: None,
+                },
+            ],
             label: Some("particles_view_layout_render"),
         });
 
@@ -428,7 +1169,7 @@ impl FromWorld for ParticlesRenderPipeline {
             entries: &[
                 BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: ShaderStages::FRAGMENT,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
                     ty: BindingType::Texture {
                         multisampled: false,
                         sample_type: TextureSampleType::Float { filterable: true },
@@ -438,7 +1179,7 @@ impl FromWorld for ParticlesRenderPipeline {
                 },
                 BindGroupLayoutEntry {
                     binding: 1,
-                    visibility: ShaderStages::FRAGMENT,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
                     ty: BindingType::Sampler(SamplerBindingType::Filtering),
                     count: None,
                 },
@@ -446,54 +1187,195 @@ impl FromWorld for ParticlesRenderPipeline {
             label: Some("particles_material_layout_render"),
         });
 
+        let (user_data_layout, user_data_texture_layout) = create_user_data_layouts(
+            &render_device,
+            ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+            "render",
+        );
+
         ParticlesRenderPipeline {
             view_layout,
             particles_buffer_layout,
             material_layout,
+            user_data_layout,
+            user_data_texture_layout,
         }
     }
 }
 
-#[derive(Default, Clone, Hash, PartialEq, Eq)]
+/// Key used to specialize the update compute pipeline of an effect.
+///
+/// The key contains the WGSL code injected into the update shader template.
+#[derive(Debug, Default, Clone, Hash, PartialEq, Eq)]
 pub struct ParticleUpdatePipelineKey {
     /// Code for the position initialization of newly emitted particles.
     position_code: String,
+    /// Code for the integration of the particle motion, including the force field.
     force_field_code: String,
+    /// Declarations at module scope required by the extension modifiers.
+    extension_includes: String,
+    /// Whether the init code reads the storage buffer of the [`InitData`] of the effect.
+    init_data: bool,
+    /// WGSL declarations of the custom user bind group of a [`UserBindGroupModifier`], if any.
+    ///
+    /// [`UserBindGroupModifier`]: crate::UserBindGroupModifier
+    user_declarations: Option<String>,
+    /// Whether the custom user bind group also binds a texture and its sampler.
+    user_texture: bool,
 }
 
-impl SpecializedComputePipeline for ParticlesUpdatePipeline {
-    type Key = ParticleUpdatePipelineKey;
+impl ParticleUpdatePipelineKey {
+    /// Create the key of the update pipeline of an effect with the given layouts.
+    pub(crate) fn new(init_layout: &InitLayout, update_layout: &UpdateLayout) -> Self {
+        Self {
+            position_code: init_position_code(init_layout),
+            force_field_code: update_force_field_code(update_layout),
+            extension_includes: String::new(),
+            init_data: init_layout.init_data.is_some(),
+            user_declarations: None,
+            user_texture: false,
+        }
+    }
 
-    fn specialize(&self, key: Self::Key, render_device: &RenderDevice) -> ComputePipeline {
-        let mut source =
-            PARTICLES_UPDATE_SHADER_TEMPLATE.replace("{{INIT_POS_VEL}}", &key.position_code);
+    /// Append the code of the init and update extension modifiers of an effect.
+    pub(crate) fn with_extensions(mut self, extensions: &ExtensionCode) -> Self {
+        self.position_code += &extensions.init;
+        self.force_field_code += &extensions.update;
+        self.extension_includes += &extensions.update_includes;
+        self
+    }
 
-        source = source.replace("{{FORCE_FIELD_CODE}}", &key.force_field_code);
+    /// Code for the position and velocity initialization of newly emitted particles.
+    pub fn position_code(&self) -> &str {
+        &self.position_code
+    }
 
-        //trace!("Specialized compute pipeline:\n{}", source);
+    /// Code for the integration of the particle motion, including the force field.
+    pub fn force_field_code(&self) -> &str {
+        &self.force_field_code
+    }
 
-        let shader_module = render_device.create_shader_module(&ShaderModuleDescriptor {
-            label: Some("particles_update.wgsl"),
-            source: ShaderSource::Wgsl(Cow::Owned(source)),
-        });
+    /// Declarations at module scope required by the extension modifiers.
+    pub fn extension_includes(&self) -> &str {
+        &self.extension_includes
+    }
 
-        render_device.create_compute_pipeline(&RawComputePipelineDescriptor {
-            label: Some("particles_update_compute_pipeline"),
-            layout: Some(&self.pipeline_layout),
+    /// Whether the init code reads the storage buffer of the [`InitData`] of the effect.
+    pub fn has_init_data(&self) -> bool {
+        self.init_data
+    }
+
+    /// Whether the pipeline binds the custom user bind group of a [`UserBindGroupModifier`].
+    ///
+    /// [`UserBindGroupModifier`]: crate::UserBindGroupModifier
+    pub fn has_user_data(&self) -> bool {
+        self.user_declarations.is_some()
+    }
+
+    /// Index of the custom user bind group, which comes after all the other bind groups.
+    fn user_group(&self) -> u32 {
+        4 + self.init_data as u32
+    }
+
+    /// Generate the full source code of the update shader for this key.
+    pub fn to_shader_source(&self) -> String {
+        let user_bindings = self
+            .user_declarations
+            .as_ref()
+            .map_or(String::new(), |decl| {
+                decl.replace("{{GROUP}}", &self.user_group().to_string())
+            });
+        let init_bindings = if self.init_data { INIT_BINDINGS } else { "" };
+        PARTICLES_UPDATE_SHADER_TEMPLATE
+            .replace("{{INIT_BINDINGS}}", init_bindings)
+            .replace("{{USER_BINDINGS}}", &user_bindings)
+            .replace("{{INIT_POS_VEL}}", &self.position_code)
+            .replace("{{FORCE_FIELD_CODE}}", &self.force_field_code)
+            .replace("{{EXTENSION_INCLUDES}}", &self.extension_includes)
+    }
+}
+
+/// Generate the source code of the render shader of an effect with the given extension code
+/// and no other modifier, before resolving the shader definitions.
+pub(crate) fn render_shader_source(extensions: &ExtensionCode) -> String {
+    PARTICLES_RENDER_SHADER_TEMPLATE
+        .replace("{{USER_BINDINGS}}", "")
+        .replace("{{EXTENSION_INCLUDES}}", &extensions.render_includes)
+        .replace("{{VERTEX_MODIFIERS}}", &extensions.vertex)
+        .replace("{{VERTEX_ORIENT}}", "")
+        .replace("{{VERTEX_DEFORM}}", "")
+        .replace("{{VERTEX_DEPTH_BIAS}}", "")
+        .replace(
+            "{{PARTICLE_TEXTURE_MAPPING}}",
+            &TextureChannelMapping::default().to_shader_code(),
+        )
+        .replace("{{FRAGMENT_MODIFIERS}}", &extensions.fragment)
+}
+
+impl SpecializedComputePipeline for ParticlesUpdatePipeline {
+    type Key = ParticleUpdatePipelineKey;
+
+    fn specialize(&self, key: Self::Key, render_device: &RenderDevice) -> ComputePipeline {
+        let source = key.to_shader_source();
+
+        //trace!("Specialized compute pipeline:\n{}", source);
+
+        let shader_module = render_device.create_shader_module(&ShaderModuleDescriptor {
+            label: Some("particles_update.wgsl"),
+            source: ShaderSource::Wgsl(Cow::Owned(source)),
+        });
+
+        let mut bind_group_layouts = vec![
+            &*self.sim_params_layout,
+            &*self.particles_buffer_layout,
+            &*self.spawner_buffer_layout,
+            &*self.indirect_buffer_layout,
+        ];
+        if key.init_data {
+            bind_group_layouts.push(&*self.init_data_layout);
+        }
+        if key.user_declarations.is_some() {
+            bind_group_layouts.push(if key.user_texture {
+                &*self.user_data_texture_layout
+            } else {
+                &*self.user_data_layout
+            });
+        }
+        let pipeline_layout = render_device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("particles_update_pipeline_layout"),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+        render_device.create_compute_pipeline(&RawComputePipelineDescriptor {
+            label: Some("particles_update_compute_pipeline"),
+            layout: Some(&pipeline_layout),
             module: &shader_module,
             entry_point: "main",
         })
     }
 }
 
+/// Type of camera a render pipeline is specialized for, when both the `2d` and `3d` features
+/// are enabled.
 #[cfg(all(feature = "2d", feature = "3d"))]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum PipelineMode {
+    /// Render through a 2D camera, in the `Transparent2d` phase.
     Camera2d,
+    /// Render through a 3D camera, in the `Transparent3d` phase.
     Camera3d,
 }
 
-#[derive(Clone, Hash, PartialEq, Eq)]
+/// Key used to specialize the render pipeline of an effect for a given view.
+///
+/// The key combines the properties of the effect, like its configured render shader and its
+/// textures, with the properties of the view the effect is rendered into, like the number of
+/// MSAA samples. Custom specialization dimensions can be added with a [`ShaderDefsModifier`],
+/// whose shader definitions are part of the key.
+///
+/// [`ShaderDefsModifier`]: crate::ShaderDefsModifier
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct ParticleRenderPipelineKey {
     /// Render shader, with template applied, but not preprocessed yet.
     shader: Handle<Shader>,
@@ -501,10 +1383,21 @@ pub struct ParticleRenderPipelineKey {
     /// Define a texture sampled to modulate the particle color.
     /// This key requires the presence of UV coordinates on the particle vertices.
     particle_texture: Option<Handle<Image>>,
+    /// Key: COLOR_RAMP
+    /// Define a ramp texture sampled by particle age to produce the particle color.
+    color_ramp: Option<Handle<Image>>,
+    /// Define a custom user bind group, with a buffer and optionally a texture.
+    user_data: bool,
+    /// Define a texture in the custom user bind group.
+    user_texture: bool,
     /// For dual-mode configurations only, the actual mode of the current render
     /// pipeline. Otherwise the mode is implicitly determined by the active feature.
     #[cfg(all(feature = "2d", feature = "3d"))]
     pipeline_mode: PipelineMode,
+    /// Number of MSAA samples of the view.
+    msaa_samples: u32,
+    /// Custom shader definitions added by the effect.
+    shader_defs: Vec<String>,
 }
 
 impl Default for ParticleRenderPipelineKey {
@@ -512,12 +1405,64 @@ impl Default for ParticleRenderPipelineKey {
         ParticleRenderPipelineKey {
             shader: PARTICLES_RENDER_SHADER_HANDLE.typed::<Shader>(),
             particle_texture: None,
+            color_ramp: None,
+            user_data: false,
+            user_texture: false,
             #[cfg(all(feature = "2d", feature = "3d"))]
             pipeline_mode: PipelineMode::Camera3d,
+            msaa_samples: 1,
+            shader_defs: vec![],
         }
     }
 }
 
+impl ParticleRenderPipelineKey {
+    /// Render shader, with template applied, but not preprocessed yet.
+    pub fn shader(&self) -> &Handle<Shader> {
+        &self.shader
+    }
+
+    /// Texture modulating the particle color, if any.
+    pub fn particle_texture(&self) -> Option<&Handle<Image>> {
+        self.particle_texture.as_ref()
+    }
+
+    /// Ramp texture sampled by particle age to produce the particle color, if any.
+    pub fn color_ramp(&self) -> Option<&Handle<Image>> {
+        self.color_ramp.as_ref()
+    }
+
+    /// Whether the pipeline binds the custom user bind group of a [`UserBindGroupModifier`].
+    ///
+    /// [`UserBindGroupModifier`]: crate::UserBindGroupModifier
+    pub fn has_user_data(&self) -> bool {
+        self.user_data
+    }
+
+    /// Whether the custom user bind group contains a texture and its sampler.
+    pub fn has_user_texture(&self) -> bool {
+        self.user_texture
+    }
+
+    /// Type of camera the pipeline renders through.
+    #[cfg(all(feature = "2d", feature = "3d"))]
+    pub fn pipeline_mode(&self) -> PipelineMode {
+        self.pipeline_mode
+    }
+
+    /// Number of MSAA samples of the view the pipeline renders into.
+    pub fn msaa_samples(&self) -> u32 {
+        self.msaa_samples
+    }
+
+    /// Custom shader definitions added by the effect with a [`ShaderDefsModifier`].
+    ///
+    /// [`ShaderDefsModifier`]: crate::ShaderDefsModifier
+    pub fn shader_defs(&self) -> &[String] {
+        &self.shader_defs
+    }
+}
+
 impl SpecializedRenderPipeline for ParticlesRenderPipeline {
     type Key = ParticleRenderPipelineKey;
 
@@ -564,7 +1509,7 @@ impl SpecializedRenderPipeline for ParticlesRenderPipeline {
             self.view_layout.clone(),
             self.particles_buffer_layout.clone(),
         ];
-        let mut shader_defs = vec![];
+        let mut shader_defs = key.shader_defs.clone();
 
         // Key: PARTICLE_TEXTURE
         if key.particle_texture.is_some() {
@@ -579,6 +1524,21 @@ impl SpecializedRenderPipeline for ParticlesRenderPipeline {
             // vertex_buffer_layout.array_stride += 8;
         }
 
+        // Key: COLOR_RAMP
+        if key.color_ramp.is_some() {
+            layout.push(self.material_layout.clone());
+            shader_defs.push("COLOR_RAMP".to_string());
+        }
+
+        // Custom user bind group, always last
+        if key.user_data {
+            if key.user_texture {
+                layout.push(self.user_data_texture_layout.clone());
+            } else {
+                layout.push(self.user_data_layout.clone());
+            }
+        }
+
         #[cfg(all(feature = "2d", feature = "3d"))]
         let depth_stencil = match key.pipeline_mode {
             // Bevy's Transparent2d render phase doesn't support a depth-stencil buffer.
@@ -635,7 +1595,7 @@ impl SpecializedRenderPipeline for ParticlesRenderPipeline {
             },
             depth_stencil,
             multisample: MultisampleState {
-                count: 4, // TODO: Res<Msaa>.samples
+                count: key.msaa_samples,
                 mask: !0,
                 alpha_to_coverage_enabled: false,
             },
@@ -654,12 +1614,38 @@ pub struct ExtractedEffect {
     /// Number of particles to spawn this frame for the effect.
     /// Obtained from calling [`Spawner::tick()`] on the source effect instance.
     pub spawn_count: u32,
+    /// Seed of the GPU random generator for the particles spawned this frame.
+    pub seed: u32,
     /// Global transform of the effect origin.
     pub transform: Mat4,
     /// Constant acceleration applied to all particles.
     pub accel: Vec3,
     /// Force field applied to all particles in the "update" phase.
     force_field: [ForceFieldParam; FFNUM],
+    /// Multiplier of the initial speed of newly spawned particles.
+    pub speed_scale: f32,
+    /// Position of the last link of the chain before this frame, for [`PositionChainModifier`].
+    ///
+    /// [`PositionChainModifier`]: crate::PositionChainModifier
+    pub chain_head: Vec3,
+    /// Offset between two links of the chain spawned this frame.
+    pub chain_step: Vec3,
+    /// Position the particles are attracted to.
+    pub attractor: Vec3,
+    /// Acceleration of the particles toward `attractor`, or zero if none.
+    pub attractor_accel: f32,
+    /// Range of burst progress swept by the particles spawned this frame.
+    pub sweep: [f32; 2],
+    /// Age of the first and last particle spawned this frame, in seconds.
+    pub age: [f32; 2],
+    /// Time step of the effect this frame, in seconds.
+    pub dt: f32,
+    /// Whether the effect restarts this frame, killing all its particles.
+    pub reset: bool,
+    /// Whether to read back the particles blocking the completion of the effect.
+    pub check_completion: bool,
+    /// Properties of the emitter, variant, and strength exposed to the render shader.
+    emitter: EmitterParams,
     /// Particles tint to modulate with the texture image.
     pub color: Color,
     pub rect: Rect<f32>,
@@ -668,12 +1654,29 @@ pub struct ExtractedEffect {
     pub has_image: bool, // TODO -> use flags
     /// Texture to modulate the particle color.
     pub image_handle_id: HandleId,
+    pub has_color_ramp: bool, // TODO -> use flags
+    /// Ramp texture sampled by particle age to produce the particle color.
+    pub color_ramp_handle_id: HandleId,
     /// Render shader.
     pub shader: Handle<Shader>,
     /// Update position code.
     pub position_code: String,
     /// Update force field code.
     pub force_field_code: String,
+    /// Update extension includes code.
+    pub extension_includes: String,
+    /// Whether the update code reads the [`InitData`] of the effect asset.
+    pub has_init_data: bool,
+    /// Geometry of a single particle.
+    pub geometry: ParticleGeometry,
+    /// Custom shader definitions of the render pipeline.
+    pub shader_defs: Vec<String>,
+    /// Content of the buffer of the custom user bind group, if any.
+    pub user_data: Option<Vec<u8>>,
+    /// WGSL declarations of the custom user bind group, if any.
+    pub user_declarations: Option<String>,
+    /// Texture of the custom user bind group, if any.
+    pub user_texture_handle_id: Option<HandleId>,
 }
 
 /// Extracted data for newly-added [`ParticleEffect`] component requiring a new GPU allocation.
@@ -703,18 +1706,74 @@ pub struct ExtractedEffects {
 #[derive(Default)]
 pub struct EffectAssetEvents {
     pub images: Vec<AssetEvent<Image>>,
+    /// New content of the [`InitData`] buffer of the effect assets, or `None` if the asset
+    /// was removed.
+    pub init_data: Vec<(Handle<EffectAsset>, Option<Vec<f32>>)>,
 }
 
 pub fn extract_effect_events(
     mut render_world: ResMut<RenderWorld>,
     mut image_events: EventReader<AssetEvent<Image>>,
+    effects: Res<Assets<EffectAsset>>,
+    meshes: Res<Assets<Mesh>>,
+    mut effect_events: EventReader<AssetEvent<EffectAsset>>,
+    mut mesh_events: EventReader<AssetEvent<Mesh>>,
 ) {
     trace!("extract_effect_events");
+
+    // Collect the init data of the effect assets not uploaded yet, or which changed since,
+    // either directly or through the mesh they're built from.
+    let mut changed_effects = HashSet::default();
+    let mut new_init_data = vec![];
+    for event in effect_events.iter() {
+        match event {
+            AssetEvent::Created { .. } => {}
+            AssetEvent::Modified { handle } => {
+                changed_effects.insert(handle.id);
+            }
+            AssetEvent::Removed { handle } => new_init_data.push((handle.clone_weak(), None)),
+        }
+    }
+    let changed_meshes: HashSet<HandleId> = mesh_events
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Created { .. } => None,
+            AssetEvent::Modified { handle } | AssetEvent::Removed { handle } => Some(handle.id),
+        })
+        .collect();
+    let effects_meta = render_world.get_resource::<EffectsMeta>().unwrap();
+    for (id, asset) in effects.iter() {
+        let init_data = if let Some(init_data) = &asset.init_layout.init_data {
+            init_data
+        } else {
+            continue;
+        };
+        let changed = changed_effects.contains(&id)
+            || matches!(init_data, InitData::Mesh(mesh) if changed_meshes.contains(&mesh.id));
+        if !changed && effects_meta.init_buffers.contains_key(&Handle::weak(id)) {
+            continue;
+        }
+        let values = match init_data {
+            InitData::Values(values) => Some(values.clone()),
+            // Meshes with an unsupported topology have no triangle
+            InitData::Mesh(mesh) => meshes
+                .get(mesh)
+                .map(|mesh| PositionMeshModifier::init_data(mesh).unwrap_or_else(|| vec![0.])),
+        };
+        if let Some(values) = values {
+            new_init_data.push((Handle::weak(id), Some(values)));
+        }
+    }
+
     let mut events = render_world
         .get_resource_mut::<EffectAssetEvents>()
         .unwrap();
-    let EffectAssetEvents { ref mut images } = *events;
+    let EffectAssetEvents {
+        ref mut images,
+        ref mut init_data,
+    } = *events;
     images.clear();
+    *init_data = new_init_data;
 
     for image in image_events.iter() {
         // AssetEvent: !Clone
@@ -732,6 +1791,23 @@ pub fn extract_effect_events(
     }
 }
 
+/// Find the entities with allocated GPU data which no longer have a [`ParticleEffect`] component.
+///
+/// The removal trackers of the main world are cleared at the end of its update, before the
+/// extraction, so [`RemovedComponents`] misses the effects despawned during the frame, for
+/// example with `despawn_recursive()` on the root of a [`CompositeEffect`].
+///
+/// [`CompositeEffect`]: crate::CompositeEffect
+fn find_removed_effects<'a>(
+    allocated: impl Iterator<Item = &'a Entity>,
+    live_effects: &Query<(), With<ParticleEffect>>,
+) -> Vec<Entity> {
+    allocated
+        .filter(|entity| live_effects.get(**entity).is_err())
+        .copied()
+        .collect()
+}
+
 /// System extracting data for rendering of all active [`ParticleEffect`] components.
 ///
 /// Extract rendering data for all [`ParticleEffect`] components in the world which are
@@ -740,18 +1816,34 @@ pub fn extract_effect_events(
 pub(crate) fn extract_effects(
     mut render_world: ResMut<RenderWorld>,
     time: Res<Time>,
+    config: Res<HanabiConfig>,
+    simulation_settings: Res<EffectSimulationSettings>,
+    globals: Res<EffectGlobals>,
+    quality: Res<EffectQuality>,
     effects: Res<Assets<EffectAsset>>,
     _images: Res<Assets<Image>>,
     mut shaders: ResMut<Assets<Shader>>,
     mut pipeline_registry: ResMut<PipelineRegistry>,
     mut rng: ResMut<Random>,
+    mut throttled_events: EventWriter<EffectThrottled>,
     mut query: ParamSet<(
         // All existing ParticleEffect components
         Query<(
             Entity,
             &ComputedVisibility,
             &mut ParticleEffect, //TODO - Split EffectAsset::Spawner (desc) and ParticleEffect::SpawnerData (runtime data), and init the latter on component add without a need for the former
-            &GlobalTransform,
+            (&GlobalTransform, Option<&CursorPainter>),
+            Option<&EffectStrength>,
+            Option<&mut ExternalSpawnCount>,
+            Option<&mut SpawnPulses>,
+            Option<&mut EffectCapture>,
+            Option<&ScreenSpaceAttractor>,
+            Option<&mut EffectTimeControl>,
+            Option<&PauseWhenOffscreen>,
+            Option<&EffectDebugView>,
+            Option<&mut EffectCullingDebug>,
+            Option<&mut EffectMetrics>,
+            Option<&EmitterColor>,
         )>,
         // Newly added ParticleEffect components
         Query<
@@ -763,20 +1855,30 @@ pub(crate) fn extract_effects(
             ),
         >,
     )>,
-    removed_effects: RemovedComponents<ParticleEffect>,
+    live_effects: Query<(), With<ParticleEffect>>,
+    cameras: Query<(&Camera, &GlobalTransform, Option<&Frustum>)>,
+    mut last_dropped_count: Local<usize>,
 ) {
     trace!("extract_effects");
 
     // Save simulation params into render world
     let mut sim_params = render_world.get_resource_mut::<SimParams>().unwrap();
-    let dt = time.delta_seconds();
-    sim_params.time = time.seconds_since_startup();
+    let dt = simulation_settings.delta_time(time.delta_seconds());
+    sim_params.time = simulation_settings.time(time.seconds_since_startup());
     sim_params.dt = dt;
-
-    let mut extracted_effects = render_world.get_resource_mut::<ExtractedEffects>().unwrap();
+    sim_params.loop_period = simulation_settings
+        .loop_period
+        .filter(|loop_period| *loop_period > 0.)
+        .unwrap_or(0.);
+    sim_params.globals = globals.values;
 
     // Collect removed effects for later GPU data purge
-    extracted_effects.removed_effect_entities = removed_effects.iter().collect();
+    let effects_meta = render_world.get_resource::<EffectsMeta>().unwrap();
+    let removed_effect_entities =
+        find_removed_effects(effects_meta.entity_map.keys(), &live_effects);
+
+    let mut extracted_effects = render_world.get_resource_mut::<ExtractedEffects>().unwrap();
+    extracted_effects.removed_effect_entities = removed_effect_entities;
 
     // Collect added effects for later GPU data allocation
     extracted_effects.added_effects = query
@@ -787,7 +1889,7 @@ pub(crate) fn extract_effects(
             let asset = effects.get(&effect.handle).unwrap();
             AddedEffect {
                 entity,
-                capacity: asset.capacity,
+                capacity: config.effect_capacity(asset.capacity),
                 item_size: Particle::std430_size_static() as u32, // effect.item_size(),
                 handle,
             }
@@ -795,20 +1897,170 @@ pub(crate) fn extract_effects(
         .collect();
 
     // Loop over all existing effects to update them
-    for (entity, computed_visibility, mut effect, transform) in query.p0().iter_mut() {
+    let mut effect_count = 0;
+    let mut dropped_count = 0;
+    for (
+        entity,
+        computed_visibility,
+        mut effect,
+        (transform, cursor_painter),
+        strength,
+        mut external_spawn_count,
+        mut spawn_pulses,
+        mut capture,
+        screen_attractor,
+        time_control,
+        pause_when_offscreen,
+        debug_view,
+        mut culling_debug,
+        metrics,
+        emitter_color,
+    ) in query.p0().iter_mut()
+    {
+        // Record the culling state of the effect, if debugged
+        let bounds = pause_when_offscreen.map(|p| (transform.translation, p.radius));
+        let mut record_culling = |culled: Option<CullReason>| {
+            if let Some(culling_debug) = culling_debug.as_mut() {
+                culling_debug.record(culled, bounds);
+            }
+        };
+
         // Check if visible
         if !computed_visibility.is_visible {
+            record_culling(Some(CullReason::Hidden));
             continue;
         }
 
+        // Check if on-screen, if the effect pauses otherwise. The effect is not extracted,
+        // so its particles keep their last simulated state on the GPU.
+        if let Some(pause_when_offscreen) = pause_when_offscreen.filter(|_| config.culling) {
+            let origin = transform.translation;
+            if !pause_when_offscreen.is_onscreen(origin, cameras.iter().map(|(_, _, f)| f)) {
+                record_culling(Some(CullReason::Offscreen));
+                continue;
+            }
+        }
+
+        // Check if the maximum number of effects is reached for this frame
+        if let Some(max_effects) = config.max_effects {
+            if effect_count >= max_effects {
+                record_culling(Some(CullReason::Budget));
+                dropped_count += 1;
+                continue;
+            }
+        }
+
         // Check if asset is available, otherwise silently ignore
         if let Some(asset) = effects.get(&effect.handle) {
             //let size = image.texture_descriptor.size;
 
+            // Map the instance strength to the multipliers of the parameters it scales
+            let strength = strength.copied().unwrap_or_default().0;
+            let spawn_scale = asset.strength_mapping.spawn_rate_multiplier(strength) * quality.0;
+            let speed_scale = asset.strength_mapping.speed_multiplier(strength);
+            let size_scale = asset.strength_mapping.size_multiplier(strength);
+
+            // Advance the effect time, restarting the effect if it was scrubbed backward
+            let mut effect_time = time.seconds_since_startup();
+            let (effect_dt, reset) = if let Some(mut time_control) = time_control {
+                let step = time_control.step(dt);
+                effect_time = time_control.time() as f64;
+                step
+            } else {
+                (dt, false)
+            };
+            if reset {
+                effect.chain_head = Vec3::ZERO;
+                effect.chain_links = 0;
+            }
+
+            // Measure the distance traveled by the emitter since the last frame
+            let distance = effect
+                .last_position
+                .replace(transform.translation)
+                .map_or(0., |pos| pos.distance(transform.translation));
+
             // Tick the effect's spawner to determine the spawn count for this frame
             let spawner = effect.spawner(&asset.spawner);
+            if reset {
+                spawner.reset();
+            }
+
+            let sweep_before = spawner.burst_progress();
+            let mut pulse_offset = Vec3::ZERO;
+            let mut age = [0.; 2];
+            let mut spawn_count = if let Some(external) = external_spawn_count.as_mut() {
+                std::mem::take(&mut external.0)
+            } else if let Some(pulses) = spawn_pulses.as_mut() {
+                let batch = pulses.consume(effect_time, effect_dt);
+                batch.map_or(0, |batch| {
+                    pulse_offset = batch.offset;
+                    age = batch.age;
+                    batch.count
+                })
+            } else {
+                let count = spawner.tick_scaled(effect_dt, spawn_scale, &mut rng.0)
+                    + spawner.tick_distance_scaled(distance, spawn_scale, &mut rng.0);
+                if reset {
+                    // Spread the particles re-simulated in a single step over that step
+                    age = [effect_dt, 0.];
+                }
+                spawner.cap_per_frame(count)
+            };
+            let sweep = burst_sweep(sweep_before, spawner.burst_progress());
+
+            // Skip spawning if the emitter is too close to where it last spawned
+            if external_spawn_count.is_none() && spawn_pulses.is_none() {
+                let spawner = *spawner;
+                spawn_count = spawner.apply_spacing(
+                    spawn_count,
+                    transform.translation,
+                    &mut effect.last_spawn_position,
+                );
+            }
 
-            let spawn_count = spawner.tick(dt, &mut rng.0);
+            // Estimate the alive particles, if measured
+            let capacity = config.effect_capacity(asset.capacity);
+            if let Some(mut metrics) = metrics {
+                let lifetime = asset
+                    .init_layout
+                    .lifetime
+                    .unwrap_or(Value::Single(5.))
+                    .range();
+                let lifetime = (lifetime[0] + lifetime[1]) * 0.5;
+                metrics.record(effect_dt, spawn_count, capacity, lifetime);
+            }
+
+            // Notify if the effect cannot spawn everything requested this frame
+            if spawn_count > capacity {
+                throttled_events.send(EffectThrottled {
+                    entity,
+                    requested: spawn_count,
+                    deficit: spawn_count - capacity,
+                });
+                spawn_count = capacity;
+            }
+            effect.has_spawned |= spawn_count > 0;
+
+            // Grow the chain of particles by the links spawned this frame
+            let (chain_head, chain_step) = if let Some(chain) = &asset.init_layout.chain {
+                if chain.max_links > 0 && effect.chain_links >= chain.max_links {
+                    effect.chain_head = Vec3::ZERO;
+                    effect.chain_links = 0;
+                }
+                let jitter = Vec3::new(
+                    rng.0.gen_range(-1.0..=1.0),
+                    rng.0.gen_range(-1.0..=1.0),
+                    rng.0.gen_range(-1.0..=1.0),
+                );
+                let step = chain.offset + jitter * chain.jitter;
+                let head = effect.chain_head;
+                effect.chain_head += step * spawn_count as f32;
+                effect.chain_links += spawn_count;
+                (head, step)
+            } else {
+                (Vec3::ZERO, Vec3::ZERO)
+            };
 
             // Extract the acceleration
             let accel = asset.update_layout.accel;
@@ -816,41 +2068,194 @@ pub(crate) fn extract_effects(
 
             // Generate the shader code for the position initializing of newly emitted particles
             // TODO - Move that to a pre-pass, not each frame!
-            let position_code = &asset.init_layout.position_code;
-            let position_code = if position_code.is_empty() {
-                DEFAULT_POSITION_CODE.to_owned()
-            } else {
-                position_code.clone()
-            };
+            let mut position_code = init_position_code(&asset.init_layout);
+
+            // Gather the shader code of the extension modifiers, which runs after the code of
+            // the built-in modifiers of the same stage.
+            let extensions = ExtensionCode::new(&asset.extensions);
+            position_code += &extensions.init;
 
             // Generate the shader code for the force field of newly emitted particles
             // TODO - Move that to a pre-pass, not each frame!
-            // let force_field_code = &asset.init_layout.force_field_code;
-            // let force_field_code = if force_field_code.is_empty() {
-            let force_field_code = if 0.0 == asset.update_layout.force_field[0].force_exponent {
-                DEFAULT_FORCE_FIELD_CODE.to_owned()
-            } else {
-                FORCE_FIELD_CODE.to_owned()
+            let mut force_field_code = update_force_field_code(&asset.update_layout);
+            force_field_code += &extensions.update;
+            if let Some(completion) = &asset.completion {
+                force_field_code += &completion.to_shader_code();
+            }
+
+            // Pick the variant of this instance, once
+            let variant = *effect
+                .variant
+                .get_or_insert_with(|| asset.variants.resolve(&mut rng.0));
+
+            // Expose the emitter properties, the variant, and the size multiplier of the
+            // instance to the render shader. The emitter properties are only set if the shader
+            // reads them, so that instances which don't can share the same batch.
+            let mut emitter_params = EmitterParams {
+                size_multiplier: variant.size_multiplier * size_scale,
+                variant: variant.color_gradient.unwrap_or(0) as u32,
+                ..Default::default()
             };
+            if asset.render_layout.inherit_emitter.is_some() {
+                emitter_params.color = emitter_color
+                    .map_or(Color::WHITE, |c| c.0)
+                    .as_linear_rgba_f32();
+                emitter_params.scale = transform.scale.max_element();
+            }
 
-            // Generate the shader code for the color over lifetime gradient.
+            // Generate the shader code for the color over lifetime gradient. The gradients of
+            // all the variants are compiled into the same shader, which selects the one of the
+            // instance from its emitter properties.
             // TODO - Move that to a pre-pass, not each frame!
-            let mut vertex_modifiers =
-                if let Some(grad) = &asset.render_layout.lifetime_color_gradient {
-                    grad.to_shader_code()
-                } else {
-                    String::new()
+            let gradient_code =
+                |grad: &Gradient<Vec4>| match asset.render_layout.lifetime_color_interpolation {
+                    ColorInterpolation::Rgb => grad.to_shader_code(),
+                    ColorInterpolation::Hsv => hsv_gradient_shader_code(grad),
                 };
+            let color_gradient = asset.render_layout.lifetime_color_gradient.as_ref();
+            let mut vertex_modifiers = if !asset.variants.color_gradients.is_empty() {
+                let mut code = String::new();
+                for (index, grad) in asset.variants.color_gradients.iter().enumerate() {
+                    if index > 0 {
+                        code += "} else ";
+                    }
+                    code += &format!("if (emitter.variant == {}u) {{\n", index);
+                    code += &gradient_code(grad);
+                }
+                code += "}\n";
+                code
+            } else if let Some(grad) = color_gradient {
+                gradient_code(grad)
+            } else if let Some(color_blend) = &asset.render_layout.color_blend {
+                color_blend.to_shader_code()
+            } else {
+                String::new()
+            };
+            // Whether the color over lifetime is set, which the other color modifiers modulate
+            let has_lifetime_color = color_gradient.is_some()
+                || !asset.variants.color_gradients.is_empty()
+                || asset.render_layout.color_blend.is_some()
+                || asset.render_layout.color_ramp_texture.is_some();
+            if let Some(init_color) = &asset.render_layout.init_color {
+                vertex_modifiers += &init_color.to_shader_code();
+                // Modulate the color over lifetime, if any, instead of replacing it
+                if has_lifetime_color {
+                    vertex_modifiers += "out.color = out.color * init_color;\n";
+                } else {
+                    vertex_modifiers += "out.color = init_color;\n";
+                }
+            }
             if let Some(grad) = &asset.render_layout.size_color_gradient {
                 vertex_modifiers += &grad.to_shader_code();
             }
+            if let Some(init_size) = &asset.render_layout.init_size {
+                vertex_modifiers += &init_size.to_shader_code();
+            }
+            // Scale the particles by the variant and strength of the instance
+            vertex_modifiers += "size = size * emitter.size_multiplier;\n";
+            if let Some(inherit_emitter) = &asset.render_layout.inherit_emitter {
+                let has_color = has_lifetime_color || asset.render_layout.init_color.is_some();
+                vertex_modifiers += &inherit_emitter.to_shader_code(has_color);
+            }
+            if let Some(grad) = &asset.render_layout.lifetime_rotation_gradient {
+                vertex_modifiers += &grad.to_shader_code();
+            }
+            if let Some(init_rotation) = &asset.render_layout.init_rotation {
+                vertex_modifiers += &init_rotation.to_shader_code();
+            }
+            if let Some(flipbook) = &asset.render_layout.flipbook {
+                vertex_modifiers += &flipbook.to_shader_code();
+            }
+            if let Some(fog) = &asset.render_layout.distance_fog {
+                vertex_modifiers += &fog.to_shader_code();
+            }
+            if let Some(shockwave) = &asset.render_layout.shockwave {
+                vertex_modifiers += &scalar_gradient_code(&shockwave.width, "ring_width");
+            }
+            vertex_modifiers += &extensions.vertex;
+            if let Some(debug_view) = debug_view {
+                vertex_modifiers += &debug_view.to_shader_code();
+            }
             trace!("vertex_modifiers={}", vertex_modifiers);
 
+            // Generate the shader code for the fragment stage, if the particle geometry needs any.
+            let geometry = asset.render_layout.geometry;
+            let mut fragment_modifiers = geometry.to_shader_code();
+            if let Some(shockwave) = &asset.render_layout.shockwave {
+                fragment_modifiers += &shockwave.to_shader_code();
+            }
+            fragment_modifiers += &extensions.fragment;
+            trace!("fragment_modifiers={}", fragment_modifiers);
+
+            // Generate the declarations of the custom user bind group, which comes after all
+            // the other bind groups.
+            let user_bindings = if let Some(user) = &asset.render_layout.user_bind_group {
+                let group = 2
+                    + asset.render_layout.particle_texture.is_some() as u32
+                    + asset.render_layout.color_ramp_texture.is_some() as u32;
+                user.declarations.replace("{{GROUP}}", &group.to_string())
+            } else {
+                String::new()
+            };
+
+            // Generate the shader code orienting the particle along its normal, if enabled.
+            let vertex_orient = if asset.render_layout.orient_along_normal {
+                VERTEX_ORIENT_CODE
+            } else {
+                ""
+            };
+
+            // Generate the shader code offsetting the particle toward the camera, if any.
+            let vertex_depth_bias = if asset.render_layout.depth_bias != 0. {
+                format!(
+                    r##"    // Offset the vertex toward the camera in view space
+    var view_position = view.inverse_view * world_position;
+    view_position.z = view_position.z + {};
+    world_position = view.view * view_position;
+"##,
+                    asset.render_layout.depth_bias.to_wgsl_string()
+                )
+            } else {
+                String::new()
+            };
+
             // Configure the shader template, and make sure a corresponding shader asset exists
-            let shader_source =
-                PARTICLES_RENDER_SHADER_TEMPLATE.replace("{{VERTEX_MODIFIERS}}", &vertex_modifiers);
+            let shader_source = PARTICLES_RENDER_SHADER_TEMPLATE
+                .replace("{{USER_BINDINGS}}", &user_bindings)
+                .replace("{{EXTENSION_INCLUDES}}", &extensions.render_includes)
+                .replace("{{VERTEX_MODIFIERS}}", &vertex_modifiers)
+                .replace("{{VERTEX_ORIENT}}", vertex_orient)
+                .replace("{{VERTEX_DEFORM}}", &asset.render_layout.vertex_deform_code)
+                .replace("{{VERTEX_DEPTH_BIAS}}", &vertex_depth_bias)
+                .replace(
+                    "{{PARTICLE_TEXTURE_MAPPING}}",
+                    &asset
+                        .render_layout
+                        .particle_texture_mapping
+                        .to_shader_code(),
+                )
+                .replace("{{FRAGMENT_MODIFIERS}}", &fragment_modifiers);
             let shader = pipeline_registry.configure(&shader_source, &mut shaders);
 
+            // Record the configured shaders on the instance for introspection
+            effect.configured_render_shader = Some(shader.clone());
+            effect.configured_update_key = Some(ParticleUpdatePipelineKey {
+                position_code: position_code.clone(),
+                force_field_code: force_field_code.clone(),
+                extension_includes: extensions.update_includes.clone(),
+                init_data: asset.init_layout.init_data.is_some(),
+                user_declarations: asset
+                    .render_layout
+                    .user_bind_group
+                    .as_ref()
+                    .map(|user| user.declarations.clone()),
+                user_texture: asset
+                    .render_layout
+                    .user_bind_group
+                    .as_ref()
+                    .is_some_and(|user| user.texture.is_some()),
+            });
+
             trace!(
                 "extracted: handle={:?} shader={:?} has_image={} position_code={} force_field_code={}",
                 effect.handle,
@@ -864,15 +2269,66 @@ pub(crate) fn extract_effects(
                 force_field_code,
             );
 
+            // Project the screen-space attractor into the world
+            let (attractor, attractor_accel) = screen_attractor
+                .and_then(|screen_attractor| {
+                    cameras.get(screen_attractor.camera).ok().map(
+                        |(camera, camera_transform, _)| {
+                            let pos = screen_attractor.world_position(
+                                &camera.projection_matrix,
+                                &camera_transform.compute_matrix(),
+                            );
+                            (pos, screen_attractor.accel)
+                        },
+                    )
+                })
+                .unwrap_or((Vec3::ZERO, 0.));
+
+            // Record the inputs of this frame, or replace them with recorded ones
+            let mut matrix = transform.compute_matrix();
+            if let Some(hit) = cursor_painter.and_then(|painter| painter.hit) {
+                // Spawn at the cursor hit position, without moving the emitter
+                matrix.w_axis = hit.extend(1.);
+            }
+            let offset = matrix.transform_vector3(pulse_offset);
+            matrix.w_axis += offset.extend(0.);
+            let mut inputs = EffectFrameInputs {
+                transform: matrix.to_cols_array(),
+                spawn_count,
+                seed: rng.0.gen(),
+                speed_scale,
+                chain_head: chain_head.to_array(),
+                chain_step: chain_step.to_array(),
+                attractor: attractor.to_array(),
+                attractor_accel,
+                sweep,
+                age,
+            };
+            if let Some(capture) = capture.as_mut() {
+                capture.process(&mut inputs);
+            }
+
             extracted_effects.effects.insert(
                 entity,
                 ExtractedEffect {
                     handle: effect.handle.clone_weak(),
-                    spawn_count,
+                    spawn_count: inputs.spawn_count,
+                    seed: inputs.seed,
                     color: Color::RED, //effect.color,
-                    transform: transform.compute_matrix(),
+                    transform: Mat4::from_cols_array(&inputs.transform),
                     accel,
                     force_field,
+                    speed_scale: inputs.speed_scale,
+                    chain_head: Vec3::from(inputs.chain_head),
+                    chain_step: Vec3::from(inputs.chain_step),
+                    attractor: Vec3::from(inputs.attractor),
+                    attractor_accel: inputs.attractor_accel,
+                    sweep: inputs.sweep,
+                    age: inputs.age,
+                    dt: effect_dt,
+                    reset,
+                    check_completion: asset.completion.is_some() && effect.has_spawned,
+                    emitter: emitter_params,
                     rect: Rect {
                         left: -0.1,
                         top: -0.1,
@@ -887,12 +2343,55 @@ pub(crate) fn extract_effects(
                         .particle_texture
                         .clone()
                         .map_or(HandleId::default::<Image>(), |handle| handle.id),
+                    has_color_ramp: asset.render_layout.color_ramp_texture.is_some(),
+                    color_ramp_handle_id: asset
+                        .render_layout
+                        .color_ramp_texture
+                        .clone()
+                        .map_or(HandleId::default::<Image>(), |handle| handle.id),
                     shader,
                     position_code,
                     force_field_code,
+                    extension_includes: extensions.update_includes,
+                    has_init_data: asset.init_layout.init_data.is_some(),
+                    geometry,
+                    shader_defs: asset.render_layout.shader_defs.clone(),
+                    user_data: asset
+                        .render_layout
+                        .user_bind_group
+                        .as_ref()
+                        .map(|user| user.data.clone()),
+                    user_declarations: asset
+                        .render_layout
+                        .user_bind_group
+                        .as_ref()
+                        .map(|user| user.declarations.clone()),
+                    user_texture_handle_id: asset
+                        .render_layout
+                        .user_bind_group
+                        .as_ref()
+                        .and_then(|user| user.texture.as_ref())
+                        .map(|handle| handle.id),
                 },
             );
+            effect_count += 1;
+            record_culling(None);
+        } else {
+            record_culling(Some(CullReason::AssetNotLoaded));
+        }
+    }
+
+    // Warn about the effects skipped by the effect budget, only when their number changes to
+    // avoid flooding the log each frame
+    if dropped_count != *last_dropped_count {
+        if dropped_count > 0 {
+            warn!(
+                "Skipped {} visible effect(s) in excess of HanabiConfig::max_effects = {}.",
+                dropped_count,
+                config.max_effects.unwrap_or_default()
+            );
         }
+        *last_dropped_count = dropped_count;
     }
 }
 
@@ -908,6 +2407,15 @@ struct Particle {
     pub velocity: [f32; 3],
     /// Total particle lifetime.
     pub lifetime: f32,
+    /// Normal of the spawn shape at the particle spawn position.
+    pub normal: [f32; 3],
+    /// Index of the particle among all the particles spawned in the same frame.
+    pub spawn_index: u32,
+    /// Random seed sampled when the particle spawned, hashed by the render modifiers into
+    /// random values which are stable over the particle lifetime.
+    pub seed: u32,
+    /// Padding to the 16-byte alignment of the WGSL struct.
+    pub __pad: [u32; 3],
 }
 
 /// A single vertex of a particle mesh as stored in a GPU buffer.
@@ -926,9 +2434,9 @@ struct ParticleVertex {
 /// for the current frame, for all views in the frame, and consumed by [`queue_effects()`]
 /// to actually enqueue the drawning commands to draw those effects.
 pub(crate) struct EffectsMeta {
-    /// Map from an entity with a [`ParticleEffect`] component attached to it, to the associated
-    /// effect slice allocated in an [`EffectCache`].
-    entity_map: HashMap<Entity, EffectSlice>,
+    /// Map from an entity with a [`ParticleEffect`] component attached to it, to the identifier
+    /// of the associated effect slice allocated in the [`EffectCache`].
+    entity_map: HashMap<Entity, EffectCacheId>,
     /// Global effect cache for all effects in use.
     effect_cache: EffectCache,
     /// Bind group for the camera view, containing the camera projection and other uniform
@@ -944,26 +2452,65 @@ pub(crate) struct EffectsMeta {
     indirect_buffer_bind_group: Option<BindGroup>,
     sim_params_uniforms: UniformVec<SimParamsUniform>,
     spawner_buffer: AlignedBufferVec<SpawnerParams>,
-    /// Unscaled vertices of the mesh of a single particle, generally a quad.
-    /// The mesh is later scaled during rendering by the "particle size".
-    // FIXME - This is a per-effect thing, unless we merge all meshes into a single buffer (makes
-    // sense) but in that case we need a vertex slice too to know which mesh to draw per effect.
+    /// Number of particles blocking the [`CompletionCondition`] of each effect after the
+    /// update pass, in the same slot as the spawner of the effect in `spawner_buffer`.
+    ///
+    /// [`CompletionCondition`]: crate::CompletionCondition
+    completion_buffer: AlignedBufferVec<i32>,
+    /// Emitter properties of each batch, selected with a dynamic offset in the view bind group.
+    emitter_buffer: AlignedBufferVec<EmitterParams>,
+    /// Arguments of the indirect draw call of each batch.
+    draw_args_buffer: AlignedBufferVec<DrawIndirectArgs>,
+    /// Storage buffer of the custom user data of each effect asset using one, with its size.
+    user_buffers: HashMap<Handle<EffectAsset>, (Buffer, usize)>,
+    /// Storage buffer of the [`InitData`] of each effect asset using one.
+    init_buffers: HashMap<Handle<EffectAsset>, Buffer>,
+    /// Unscaled vertices of the meshes of all the built-in [`ParticleGeometry`], merged into
+    /// a single buffer. The mesh is later scaled during rendering by the "particle size".
     vertices: BufferVec<ParticleVertex>,
+    /// Range of vertices in [`EffectsMeta::vertices`] for each [`ParticleGeometry`].
+    geometry_ranges: HashMap<ParticleGeometry, Range<u32>>,
+    /// Sampler overriding the sampler of the particle textures, if any.
+    texture_sampler: Option<Sampler>,
+    /// Whether the update pass already ran this frame. The update node runs once per view,
+    /// like for each eye of a stereo rendering, but the particles must be simulated only once.
+    update_done: AtomicBool,
 }
 
 impl EffectsMeta {
-    pub fn new(device: RenderDevice) -> Self {
+    pub fn new(device: RenderDevice, texture_filter: Option<FilterMode>) -> Self {
         let mut vertices = BufferVec::new(BufferUsages::VERTEX);
-        for v in QUAD_VERTEX_POSITIONS {
-            let uv = v.truncate() + 0.5;
-            let v = *v * Vec3::new(1.0, 1.0, 1.0);
-            vertices.push(ParticleVertex {
-                position: v.into(),
-                uv: uv.into(),
-            });
+        let mut geometry_ranges = HashMap::default();
+        for geometry in [
+            ParticleGeometry::Quad,
+            ParticleGeometry::Triangle,
+            ParticleGeometry::Hexagon,
+            ParticleGeometry::SphereImpostor,
+        ] {
+            let start = vertices.len() as u32;
+            for v in geometry_vertex_positions(geometry) {
+                let uv = v.truncate() + 0.5;
+                vertices.push(ParticleVertex {
+                    position: (*v).into(),
+                    uv: uv.into(),
+                });
+            }
+            let end = vertices.len() as u32;
+            geometry_ranges.insert(geometry, start..end);
         }
 
         let item_align = device.limits().min_storage_buffer_offset_alignment as usize;
+        let uniform_align = device.limits().min_uniform_buffer_offset_alignment as usize;
+
+        let texture_sampler = texture_filter.map(|filter| {
+            device.create_sampler(&SamplerDescriptor {
+                label: Some("hanabi:texture_sampler"),
+                mag_filter: filter,
+                min_filter: filter,
+                mipmap_filter: filter,
+                ..Default::default()
+            })
+        });
 
         Self {
             entity_map: HashMap::default(),
@@ -975,13 +2522,61 @@ impl EffectsMeta {
             indirect_buffer_bind_group: None,
             sim_params_uniforms: UniformVec::default(),
             spawner_buffer: AlignedBufferVec::new(
-                BufferUsages::STORAGE,
+                BufferUsages::STORAGE | BufferUsages::COPY_SRC,
                 item_align,
                 Some("spawner_buffer".to_string()),
             ),
+            completion_buffer: AlignedBufferVec::new(
+                BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+                item_align,
+                Some("hanabi:completion_buffer".to_string()),
+            ),
+            emitter_buffer: AlignedBufferVec::new(
+                BufferUsages::UNIFORM,
+                uniform_align,
+                Some("hanabi:emitter_buffer".to_string()),
+            ),
+            draw_args_buffer: AlignedBufferVec::new(
+                BufferUsages::INDIRECT | BufferUsages::STORAGE,
+                item_align,
+                Some("hanabi:draw_args_buffer".to_string()),
+            ),
+            user_buffers: HashMap::default(),
+            init_buffers: HashMap::default(),
             vertices,
+            geometry_ranges,
+            texture_sampler,
+            update_done: AtomicBool::new(false),
         }
     }
+
+    /// Record the emitter properties of a batch, returning their dynamic offset.
+    fn push_emitter(&mut self, emitter: EmitterParams) -> u32 {
+        let index = self.emitter_buffer.push(emitter);
+        (index * self.emitter_buffer.aligned_size()) as u32
+    }
+
+    /// Record the draw call arguments of a batch drawing `instance_count` particles with the
+    /// given geometry, returning their offset in bytes in the draw arguments buffer.
+    fn push_draw_args(&mut self, geometry: ParticleGeometry, instance_count: u32) -> u64 {
+        let vertices = &self.geometry_ranges[&geometry];
+        let index = self.draw_args_buffer.push(DrawIndirectArgs {
+            vertex_count: vertices.end - vertices.start,
+            instance_count,
+            first_vertex: vertices.start,
+            first_instance: 0,
+        });
+        (index * self.draw_args_buffer.aligned_size()) as u64
+    }
+}
+
+/// Get the unscaled vertex positions of the mesh of a particle geometry, as a triangle list.
+fn geometry_vertex_positions(geometry: ParticleGeometry) -> &'static [Vec3] {
+    match geometry {
+        ParticleGeometry::Quad | ParticleGeometry::SphereImpostor => QUAD_VERTEX_POSITIONS,
+        ParticleGeometry::Triangle => TRIANGLE_VERTEX_POSITIONS,
+        ParticleGeometry::Hexagon => HEXAGON_VERTEX_POSITIONS,
+    }
 }
 
 const QUAD_VERTEX_POSITIONS: &[Vec3] = &[
@@ -993,10 +2588,41 @@ const QUAD_VERTEX_POSITIONS: &[Vec3] = &[
     const_vec3!([0.5, 0.5, 0.0]),
 ];
 
+const TRIANGLE_VERTEX_POSITIONS: &[Vec3] = &[
+    const_vec3!([-0.5, -0.433013, 0.0]),
+    const_vec3!([0.5, -0.433013, 0.0]),
+    const_vec3!([0.0, 0.433013, 0.0]),
+];
+
+const HEXAGON_VERTEX_POSITIONS: &[Vec3] = &[
+    const_vec3!([0.0, 0.0, 0.0]),
+    const_vec3!([0.5, 0.0, 0.0]),
+    const_vec3!([0.25, 0.433013, 0.0]),
+    const_vec3!([0.0, 0.0, 0.0]),
+    const_vec3!([0.25, 0.433013, 0.0]),
+    const_vec3!([-0.25, 0.433013, 0.0]),
+    const_vec3!([0.0, 0.0, 0.0]),
+    const_vec3!([-0.25, 0.433013, 0.0]),
+    const_vec3!([-0.5, 0.0, 0.0]),
+    const_vec3!([0.0, 0.0, 0.0]),
+    const_vec3!([-0.5, 0.0, 0.0]),
+    const_vec3!([-0.25, -0.433013, 0.0]),
+    const_vec3!([0.0, 0.0, 0.0]),
+    const_vec3!([-0.25, -0.433013, 0.0]),
+    const_vec3!([0.25, -0.433013, 0.0]),
+    const_vec3!([0.0, 0.0, 0.0]),
+    const_vec3!([0.25, -0.433013, 0.0]),
+    const_vec3!([0.5, 0.0, 0.0]),
+];
+
 bitflags! {
     struct LayoutFlags: u32 {
         const NONE = 0;
         const PARTICLE_TEXTURE = 0b00000001;
+        const COLOR_RAMP = 0b00000010;
+        const USER_DATA = 0b00000100;
+        const USER_TEXTURE = 0b00001000;
+        const INIT_DATA = 0b00010000;
     }
 }
 
@@ -1024,14 +2650,33 @@ pub struct EffectBatch {
     layout_flags: LayoutFlags,
     /// Texture to modulate the particle color.
     image_handle_id: HandleId,
+    /// Ramp texture sampled by particle age to produce the particle color.
+    color_ramp_handle_id: HandleId,
     /// Render shader.
     shader: Handle<Shader>,
     /// Update position code.
     position_code: String,
     /// Update force field code.
     force_field_code: String,
-    /// Compute pipeline specialized for this batch.
-    compute_pipeline: Option<ComputePipeline>,
+    /// Update extension includes code.
+    extension_includes: String,
+    /// Geometry of a single particle.
+    geometry: ParticleGeometry,
+    /// Custom shader definitions of the render pipeline.
+    shader_defs: Vec<String>,
+    /// WGSL declarations of the custom user bind group, if any. The buffer of the bind group
+    /// is the one of the effect asset, in [`EffectsMeta`].
+    user_declarations: Option<String>,
+    /// Texture of the custom user bind group, if any.
+    user_texture_handle_id: HandleId,
+    /// Dynamic offset of the emitter properties of the batch in the emitter buffer.
+    emitter_offset: u32,
+    /// Offset in bytes of the draw call arguments of the batch in the draw arguments buffer.
+    draw_args_offset: u64,
+    /// Average origin of the effects of the batch, to sort the batches when drawing them.
+    origin: Vec3,
+    /// Index in the [`ComputeCache`] of the compute pipeline specialized for this batch.
+    compute_pipeline: Option<usize>,
 }
 
 pub(crate) fn prepare_effects(
@@ -1042,9 +2687,17 @@ pub(crate) fn prepare_effects(
     //update_pipeline: Res<ParticlesUpdatePipeline>, // TODO move update_pipeline.pipeline to EffectsMeta
     mut effects_meta: ResMut<EffectsMeta>,
     mut extracted_effects: ResMut<ExtractedEffects>,
+    mut gpu_buffers: ResMut<EffectGpuBuffers>,
+    mut completion_readback: ResMut<CompletionReadback>,
+    config: Res<HanabiConfig>,
+    events: Res<EffectAssetEvents>,
+    mut effect_bind_groups: ResMut<EffectBindGroups>,
 ) {
     trace!("prepare_effects");
 
+    // Allow the update pass to run once this frame
+    *effects_meta.update_done.get_mut() = false;
+
     // Allocate simulation uniform if needed
     if effects_meta.sim_params_uniforms.is_empty() {
         effects_meta
@@ -1077,9 +2730,72 @@ pub(crate) fn prepare_effects(
         .vertices
         .write_buffer(&render_device, &render_queue);
 
-    // Allocate GPU data for newly created effect instances. Do this first to ensure a group is not left
-    // unused and dropped due to the last effect being removed but a new compatible one added not being
-    // inserted yet. By inserting first, we ensure the group is not dropped in this case.
+    // Upload the init data of the effect assets which changed. The bind group referencing the
+    // previous buffer, if any, is recreated during the queue stage.
+    for (handle, init_data) in &events.init_data {
+        effect_bind_groups.init_data.remove(handle);
+        if let Some(values) = init_data {
+            let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+                label: Some("hanabi:init_data_buffer"),
+                contents: cast_slice(values),
+                usage: BufferUsages::STORAGE,
+            });
+            effects_meta
+                .init_buffers
+                .insert(handle.clone_weak(), buffer);
+        } else {
+            effects_meta.init_buffers.remove(handle);
+        }
+    }
+
+    // Upload the custom user data of the effect assets. The buffer of an asset is created once,
+    // and written again each frame to reflect any change to the effect asset. It is only
+    // recreated if the size of the data changes, and dropped once no instance of the asset is
+    // extracted anymore.
+    let mut uploaded_user_data = HashSet::default();
+    for extracted_effect in extracted_effects.effects.values() {
+        let data = if let Some(data) = &extracted_effect.user_data {
+            data
+        } else {
+            continue;
+        };
+        if !uploaded_user_data.insert(extracted_effect.handle.id) {
+            continue;
+        }
+
+        // Storage buffers can't be empty, and their size must be a multiple of 4 bytes
+        let mut contents = data.clone();
+        contents.resize(((data.len() + 3) & !3).max(4), 0);
+        match effects_meta.user_buffers.get(&extracted_effect.handle) {
+            Some((buffer, size)) if *size == contents.len() => {
+                render_queue.write_buffer(buffer, 0, &contents);
+            }
+            _ => {
+                let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+                    label: Some("hanabi:user_data_buffer"),
+                    contents: &contents,
+                    usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+                });
+                effects_meta.user_buffers.insert(
+                    extracted_effect.handle.clone_weak(),
+                    (buffer, contents.len()),
+                );
+            }
+        }
+    }
+    effects_meta
+        .user_buffers
+        .retain(|handle, _| uploaded_user_data.contains(&handle.id));
+
+    // Deallocate GPU data for destroyed effect instances, returning their slice to the effect
+    // buffer for reuse by later effects.
+    for entity in extracted_effects.removed_effect_entities.drain(..) {
+        if let Some(id) = effects_meta.entity_map.remove(&entity) {
+            effects_meta.effect_cache.remove(id);
+        }
+    }
+
+    // Allocate GPU data for newly created effect instances
     for added_effect in extracted_effects.added_effects.drain(..) {
         let entity = added_effect.entity;
         let id = effects_meta.effect_cache.insert(
@@ -1089,15 +2805,10 @@ pub(crate) fn prepare_effects(
             //update_pipeline.pipeline.clone(),
             &render_queue,
         );
-        let slice = effects_meta.effect_cache.get_slice(id);
-        effects_meta.entity_map.insert(entity, slice);
-    }
-
-    // Deallocate GPU data for destroyed effect instances. This will automatically drop any group where
-    // there is no more effect slice.
-    for _entity in extracted_effects.removed_effect_entities.iter() {
-        unimplemented!("Remove particle effect.");
-        //effects_meta.remove(&*entity);
+        // The component may have been removed and re-added this frame, leaving the old slice
+        if let Some(old_id) = effects_meta.entity_map.insert(entity, id) {
+            effects_meta.effect_cache.remove(old_id);
+        }
     }
 
     // // sort first by z and then by handle. this ensures that, when possible, batches span multiple z layers
@@ -1114,8 +2825,9 @@ pub(crate) fn prepare_effects(
         .effects
         .iter()
         .map(|(entity, extracted_effect)| {
-            let slice = effects_meta.entity_map.get(entity).unwrap().clone();
-            (slice, extracted_effect)
+            let id = *effects_meta.entity_map.get(entity).unwrap();
+            let slice = effects_meta.effect_cache.get_slice(id);
+            (slice, *entity, extracted_effect)
         })
         .collect::<Vec<_>>();
     trace!("Collected {} extracted effects", effect_entity_list.len());
@@ -1125,28 +2837,57 @@ pub(crate) fn prepare_effects(
 
     // Loop on all extracted effects in order
     effects_meta.spawner_buffer.clear();
+    effects_meta.completion_buffer.clear();
+    effects_meta.emitter_buffer.clear();
+    effects_meta.draw_args_buffer.clear();
     let mut spawner_base = 0;
     let mut item_size = 0;
     let mut current_buffer_index = u32::MAX;
     let mut asset: Handle<EffectAsset> = Default::default();
     let mut layout_flags = LayoutFlags::NONE;
     let mut image_handle_id: HandleId = HandleId::default::<Image>();
+    let mut color_ramp_handle_id: HandleId = HandleId::default::<Image>();
     let mut shader: Handle<Shader> = Default::default();
     let mut start = 0;
     let mut end = 0;
     let mut num_emitted = 0;
     let mut position_code = String::default();
     let mut force_field_code = String::default();
-
-    for (slice, extracted_effect) in effect_entity_list {
+    let mut extension_includes = String::default();
+    let mut geometry = ParticleGeometry::default();
+    let mut shader_defs = vec![];
+    let mut user_declarations = None;
+    let mut user_texture_handle_id: HandleId = HandleId::default::<Image>();
+    let mut batch_checks_completion = false;
+    let mut spawner_indices = Vec::with_capacity(effect_entity_list.len());
+    let mut emitter = EmitterParams::default();
+    let mut batch_entities = vec![];
+    let mut batch_origin = Vec3::ZERO;
+    let mut draw_args_offsets = HashMap::default();
+
+    for (slice, entity, extracted_effect) in effect_entity_list {
         let buffer_index = slice.group_index;
-        let range = slice.slice;
+        let range = slice.slice.clone();
         layout_flags = if extracted_effect.has_image {
             LayoutFlags::PARTICLE_TEXTURE
         } else {
             LayoutFlags::NONE
         };
+        if extracted_effect.has_color_ramp {
+            layout_flags |= LayoutFlags::COLOR_RAMP;
+        }
+        if extracted_effect.user_data.is_some() {
+            layout_flags |= LayoutFlags::USER_DATA;
+        }
+        if extracted_effect.has_init_data {
+            layout_flags |= LayoutFlags::INIT_DATA;
+        }
+        if let Some(handle_id) = extracted_effect.user_texture_handle_id {
+            layout_flags |= LayoutFlags::USER_TEXTURE;
+            user_texture_handle_id = handle_id;
+        }
         image_handle_id = extracted_effect.image_handle_id;
+        color_ramp_handle_id = extracted_effect.color_ramp_handle_id;
         trace!("Effect: buffer #{} | range {:?}", buffer_index, range);
 
         // Check the buffer the effect is in
@@ -1172,6 +2913,10 @@ pub(crate) fn prepare_effects(
                         item_size,
                         shader
                     );
+                    let draw_args_offset = effects_meta.push_draw_args(geometry, end - start);
+                    let origin = batch_origin / batch_entities.len() as f32;
+                    draw_args_offsets
+                        .extend(batch_entities.drain(..).map(|e| (e, draw_args_offset)));
                     commands.spawn_bundle((EffectBatch {
                         buffer_index: current_buffer_index,
                         spawner_base: spawner_base as u32,
@@ -1180,16 +2925,27 @@ pub(crate) fn prepare_effects(
                         handle: asset.clone_weak(),
                         layout_flags,
                         image_handle_id,
+                        color_ramp_handle_id,
                         shader: shader.clone(),
                         position_code: position_code.clone(),
                         force_field_code: force_field_code.clone(),
+                        extension_includes: extension_includes.clone(),
+                        geometry,
+                        shader_defs: shader_defs.clone(),
+                        user_declarations: user_declarations.clone(),
+                        user_texture_handle_id,
+                        emitter_offset: effects_meta.push_emitter(emitter),
+                        draw_args_offset,
+                        origin,
                         compute_pipeline: None,
                     },));
                     num_emitted += 1;
                 }
             }
 
-            // Move to next buffer
+            // Move to next buffer, dropping the effects of any empty batch
+            batch_entities.clear();
+            batch_origin = Vec3::ZERO;
             current_buffer_index = buffer_index;
             start = 0;
             end = 0;
@@ -1203,9 +2959,6 @@ pub(crate) fn prepare_effects(
 
         assert_ne!(asset, Handle::<EffectAsset>::default());
 
-        shader = extracted_effect.shader.clone();
-        trace!("shader = {:?}", shader);
-
         trace!("item_size = {}B", slice.item_size);
 
         position_code = extracted_effect.position_code.clone();
@@ -1214,6 +2967,16 @@ pub(crate) fn prepare_effects(
         force_field_code = extracted_effect.force_field_code.clone();
         trace!("force_field_code = {}", force_field_code);
 
+        extension_includes = extracted_effect.extension_includes.clone();
+
+        geometry = extracted_effect.geometry;
+        trace!("geometry = {:?}", geometry);
+
+        shader_defs = extracted_effect.shader_defs.clone();
+        trace!("shader_defs = {:?}", shader_defs);
+
+        user_declarations = extracted_effect.user_declarations.clone();
+
         // extract the force field and turn it into a struct that is compliant with Std430,
         // namely ForceFieldStd430
         let mut extracted_force_field = [ForceFieldStd430::default(); FFNUM];
@@ -1229,15 +2992,43 @@ pub(crate) fn prepare_effects(
             origin: extracted_effect.transform.col(3).truncate(),
             accel: extracted_effect.accel,
             force_field: extracted_force_field, // extracted_effect.force_field,
-            seed: random::<u32>(),
+            seed: extracted_effect.seed,
+            speed_scale: extracted_effect.speed_scale,
+            chain_head: extracted_effect.chain_head,
+            chain_step: extracted_effect.chain_step,
+            spawn_total: extracted_effect.spawn_count as i32,
+            attractor: extracted_effect.attractor,
+            attractor_accel: extracted_effect.attractor_accel,
+            sweep_start: extracted_effect.sweep[0],
+            sweep_end: extracted_effect.sweep[1],
+            dt: extracted_effect.dt,
+            reset: extracted_effect.reset as u32,
+            age_start: extracted_effect.age[0],
+            age_end: extracted_effect.age[1],
             ..Default::default()
         };
         trace!("spawner_params = {:?}", spawner_params);
-        effects_meta.spawner_buffer.push(spawner_params);
+        let spawner_index = effects_meta.spawner_buffer.push(spawner_params);
+        effects_meta.completion_buffer.push(0);
+        spawner_indices.push((
+            entity,
+            slice.clone(),
+            spawner_index,
+            extracted_effect.check_completion,
+        ));
 
         trace!("slice = {}-{} | prev end = {}", range.start, range.end, end);
-        if (range.start > end) || (item_size != slice.item_size) {
-            // Discontinuous slices; create a new batch
+        if (range.start > end)
+            || (item_size != slice.item_size)
+            || (shader != extracted_effect.shader)
+            || (emitter != extracted_effect.emitter)
+            || batch_checks_completion
+            || extracted_effect.check_completion
+        {
+            // Discontinuous slices, or instances with different shaders, or with different
+            // emitter properties like different variants of the same effect; create a
+            // new batch. Effects checking their completion get a batch of their own, since
+            // only the completion counter of the first effect of a batch is bound.
             if end > start {
                 // Record the previous batch
                 assert_ne!(asset, Handle::<EffectAsset>::default());
@@ -1250,6 +3041,9 @@ pub(crate) fn prepare_effects(
                     item_size,
                     shader
                 );
+                let draw_args_offset = effects_meta.push_draw_args(geometry, end - start);
+                let origin = batch_origin / batch_entities.len() as f32;
+                draw_args_offsets.extend(batch_entities.drain(..).map(|e| (e, draw_args_offset)));
                 commands.spawn_bundle((EffectBatch {
                     buffer_index,
                     spawner_base: spawner_base as u32,
@@ -1258,21 +3052,41 @@ pub(crate) fn prepare_effects(
                     handle: asset.clone_weak(),
                     layout_flags,
                     image_handle_id,
+                    color_ramp_handle_id,
                     shader: shader.clone(),
                     position_code: position_code.clone(),
                     force_field_code: force_field_code.clone(),
+                    extension_includes: extension_includes.clone(),
+                    geometry,
+                    shader_defs: shader_defs.clone(),
+                    user_declarations: user_declarations.clone(),
+                    user_texture_handle_id,
+                    emitter_offset: effects_meta.push_emitter(emitter),
+                    draw_args_offset,
+                    origin,
                     compute_pipeline: None,
                 },));
                 num_emitted += 1;
             }
+            batch_entities.clear();
+            batch_origin = Vec3::ZERO;
             start = range.start;
             item_size = slice.item_size;
+            spawner_base = effects_meta.spawner_buffer.len() - 1;
         }
         end = range.end;
-    }
+        batch_entities.push(entity);
+        batch_origin += spawner_params.origin;
 
-    // Record last open batch if any
-    if end > start {
+        shader = extracted_effect.shader.clone();
+        trace!("shader = {:?}", shader);
+
+        emitter = extracted_effect.emitter;
+        batch_checks_completion = extracted_effect.check_completion;
+    }
+
+    // Record last open batch if any
+    if end > start {
         assert_ne!(asset, Handle::<EffectAsset>::default());
         assert!(item_size > 0);
         trace!(
@@ -1283,6 +3097,9 @@ pub(crate) fn prepare_effects(
             item_size,
             shader
         );
+        let draw_args_offset = effects_meta.push_draw_args(geometry, end - start);
+        let origin = batch_origin / batch_entities.len() as f32;
+        draw_args_offsets.extend(batch_entities.drain(..).map(|e| (e, draw_args_offset)));
         commands.spawn_bundle((EffectBatch {
             buffer_index: current_buffer_index,
             spawner_base: spawner_base as u32,
@@ -1291,9 +3108,18 @@ pub(crate) fn prepare_effects(
             handle: asset.clone_weak(),
             layout_flags,
             image_handle_id,
+            color_ramp_handle_id,
             shader,
             position_code,
             force_field_code,
+            extension_includes,
+            geometry,
+            shader_defs,
+            user_declarations,
+            user_texture_handle_id,
+            emitter_offset: effects_meta.push_emitter(emitter),
+            draw_args_offset,
+            origin,
             compute_pipeline: None,
         },));
         num_emitted += 1;
@@ -1308,6 +3134,217 @@ pub(crate) fn prepare_effects(
     effects_meta
         .spawner_buffer
         .write_buffer(&render_device, &render_queue);
+    // Also reset the completion counters of all effects
+    effects_meta
+        .completion_buffer
+        .write_buffer(&render_device, &render_queue);
+    effects_meta
+        .emitter_buffer
+        .write_buffer(&render_device, &render_queue);
+    effects_meta
+        .draw_args_buffer
+        .write_buffer(&render_device, &render_queue);
+
+    // Check the completion of the effects only every few frames, to limit the readbacks
+    completion_readback.frame = completion_readback.frame.wrapping_add(1);
+    let check_completion = config.readback
+        && completion_readback
+            .frame
+            .is_multiple_of(COMPLETION_READBACK_PERIOD);
+    let completion_stride = effects_meta.completion_buffer.aligned_size() as u64;
+
+    // Expose the GPU buffers of each effect for custom render nodes
+    gpu_buffers.buffers.clear();
+    if let Some(spawner_buffer) = effects_meta.spawner_buffer.buffer() {
+        let spawner_stride = effects_meta.spawner_buffer.aligned_size() as u64;
+        let buffers = effects_meta.effect_cache.buffers();
+        for (entity, slice, spawner_index, check) in spawner_indices {
+            if check && check_completion {
+                completion_readback
+                    .effects
+                    .push((entity, spawner_index as u64 * completion_stride));
+            }
+            // Effects with an empty slice are not drawn, so have no draw call arguments
+            let (draw_args_buffer, draw_args_offset) = match (
+                effects_meta.draw_args_buffer.buffer(),
+                draw_args_offsets.get(&entity),
+            ) {
+                (Some(buffer), Some(offset)) => (buffer, *offset),
+                _ => continue,
+            };
+            let buffer = &buffers[slice.group_index as usize];
+            gpu_buffers.buffers.insert(
+                entity,
+                EffectGpuBuffer {
+                    particle_buffer: buffer.particle_buffer().clone(),
+                    slice: slice.slice,
+                    item_size: slice.item_size,
+                    indirect_buffer: buffer.indirect_buffer().clone(),
+                    spawner_buffer: spawner_buffer.clone(),
+                    spawner_offset: spawner_index as u64 * spawner_stride,
+                    draw_args_buffer: draw_args_buffer.clone(),
+                    draw_args_offset,
+                },
+            );
+        }
+    }
+
+    // Allocate the buffer the pending counts of the effects are read back into
+    if !completion_readback.effects.is_empty() {
+        completion_readback.buffer = Some(render_device.create_buffer(&BufferDescriptor {
+            label: Some("hanabi:completion_readback"),
+            size: completion_readback.effects.len() as u64 * 4,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        }));
+    }
+}
+
+/// Number of frames between two readbacks of the completion of the effects.
+const COMPLETION_READBACK_PERIOD: u32 = 8;
+
+/// Readback of the number of particles blocking the [`CompletionCondition`] of the effects.
+///
+/// [`CompletionCondition`]: crate::CompletionCondition
+#[derive(Default)]
+pub(crate) struct CompletionReadback {
+    /// Frame counter, to read back only every [`COMPLETION_READBACK_PERIOD`] frames.
+    frame: u32,
+    /// Effects read back this frame, with the byte offset of their pending count in the
+    /// completion buffer.
+    effects: Vec<(Entity, u64)>,
+    /// Buffer the pending counts are copied into, one `i32` per effect.
+    buffer: Option<Buffer>,
+    /// Whether the pending counts were copied this frame, after the update pass.
+    copied: AtomicBool,
+    /// Readbacks of previous frames not mapped yet, oldest first, with the effects they
+    /// read back.
+    in_flight: Vec<(Vec<Entity>, AsyncReadback)>,
+}
+
+/// Read back the number of particles blocking the completion of the effects, and forward the
+/// completed ones to the main world.
+///
+/// The readback buffer is mapped asynchronously once the commands of the frame are
+/// submitted, and read on a later frame, so the render thread never waits for the GPU.
+pub(crate) fn readback_completion(
+    render_device: Res<RenderDevice>,
+    mut readback: ResMut<CompletionReadback>,
+    completed: Res<CompletedEffects>,
+) {
+    // Request the mapping of the pending counts copied this frame
+    let effects = std::mem::take(&mut readback.effects);
+    if let Some(buffer) = readback.buffer.take() {
+        if std::mem::take(readback.copied.get_mut()) {
+            let entities = effects.into_iter().map(|(entity, _)| entity).collect();
+            readback
+                .in_flight
+                .push((entities, AsyncReadback::new(buffer)));
+        }
+    }
+    if readback.in_flight.is_empty() {
+        return;
+    }
+
+    // Process the readbacks already mapped, in order
+    render_device.poll(wgpu::Maintain::Poll);
+    let mut in_flight = std::mem::take(&mut readback.in_flight).into_iter();
+    for (entities, async_readback) in in_flight.by_ref() {
+        let result = async_readback.try_read(|data| {
+            let pending: &[i32] = cast_slice(data);
+            entities
+                .iter()
+                .zip(pending)
+                .filter(|(_, pending)| **pending == 0)
+                .map(|(entity, _)| *entity)
+                .collect::<Vec<_>>()
+        });
+        match result {
+            Ok(Some(entities)) => completed.0.lock().unwrap().extend(entities),
+            Ok(None) => warn!("Failed to read back the completion of the effects."),
+            Err(async_readback) => {
+                readback.in_flight.push((entities, async_readback));
+                break;
+            }
+        }
+    }
+    readback.in_flight.extend(in_flight);
+}
+
+/// GPU buffers of a single effect instance, for use by custom render nodes.
+///
+/// The buffers are shared with other effect instances; only the ranges documented on each
+/// field belong to the effect.
+#[derive(Debug, Clone)]
+pub struct EffectGpuBuffer {
+    /// Buffer containing the particles of the effect.
+    pub particle_buffer: Buffer,
+    /// Range of particles of the effect in [`particle_buffer`], in number of particles.
+    ///
+    /// [`particle_buffer`]: EffectGpuBuffer::particle_buffer
+    pub slice: Range<u32>,
+    /// Size in bytes of a single particle.
+    pub item_size: u32,
+    /// Buffer of the indices of the particles alive after the update pass, over the same
+    /// [`slice`] as the particle buffer.
+    ///
+    /// [`slice`]: EffectGpuBuffer::slice
+    pub indirect_buffer: Buffer,
+    /// Buffer containing the spawner block of the effect for this frame.
+    ///
+    /// After the update pass, the 32-bit signed integer at byte offset 28 of the block
+    /// holds the number of particles alive, that is the number of valid indices in the
+    /// [`indirect_buffer`].
+    ///
+    /// [`indirect_buffer`]: EffectGpuBuffer::indirect_buffer
+    pub spawner_buffer: Buffer,
+    /// Offset in bytes of the spawner block of the effect in [`spawner_buffer`].
+    ///
+    /// [`spawner_buffer`]: EffectGpuBuffer::spawner_buffer
+    pub spawner_offset: u64,
+    /// Buffer containing the arguments of the indirect draw call rendering the effect.
+    ///
+    /// The arguments are four 32-bit unsigned integers: the vertex count, the instance count,
+    /// the first vertex, and the first instance, which is always zero. Each instance is one
+    /// particle slot. Setting the instance count to zero culls the effect, and lowering it
+    /// draws only the first slots. The draw call is shared by the effects of the same asset
+    /// batched together, so patching it affects all of them.
+    pub draw_args_buffer: Buffer,
+    /// Offset in bytes of the draw call arguments of the effect in [`draw_args_buffer`].
+    ///
+    /// [`draw_args_buffer`]: EffectGpuBuffer::draw_args_buffer
+    pub draw_args_offset: u64,
+}
+
+/// Render world resource exposing the GPU buffers of all the effects prepared this frame.
+///
+/// The resource is updated during [`RenderStage::Prepare`]. A custom render node can read
+/// or patch the buffers on the GPU after the particle update pass, by adding a node edge from
+/// [`draw_graph::node::PARTICLE_UPDATE_PASS`] in the 2D or 3D draw graph, and before the
+/// main pass which renders the particles. For example, a culling compute pass can patch the
+/// instance count of the draw call arguments of an effect.
+///
+/// [`RenderStage::Prepare`]: bevy::render::RenderStage::Prepare
+/// [`draw_graph::node::PARTICLE_UPDATE_PASS`]: crate::draw_graph::node::PARTICLE_UPDATE_PASS
+#[derive(Default)]
+pub struct EffectGpuBuffers {
+    buffers: HashMap<Entity, EffectGpuBuffer>,
+}
+
+impl EffectGpuBuffers {
+    /// Get the GPU buffers of the effect on the given main world entity, if it was prepared
+    /// this frame.
+    pub fn get(&self, entity: Entity) -> Option<&EffectGpuBuffer> {
+        self.buffers.get(&entity)
+    }
+
+    /// Iterate over the GPU buffers of all the effects prepared this frame, along with the
+    /// main world entity of each effect.
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &EffectGpuBuffer)> {
+        self.buffers
+            .iter()
+            .map(|(entity, buffers)| (*entity, buffers))
+    }
 }
 
 #[derive(Default)]
@@ -1320,8 +3357,14 @@ pub struct EffectBindGroups {
     update_indirect_buffers: HashMap<u32, BindGroup>,
     /// Bind groups for each indirect buffer associated with each particle buffer (render stage).
     render_indirect_buffers: HashMap<u32, BindGroup>,
-    ///
+    /// Bind groups of the particle textures.
     images: HashMap<Handle<Image>, BindGroup>,
+    /// Custom user bind group of each effect batch entity, recreated each frame.
+    user_data: HashMap<Entity, BindGroup>,
+    /// Custom user bind group of the update pass of each effect asset, recreated each frame.
+    update_user_data: HashMap<Handle<EffectAsset>, BindGroup>,
+    /// Bind group of the [`InitData`] buffer of each effect asset using one.
+    init_data: HashMap<Handle<EffectAsset>, BindGroup>,
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -1331,17 +3374,20 @@ pub(crate) fn queue_effects(
     render_device: Res<RenderDevice>,
     mut effects_meta: ResMut<EffectsMeta>,
     view_uniforms: Res<ViewUniforms>,
-    update_pipeline: Res<ParticlesUpdatePipeline>,
-    mut compute_cache: ResMut<ComputeCache<ParticlesUpdatePipeline>>,
+    (update_pipeline, mut compute_cache): (
+        Res<ParticlesUpdatePipeline>,
+        ResMut<ComputeCache<ParticlesUpdatePipeline>>,
+    ),
     render_pipeline: Res<ParticlesRenderPipeline>,
     mut specialized_render_pipelines: ResMut<SpecializedRenderPipelines<ParticlesRenderPipeline>>,
     mut render_pipeline_cache: ResMut<PipelineCache>,
     mut effect_bind_groups: ResMut<EffectBindGroups>,
     gpu_images: Res<RenderAssets<Image>>,
+    msaa: Res<Msaa>,
     mut effect_batches: Query<(Entity, &mut EffectBatch)>,
     #[cfg(feature = "2d")] mut views_2d: Query<&mut RenderPhase<Transparent2d>>,
-    #[cfg(feature = "3d")] mut views_3d: Query<&mut RenderPhase<Transparent3d>>,
-    events: Res<EffectAssetEvents>,
+    #[cfg(feature = "3d")] mut views_3d: Query<(&ExtractedView, &mut RenderPhase<Transparent3d>)>,
+    (events, config): (Res<EffectAssetEvents>, Res<HanabiConfig>),
 ) {
     trace!("queue_effects");
 
@@ -1367,13 +3413,32 @@ pub(crate) fn queue_effects(
         // No spawners are active
         return;
     }
+    let emitter_buffer = match effects_meta.emitter_buffer.buffer() {
+        Some(emitter_buffer) => emitter_buffer.clone(),
+        None => return,
+    };
 
-    // Create the bind group for the camera/view parameters
+    // Create the bind group for the camera/view parameters, which also exposes the
+    // simulation parameters to the render shaders.
     effects_meta.view_bind_group = Some(render_device.create_bind_group(&BindGroupDescriptor {
-        entries: &[BindGroupEntry {
-            binding: 0,
-            resource: view_binding,
-        }],
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: view_binding,
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: effects_meta.sim_params_uniforms.binding().unwrap(),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: BindingResource::Buffer(BufferBinding {
+                    buffer: &emitter_buffer,
+                    offset: 0,
+                    size: NonZeroU64::new(size_of::<EmitterParams>() as u64),
+                }),
+            },
+        ],
         label: Some("particles_view_bind_group"),
         layout: &render_pipeline.view_layout,
     }));
@@ -1395,14 +3460,26 @@ pub(crate) fn queue_effects(
         SpawnerParams::std430_size_static()
     );
     effects_meta.spawner_bind_group = Some(render_device.create_bind_group(&BindGroupDescriptor {
-        entries: &[BindGroupEntry {
-            binding: 0,
-            resource: BindingResource::Buffer(BufferBinding {
-                buffer: effects_meta.spawner_buffer.buffer().unwrap(),
-                offset: 0,
-                size: Some(NonZeroU64::new(SpawnerParams::std430_size_static() as u64).unwrap()),
-            }),
-        }],
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::Buffer(BufferBinding {
+                    buffer: effects_meta.spawner_buffer.buffer().unwrap(),
+                    offset: 0,
+                    size: Some(
+                        NonZeroU64::new(SpawnerParams::std430_size_static() as u64).unwrap(),
+                    ),
+                }),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Buffer(BufferBinding {
+                    buffer: effects_meta.completion_buffer.buffer().unwrap(),
+                    offset: 0,
+                    size: Some(NonZeroU64::new(size_of::<i32>() as u64).unwrap()),
+                }),
+            },
+        ],
         label: Some("particles_spawner_bind_group"),
         layout: &update_pipeline.spawner_buffer_layout,
     }));
@@ -1507,12 +3584,112 @@ pub(crate) fn queue_effects(
             ParticleUpdatePipelineKey {
                 position_code: batch.position_code.clone(),
                 force_field_code: batch.force_field_code.clone(),
+                extension_includes: batch.extension_includes.clone(),
+                init_data: batch.layout_flags.contains(LayoutFlags::INIT_DATA),
+                user_declarations: batch.user_declarations.clone(),
+                user_texture: batch.layout_flags.contains(LayoutFlags::USER_TEXTURE),
             },
             &render_device,
         );
-        trace!("Update pipeline specialized: {:?}", compute_pipeline);
+        trace!("Update pipeline specialized: index={}", compute_pipeline);
 
-        batch.compute_pipeline = Some(compute_pipeline.clone());
+        batch.compute_pipeline = Some(compute_pipeline);
+
+        // Ensure the init data of the effect has a bind group, if uploaded
+        if batch.layout_flags.contains(LayoutFlags::INIT_DATA) {
+            if let Some(buffer) = effects_meta.init_buffers.get(&batch.handle) {
+                effect_bind_groups
+                    .init_data
+                    .entry(batch.handle.clone_weak())
+                    .or_insert_with(|| {
+                        render_device.create_bind_group(&BindGroupDescriptor {
+                            entries: &[BindGroupEntry {
+                                binding: 0,
+                                resource: buffer.as_entire_binding(),
+                            }],
+                            label: Some("particles_init_data_bind_group"),
+                            layout: &update_pipeline.init_data_layout,
+                        })
+                    });
+            }
+        }
+    }
+
+    // Create the custom user bind group of each batch for the render pass, and of each effect
+    // asset for the update pass, from the buffers uploaded during the prepare stage.
+    effect_bind_groups.user_data.clear();
+    effect_bind_groups.update_user_data.clear();
+    for (entity, batch) in effect_batches.iter() {
+        if batch.user_declarations.is_none() {
+            continue;
+        }
+        let buffer = if let Some((buffer, _)) = effects_meta.user_buffers.get(&batch.handle) {
+            buffer
+        } else {
+            continue;
+        };
+
+        let gpu_image = if batch.layout_flags.contains(LayoutFlags::USER_TEXTURE) {
+            let image_handle = Handle::weak(batch.user_texture_handle_id);
+            if let Some(gpu_image) = gpu_images.get(&image_handle) {
+                Some(gpu_image)
+            } else {
+                // Texture is not ready; the batch is not drawn until it is
+                trace!("GPU image of user data not yet available; skipping batch for now.");
+                continue;
+            }
+        } else {
+            None
+        };
+
+        let create_bind_group = |layout: &BindGroupLayout, texture_layout: &BindGroupLayout| {
+            if let Some(gpu_image) = gpu_image {
+                render_device.create_bind_group(&BindGroupDescriptor {
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: buffer.as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: BindingResource::TextureView(&gpu_image.texture_view),
+                        },
+                        BindGroupEntry {
+                            binding: 2,
+                            resource: BindingResource::Sampler(&gpu_image.sampler),
+                        },
+                    ],
+                    label: Some("particles_user_data_bind_group"),
+                    layout: texture_layout,
+                })
+            } else {
+                render_device.create_bind_group(&BindGroupDescriptor {
+                    entries: &[BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                    label: Some("particles_user_data_bind_group"),
+                    layout,
+                })
+            }
+        };
+        let bind_group = create_bind_group(
+            &render_pipeline.user_data_layout,
+            &render_pipeline.user_data_texture_layout,
+        );
+        effect_bind_groups.user_data.insert(entity, bind_group);
+        if !effect_bind_groups
+            .update_user_data
+            .contains_key(&batch.handle)
+        {
+            let bind_group = create_bind_group(
+                &update_pipeline.user_data_layout,
+                &update_pipeline.user_data_texture_layout,
+            );
+            effect_bind_groups
+                .update_user_data
+                .insert(batch.handle.clone_weak(), bind_group);
+        }
     }
 
     // Loop over all 2D cameras/views that need to render effects
@@ -1534,63 +3711,54 @@ pub(crate) fn queue_effects(
                 // Ensure the particle texture is available as a GPU resource and create a bind group for it
                 let particle_texture = if batch.layout_flags.contains(LayoutFlags::PARTICLE_TEXTURE)
                 {
-                    let image_handle = Handle::weak(batch.image_handle_id);
-                    if effect_bind_groups.images.get(&image_handle).is_none() {
-                        trace!(
-                            "Batch buffer #{} slice={:?} has missing GPU image bind group, creating...",
-                            batch.buffer_index,
-                            batch.slice
-                        );
-                        // If texture doesn't have a bind group yet from another instance of the same effect,
-                        // then try to create one now
-                        if let Some(gpu_image) = gpu_images.get(&image_handle) {
-                            let bind_group =
-                                render_device.create_bind_group(&BindGroupDescriptor {
-                                    entries: &[
-                                        BindGroupEntry {
-                                            binding: 0,
-                                            resource: BindingResource::TextureView(
-                                                &gpu_image.texture_view,
-                                            ),
-                                        },
-                                        BindGroupEntry {
-                                            binding: 1,
-                                            resource: BindingResource::Sampler(&gpu_image.sampler),
-                                        },
-                                    ],
-                                    label: Some("particles_material_bind_group"),
-                                    layout: &render_pipeline.material_layout,
-                                });
-                            effect_bind_groups
-                                .images
-                                .insert(image_handle.clone(), bind_group);
-                            Some(image_handle)
-                        } else {
-                            // Texture is not ready; skip for now...
-                            trace!("GPU image not yet available; skipping batch for now.");
-                            None
-                        }
-                    } else {
-                        // Bind group already exists, meaning texture is ready
-                        Some(image_handle)
-                    }
+                    prepare_image_bind_group(
+                        batch.image_handle_id,
+                        batch,
+                        &mut effect_bind_groups,
+                        &gpu_images,
+                        &render_device,
+                        &render_pipeline.material_layout,
+                        effects_meta.texture_sampler.as_ref(),
+                    )
                 } else {
                     // Batch doesn't use particle texture
                     None
                 };
 
+                // Same for the color ramp texture
+                let color_ramp = if batch.layout_flags.contains(LayoutFlags::COLOR_RAMP) {
+                    prepare_image_bind_group(
+                        batch.color_ramp_handle_id,
+                        batch,
+                        &mut effect_bind_groups,
+                        &gpu_images,
+                        &render_device,
+                        &render_pipeline.material_layout,
+                        None,
+                    )
+                } else {
+                    // Batch doesn't use color ramp
+                    None
+                };
+
                 // Specialize the render pipeline based on the effect batch
                 trace!(
-                    "Specializing render pipeline: shader={:?} particle_texture={:?}",
+                    "Specializing render pipeline: shader={:?} particle_texture={:?} color_ramp={:?}",
                     batch.shader,
-                    particle_texture
+                    particle_texture,
+                    color_ramp
                 );
                 let render_pipeline_id = specialized_render_pipelines.specialize(
                     &mut render_pipeline_cache,
                     &render_pipeline,
                     ParticleRenderPipelineKey {
                         particle_texture,
+                        color_ramp,
+                        user_data: batch.layout_flags.contains(LayoutFlags::USER_DATA),
+                        user_texture: batch.layout_flags.contains(LayoutFlags::USER_TEXTURE),
                         shader: batch.shader.clone(),
+                        msaa_samples: msaa.samples,
+                        shader_defs: batch.shader_defs.clone(),
                         #[cfg(feature = "3d")]
                         pipeline_mode: PipelineMode::Camera2d,
                     },
@@ -1603,7 +3771,8 @@ pub(crate) fn queue_effects(
                     draw_function: draw_effects_function_2d,
                     pipeline: render_pipeline_id,
                     entity,
-                    sort_key: FloatOrd(0.0),
+                    // Sprites are sorted by depth, which is the Z coordinate in 2D
+                    sort_key: FloatOrd(if config.sorting { batch.origin.z } else { 0. }),
                     batch_range: None,
                 });
             }
@@ -1614,8 +3783,11 @@ pub(crate) fn queue_effects(
     #[cfg(feature = "3d")]
     {
         let draw_effects_function_3d = draw_functions_3d.read().get_id::<DrawEffects>().unwrap();
-        for mut transparent_phase_3d in views_3d.iter_mut() {
+        for (view, mut transparent_phase_3d) in views_3d.iter_mut() {
             trace!("Process new Transparent3d view");
+            // The view space depth of a point, increasing toward the camera, so that the
+            // ascending sort of the transparent phase draws back to front
+            let inverse_view_row_2 = view.transform.compute_matrix().inverse().row(2);
             // For each view, loop over all the effect batches to determine if the effect needs to be rendered
             // for that view, and enqueue a view-dependent batch if so.
             for (entity, batch) in effect_batches.iter() {
@@ -1629,63 +3801,54 @@ pub(crate) fn queue_effects(
                 // Ensure the particle texture is available as a GPU resource and create a bind group for it
                 let particle_texture = if batch.layout_flags.contains(LayoutFlags::PARTICLE_TEXTURE)
                 {
-                    let image_handle = Handle::weak(batch.image_handle_id);
-                    if effect_bind_groups.images.get(&image_handle).is_none() {
-                        trace!(
-                            "Batch buffer #{} slice={:?} has missing GPU image bind group, creating...",
-                            batch.buffer_index,
-                            batch.slice
-                        );
-                        // If texture doesn't have a bind group yet from another instance of the same effect,
-                        // then try to create one now
-                        if let Some(gpu_image) = gpu_images.get(&image_handle) {
-                            let bind_group =
-                                render_device.create_bind_group(&BindGroupDescriptor {
-                                    entries: &[
-                                        BindGroupEntry {
-                                            binding: 0,
-                                            resource: BindingResource::TextureView(
-                                                &gpu_image.texture_view,
-                                            ),
-                                        },
-                                        BindGroupEntry {
-                                            binding: 1,
-                                            resource: BindingResource::Sampler(&gpu_image.sampler),
-                                        },
-                                    ],
-                                    label: Some("particles_material_bind_group"),
-                                    layout: &render_pipeline.material_layout,
-                                });
-                            effect_bind_groups
-                                .images
-                                .insert(image_handle.clone(), bind_group);
-                            Some(image_handle)
-                        } else {
-                            // Texture is not ready; skip for now...
-                            trace!("GPU image not yet available; skipping batch for now.");
-                            None
-                        }
-                    } else {
-                        // Bind group already exists, meaning texture is ready
-                        Some(image_handle)
-                    }
+                    prepare_image_bind_group(
+                        batch.image_handle_id,
+                        batch,
+                        &mut effect_bind_groups,
+                        &gpu_images,
+                        &render_device,
+                        &render_pipeline.material_layout,
+                        effects_meta.texture_sampler.as_ref(),
+                    )
                 } else {
                     // Batch doesn't use particle texture
                     None
                 };
 
+                // Same for the color ramp texture
+                let color_ramp = if batch.layout_flags.contains(LayoutFlags::COLOR_RAMP) {
+                    prepare_image_bind_group(
+                        batch.color_ramp_handle_id,
+                        batch,
+                        &mut effect_bind_groups,
+                        &gpu_images,
+                        &render_device,
+                        &render_pipeline.material_layout,
+                        None,
+                    )
+                } else {
+                    // Batch doesn't use color ramp
+                    None
+                };
+
                 // Specialize the render pipeline based on the effect batch
                 trace!(
-                    "Specializing render pipeline: shader={:?} particle_texture={:?}",
+                    "Specializing render pipeline: shader={:?} particle_texture={:?} color_ramp={:?}",
                     batch.shader,
-                    particle_texture
+                    particle_texture,
+                    color_ramp
                 );
                 let render_pipeline_id = specialized_render_pipelines.specialize(
                     &mut render_pipeline_cache,
                     &render_pipeline,
                     ParticleRenderPipelineKey {
                         particle_texture,
+                        color_ramp,
+                        user_data: batch.layout_flags.contains(LayoutFlags::USER_DATA),
+                        user_texture: batch.layout_flags.contains(LayoutFlags::USER_TEXTURE),
                         shader: batch.shader.clone(),
+                        msaa_samples: msaa.samples,
+                        shader_defs: batch.shader_defs.clone(),
                         #[cfg(feature = "2d")]
                         pipeline_mode: PipelineMode::Camera3d,
                     },
@@ -1698,13 +3861,73 @@ pub(crate) fn queue_effects(
                     draw_function: draw_effects_function_3d,
                     pipeline: render_pipeline_id,
                     entity,
-                    distance: 0.0, // TODO ??????
+                    distance: if config.sorting {
+                        inverse_view_row_2.dot(batch.origin.extend(1.))
+                    } else {
+                        0.
+                    },
                 });
             }
         }
     }
 }
 
+/// Ensure an image used by an effect batch has a bind group, creating one if the image is
+/// available as a GPU resource.
+///
+/// Returns the handle of the image if its bind group is ready, or `None` if the GPU image is
+/// not available yet, in which case the batch should be skipped for now. If a sampler
+/// override is provided, it's used in place of the sampler of the image.
+fn prepare_image_bind_group(
+    image_handle_id: HandleId,
+    batch: &EffectBatch,
+    effect_bind_groups: &mut EffectBindGroups,
+    gpu_images: &RenderAssets<Image>,
+    render_device: &RenderDevice,
+    material_layout: &BindGroupLayout,
+    sampler_override: Option<&Sampler>,
+) -> Option<Handle<Image>> {
+    let image_handle = Handle::weak(image_handle_id);
+    if effect_bind_groups.images.get(&image_handle).is_some() {
+        // Bind group already exists, meaning texture is ready
+        return Some(image_handle);
+    }
+
+    trace!(
+        "Batch buffer #{} slice={:?} has missing GPU image bind group, creating...",
+        batch.buffer_index,
+        batch.slice
+    );
+    // If texture doesn't have a bind group yet from another instance of the same effect,
+    // then try to create one now
+    if let Some(gpu_image) = gpu_images.get(&image_handle) {
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&gpu_image.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(
+                        sampler_override.unwrap_or(&gpu_image.sampler),
+                    ),
+                },
+            ],
+            label: Some("particles_material_bind_group"),
+            layout: material_layout,
+        });
+        effect_bind_groups
+            .images
+            .insert(image_handle.clone(), bind_group);
+        Some(image_handle)
+    } else {
+        // Texture is not ready; skip for now...
+        trace!("GPU image not yet available; skipping batch for now.");
+        None
+    }
+}
+
 /// Component to hold all the entities with a [`ExtractedEffect`] component on them
 /// that need to be updated this frame with a compute pass. This is view-independent
 /// because the update phase itself is also view-independent (effects like camera
@@ -1761,14 +3984,14 @@ impl Draw<Transparent2d> for DrawEffects {
 
             pass.set_render_pipeline(pipeline);
 
-            // Vertex buffer containing the particle model to draw. Generally a quad.
+            // Vertex buffer containing the particle models of all geometries.
             pass.set_vertex_buffer(0, effects_meta.vertices.buffer().unwrap().slice(..));
 
             // View properties (camera matrix, etc.)
             pass.set_bind_group(
                 0,
                 effects_meta.view_bind_group.as_ref().unwrap(),
-                &[view_uniform.offset],
+                &[view_uniform.offset, effect_batch.emitter_offset],
             );
 
             // Particles buffer
@@ -1782,13 +4005,15 @@ impl Draw<Transparent2d> for DrawEffects {
             );
 
             // Particle texture
+            let mut next_group = 2;
             if effect_batch
                 .layout_flags
                 .contains(LayoutFlags::PARTICLE_TEXTURE)
             {
                 let image_handle = Handle::weak(effect_batch.image_handle_id);
                 if let Some(bind_group) = effect_bind_groups.images.get(&image_handle) {
-                    pass.set_bind_group(2, bind_group, &[]);
+                    pass.set_bind_group(next_group, bind_group, &[]);
+                    next_group += 1;
                 } else {
                     // Texture not ready; skip this drawing for now
                     trace!(
@@ -1800,16 +4025,47 @@ impl Draw<Transparent2d> for DrawEffects {
                 }
             }
 
-            let vertex_count = effects_meta.vertices.len() as u32;
-            let particle_count = effect_batch.slice.end - effect_batch.slice.start;
+            // Color ramp texture
+            if effect_batch.layout_flags.contains(LayoutFlags::COLOR_RAMP) {
+                let image_handle = Handle::weak(effect_batch.color_ramp_handle_id);
+                if let Some(bind_group) = effect_bind_groups.images.get(&image_handle) {
+                    pass.set_bind_group(next_group, bind_group, &[]);
+                    next_group += 1;
+                } else {
+                    // Texture not ready; skip this drawing for now
+                    trace!(
+                        "Color ramp bind group not available for batch buf={} slice={:?}. Skipping draw call.",
+                        effect_batch.buffer_index,
+                        effect_batch.slice
+                    );
+                    return; //continue;
+                }
+            }
+
+            // Custom user data
+            if effect_batch.layout_flags.contains(LayoutFlags::USER_DATA) {
+                if let Some(bind_group) = effect_bind_groups.user_data.get(&item.entity) {
+                    pass.set_bind_group(next_group, bind_group, &[]);
+                } else {
+                    // User texture not ready; skip this drawing for now
+                    trace!(
+                        "User data bind group not available for batch buf={} slice={:?}. Skipping draw call.",
+                        effect_batch.buffer_index,
+                        effect_batch.slice
+                    );
+                    return; //continue;
+                }
+            }
 
             trace!(
-                "Draw {} particles with {} vertices per particle for batch from buffer #{}.",
-                particle_count,
-                vertex_count,
-                effect_batch.buffer_index
+                "Draw batch from buffer #{} with indirect arguments at offset {}.",
+                effect_batch.buffer_index,
+                effect_batch.draw_args_offset
+            );
+            pass.draw_indirect(
+                effects_meta.draw_args_buffer.buffer().unwrap(),
+                effect_batch.draw_args_offset,
             );
-            pass.draw(0..vertex_count, 0..particle_count);
         }
     }
 }
@@ -1839,14 +4095,14 @@ impl Draw<Transparent3d> for DrawEffects {
 
             pass.set_render_pipeline(pipeline);
 
-            // Vertex buffer containing the particle model to draw. Generally a quad.
+            // Vertex buffer containing the particle models of all geometries.
             pass.set_vertex_buffer(0, effects_meta.vertices.buffer().unwrap().slice(..));
 
             // View properties (camera matrix, etc.)
             pass.set_bind_group(
                 0,
                 effects_meta.view_bind_group.as_ref().unwrap(),
-                &[view_uniform.offset],
+                &[view_uniform.offset, effect_batch.emitter_offset],
             );
 
             // Particles buffer
@@ -1860,13 +4116,15 @@ impl Draw<Transparent3d> for DrawEffects {
             );
 
             // Particle texture
+            let mut next_group = 2;
             if effect_batch
                 .layout_flags
                 .contains(LayoutFlags::PARTICLE_TEXTURE)
             {
                 let image_handle = Handle::weak(effect_batch.image_handle_id);
                 if let Some(bind_group) = effect_bind_groups.images.get(&image_handle) {
-                    pass.set_bind_group(2, bind_group, &[]);
+                    pass.set_bind_group(next_group, bind_group, &[]);
+                    next_group += 1;
                 } else {
                     // Texture not ready; skip this drawing for now
                     trace!(
@@ -1878,16 +4136,47 @@ impl Draw<Transparent3d> for DrawEffects {
                 }
             }
 
-            let vertex_count = effects_meta.vertices.len() as u32;
-            let particle_count = effect_batch.slice.end - effect_batch.slice.start;
+            // Color ramp texture
+            if effect_batch.layout_flags.contains(LayoutFlags::COLOR_RAMP) {
+                let image_handle = Handle::weak(effect_batch.color_ramp_handle_id);
+                if let Some(bind_group) = effect_bind_groups.images.get(&image_handle) {
+                    pass.set_bind_group(next_group, bind_group, &[]);
+                    next_group += 1;
+                } else {
+                    // Texture not ready; skip this drawing for now
+                    trace!(
+                        "Color ramp bind group not available for batch buf={} slice={:?}. Skipping draw call.",
+                        effect_batch.buffer_index,
+                        effect_batch.slice
+                    );
+                    return; //continue;
+                }
+            }
+
+            // Custom user data
+            if effect_batch.layout_flags.contains(LayoutFlags::USER_DATA) {
+                if let Some(bind_group) = effect_bind_groups.user_data.get(&item.entity) {
+                    pass.set_bind_group(next_group, bind_group, &[]);
+                } else {
+                    // User texture not ready; skip this drawing for now
+                    trace!(
+                        "User data bind group not available for batch buf={} slice={:?}. Skipping draw call.",
+                        effect_batch.buffer_index,
+                        effect_batch.slice
+                    );
+                    return; //continue;
+                }
+            }
 
             trace!(
-                "Draw {} particles with {} vertices per particle for batch from buffer #{}.",
-                particle_count,
-                vertex_count,
-                effect_batch.buffer_index
+                "Draw batch from buffer #{} with indirect arguments at offset {}.",
+                effect_batch.buffer_index,
+                effect_batch.draw_args_offset
+            );
+            pass.draw_indirect(
+                effects_meta.draw_args_buffer.buffer().unwrap(),
+                effect_batch.draw_args_offset,
             );
-            pass.draw(0..vertex_count, 0..particle_count);
         }
     }
 }
@@ -1903,7 +4192,7 @@ pub struct ParticleUpdateNode {
 impl ParticleUpdateNode {
     /// Input entity marking the view.
     pub const IN_VIEW: &'static str = "view";
-    /// Output particle buffer for that view. TODO - how to handle multiple buffers?! Should use Entity instead??
+    // Output particle buffer for that view. TODO - how to handle multiple buffers?! Should use Entity instead??
     //pub const OUT_PARTICLE_BUFFER: &'static str = "particle_buffer";
 
     pub fn new(world: &mut World) -> Self {
@@ -1940,6 +4229,13 @@ impl Node for ParticleUpdateNode {
     ) -> Result<(), NodeRunError> {
         trace!("ParticleUpdateNode::run()");
 
+        // Simulate the particles only once per frame, for the first view rendered
+        let effects_meta = world.get_resource::<EffectsMeta>().unwrap();
+        if effects_meta.update_done.swap(true, AtomicOrdering::AcqRel) {
+            trace!("update pass already ran this frame; skipped.");
+            return Ok(());
+        }
+
         // Get the Entity containing the ViewEffectsEntity component used as container
         // for the input data for this node.
         //let view_entity = graph.get_input_entity(Self::IN_VIEW)?;
@@ -1958,6 +4254,7 @@ impl Node for ParticleUpdateNode {
 
         // Compute update pass
         {
+            let completion_buffer_aligned = effects_meta.completion_buffer.aligned_size();
             let mut compute_pass =
                 render_context
                     .command_encoder
@@ -1968,77 +4265,131 @@ impl Node for ParticleUpdateNode {
             let effects_meta = world.get_resource::<EffectsMeta>().unwrap();
             let effect_bind_groups = world.get_resource::<EffectBindGroups>().unwrap();
 
-            // Retrieve the ExtractedEffectEntities component itself
-            //if let Ok(extracted_effect_entities) = self.entity_query.get_manual(world, view_entity)
-            //if let Ok(effect_batches) = self.effect_query.get_manual(world, )
-            {
-                // Loop on all entities recorded inside the ExtractedEffectEntities input
-                trace!("loop over effect batches...");
-                //for effect_entity in extracted_effect_entities.entities.iter().copied() {
-
-                for batch in self.effect_query.iter_manual(world) {
-                    if let Some(compute_pipeline) = &batch.compute_pipeline {
-                        //for (effect_entity, effect_slice) in effects_meta.entity_map.iter() {
-                        // Retrieve the ExtractedEffect from the entity
-                        //trace!("effect_entity={:?} effect_slice={:?}", effect_entity, effect_slice);
-                        //let effect = self.effect_query.get_manual(world, *effect_entity).unwrap();
-
-                        // Get the slice to update
-                        //let effect_slice = effects_meta.get(&effect_entity);
-                        // let effect_group =
-                        //     &effects_meta.effect_cache.buffers()[batch.buffer_index as usize];
-                        let particles_bind_group = effect_bind_groups
-                            .update_particle_buffers
-                            .get(&batch.buffer_index)
-                            .unwrap();
-
-                        let indirect_bind_group = effect_bind_groups
-                            .update_indirect_buffers
-                            .get(&batch.buffer_index)
-                            .unwrap();
-
-                        let item_size = batch.item_size;
-                        let item_count = batch.slice.end - batch.slice.start;
-                        let workgroup_count = (item_count + 63) / 64;
-
-                        let spawner_base = batch.spawner_base;
-                        let buffer_offset = batch.slice.start;
-
-                        let spawner_buffer_aligned = effects_meta.spawner_buffer.aligned_size();
-                        assert!(spawner_buffer_aligned >= SpawnerParams::std430_size_static());
-
-                        trace!(
-                            "record commands for pipeline of effect {:?} ({} items / {}B/item = {} workgroups) spawner_base={} buffer_offset={}...",
-                            batch.handle,
-                            item_count,
-                            item_size,
-                            workgroup_count,
-                            spawner_base,
-                            buffer_offset,
-                        );
+            let spawner_buffer_aligned = effects_meta.spawner_buffer.aligned_size();
+            assert!(spawner_buffer_aligned >= SpawnerParams::std430_size_static());
+
+            let compute_cache = world
+                .get_resource::<ComputeCache<ParticlesUpdatePipeline>>()
+                .unwrap();
+
+            // Sort the batches by pipeline, then by effect buffer, and group the batches
+            // sharing the same pipeline, so that each pipeline is set only once. Each batch
+            // is still dispatched separately, with its data selected by dynamic offsets.
+            let mut batches: Vec<(usize, &EffectBatch)> = self
+                .effect_query
+                .iter_manual(world)
+                .filter_map(|batch| batch.compute_pipeline.map(|index| (index, batch)))
+                .collect();
+            batches.sort_by_key(|(index, batch)| (*index, batch.buffer_index));
+            let mut pipeline_batches: Vec<(usize, Vec<&EffectBatch>)> = vec![];
+            for (index, batch) in batches {
+                match pipeline_batches.last_mut() {
+                    Some((last_index, merged)) if *last_index == index => merged.push(batch),
+                    _ => pipeline_batches.push((index, vec![batch])),
+                }
+            }
 
-                        // Setup compute pass
-                        //compute_pass.set_pipeline(&effect_group.compute_pipeline);
-                        compute_pass.set_pipeline(compute_pipeline);
-                        compute_pass.set_bind_group(
-                            0,
-                            effects_meta.sim_params_bind_group.as_ref().unwrap(),
-                            &[],
-                        );
-                        compute_pass.set_bind_group(1, particles_bind_group, &[buffer_offset]);
-                        compute_pass.set_bind_group(
-                            2,
-                            effects_meta.spawner_bind_group.as_ref().unwrap(),
-                            &[spawner_base * spawner_buffer_aligned as u32],
-                        );
-                        compute_pass.set_bind_group(3, indirect_bind_group, &[buffer_offset]);
-                        compute_pass.dispatch(workgroup_count, 1, 1);
-                        trace!("compute dispatched");
+            // The simulation parameters are shared by all effects
+            if !pipeline_batches.is_empty() {
+                compute_pass.set_bind_group(
+                    0,
+                    effects_meta.sim_params_bind_group.as_ref().unwrap(),
+                    &[],
+                );
+            }
+
+            trace!("loop over {} pipeline batches...", pipeline_batches.len());
+            for (index, batches) in pipeline_batches {
+                compute_pass.set_pipeline(compute_cache.get(index));
+                for batch in batches {
+                    let particles_bind_group = effect_bind_groups
+                        .update_particle_buffers
+                        .get(&batch.buffer_index)
+                        .unwrap();
+
+                    let indirect_bind_group = effect_bind_groups
+                        .update_indirect_buffers
+                        .get(&batch.buffer_index)
+                        .unwrap();
+
+                    // Skip the effects whose init data is not uploaded yet
+                    let init_data_bind_group = effect_bind_groups.init_data.get(&batch.handle);
+                    if batch.layout_flags.contains(LayoutFlags::INIT_DATA)
+                        && init_data_bind_group.is_none()
+                    {
+                        trace!("Init data not available for batch; skipping update.");
+                        continue;
                     }
+
+                    // Skip the effects whose custom user bind group is not ready yet
+                    let user_data_bind_group =
+                        effect_bind_groups.update_user_data.get(&batch.handle);
+                    if batch.user_declarations.is_some() && user_data_bind_group.is_none() {
+                        trace!("User data not available for batch; skipping update.");
+                        continue;
+                    }
+
+                    let item_size = batch.item_size;
+                    let item_count = batch.slice.end - batch.slice.start;
+                    let workgroup_count = item_count.div_ceil(64);
+
+                    let spawner_base = batch.spawner_base;
+                    let buffer_offset = batch.slice.start;
+
+                    trace!(
+                        "record commands for pipeline of effect {:?} ({} items / {}B/item = {} workgroups) spawner_base={} buffer_offset={}...",
+                        batch.handle,
+                        item_count,
+                        item_size,
+                        workgroup_count,
+                        spawner_base,
+                        buffer_offset,
+                    );
+
+                    // Setup compute pass
+                    compute_pass.set_bind_group(1, particles_bind_group, &[buffer_offset]);
+                    compute_pass.set_bind_group(
+                        2,
+                        effects_meta.spawner_bind_group.as_ref().unwrap(),
+                        &[
+                            spawner_base * spawner_buffer_aligned as u32,
+                            spawner_base * completion_buffer_aligned as u32,
+                        ],
+                    );
+                    compute_pass.set_bind_group(3, indirect_bind_group, &[buffer_offset]);
+                    if let Some(init_data_bind_group) = init_data_bind_group {
+                        compute_pass.set_bind_group(4, init_data_bind_group, &[]);
+                    }
+                    if let Some(user_data_bind_group) = user_data_bind_group {
+                        let group = 4 + batch.layout_flags.contains(LayoutFlags::INIT_DATA) as u32;
+                        compute_pass.set_bind_group(group, user_data_bind_group, &[]);
+                    }
+                    compute_pass.dispatch(workgroup_count, 1, 1);
+                    trace!("compute dispatched");
                 }
             }
         }
 
+        // Copy the completion counts of the effects to read back
+        let completion_readback = world.get_resource::<CompletionReadback>().unwrap();
+        if let (Some(readback_buffer), Some(completion_buffer)) = (
+            completion_readback.buffer.as_ref(),
+            effects_meta.completion_buffer.buffer(),
+        ) {
+            for (index, (_, offset)) in completion_readback.effects.iter().enumerate() {
+                render_context.command_encoder.copy_buffer_to_buffer(
+                    completion_buffer,
+                    *offset,
+                    readback_buffer,
+                    index as u64 * 4,
+                    4,
+                );
+            }
+            completion_readback
+                .copied
+                .store(true, AtomicOrdering::Release);
+        }
+
         // End encoder
         render_context.command_encoder.pop_debug_group();
         trace!("compute update pass done");
@@ -2050,7 +4401,137 @@ impl Node for ParticleUpdateNode {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use bevy::math::Vec4;
+    use crate::{
+        BoundaryCondition, BuoyancyModifier, CompositeEffect, DragModifier, FlowZoneModifier,
+        PlaneCollisionModifier, SimulationBoundsModifier, UpdateModifier, Value, VortexModifier,
+        WindModifier,
+    };
+    use bevy::{
+        ecs::system::CommandQueue,
+        math::{Quat, UVec2, Vec4},
+        prelude::{Children, Transform},
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn emitter_params_size() {
+        // Must match the size of the WGSL Emitter uniform struct
+        assert_eq!(size_of::<EmitterParams>(), 32);
+    }
+
+    #[test]
+    fn particle_size() {
+        // Must match the size of the WGSL Particle struct, and the stride of the particle arrays
+        assert_eq!(Particle::std430_size_static(), 64);
+        let source = ParticleUpdatePipelineKey::default().to_shader_source();
+        let module = naga::front::wgsl::parse_str(&source).unwrap();
+        assert_eq!(wgsl_struct(&module, "Particle").1, 64);
+        assert!(source.contains("[[stride(64)]] array<Particle>"));
+        assert!(include_str!("particles_render.wgsl").contains("[[stride(64)]] array<Particle>"));
+    }
+
+    #[test]
+    fn sim_params_size() {
+        // Must match the size of the WGSL SimParams uniform struct, without implicit padding
+        assert_eq!(size_of::<SimParamsUniform>(), 80);
+        assert_eq!(SimParamsUniform::std140_size_static(), 80);
+    }
+
+    /// Find a struct declared in a WGSL module, and return its members and byte size.
+    fn wgsl_struct<'a>(module: &'a naga::Module, name: &str) -> (&'a [naga::StructMember], u32) {
+        module
+            .types
+            .iter()
+            .find_map(|(_, ty)| match &ty.inner {
+                naga::TypeInner::Struct { members, span } if ty.name.as_deref() == Some(name) => {
+                    Some((&members[..], *span))
+                }
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("struct {} not found in WGSL", name))
+    }
+
+    #[test]
+    fn update_shader_user_bindings() {
+        // The custom user bind group comes after the init data bind group, if any
+        let key = ParticleUpdatePipelineKey {
+            init_data: true,
+            user_declarations: Some(
+                "struct Wind { dir: vec4<f32>; };\n\
+                [[group({{GROUP}}), binding(0)]] var<storage, read> wind: Wind;"
+                    .to_string(),
+            ),
+            ..Default::default()
+        };
+        assert!(key.has_user_data());
+        let source = key.to_shader_source();
+        assert!(source.contains("[[group(5), binding(0)]] var<storage, read> wind: Wind;"));
+        naga::front::wgsl::parse_str(&source).unwrap();
+    }
+
+    #[test]
+    fn update_shader_layout() {
+        let source = ParticleUpdatePipelineKey::default().to_shader_source();
+        let module = naga::front::wgsl::parse_str(&source).unwrap();
+
+        // The SpawnerParams uploaded by the CPU must match the WGSL Spawner struct
+        let (members, span) = wgsl_struct(&module, "Spawner");
+        let offsets = [
+            ("origin", bytemuck::offset_of!(SpawnerParams, origin)),
+            ("spawn", bytemuck::offset_of!(SpawnerParams, spawn)),
+            ("accel", bytemuck::offset_of!(SpawnerParams, accel)),
+            ("count", bytemuck::offset_of!(SpawnerParams, count)),
+            (
+                "force_field",
+                bytemuck::offset_of!(SpawnerParams, force_field),
+            ),
+            ("__pad0", bytemuck::offset_of!(SpawnerParams, __pad0)),
+            ("seed", bytemuck::offset_of!(SpawnerParams, seed)),
+            (
+                "chain_head",
+                bytemuck::offset_of!(SpawnerParams, chain_head),
+            ),
+            (
+                "speed_scale",
+                bytemuck::offset_of!(SpawnerParams, speed_scale),
+            ),
+            (
+                "chain_step",
+                bytemuck::offset_of!(SpawnerParams, chain_step),
+            ),
+            (
+                "spawn_total",
+                bytemuck::offset_of!(SpawnerParams, spawn_total),
+            ),
+            ("attractor", bytemuck::offset_of!(SpawnerParams, attractor)),
+            (
+                "attractor_accel",
+                bytemuck::offset_of!(SpawnerParams, attractor_accel),
+            ),
+            (
+                "sweep_start",
+                bytemuck::offset_of!(SpawnerParams, sweep_start),
+            ),
+            ("sweep_end", bytemuck::offset_of!(SpawnerParams, sweep_end)),
+            ("dt", bytemuck::offset_of!(SpawnerParams, dt)),
+            ("reset", bytemuck::offset_of!(SpawnerParams, reset)),
+            ("age_start", bytemuck::offset_of!(SpawnerParams, age_start)),
+            ("age_end", bytemuck::offset_of!(SpawnerParams, age_end)),
+        ];
+        assert_eq!(members.len(), offsets.len());
+        for (member, (name, offset)) in members.iter().zip(offsets) {
+            assert_eq!(member.name.as_deref(), Some(name));
+            assert_eq!(member.offset as usize, offset, "offset of Spawner.{}", name);
+        }
+        assert_eq!(span as usize, SpawnerParams::std430_size_static());
+
+        // The completion counter read back is a single i32
+        let (members, span) = wgsl_struct(&module, "Completion");
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].name.as_deref(), Some("pending"));
+        assert_eq!(members[0].offset, 0);
+        assert_eq!(span as usize, size_of::<i32>());
+    }
 
     #[test]
     fn layout_flags() {
@@ -2084,4 +4565,534 @@ else { out.color = c1; }
             grad.to_shader_code()
         );
     }
+
+    #[test]
+    fn rotation_shader_code() {
+        let mut grad = Gradient::<f32>::new();
+        assert_eq!("", grad.to_shader_code());
+
+        grad.add_key(0.0, 0.0);
+        assert_eq!(
+            "// Gradient\n{\nlet t0 = 0.;\nlet r0 = 0.;\nrotation = r0;\n}\n",
+            grad.to_shader_code()
+        );
+
+        grad.add_key(1.0, 3.0);
+        assert_eq!(
+            r#"// Gradient
+{
+let t0 = 0.;
+let r0 = 0.;
+let t1 = 1.;
+let r1 = 3.;
+let life = particle.age / particle.lifetime;
+if (life <= t0) { rotation = r0; }
+else if (life <= t1) { rotation = mix(r0, r1, (life - t0) / (t1 - t0)); }
+else { rotation = r1; }
+}
+"#,
+            grad.to_shader_code()
+        );
+    }
+
+    #[test]
+    fn distance_fog_shader_code() {
+        let fog = DistanceFogModifier {
+            color: Vec4::new(0.5, 0.5, 0.5, 1.0),
+            start: 10.0,
+            end: 50.0,
+        };
+        let code = fog.to_shader_code();
+        assert!(code.starts_with("// Distance fog\n"));
+        assert!(code.contains("let fog_color = vec4<f32>(0.5, 0.5, 0.5, 1.);"));
+        assert!(code.contains("(fog_dist - 10.) / max(50. - 10., 0.0001)"));
+    }
+
+    #[test]
+    fn buoyancy_shader_code() {
+        let mut update_layout = UpdateLayout::default();
+        let code = update_force_field_code(&update_layout);
+        assert!(code.contains("let buoyancy_accel = vec3<f32>(0.0);"));
+        assert!(code.contains("+ buoyancy_accel;"));
+
+        BuoyancyModifier {
+            temperature: Value::Uniform((1., 2.)),
+            cooling_rate: 0.5,
+            ..Default::default()
+        }
+        .apply(&mut update_layout);
+        let code = update_force_field_code(&update_layout);
+        assert!(code.contains("let initial_temperature = mix(1., 2., "));
+        assert!(code.contains("* exp(-0.5 * vAge);"));
+        assert!(!code.contains("{{"));
+    }
+
+    #[test]
+    fn drag_shader_code() {
+        let mut update_layout = UpdateLayout::default();
+        assert!(!update_force_field_code(&update_layout).contains("// Drag"));
+
+        DragModifier::new(0.5).apply(&mut update_layout);
+        let code = update_force_field_code(&update_layout);
+        assert!(code.contains("let drag = 0.5;"));
+        assert!(code.contains("vVel = vVel * exp(-drag * spawner.dt);"));
+    }
+
+    #[test]
+    fn vortex_shader_code() {
+        let mut update_layout = UpdateLayout::default();
+        VortexModifier {
+            axis: Vec3::Y * 2.,
+            strength: 3.,
+            pull: 0.5,
+            ..Default::default()
+        }
+        .apply(&mut update_layout);
+        let code = update_force_field_code(&update_layout);
+        assert!(code.contains(&format!("let vortex_axis = {};", Vec3::Y.to_wgsl_string())));
+        assert!(code.contains("* 3. - vortex_dir * 0.5) * 1.;"));
+
+        // The vortex accelerates the particles before the drag damps them
+        DragModifier::default().apply(&mut update_layout);
+        update_layout.vortex.as_mut().unwrap().radius = 4.;
+        let code = update_force_field_code(&update_layout);
+        assert!(code.contains("clamp(1. - vortex_dist / 4., 0., 1.)"));
+        assert!(code.find("// Vortex").unwrap() < code.find("// Drag").unwrap());
+    }
+
+    #[test]
+    fn wind_shader_code() {
+        let mut update_layout = UpdateLayout::default();
+        WindModifier::new(Vec3::X * 2., 3.).apply(&mut update_layout);
+        let code = update_force_field_code(&update_layout);
+        assert!(code.contains(&format!("let wind_dir = {};", Vec3::X.to_wgsl_string())));
+        assert!(code.contains("var wind_accel = wind_dir * 3.;"));
+        assert!(!code.contains("noise1"));
+
+        update_layout.wind = Some(WindModifier {
+            gust_amplitude: 2.,
+            gust_scale: 4.,
+            turbulence: 0.5,
+            ..Default::default()
+        });
+        let code = update_force_field_code(&update_layout);
+        assert!(code.contains("let wind_t = sim_params.time * 0.5;"));
+        assert!(code.contains("let wind_period = sim_params.loop_period * 0.5;"));
+        assert!(code.contains("noise1(wind_t - dot(vPos, wind_dir) / 4., wind_period, 0u) * 2.;"));
+        assert!(code.contains("(wind_noise * 2. - 1.) * 0.5;"));
+
+        // The wind accelerates the particles before the drag damps them
+        DragModifier::default().apply(&mut update_layout);
+        let code = update_force_field_code(&update_layout);
+        assert!(code.find("// Wind").unwrap() < code.find("// Drag").unwrap());
+    }
+
+    #[test]
+    fn flow_zone_shader_code() {
+        let mut update_layout = UpdateLayout::default();
+        FlowZoneModifier {
+            half_size: Vec3::new(1., -2., 1.),
+            rotation: Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+            strength: 4.,
+            ..Default::default()
+        }
+        .apply(&mut update_layout);
+        let code = update_force_field_code(&update_layout);
+        assert!(code.contains("let flow_offset = vPos - vec3<f32>(0., 0., 0.);"));
+        assert!(code.contains("if (all(abs(flow_local) <= vec3<f32>(1., 2., 1.)))"));
+        assert!(code.contains(
+            "vVel = mix(vec3<f32>(0., 1., 0.), vVel, vec3<f32>(exp(-4. * spawner.dt)));"
+        ));
+
+        // The local X axis of the box is rotated toward -Z
+        let axis_x = Quat::from_rotation_y(std::f32::consts::FRAC_PI_2) * Vec3::X;
+        assert!(code.contains(&format!("dot(flow_offset, {})", axis_x.to_wgsl_string())));
+    }
+
+    #[test]
+    fn plane_collision_shader_code() {
+        let mut update_layout = UpdateLayout::default();
+        PlaneCollisionModifier {
+            normal: Vec3::Y * 2.,
+            restitution: 0.5,
+            friction: 0.25,
+            ..PlaneCollisionModifier::ground(-1.)
+        }
+        .apply(&mut update_layout);
+        let code = update_force_field_code(&update_layout);
+        assert!(code.contains("let plane_normal = vec3<f32>(0., 1., 0.);"));
+        assert!(code.contains("let plane_dist = dot(vPos - vec3<f32>(0., -1., 0.), plane_normal);"));
+        assert!(code.contains("vPos = vPos - plane_normal * plane_dist * (1. + 0.5);"));
+        assert!(code.contains(
+            "vVel = (vVel - plane_normal * plane_vn) * 0.75 - plane_normal * plane_vn * 0.5;"
+        ));
+
+        update_layout
+            .plane_collision
+            .as_mut()
+            .unwrap()
+            .kill_on_contact = true;
+        let code = update_force_field_code(&update_layout);
+        assert!(code.contains("particle_buffer.particles[index].age = vLifetime;"));
+        assert!(!code.contains("plane_vn"));
+    }
+
+    #[test]
+    fn bounds_shader_code() {
+        let mut update_layout = UpdateLayout::default();
+        SimulationBoundsModifier {
+            center: Vec3::Y,
+            half_size: Vec3::new(-2., 1., 1.),
+            boundary: BoundaryCondition::Kill,
+        }
+        .apply(&mut update_layout);
+        let code = update_force_field_code(&update_layout);
+        assert!(code.contains("let bounds_center = spawner.origin + vec3<f32>(0., 1., 0.);"));
+        assert!(code.contains("let bounds_min = bounds_center - vec3<f32>(2., 1., 1.);"));
+        assert!(code.contains("particle_buffer.particles[index].age = vLifetime;"));
+
+        update_layout.bounds.as_mut().unwrap().boundary = BoundaryCondition::Clamp;
+        let code = update_force_field_code(&update_layout);
+        assert!(code.contains("let bounds_pos = clamp(vPos, bounds_min, bounds_max);"));
+
+        update_layout.bounds.as_mut().unwrap().boundary =
+            BoundaryCondition::Reflect { restitution: 2. };
+        let code = update_force_field_code(&update_layout);
+        assert!(code.contains("vVel = select(vVel, abs(vVel) * 1., bounds_below);"));
+
+        update_layout.bounds.as_mut().unwrap().boundary = BoundaryCondition::Wrap;
+        let code = update_force_field_code(&update_layout);
+        assert!(code.contains("vPos = vPos - bounds_size * floor("));
+    }
+
+    #[test]
+    fn integration() {
+        let code = integration_code(Integrator::ExplicitEuler, false);
+        assert_eq!(
+            "    vPos = vPos + vVel * spawner.dt;\n    vVel = vVel + accel * spawner.dt;\n",
+            code
+        );
+        let code = integration_code(Integrator::SemiImplicitEuler, false);
+        assert_eq!(
+            "    vVel = vVel + accel * spawner.dt;\n    vPos = vPos + vVel * spawner.dt;\n",
+            code
+        );
+        let code = integration_code(Integrator::VelocityVerlet, false);
+        assert!(!code.contains("force_field_accel"));
+        let code = integration_code(Integrator::VelocityVerlet, true);
+        assert!(code.contains(
+            "let accel_next = spawner.accel + attractor_accel(vPos) + force_field_accel(vPos) + buoyancy_accel;"
+        ));
+        assert!(code.contains("* not_conformed_to_sphere + conformed_field;"));
+        for integrator in [
+            Integrator::ExplicitEuler,
+            Integrator::SemiImplicitEuler,
+            Integrator::VelocityVerlet,
+        ] {
+            for force_field in [false, true] {
+                let code = integration_code(integrator, force_field);
+                assert!(!code.contains("{{"));
+            }
+        }
+    }
+
+    #[test]
+    fn rgb_to_hsv_colors() {
+        assert_eq!(
+            rgb_to_hsv(Vec4::new(1., 0., 0., 1.)),
+            Vec4::new(0., 1., 1., 1.)
+        );
+        assert_eq!(
+            rgb_to_hsv(Vec4::new(0., 0., 1., 0.5)),
+            Vec4::new(2. / 3., 1., 1., 0.5)
+        );
+        assert_eq!(
+            rgb_to_hsv(Vec4::new(1., 0., 1., 1.)),
+            Vec4::new(5. / 6., 1., 1., 1.)
+        );
+        assert_eq!(
+            rgb_to_hsv(Vec4::new(0.5, 0.5, 0.5, 1.)),
+            Vec4::new(0., 0., 0.5, 1.)
+        );
+        assert_eq!(rgb_to_hsv(Vec4::ZERO), Vec4::ZERO);
+    }
+
+    #[test]
+    fn hsv_gradient_shader_code_hue() {
+        let mut gradient = Gradient::new();
+        gradient.add_key(0., Vec4::new(1., 1., 1., 1.));
+        gradient.add_key(0.5, Vec4::new(0., 0., 1., 1.));
+        gradient.add_key(1., Vec4::new(1., 0., 0., 1.));
+        let code = hsv_gradient_shader_code(&gradient);
+        assert!(code.starts_with("// HSV gradient\n"));
+        // White takes the hue of the next saturated key
+        assert!(code.contains(&format!(
+            "let c0 = {};",
+            Vec4::new(2. / 3., 0., 1., 1.).to_wgsl_string()
+        )));
+        assert!(code.contains(&format!(
+            "let c1 = {};",
+            Vec4::new(2. / 3., 1., 1., 1.).to_wgsl_string()
+        )));
+        assert!(code.contains("let c2 = vec4<f32>(0., 1., 1., 1.);"));
+        assert!(code.contains("else { hsv = c2; }"));
+
+        // A single key needs no interpolation
+        let gradient = Gradient::constant(Vec4::ONE);
+        assert_eq!(
+            hsv_gradient_shader_code(&gradient),
+            gradient.to_shader_code()
+        );
+    }
+
+    #[test]
+    fn init_color_shader_code() {
+        let code =
+            InitColorModifier::uniform(Vec4::new(1., 0., 0., 1.), Vec4::ONE).to_shader_code();
+        assert!(code.starts_with("// Init color\n"));
+        assert!(code.contains("let color_seed = particle_hash(particle.seed, 0x27d4eb2du);"));
+        assert!(code.contains(
+            "let init_color = mix(vec4<f32>(1., 0., 0., 1.), vec4<f32>(1., 1., 1., 1.), vec4<f32>(color_t));"
+        ));
+
+        let code = InitColorModifier::palette(vec![Vec4::X, Vec4::Y, Vec4::Z]).to_shader_code();
+        assert!(code.contains("var init_palette = array<vec4<f32>, 3>(vec4<f32>(1., 0., 0., 0.), "));
+        assert!(code.contains("init_palette[min(u32(color_t * 3.), 3u - 1u)]"));
+
+        let code = InitColorModifier::palette(vec![]).to_shader_code();
+        assert!(code.contains("let init_color = vec4<f32>(1.);"));
+
+        let mut gradient = Gradient::new();
+        gradient.add_key(0., Vec4::X);
+        gradient.add_key(1., Vec4::Y);
+        let code = InitColorModifier::gradient(gradient).to_shader_code();
+        assert!(code.contains("let ct1 = 1.;\nlet cc1 = vec4<f32>(0., 1., 0., 0.);"));
+        assert!(code.contains("else if (color_t <= ct1) { init_color = mix(cc0, cc1, "));
+        assert!(code.contains("else { init_color = cc1; }"));
+    }
+
+    #[test]
+    fn color_blend_shader_code() {
+        let mut fire = Gradient::new();
+        fire.add_key(0., Vec4::X);
+        fire.add_key(1., Vec4::Y);
+        let mut smoke = Gradient::new();
+        smoke.add_key(0., Vec4::ONE);
+        let modifier = ColorBlendModifier::random(fire, smoke);
+        let code = modifier.to_shader_code();
+        assert!(code.starts_with("// Color blend\n"));
+        assert!(code.contains("else if (life <= t1) { blend_a = mix(c0, c1, "));
+        assert!(code
+            .contains("{\nlet t0 = 0.;\nlet c0 = vec4<f32>(1., 1., 1., 1.);\nblend_b = c0;\n}\n"));
+        assert!(code.contains("let blend_seed = particle_hash(particle.seed, 0x165667b1u);"));
+        assert!(code.contains("let blend_w = mix(0., 1., f32(blend_seed & 0xffffu) / 65535.);"));
+        assert!(code.ends_with("out.color = mix(blend_a, blend_b, vec4<f32>(blend_w));\n"));
+
+        let code = ColorBlendModifier {
+            weight: ColorBlendWeight::Speed((1., 3.)),
+            ..modifier
+        }
+        .to_shader_code();
+        assert!(code.contains("let blend_w = clamp((length(particle.vel) - 1.) * 0.5, 0., 1.);"));
+    }
+
+    #[test]
+    fn init_rotation_shader_code() {
+        let code = InitRotationModifier {
+            angle: Value::Single(1.),
+            angular_velocity: Value::Single(2.),
+        }
+        .to_shader_code();
+        assert_eq!(
+            code,
+            "// Init rotation\nrotation = rotation + 1. + 2. * particle.age;\n"
+        );
+
+        let code = InitRotationModifier {
+            angle: Value::Uniform((0., 1.)),
+            angular_velocity: Value::Uniform((-2., 2.)),
+        }
+        .to_shader_code();
+        assert!(code.contains("let rotation_seed = particle_hash(particle.seed, 0x68e31da4u);"));
+        assert!(code.contains(
+            "rotation = rotation + mix(0., 1., f32(rotation_seed & 0xffffu) / 65535.) + mix(-2., 2., f32(rotation_seed >> 16u) / 65535.) * particle.age;"
+        ));
+    }
+
+    #[test]
+    fn init_size_shader_code() {
+        let code = InitSizeModifier::uniform(Value::Single(2.)).to_shader_code();
+        assert!(code.contains("size = size * vec2<f32>(2., 2.);"));
+
+        let code = InitSizeModifier {
+            size: Value::Uniform((Vec2::new(1., 2.), Vec2::new(2., 4.))),
+        }
+        .to_shader_code();
+        assert!(code.starts_with("// Init size\n"));
+        assert!(code.contains("size = size * mix(vec2<f32>(1., 2.), vec2<f32>(2., 4.), "));
+        assert!(code.contains("let size_seed = particle_hash(particle.seed, 0x5bd1e995u);"));
+        assert!(!code.contains("instance_index"));
+    }
+
+    #[test]
+    fn flipbook_shader_code() {
+        let flipbook = FlipbookModifier {
+            sprite_grid_size: UVec2::new(4, 2),
+            random_row: false,
+        };
+        let code = flipbook.to_shader_code();
+        assert!(code.starts_with("// Flipbook\n"));
+        assert!(code.contains("let flipbook_frame = min(u32(flipbook_life * 8.), 8u - 1u);"));
+        assert!(code.contains("/ vec2<f32>(4., 2.);"));
+        assert!(!code.contains("instance_index"));
+
+        let flipbook = FlipbookModifier {
+            random_row: true,
+            ..flipbook
+        };
+        let code = flipbook.to_shader_code();
+        assert!(code.contains("let flipbook_row = particle_hash(particle.seed, 0x7feb352du) % 2u;"));
+        assert!(code.contains("let flipbook_col = min(u32(flipbook_life * 4.), 4u - 1u);"));
+    }
+
+    #[test]
+    fn geometry_shader_code() {
+        assert_eq!("", ParticleGeometry::Quad.to_shader_code());
+        assert_eq!("", ParticleGeometry::Triangle.to_shader_code());
+        assert_eq!("", ParticleGeometry::Hexagon.to_shader_code());
+        let code = ParticleGeometry::SphereImpostor.to_shader_code();
+        assert!(code.starts_with("// Sphere impostor\n"));
+        assert!(code.contains("discard;"));
+    }
+
+    #[test]
+    fn geometry_vertices() {
+        for geometry in [
+            ParticleGeometry::Quad,
+            ParticleGeometry::Triangle,
+            ParticleGeometry::Hexagon,
+            ParticleGeometry::SphereImpostor,
+        ] {
+            let positions = geometry_vertex_positions(geometry);
+            // Triangle list
+            assert_eq!(positions.len() % 3, 0);
+            // Unit-sized, to keep UVs in [0:1]
+            for v in positions {
+                assert!(v.x.abs() <= 0.5 && v.y.abs() <= 0.5);
+            }
+        }
+        assert_eq!(
+            geometry_vertex_positions(ParticleGeometry::Triangle).len(),
+            3
+        );
+        assert_eq!(
+            geometry_vertex_positions(ParticleGeometry::Hexagon).len(),
+            18
+        );
+    }
+
+    /// Create a render device and queue, or `None` if no GPU adapter is available.
+    fn test_renderer() -> Option<(RenderDevice, RenderQueue)> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = futures_lite::future::block_on(
+            instance.request_adapter(&wgpu::RequestAdapterOptions::default()),
+        )?;
+        let (device, queue) = futures_lite::future::block_on(
+            adapter.request_device(&wgpu::DeviceDescriptor::default(), None),
+        )
+        .ok()?;
+        Some((RenderDevice::from(Arc::new(device)), Arc::new(queue)))
+    }
+
+    #[test]
+    fn prepare_effects_removes_despawned_effect() {
+        let (device, queue) = match test_renderer() {
+            Some(renderer) => renderer,
+            None => {
+                eprintln!("No GPU adapter available, skipping test.");
+                return;
+            }
+        };
+        let mut world = World::new();
+        world.insert_resource(SimParams::default());
+        world.insert_resource(EffectsMeta::new(device.clone(), None));
+        world.insert_resource(device);
+        world.insert_resource(queue);
+        world.init_resource::<ExtractedEffects>();
+        world.init_resource::<EffectGpuBuffers>();
+        world.init_resource::<CompletionReadback>();
+        world.init_resource::<HanabiConfig>();
+        world.init_resource::<EffectAssetEvents>();
+        world.init_resource::<EffectBindGroups>();
+        let mut stage = SystemStage::single(prepare_effects);
+
+        let handle = Handle::<EffectAsset>::weak(HandleId::random::<EffectAsset>());
+        let mut spawn_effect = |world: &mut World| {
+            let entity = world.spawn().id();
+            world
+                .resource_mut::<ExtractedEffects>()
+                .added_effects
+                .push(AddedEffect {
+                    entity,
+                    capacity: 256,
+                    item_size: Particle::std430_size_static() as u32,
+                    handle: handle.clone(),
+                });
+            stage.run(world);
+            let effects_meta = world.resource::<EffectsMeta>();
+            let id = *effects_meta.entity_map.get(&entity).unwrap();
+            (entity, effects_meta.effect_cache.get_slice(id))
+        };
+
+        let (entity, slice) = spawn_effect(&mut world);
+        assert_eq!(slice.slice, 0..256);
+
+        // Despawn the effect; this frees its slice instead of panicking
+        world.despawn(entity);
+        world
+            .resource_mut::<ExtractedEffects>()
+            .removed_effect_entities
+            .push(entity);
+        SystemStage::single(prepare_effects).run(&mut world);
+        assert!(world.resource::<EffectsMeta>().entity_map.is_empty());
+        assert!(world
+            .resource::<ExtractedEffects>()
+            .removed_effect_entities
+            .is_empty());
+
+        // A new effect reuses the freed slice
+        let (_, new_slice) = spawn_effect(&mut world);
+        assert_eq!(new_slice, slice);
+    }
+
+    #[test]
+    fn find_despawned_composite_effects() {
+        let mut world = World::new();
+        world.insert_resource(Time::default());
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        let root = CompositeEffect::new()
+            .with_effect(Handle::default())
+            .with_effect(Handle::default())
+            .with_lifetime(0.)
+            .spawn(&mut commands, Transform::default());
+        queue.apply(&mut world);
+        let children: Vec<Entity> = world
+            .get::<Children>(root)
+            .unwrap()
+            .iter()
+            .copied()
+            .collect();
+
+        let mut state: SystemState<Query<(), With<ParticleEffect>>> = SystemState::new(&mut world);
+        assert!(find_removed_effects(children.iter(), &state.get(&world)).is_empty());
+
+        // The elapsed lifetime despawns the root and its effects
+        SystemStage::single(crate::composite::composite_effect_lifetime_system).run(&mut world);
+        assert!(world.get_entity(root).is_none());
+        assert_eq!(
+            find_removed_effects(children.iter(), &state.get(&world)),
+            children
+        );
+    }
 }
@@ -1,5 +1,28 @@
 struct View {
     view_proj: mat4x4<f32>;
+    view: mat4x4<f32>;
+    inverse_view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    world_position: vec3<f32>;
+    near: f32;
+    far: f32;
+    width: f32;
+    height: f32;
+};
+
+struct SimParams {
+    dt: f32;
+    time: f32;
+    loop_period: f32;
+    __pad1: f32;
+    globals: array<vec4<f32>, 4>;
+};
+
+struct Emitter {
+    color: vec4<f32>;
+    scale: f32;
+    size_multiplier: f32;
+    variant: u32;
 };
 
 struct Particle {
@@ -7,26 +30,41 @@ struct Particle {
     age: f32;
     vel: vec3<f32>;
     lifetime: f32;
+    normal: vec3<f32>;
+    spawn_index: u32;
+    seed: u32;
 };
 
 struct ParticlesBuffer {
-    particles: [[stride(32)]] array<Particle>;
+    particles: [[stride(64)]] array<Particle>;
 };
 
 struct VertexOutput {
     [[builtin(position)]] position: vec4<f32>;
     [[location(0)]] color: vec4<f32>;
-#ifdef PARTICLE_TEXTURE
     [[location(1)]] uv: vec2<f32>;
-#endif
+    [[location(2)]] ring_width: f32;
 };
 
 [[group(0), binding(0)]] var<uniform> view: View;
+[[group(0), binding(1)]] var<uniform> sim_params: SimParams;
+[[group(0), binding(2)]] var<uniform> emitter: Emitter;
 [[group(1), binding(0)]] var<storage, read> particle_buffer : ParticlesBuffer;
 #ifdef PARTICLE_TEXTURE
 [[group(2), binding(0)]] var particle_texture: texture_2d<f32>;
 [[group(2), binding(1)]] var particle_sampler: sampler;
+#ifdef COLOR_RAMP
+[[group(3), binding(0)]] var color_ramp_texture: texture_2d<f32>;
+[[group(3), binding(1)]] var color_ramp_sampler: sampler;
 #endif
+#else
+#ifdef COLOR_RAMP
+[[group(2), binding(0)]] var color_ramp_texture: texture_2d<f32>;
+[[group(2), binding(1)]] var color_ramp_sampler: sampler;
+#endif
+#endif
+{{USER_BINDINGS}}
+{{EXTENSION_INCLUDES}}
 // #ifdef PARTICLE_GRADIENTS
 // [[group(3), binding(0)]] var gradient_texture: texture_2d<f32>;
 // [[group(3), binding(1)]] var gradient_sampler: sampler;
@@ -48,31 +86,57 @@ struct VertexOutput {
 //     }
 // }
 
+// Hash the random seed of a particle with a salt into a random value, stable over the
+// particle lifetime and independent for each salt. See pcg_hash() in the update shader.
+fn particle_hash(seed: u32, salt: u32) -> u32 {
+    let state = (seed ^ salt) * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
 [[stage(vertex)]]
 fn vertex(
     [[builtin(instance_index)]] instance_index: u32,
     [[location(0)]] vertex_position: vec3<f32>,
-#ifdef PARTICLE_TEXTURE
     [[location(1)]] vertex_uv: vec2<f32>,
-#endif
     //[[location(1)]] vertex_color: u32,
     //[[location(1)]] vertex_velocity: vec3<f32>,
 ) -> VertexOutput {
     var particle = particle_buffer.particles[instance_index];
     var out: VertexOutput;
-#ifdef PARTICLE_TEXTURE
     out.uv = vertex_uv;
-#endif
 
     var size = vec2<f32>(1.0, 1.0);
+    var rotation = 0.0;
+    var ring_width = 1.0;
+
+#ifdef COLOR_RAMP
+    let ramp_uv = vec2<f32>(clamp(particle.age / particle.lifetime, 0., 1.), 0.5);
+    out.color = textureSampleLevel(color_ramp_texture, color_ramp_sampler, ramp_uv, 0.);
+#endif
 
 {{VERTEX_MODIFIERS}}
 
+    out.ring_width = ring_width;
+
     // Set the particle size
     var vpos = vertex_position;
     vpos = vpos * vec3<f32>(size.x, size.y, 1.0);
 
-    out.position = view.view_proj * vec4<f32>(particle.pos + vpos, 1.0);
+    // Rotate the particle in its plane
+    let rot_cos = cos(rotation);
+    let rot_sin = sin(rotation);
+    vpos = vec3<f32>(vpos.x * rot_cos - vpos.y * rot_sin, vpos.x * rot_sin + vpos.y * rot_cos, vpos.z);
+
+{{VERTEX_ORIENT}}
+
+{{VERTEX_DEFORM}}
+
+    var world_position = vec4<f32>(particle.pos + vpos, 1.0);
+
+{{VERTEX_DEPTH_BIAS}}
+
+    out.position = view.view_proj * world_position;
     //out.color = vec4<f32>((vec4<u32>(vertex_color) >> vec4<u32>(0u, 8u, 16u, 24u)) & vec4<u32>(255u)) / 255.0;
     //out.color = color_over_lifetime(particle.age / particle.lifetime);
     // out.color[3] = 1.0;
@@ -85,11 +149,13 @@ fn vertex(
 [[stage(fragment)]]
 fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
 #ifdef PARTICLE_TEXTURE
-    var color = textureSample(particle_texture, particle_sampler, in.uv);
-    color = vec4<f32>(1.0, 1.0, 1.0, color.r); // FIXME - grayscale modulate
-    color = in.color * color;
+    let texel = textureSample(particle_texture, particle_sampler, in.uv);
+    var color = in.color * {{PARTICLE_TEXTURE_MAPPING}};
 #else
     var color = in.color;
 #endif
+
+{{FRAGMENT_MODIFIERS}}
+
     return color;
 }
\ No newline at end of file
@@ -3,15 +3,21 @@ struct Particle {
     age: f32;
     vel: vec3<f32>;
     lifetime: f32;
+    normal: vec3<f32>;
+    spawn_index: u32;
+    seed: u32;
 };
 
 struct ParticleBuffer {
-    particles: [[stride(32)]] array<Particle>;
+    particles: [[stride(64)]] array<Particle>;
 };
 
 struct SimParams {
     dt: f32;
     time: f32;
+    loop_period: f32;
+    __pad1: f32;
+    globals: array<vec4<f32>, 4>;
 };
 
 struct ForceFieldParam {
@@ -31,7 +37,22 @@ struct Spawner {
     force_field: array<ForceFieldParam, 16>;
     __pad0: vec3<f32>;
     seed: u32;
-    __pad1: vec4<f32>;
+    chain_head: vec3<f32>;
+    speed_scale: f32;
+    chain_step: vec3<f32>;
+    spawn_total: i32;
+    attractor: vec3<f32>;
+    attractor_accel: f32;
+    sweep_start: f32;
+    sweep_end: f32;
+    dt: f32;
+    reset: u32;
+    age_start: f32;
+    age_end: f32;
+};
+
+struct Completion {
+    pending: atomic<i32>;
 };
 
 struct IndirectBuffer {
@@ -41,12 +62,23 @@ struct IndirectBuffer {
 [[group(0), binding(0)]] var<uniform> sim_params : SimParams;
 [[group(1), binding(0)]] var<storage, read_write> particle_buffer : ParticleBuffer;
 [[group(2), binding(0)]] var<storage, read_write> spawner : Spawner;
+[[group(2), binding(1)]] var<storage, read_write> completion : Completion;
 [[group(3), binding(0)]] var<storage, read_write> indirect_buffer : IndirectBuffer;
+{{INIT_BINDINGS}}
+{{USER_BINDINGS}}
 
 var<private> seed : u32 = 0u;
 
+// Index of the particle being spawned among all the particles spawned this frame.
+var<private> spawn_index : u32 = 0u;
+
+// Number of particles spawned this frame.
+var<private> spawn_count : u32 = 0u;
+
 let tau: f32 = 6.283185307179586476925286766559;
 
+{{EXTENSION_INCLUDES}}
+
 // Rand: PCG
 // https://www.reedbeta.com/blog/hash-functions-for-gpu-rendering/
 fn pcg_hash(input: u32) -> u32 {
@@ -86,6 +118,43 @@ fn rand3() -> vec3<f32> {
     return vec3<f32>(x, y, z);
 }
 
+// Random floating-point number with a standard normal distribution, by Box-Muller transform
+fn rand_normal() -> f32 {
+    let u = max(rand(), 0.0000001);
+    return sqrt(-2. * log(u)) * cos(tau * rand());
+}
+
+// Smooth value noise in [0:1] at the position x, decorrelated by salt. If period is positive,
+// the noise repeats every period, with the lattice stretched to a whole number of cells.
+fn noise1(x: f32, period: f32, salt: u32) -> f32 {
+    var p = x;
+    var cells = 0;
+    if (period > 0.) {
+        let n = max(round(period), 1.);
+        p = x * n / period;
+        cells = i32(n);
+    }
+    let i = floor(p);
+    let f = p - i;
+    var i0 = i32(i);
+    var i1 = i0 + 1;
+    if (cells > 0) {
+        i0 = ((i0 % cells) + cells) % cells;
+        i1 = ((i1 % cells) + cells) % cells;
+    }
+    let h0 = to_float01(pcg_hash(bitcast<u32>(i0) ^ (salt * 0x9e3779b9u)));
+    let h1 = to_float01(pcg_hash(bitcast<u32>(i1) ^ (salt * 0x9e3779b9u)));
+    return mix(h0, h1, f * f * (3. - 2. * f));
+}
+
+// Sample a curve of 8 evenly spaced points at the position t in [0:1]
+fn sample_curve(points: array<f32, 8>, t: f32) -> f32 {
+    var p = points;
+    let x = clamp(t, 0., 1.) * 7.;
+    let i = min(u32(x), 6u);
+    return mix(p[i], p[i + 1u], x - f32(i));
+}
+
 // Random floating-point number in [0:1]^4
 fn rand4(input: u32) -> vec4<f32> {
     // Each rand() produces 32 bits, and we need 24 bits per component,
@@ -107,22 +176,60 @@ fn rand4(input: u32) -> vec4<f32> {
 struct PosVel {
     pos: vec3<f32>;
     vel: vec3<f32>;
+    normal: vec3<f32>;
+    lifetime: f32;
 };
 
 fn init_pos_vel(index: u32) -> PosVel {
     var ret : PosVel;
+    ret.normal = vec3<f32>(0., 0., 1.);
+    ret.lifetime = 5.0;
 {{INIT_POS_VEL}}
     return ret;
 }
 
-fn init_lifetime() -> f32 {
-    return 5.0;
-}
-
 fn proj(u: vec3<f32>, v: vec3<f32>) -> vec3<f32> {
     return dot(v, u) / dot(u,u) * u;
 }
 
+// Acceleration produced by the force field at the given position.
+fn force_field_accel(pos: vec3<f32>) -> vec3<f32> {
+    var ff_acceleration: vec3<f32> = vec3<f32>(0.0);
+    for (var kk: i32 = 0; kk < 16; kk=kk+1) {
+        // As soon as a field component has a null mass, skip it and all subsequent ones.
+        if (spawner.force_field[kk].mass == 0.0) {
+            break;
+        }
+
+        let particle_to_point_source = pos - spawner.force_field[kk].position;
+        let distance = length(particle_to_point_source);
+        let unit_p2p = normalize(particle_to_point_source);
+
+        let min_dist_check = step(spawner.force_field[kk].min_radius, distance);
+        let max_dist_check = 1.0 - step(spawner.force_field[kk].max_radius, distance);
+
+        let point_source_force =
+            - unit_p2p
+            * min_dist_check * max_dist_check
+            * spawner.force_field[kk].mass /
+                (0.0000001 + pow(distance, spawner.force_field[kk].force_exponent));
+
+        // if the particle is within the min_radius of a source, then forget about
+        // the other sources and only use the conformed field, thus the "* min_dist_check"
+        ff_acceleration = ff_acceleration * min_dist_check + point_source_force;
+    }
+    return ff_acceleration;
+}
+
+// Acceleration toward the attractor of the effect, if any, at the given position.
+fn attractor_accel(pos: vec3<f32>) -> vec3<f32> {
+    let to_attractor = spawner.attractor - pos;
+    let distance = length(to_attractor);
+    if (spawner.attractor_accel == 0.0 || distance < 0.0001) {
+        return vec3<f32>(0.0);
+    }
+    return to_attractor / distance * spawner.attractor_accel;
+}
 
 [[stage(compute), workgroup_size(64)]]
 fn main([[builtin(global_invocation_id)]] global_invocation_id: vec3<u32>) {
@@ -136,23 +243,41 @@ fn main([[builtin(global_invocation_id)]] global_invocation_id: vec3<u32>) {
     var vVel : vec3<f32> = particle_buffer.particles[index].vel;
     var vAge : f32 = particle_buffer.particles[index].age;
     var vLifetime : f32 = particle_buffer.particles[index].lifetime;
+    var vNormal : vec3<f32> = particle_buffer.particles[index].normal;
+
+    // Kill all particles when the effect restarts
+    if (spawner.reset != 0u) {
+        vAge = vLifetime;
+    }
 
     // Age the particle
-    vAge = vAge + sim_params.dt;
+    vAge = vAge + spawner.dt;
     if (vAge >= vLifetime) {
         // Particle dead; try to recycle into newly-spawned one
-        if (atomicSub(&spawner.spawn, 1) > 0) {
+        let spawn_remaining = atomicSub(&spawner.spawn, 1);
+        if (spawn_remaining > 0) {
             // Update PRNG seed
             seed = pcg_hash(index ^ spawner.seed);
+            spawn_index = u32(spawner.spawn_total - spawn_remaining);
+            spawn_count = u32(spawner.spawn_total);
 
             // Initialize new particle
             var posVel = init_pos_vel(index);
             vPos = posVel.pos + spawner.origin;
-            vVel = posVel.vel;
-            vAge = 0.0;
-            vLifetime = init_lifetime();
+            vVel = posVel.vel * spawner.speed_scale;
+            // Age the particle by the time elapsed since it spawned within the frame
+            vAge = mix(spawner.age_start, spawner.age_end, f32(spawn_index) / max(f32(spawn_count) - 1.0, 1.0));
+            vPos = vPos + vVel * vAge;
+            vLifetime = posVel.lifetime;
+            vNormal = posVel.normal;
+            particle_buffer.particles[index].spawn_index = spawn_index;
+            particle_buffer.particles[index].seed = pcg_hash(seed);
         } else {
-            // Nothing to spawn; simply return without writing any update
+            // Nothing to spawn; write back the age of a particle killed by a reset, which
+            // would otherwise still be alive next frame, and return without any other update
+            if (spawner.reset != 0u) {
+                particle_buffer.particles[index].age = vAge;
+            }
             return;
         }
     }
@@ -168,4 +293,5 @@ fn main([[builtin(global_invocation_id)]] global_invocation_id: vec3<u32>) {
     particle_buffer.particles[index].vel = vVel;
     particle_buffer.particles[index].age = vAge;
     particle_buffer.particles[index].lifetime = vLifetime;
+    particle_buffer.particles[index].normal = vNormal;
 }
\ No newline at end of file
@@ -22,4 +22,14 @@ impl PipelineRegistry {
             handle
         }
     }
+
+    /// Number of shader variants configured so far.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Check if no shader variant was configured yet.
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
 }
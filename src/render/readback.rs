@@ -0,0 +1,53 @@
+use std::{future::Future, pin::Pin, sync::Mutex};
+
+use bevy::render::render_resource::{Buffer, MapMode};
+use wgpu::BufferAsyncError;
+
+type MapFuture = Pin<Box<dyn Future<Output = Result<(), BufferAsyncError>> + Send>>;
+
+/// Buffer mapped asynchronously to read GPU data back on the CPU.
+///
+/// The mapping is requested once the commands copying the data into the buffer are
+/// submitted, and usually completes one or two frames later. The render thread polls it
+/// each frame with [`try_read()`] instead of waiting for the GPU.
+///
+/// [`try_read()`]: AsyncReadback::try_read
+pub(crate) struct AsyncReadback {
+    buffer: Buffer,
+    future: Mutex<MapFuture>,
+}
+
+impl AsyncReadback {
+    /// Request the mapping of the whole buffer for reading.
+    ///
+    /// The commands writing into the buffer must have been submitted already.
+    pub fn new(buffer: Buffer) -> Self {
+        let future = Box::pin(buffer.slice(..).map_async(MapMode::Read));
+        Self {
+            buffer,
+            future: Mutex::new(future),
+        }
+    }
+
+    /// Read the content of the buffer if the mapping completed, then release the buffer.
+    ///
+    /// Returns `Err(self)` if the mapping is still pending, and `Ok(None)` if it failed. The
+    /// render device must be polled for the mapping to make progress.
+    pub fn try_read<R>(self, read: impl FnOnce(&[u8]) -> R) -> Result<Option<R>, Self> {
+        let status = futures_lite::future::block_on(futures_lite::future::poll_once(
+            &mut *self.future.lock().unwrap(),
+        ));
+        match status {
+            None => Err(self),
+            Some(Err(_)) => Ok(None),
+            Some(Ok(())) => {
+                let result = {
+                    let data = self.buffer.slice(..).get_mapped_range();
+                    read(&data)
+                };
+                self.buffer.unmap();
+                Ok(Some(result))
+            }
+        }
+    }
+}
@@ -1,10 +1,15 @@
+use std::ops::{Add, Mul, Sub};
+
+use bevy::{ecs::component::Component, math::Vec3};
 use rand::{
     distributions::{uniform::SampleUniform, Distribution, Uniform},
-    SeedableRng,
+    Rng, RngCore, SeedableRng,
 };
 use rand_pcg::Pcg32;
 use serde::{Deserialize, Serialize};
 
+use crate::gradient::Lerp;
+
 /// An RNG to be used in the CPU for the particle system engine
 pub(crate) fn new_rng() -> Pcg32 {
     let mut rng = rand::thread_rng();
@@ -16,6 +21,18 @@ pub(crate) fn new_rng() -> Pcg32 {
 /// An RNG resource
 pub struct Random(pub Pcg32);
 
+/// Number of particles to spawn, supplied externally instead of ticking the spawner.
+///
+/// When this component is present on the entity of a [`ParticleEffect`], the spawner of the
+/// effect is not ticked. Instead, the effect spawns the number of particles stored in the
+/// component at the next frame, and resets it to zero. This allows driving an effect from an
+/// external source, like a simulation stepped in lockstep over the network with
+/// [`Spawner::tick()`].
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Component)]
+pub struct ExternalSpawnCount(pub u32);
+
 /// A constant or random value.
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Value<T: Copy> {
@@ -23,6 +40,18 @@ pub enum Value<T: Copy> {
     Single(T),
     /// Random value distributed uniformly between two bounds.
     Uniform((T, T)),
+    /// Random value with a normal (Gaussian) distribution, given as its mean and standard
+    /// deviation. The value is unbounded; about 99.7% of the values fall within three
+    /// standard deviations of the mean.
+    Normal((T, T)),
+    /// Random value sampled from a curve at a uniformly random position.
+    ///
+    /// The curve is made of 8 evenly spaced points, linearly interpolated, from the point at
+    /// position 0 to the one at position 1. This is the inverse of the cumulative distribution
+    /// of the value, so any distribution can be approximated. For example, points which are
+    /// close together near the start and spread apart near the end produce mostly small
+    /// values, and a few large ones.
+    Curve([T; 8]),
 }
 
 impl<T: Copy + Default> Default for Value<T> {
@@ -31,29 +60,53 @@ impl<T: Copy + Default> Default for Value<T> {
     }
 }
 
-impl<T: Copy + SampleUniform> Value<T> {
+impl<T: Copy> Value<T> {
+    /// Check if the value is random, that is anything but a single constant value.
+    pub fn is_random(&self) -> bool {
+        !matches!(self, Value::Single(_))
+    }
+}
+
+/// Sample a curve of evenly spaced points at the position `t` in `[0:1]`.
+fn sample_curve<T: Lerp>(points: &[T; 8], t: f32) -> T {
+    let x = t.clamp(0., 1.) * (points.len() - 1) as f32;
+    let index = (x as usize).min(points.len() - 2);
+    points[index].lerp(points[index + 1], x - index as f32)
+}
+
+impl<T: Copy + SampleUniform + Lerp + Add<Output = T> + Mul<f32, Output = T>> Value<T> {
     /// Sample the value.
-    pub fn sample(&self, rng: &mut Pcg32) -> T {
+    pub fn sample(&self, rng: &mut impl RngCore) -> T {
         match self {
             Value::Single(x) => *x,
             Value::Uniform((a, b)) => Uniform::new_inclusive(*a, *b).sample(rng),
+            Value::Normal((mean, std_dev)) => {
+                // Box-Muller transform
+                let u: f32 = rng.gen_range(f32::EPSILON..=1.);
+                let v: f32 = rng.gen();
+                let z = (-2. * u.ln()).sqrt() * (std::f32::consts::TAU * v).cos();
+                *mean + *std_dev * z
+            }
+            Value::Curve(points) => sample_curve(points, rng.gen()),
         }
     }
 }
 
-impl<T: Copy + PartialOrd> Value<T> {
+impl<T: Copy + PartialOrd + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>> Value<T> {
     /// Returns the range of values this can be
     /// in the form `[minimum, maximum]`
+    ///
+    /// For a [`Value::Normal`], this is the range within three standard deviations of the
+    /// mean.
     pub fn range(&self) -> [T; 2] {
+        let ordered = |a: T, b: T| if a <= b { [a, b] } else { [b, a] };
         match self {
             Value::Single(x) => [*x; 2],
-            Value::Uniform((a, b)) => {
-                if a <= b {
-                    [*a, *b]
-                } else {
-                    [*b, *a]
-                }
-            }
+            Value::Uniform((a, b)) => ordered(*a, *b),
+            Value::Normal((mean, std_dev)) => ordered(*mean - *std_dev * 3., *mean + *std_dev * 3.),
+            Value::Curve(points) => points[1..].iter().fold([points[0]; 2], |[min, max], &p| {
+                [if p < min { p } else { min }, if p > max { p } else { max }]
+            }),
         }
     }
 }
@@ -90,6 +143,22 @@ pub struct Spawner {
     /// Fractional remainder of particle count to spawn.
     spawn: f32,
 
+    /// Number of particles to spawn per unit of distance traveled by the emitter.
+    #[serde(default)]
+    distance_rate: Value<f32>,
+
+    /// Fractional remainder of particle count to spawn from the distance traveled.
+    #[serde(default)]
+    distance_spawn: f32,
+
+    /// Minimum distance between the emitter positions of two consecutive spawns.
+    #[serde(default)]
+    min_spacing: f32,
+
+    /// Maximum number of particles spawned in a single frame.
+    #[serde(default)]
+    max_per_frame: Option<u32>,
+
     /// Whether the system is active
     active: bool,
 }
@@ -126,10 +195,45 @@ impl Spawner {
             curr_spawn_time: 0.,
             limit: 0.,
             spawn: 0.,
+            distance_rate: Value::Single(0.),
+            distance_spawn: 0.,
+            min_spacing: 0.,
+            max_per_frame: None,
             active: true,
         }
     }
 
+    /// Sets the number of particles to spawn per unit of distance traveled by the emitter,
+    /// in addition to the particles spawned over time.
+    ///
+    /// Both emissions are accumulated independently, so an effect can for example emit a
+    /// baseline of particles while idle, and extra particles when moving.
+    pub fn with_distance_rate(mut self, rate: Value<f32>) -> Self {
+        self.distance_rate = rate;
+        self
+    }
+
+    /// Sets the minimum distance the emitter must travel between two spawns.
+    ///
+    /// Particles that would spawn while the emitter is closer than `min_spacing` to where it
+    /// last spawned particles are skipped, which prevents over-dense trails when the emitter
+    /// moves slowly or stands still. A spacing of zero disables the constraint.
+    pub fn with_min_spacing(mut self, min_spacing: f32) -> Self {
+        self.min_spacing = min_spacing;
+        self
+    }
+
+    /// Sets the maximum number of particles spawned in a single frame.
+    ///
+    /// The particles the spawner would spawn in a frame beyond this limit are dropped, which
+    /// prevents bursty spawners, or spawners catching up after a long frame, from spiking
+    /// the GPU cost. The limit is independent of the spawn rate, and doesn't apply to spawn
+    /// counts set with an [`ExternalSpawnCount`].
+    pub fn with_max_per_frame(mut self, max_per_frame: u32) -> Self {
+        self.max_per_frame = Some(max_per_frame);
+        self
+    }
+
     /// Sets whether the spawner starts active.
     pub fn with_active(mut self, active: bool) -> Self {
         self.active = active;
@@ -159,6 +263,12 @@ impl Spawner {
         Self::new(count, 0.0.into(), period)
     }
 
+    /// Create a spawner that spawns particles at `rate` per unit of distance traveled by the
+    /// emitter, and none while it doesn't move.
+    pub fn distance(rate: Value<f32>) -> Self {
+        Self::rate(0.0.into()).with_distance_rate(rate)
+    }
+
     /// Resets the spawner.
     /// Use this, for example, to immediately spawn some particles
     /// in a spawner constructed with `Spawner::once`.
@@ -166,6 +276,7 @@ impl Spawner {
         self.time = 0.;
         self.limit = 0.;
         self.spawn = 0.;
+        self.distance_spawn = 0.;
     }
 
     /// Sets whether the spawner is active.
@@ -178,13 +289,58 @@ impl Spawner {
         self.active
     }
 
+    /// Gets the progress of the current burst, from 0 when the burst starts to 1 once it
+    /// has spawned all its particles.
+    ///
+    /// Bursts spawning all their particles at once are always complete.
+    pub fn burst_progress(&self) -> f32 {
+        if self.curr_spawn_time < 1e-5 {
+            1.
+        } else {
+            (self.time / self.curr_spawn_time).min(1.)
+        }
+    }
+
+    /// Minimum sampled period, in seconds, to prevent a period sampled at or below zero from
+    /// ticking the spawner indefinitely.
+    const MIN_PERIOD: f32 = 1e-4;
+
     /// Resamples the spawn time and period.
-    fn resample(&mut self, rng: &mut Pcg32) {
-        self.limit = self.period.sample(rng);
-        self.curr_spawn_time = self.spawn_time.sample(rng).clamp(0.0, self.limit);
+    ///
+    /// Distributions like [`Value::Normal`] can sample any value, so the period is clamped to
+    /// [`MIN_PERIOD`], and the spawn time to the period.
+    ///
+    /// [`MIN_PERIOD`]: Spawner::MIN_PERIOD
+    fn resample(&mut self, rng: &mut impl RngCore) {
+        self.limit = self.period.sample(rng).max(Self::MIN_PERIOD);
+        self.curr_spawn_time = self.spawn_time.sample(rng).max(0.).min(self.limit);
+    }
+
+    /// Samples the number of particles to spawn over the spawn time, clamped to a finite
+    /// non-negative count.
+    fn sample_count(&self, rng: &mut impl RngCore) -> f32 {
+        self.num_particles.sample(rng).max(0.).min(u32::MAX as f32)
+    }
+
+    /// Tick the spawner and return the number of particles to spawn.
+    ///
+    /// The result only depends on the current state of the spawner, the elapsed time `dt` in
+    /// seconds, and the random generator `rng`. Ticking two identical spawners with the same
+    /// sequence of time steps and identically seeded generators produces the same spawn counts,
+    /// which allows lockstep-networked games to step a copy of the spawner on each client and
+    /// drive the effect with an [`ExternalSpawnCount`]. Any random generator can be used, like
+    /// the seedable generators of the `rand` crates.
+    pub fn tick(&mut self, dt: f32, rng: &mut impl RngCore) -> u32 {
+        self.tick_scaled(dt, 1.0, rng)
     }
 
-    pub(crate) fn tick(&mut self, mut dt: f32, rng: &mut Pcg32) -> u32 {
+    /// Tick the spawner, scaling the number of particles spawned by `count_scale`.
+    pub(crate) fn tick_scaled(
+        &mut self,
+        mut dt: f32,
+        count_scale: f32,
+        rng: &mut impl RngCore,
+    ) -> u32 {
         if !self.active {
             return 0;
         }
@@ -199,9 +355,10 @@ impl Spawner {
             let new_time = self.time + dt;
             if self.time <= self.curr_spawn_time {
                 self.spawn += if self.curr_spawn_time < 1e-5f32.max(dt / 100.0) {
-                    self.num_particles.sample(rng)
+                    self.sample_count(rng) * count_scale
                 } else {
-                    self.num_particles.sample(rng)
+                    self.sample_count(rng)
+                        * count_scale
                         * (new_time.min(self.curr_spawn_time) - self.time)
                         / self.curr_spawn_time
                 };
@@ -223,12 +380,135 @@ impl Spawner {
         self.spawn -= count;
         count as u32
     }
+
+    /// Tick the spawner for a `distance` traveled by the emitter, and return the number of
+    /// particles to spawn.
+    ///
+    /// Like [`tick()`], the result is deterministic. The distance accumulator is independent
+    /// of the time one, so both can be ticked each frame and their counts summed.
+    ///
+    /// [`tick()`]: Spawner::tick
+    pub fn tick_distance(&mut self, distance: f32, rng: &mut impl RngCore) -> u32 {
+        self.tick_distance_scaled(distance, 1.0, rng)
+    }
+
+    /// Tick the spawner for a traveled distance, scaling the number of particles spawned by
+    /// `count_scale`.
+    pub(crate) fn tick_distance_scaled(
+        &mut self,
+        distance: f32,
+        count_scale: f32,
+        rng: &mut impl RngCore,
+    ) -> u32 {
+        if !self.active || distance <= 0. {
+            return 0;
+        }
+
+        self.distance_spawn += self.distance_rate.sample(rng) * count_scale * distance;
+
+        let count = self.distance_spawn.floor();
+        self.distance_spawn -= count;
+        count as u32
+    }
+
+    /// Estimate the maximum number of particles alive at once, for particles living at most
+    /// `lifetime` seconds.
+    pub(crate) fn peak_count(&self, lifetime: f32) -> f32 {
+        let count = self.num_particles.range()[1];
+        let period = self.period.range()[0];
+        let peak = count * (lifetime / period).max(1.);
+        self.max_per_frame.map_or(peak, |max| {
+            // Each frame can't spawn more than the cap, assuming at least 60 frames per second
+            peak.min(max as f32 * (lifetime * 60.).max(1.))
+        })
+    }
+
+    /// Clamp a spawn `count` for a single frame to the maximum number of particles spawned
+    /// per frame, if any.
+    pub(crate) fn cap_per_frame(&self, count: u32) -> u32 {
+        self.max_per_frame.map_or(count, |max| count.min(max))
+    }
+
+    /// Apply the minimum spacing of the spawner to a spawn `count` with the emitter at
+    /// `position`, and return the number of particles to spawn.
+    ///
+    /// `last_spawn` is the emitter position when particles were last spawned, updated if any
+    /// particle spawns.
+    pub(crate) fn apply_spacing(
+        &self,
+        count: u32,
+        position: Vec3,
+        last_spawn: &mut Option<Vec3>,
+    ) -> u32 {
+        if count == 0 {
+            return 0;
+        }
+        if self.min_spacing > 0. {
+            if let Some(last_spawn) = last_spawn {
+                if last_spawn.distance_squared(position) < self.min_spacing * self.min_spacing {
+                    return 0;
+                }
+            }
+        }
+        *last_spawn = Some(position);
+        count
+    }
+}
+
+/// Get the range of burst progress swept by the particles spawned during a frame, from the
+/// burst progress of the spawner before and after ticking it.
+pub(crate) fn burst_sweep(before: f32, after: f32) -> [f32; 2] {
+    if after > before {
+        [before, after]
+    } else if after < before {
+        // A new burst started during the frame
+        [0., after]
+    } else {
+        // Instantaneous burst, or spawner not ticked
+        [0., 1.]
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn test_range_normal() {
+        let value = Value::Normal((2.0, -0.5));
+        assert_eq!(value.range(), [0.5, 3.5]);
+    }
+
+    #[test]
+    fn test_range_curve() {
+        let value = Value::Curve([1., 0., 2., 3., 4., 5., 6., -1.]);
+        assert_eq!(value.range(), [-1., 6.]);
+    }
+
+    #[test]
+    fn test_sample_normal() {
+        let mut rng = new_rng();
+        let value = Value::Normal((10.0, 1.0));
+        let samples: Vec<f32> = (0..1000).map(|_| value.sample(&mut rng)).collect();
+        let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+        assert!((mean - 10.).abs() < 0.2);
+        assert!(samples.iter().all(|s| s.is_finite()));
+    }
+
+    #[test]
+    fn test_sample_curve() {
+        let points = [0., 1., 2., 3., 4., 5., 6., 7.];
+        assert_eq!(sample_curve(&points, 0.), 0.);
+        assert_eq!(sample_curve(&points, 0.5), 3.5);
+        assert_eq!(sample_curve(&points, 1.), 7.);
+        let mut rng = new_rng();
+        let value = Value::Curve(points);
+        for _ in 0..100 {
+            let x = value.sample(&mut rng);
+            assert!((0. ..=7.).contains(&x));
+        }
+    }
+
     #[test]
     fn test_range_single() {
         let value = Value::Single(1.0);
@@ -247,6 +527,34 @@ mod test {
         assert_eq!(value.range(), [1.0, 3.0]);
     }
 
+    #[test]
+    fn test_resample_normal_near_zero() {
+        // The period range passes the constructor check, but the distribution still samples
+        // negative periods, spawn times, and counts
+        let mut spawner = Spawner::new(
+            Value::Normal((0., 10.)),
+            Value::Normal((0., 1.)),
+            Value::Normal((3., 1.)),
+        );
+        let rng = &mut Pcg32::seed_from_u64(0);
+        for _ in 0..10000 {
+            spawner.resample(rng);
+            assert!(spawner.limit >= Spawner::MIN_PERIOD && spawner.limit.is_finite());
+            assert!((0. ..=spawner.limit).contains(&spawner.curr_spawn_time));
+            assert!(spawner.sample_count(rng) >= 0.);
+        }
+
+        // A period close to zero ticks in a bounded number of iterations
+        let mut spawner = Spawner::new(
+            Value::Normal((10., 1.)),
+            Value::Single(0.),
+            Value::Normal((0.004, 0.001)),
+        );
+        let count = spawner.tick(1., rng);
+        assert!(count > 0);
+        assert!(spawner.spawn.is_finite() && spawner.spawn >= 0.);
+    }
+
     #[test]
     fn test_once() {
         let rng = &mut new_rng();
@@ -313,6 +621,92 @@ mod test {
         assert_eq!(count, 1);
     }
 
+    #[test]
+    fn test_deterministic() {
+        let mut spawner1 = Spawner::new(
+            Value::Uniform((2.0, 8.0)),
+            Value::Uniform((0.1, 0.5)),
+            Value::Uniform((0.5, 1.0)),
+        );
+        let mut spawner2 = spawner1;
+        let rng1 = &mut Pcg32::seed_from_u64(42);
+        let rng2 = &mut Pcg32::seed_from_u64(42);
+        for i in 0..100 {
+            let dt = (i % 7) as f32 / 60.0;
+            assert_eq!(spawner1.tick(dt, rng1), spawner2.tick(dt, rng2));
+        }
+    }
+
+    #[test]
+    fn test_any_rng() {
+        // The spawner can be ticked with any random generator
+        let mut spawner = Spawner::rate(5.0.into());
+        let rng = &mut rand::rngs::mock::StepRng::new(0, 1);
+        assert_eq!(spawner.tick(1.01, rng), 5);
+    }
+
+    #[test]
+    fn test_rate_scaled() {
+        let rng = &mut new_rng();
+        let mut spawner = Spawner::rate(5.0.into());
+        // Slightly over 1.0 to avoid edge case
+        let count = spawner.tick_scaled(1.01, 2.0, rng);
+        assert_eq!(count, 10);
+        let count = spawner.tick_scaled(0.4, 0.5, rng);
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_distance() {
+        let rng = &mut new_rng();
+        let mut spawner = Spawner::distance(2.0.into());
+        assert_eq!(spawner.tick(1.0, rng), 0);
+        assert_eq!(spawner.tick_distance(1.5, rng), 3);
+        assert_eq!(spawner.tick_distance(0.25, rng), 0);
+        assert_eq!(spawner.tick_distance(0.25, rng), 1);
+        assert_eq!(spawner.tick_distance(0.0, rng), 0);
+    }
+
+    #[test]
+    fn test_rate_and_distance() {
+        let rng = &mut new_rng();
+        let mut spawner = Spawner::rate(5.0.into()).with_distance_rate(4.0.into());
+        // Slightly over 1.0 to avoid edge case
+        let count = spawner.tick(1.01, rng) + spawner.tick_distance(0.5, rng);
+        assert_eq!(count, 7);
+        // The time accumulator is not affected by the distance one
+        let count = spawner.tick(0.4, rng);
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn test_max_per_frame() {
+        let rng = &mut new_rng();
+        let mut spawner = Spawner::burst(10.0.into(), 1.0.into()).with_max_per_frame(4);
+        let count = spawner.tick(0.1, rng);
+        assert_eq!(count, 10);
+        assert_eq!(spawner.cap_per_frame(count), 4);
+        assert_eq!(spawner.cap_per_frame(3), 3);
+        assert_eq!(Spawner::rate(5.0.into()).cap_per_frame(100), 100);
+    }
+
+    #[test]
+    fn test_min_spacing() {
+        let spawner = Spawner::rate(5.0.into()).with_min_spacing(1.0);
+        let mut last_spawn = None;
+        assert_eq!(spawner.apply_spacing(2, Vec3::ZERO, &mut last_spawn), 2);
+        assert_eq!(last_spawn, Some(Vec3::ZERO));
+        assert_eq!(spawner.apply_spacing(2, Vec3::X * 0.5, &mut last_spawn), 0);
+        assert_eq!(last_spawn, Some(Vec3::ZERO));
+        assert_eq!(spawner.apply_spacing(0, Vec3::X * 2.0, &mut last_spawn), 0);
+        assert_eq!(spawner.apply_spacing(1, Vec3::X * 1.5, &mut last_spawn), 1);
+        assert_eq!(last_spawn, Some(Vec3::X * 1.5));
+
+        // No spacing constraint by default
+        let spawner = Spawner::rate(5.0.into());
+        assert_eq!(spawner.apply_spacing(3, Vec3::X * 1.5, &mut last_spawn), 3);
+    }
+
     #[test]
     fn test_burst() {
         let rng = &mut new_rng();
@@ -324,4 +718,30 @@ mod test {
         let count = spawner.tick(0.1, rng);
         assert_eq!(count, 0);
     }
+
+    #[test]
+    fn test_burst_progress() {
+        let rng = &mut new_rng();
+        let mut spawner = Spawner::new(10.0.into(), 1.0.into(), f32::INFINITY.into());
+        let before = spawner.burst_progress();
+        let count = spawner.tick(0.25, rng);
+        let after = spawner.burst_progress();
+        assert_eq!(count, 2);
+        assert_eq!(burst_sweep(before, after), [0., 0.25]);
+
+        let before = after;
+        spawner.tick(0.25, rng);
+        let after = spawner.burst_progress();
+        assert_eq!(burst_sweep(before, after), [0.25, 0.5]);
+
+        spawner.tick(1.0, rng);
+        assert_eq!(spawner.burst_progress(), 1.);
+
+        // Instantaneous bursts spread their particles over the entire sweep
+        let mut spawner = Spawner::once(5.0.into(), true);
+        let before = spawner.burst_progress();
+        spawner.tick(0.1, rng);
+        let after = spawner.burst_progress();
+        assert_eq!(burst_sweep(before, after), [0., 1.]);
+    }
 }
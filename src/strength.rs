@@ -0,0 +1,93 @@
+use bevy::prelude::*;
+
+use crate::Gradient;
+
+/// Scalar intensity of a single [`ParticleEffect`] instance.
+///
+/// The strength scales several parameters of the effect at once, as defined by the
+/// [`StrengthMapping`] of its [`EffectAsset`], giving gameplay code a single dial to control
+/// an effect instance, like the intensity of a fire. A strength of 1 is the nominal intensity
+/// of the effect. Effect instances without this component use the nominal intensity.
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+/// [`EffectAsset`]: crate::EffectAsset
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub struct EffectStrength(pub f32);
+
+impl Default for EffectStrength {
+    fn default() -> Self {
+        EffectStrength(1.0)
+    }
+}
+
+/// Mapping from an [`EffectStrength`] to the multipliers of the effect parameters it scales.
+///
+/// Each mapping is a gradient sampled at the strength of the effect instance to obtain the
+/// multiplier of the associated parameter. An empty gradient maps the strength linearly,
+/// such that the multiplier is equal to the strength.
+///
+/// Like any [`Gradient`], a non-empty mapping is clamped to its keys, which lie in \[0:1\]: a
+/// strength before the first key or after the last key gives the multiplier of that key. In
+/// particular, strengths above 1 all give the multiplier of the last key, so a non-empty
+/// mapping can't scale a parameter beyond its largest key value. Only the empty, linear
+/// mapping scales parameters with strengths above 1.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StrengthMapping {
+    /// Multiplier of the spawner rate, or count for bursts.
+    pub spawn_rate: Gradient<f32>,
+    /// Multiplier of the initial speed of newly spawned particles.
+    pub speed: Gradient<f32>,
+    /// Multiplier of the size of all the particles, applied when rendering them.
+    pub size: Gradient<f32>,
+}
+
+impl StrengthMapping {
+    /// Get the spawn rate multiplier for the given strength.
+    pub fn spawn_rate_multiplier(&self, strength: f32) -> f32 {
+        Self::multiplier(&self.spawn_rate, strength)
+    }
+
+    /// Get the initial speed multiplier for the given strength.
+    pub fn speed_multiplier(&self, strength: f32) -> f32 {
+        Self::multiplier(&self.speed, strength)
+    }
+
+    /// Get the particle size multiplier for the given strength.
+    pub fn size_multiplier(&self, strength: f32) -> f32 {
+        Self::multiplier(&self.size, strength)
+    }
+
+    fn multiplier(mapping: &Gradient<f32>, strength: f32) -> f32 {
+        if mapping.keys().is_empty() {
+            strength
+        } else {
+            mapping.sample(strength)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multiplier() {
+        let mut mapping = StrengthMapping::default();
+        assert_eq!(mapping.spawn_rate_multiplier(0.5), 0.5);
+        assert_eq!(mapping.speed_multiplier(2.0), 2.0);
+        assert_eq!(mapping.size_multiplier(1.5), 1.5);
+
+        mapping.speed.add_key(0.0, 1.0);
+        mapping.speed.add_key(1.0, 3.0);
+        assert_eq!(mapping.spawn_rate_multiplier(0.5), 0.5);
+        assert_eq!(mapping.speed_multiplier(0.5), 2.0);
+        assert_eq!(mapping.speed_multiplier(2.0), 3.0);
+
+        // Strengths outside of the mapping keys are clamped
+        mapping.size.add_key(0.25, 1.0);
+        mapping.size.add_key(0.75, 2.0);
+        assert_eq!(mapping.size_multiplier(0.0), 1.0);
+        assert_eq!(mapping.size_multiplier(0.5), 1.5);
+        assert_eq!(mapping.size_multiplier(4.0), 2.0);
+    }
+}
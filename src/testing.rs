@@ -0,0 +1,288 @@
+//! Utilities to test the shader code generated by modifiers.
+//!
+//! Modifiers generate WGSL code injected into the shaders of the effects, which is only
+//! compiled when an effect using them is first rendered. These utilities build the full
+//! shader of an effect using a modifier, and validate it on the CPU, so that custom
+//! modifiers can be tested without a GPU, for example in unit tests:
+//!
+//! ```
+//! # use bevy::prelude::*;
+//! # use bevy_hanabi::*;
+//! let modifier = PositionSphereModifier {
+//!     radius: 2.,
+//!     ..Default::default()
+//! };
+//! assert!(testing::validate_init_modifier(&modifier).is_ok());
+//! ```
+//!
+//! The scope of these utilities is limited to checking that the generated code compiles. The
+//! shaders are parsed and validated, but not executed, so the tests can't check the values
+//! the code produces; this requires rendering the effect on a GPU.
+
+use bevy::{
+    render::render_resource::{ProcessedShader, Shader, ShaderProcessor, ShaderReflectError},
+    utils::HashMap,
+};
+use std::borrow::Cow;
+
+use crate::{
+    asset::{InitLayout, UpdateLayout},
+    extension::ExtensionCode,
+    render::{render_shader_source, ParticleUpdatePipelineKey},
+    ExtensionInstance, ExtensionModifier, InitModifier, ModifierStage, UpdateModifier,
+};
+
+/// Generate the source code of the update shader of an effect with a single init modifier.
+pub fn init_shader_source<M: InitModifier>(modifier: &M) -> String {
+    let mut init_layout = InitLayout::default();
+    modifier.apply(&mut init_layout);
+    ParticleUpdatePipelineKey::new(&init_layout, &UpdateLayout::default()).to_shader_source()
+}
+
+/// Generate the source code of the update shader of an effect with a single update modifier.
+pub fn update_shader_source<M: UpdateModifier>(modifier: &M) -> String {
+    let mut update_layout = UpdateLayout::default();
+    modifier.apply(&mut update_layout);
+    ParticleUpdatePipelineKey::new(&InitLayout::default(), &update_layout).to_shader_source()
+}
+
+/// Generate the source code of the shader of an effect with a single extension modifier: the
+/// update shader for the [`ModifierStage::Init`] and [`ModifierStage::Update`] stages, or the
+/// render shader for the [`ModifierStage::Vertex`] and [`ModifierStage::Fragment`] stages.
+///
+/// The render shader is generated without particle texture nor color ramp.
+///
+/// # Panics
+///
+/// Panics if the modifier reads a particle attribute not available in its stage.
+pub fn extension_shader_source<M: ExtensionModifier>(modifier: &M) -> String {
+    let instance = ExtensionInstance::new(modifier).unwrap();
+    let extensions = ExtensionCode::new(&[instance]);
+    match M::STAGE {
+        ModifierStage::Init | ModifierStage::Update => {
+            ParticleUpdatePipelineKey::new(&InitLayout::default(), &UpdateLayout::default())
+                .with_extensions(&extensions)
+                .to_shader_source()
+        }
+        ModifierStage::Vertex | ModifierStage::Fragment => {
+            // Resolve the shader definitions of the render shader template, none being defined
+            let shader = Shader::from_wgsl(render_shader_source(&extensions));
+            match ShaderProcessor::default()
+                .process(&shader, &[], &HashMap::default(), &HashMap::default())
+                .unwrap()
+            {
+                ProcessedShader::Wgsl(source) => source.into_owned(),
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+/// Parse and validate the update shader of an effect with a single init modifier.
+pub fn validate_init_modifier<M: InitModifier>(modifier: &M) -> Result<(), ShaderReflectError> {
+    validate(init_shader_source(modifier))
+}
+
+/// Parse and validate the update shader of an effect with a single update modifier.
+pub fn validate_update_modifier<M: UpdateModifier>(modifier: &M) -> Result<(), ShaderReflectError> {
+    validate(update_shader_source(modifier))
+}
+
+/// Parse and validate the shader of an effect with a single extension modifier, as generated
+/// by [`extension_shader_source()`].
+pub fn validate_extension_modifier<M: ExtensionModifier>(
+    modifier: &M,
+) -> Result<(), ShaderReflectError> {
+    validate(extension_shader_source(modifier))
+}
+
+fn validate(source: String) -> Result<(), ShaderReflectError> {
+    ProcessedShader::Wgsl(Cow::Owned(source))
+        .reflect()
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn default_shader() {
+        let source = init_shader_source(&VelocitySphereModifier::default());
+        assert!(!source.contains("{{"));
+    }
+
+    #[test]
+    fn validate_init_modifiers() {
+        validate_init_modifier(&PositionCircleModifier::default()).unwrap();
+        validate_init_modifier(&PositionCircleModifier {
+            radii: Some(Vec2::new(2., 1.)),
+            inner_radius: 0.5,
+            dimension: ShapeDimension::Volume,
+            ..Default::default()
+        })
+        .unwrap();
+        validate_init_modifier(&PositionCircleModifier {
+            arc_length: Value::Single(0.),
+            sweep_speed: Some(1.),
+            ..Default::default()
+        })
+        .unwrap();
+        validate_init_modifier(&PositionEllipseModifier::default()).unwrap();
+        validate_init_modifier(&PositionSphereModifier::default()).unwrap();
+        validate_init_modifier(&PositionConeModifier::default()).unwrap();
+        validate_init_modifier(&PositionTorusModifier::default()).unwrap();
+        validate_init_modifier(&PositionCylinderModifier::default()).unwrap();
+        for region in [CubeRegion::Faces, CubeRegion::Edges, CubeRegion::Volume] {
+            validate_init_modifier(&PositionCubeModifier {
+                region,
+                ..Default::default()
+            })
+            .unwrap();
+        }
+        validate_init_modifier(&PositionMaskModifier {
+            points: vec![Vec2::ZERO, Vec2::ONE],
+            cell_size: Vec2::splat(0.1),
+            ..Default::default()
+        })
+        .unwrap();
+        validate_init_modifier(&PositionCapsuleModifier::default()).unwrap();
+        validate_init_modifier(&PositionPlaneModifier::default()).unwrap();
+        validate_init_modifier(&PositionGridModifier::default()).unwrap();
+        validate_init_modifier(&PositionLineModifier::default()).unwrap();
+        validate_init_modifier(&PositionMeshModifier::default()).unwrap();
+        validate_init_modifier(&VelocityTangentModifier::default()).unwrap();
+        validate_init_modifier(&VelocityDistributionModifier::new(
+            vec![1., 1., 1., 1., 0.5, 0.5, 0.5, 0.5, 0., 0., 0., 0.],
+            UVec2::new(4, 3),
+            Value::Uniform((1., 2.)),
+        ))
+        .unwrap();
+        validate_init_modifier(&ParticleLifetimeModifier {
+            lifetime: Value::Uniform((1., 2.)),
+        })
+        .unwrap();
+        validate_init_modifier(&ParticleLifetimeModifier {
+            lifetime: Value::Normal((2., 0.5)),
+        })
+        .unwrap();
+        validate_init_modifier(&ParticleLifetimeModifier {
+            lifetime: Value::Curve([1., 1.1, 1.2, 1.3, 1.5, 2., 3., 5.]),
+        })
+        .unwrap();
+        validate_init_modifier(&VelocitySpreadModifier {
+            spread: Value::Uniform((0., 0.5)),
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn validate_update_modifiers() {
+        validate_update_modifier(&AccelModifier {
+            accel: Vec3::new(0., -9.81, 0.),
+        })
+        .unwrap();
+        validate_update_modifier(&BuoyancyModifier {
+            temperature: Value::Uniform((1., 2.)),
+            ..Default::default()
+        })
+        .unwrap();
+        validate_update_modifier(&BuoyancyModifier {
+            temperature: Value::Normal((1., 0.2)),
+            ..Default::default()
+        })
+        .unwrap();
+        validate_update_modifier(&DragModifier::new(2.)).unwrap();
+        validate_update_modifier(&VortexModifier {
+            pull: 1.,
+            radius: 5.,
+            ..Default::default()
+        })
+        .unwrap();
+        validate_update_modifier(&DragModifier {
+            drag: Value::Uniform((0.5, 1.5)),
+        })
+        .unwrap();
+        validate_update_modifier(&FlowZoneModifier {
+            rotation: Quat::from_rotation_x(0.5),
+            ..Default::default()
+        })
+        .unwrap();
+        validate_update_modifier(&PlaneCollisionModifier::ground(0.)).unwrap();
+        validate_update_modifier(&PlaneCollisionModifier {
+            kill_on_contact: true,
+            ..Default::default()
+        })
+        .unwrap();
+        validate_update_modifier(&WindModifier {
+            gust_amplitude: 2.,
+            gust_scale: 4.,
+            turbulence: 0.5,
+            ..Default::default()
+        })
+        .unwrap();
+        for boundary in [
+            BoundaryCondition::Kill,
+            BoundaryCondition::Clamp,
+            BoundaryCondition::Reflect { restitution: 0.5 },
+            BoundaryCondition::Wrap,
+        ] {
+            validate_update_modifier(&SimulationBoundsModifier {
+                boundary,
+                ..Default::default()
+            })
+            .unwrap();
+        }
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct NoiseModifier {
+        amplitude: f32,
+    }
+
+    impl ExtensionModifier for NoiseModifier {
+        const TYPE_NAME: &'static str = "test::NoiseModifier";
+        const STAGE: ModifierStage = ModifierStage::Update;
+
+        fn to_shader_code(&self) -> ModifierCode {
+            ModifierCode::new(format!(
+                "    vVel = vVel + noise(vPos) * {};\n",
+                self.amplitude.to_wgsl_string()
+            ))
+            .with_include(
+                "test::noise",
+                "fn noise(p: vec3<f32>) -> vec3<f32> { return sin(p * 12.9898); }",
+            )
+            .with_attribute(ParticleAttribute::Position)
+        }
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct TintModifier;
+
+    impl ExtensionModifier for TintModifier {
+        const TYPE_NAME: &'static str = "test::TintModifier";
+        const STAGE: ModifierStage = ModifierStage::Fragment;
+
+        fn to_shader_code(&self) -> ModifierCode {
+            ModifierCode::new("    color = tint(color);\n").with_include(
+                "test::tint",
+                "fn tint(c: vec4<f32>) -> vec4<f32> { return c * vec4<f32>(1., 0.5, 0.5, 1.); }",
+            )
+        }
+    }
+
+    #[test]
+    fn validate_extension_modifiers() {
+        validate_extension_modifier(&NoiseModifier { amplitude: 0.5 }).unwrap();
+
+        // Render stage modifiers are validated in the render shader
+        let source = extension_shader_source(&TintModifier);
+        assert!(source.contains("color = tint(color);"));
+        assert!(!source
+            .lines()
+            .any(|line| line.trim_start().starts_with('#')));
+        validate_extension_modifier(&TintModifier).unwrap();
+    }
+}
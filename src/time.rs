@@ -0,0 +1,164 @@
+use bevy::prelude::*;
+
+/// Time control of a single [`ParticleEffect`] instance.
+///
+/// Add this component to the entity of a [`ParticleEffect`] to play the effect at a custom
+/// rate, pause it, or scrub it to a given time, for example from an editor timeline or a
+/// cinematic sequence. The effect time starts at zero when the component is added.
+///
+/// The GPU simulation can only move forward in time. Scrubbing backward restarts the effect,
+/// killing all its particles, then re-simulates it from the start up to the requested time.
+/// Re-simulating is performed in steps of at most `catch_up_step` seconds, one step per
+/// frame, so reaching a distant time takes several frames. A negative rate plays the effect
+/// in reverse: each frame the effect restarts and re-simulates up to the earlier time in a
+/// single step, which approximates the motion of particles subject to forces. Effects with
+/// random spawners or modifiers re-simulate to a different but similar state.
+///
+/// [`ParticleEffect`]: crate::ParticleEffect
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub struct EffectTimeControl {
+    /// Rate of the effect time relative to the simulation time. A rate of zero pauses the
+    /// effect, and a negative rate plays it in reverse.
+    pub rate: f32,
+    /// Maximum time step when re-simulating the effect toward a scrubbed time, in seconds.
+    pub catch_up_step: f32,
+    /// Current effect time, in seconds.
+    time: f32,
+    /// Time the effect is scrubbed to, if any.
+    target: Option<f32>,
+    /// Whether the effect restarts at the next frame.
+    restart: bool,
+}
+
+impl Default for EffectTimeControl {
+    fn default() -> Self {
+        Self {
+            rate: 1.,
+            catch_up_step: 1. / 30.,
+            time: 0.,
+            target: None,
+            restart: false,
+        }
+    }
+}
+
+impl EffectTimeControl {
+    /// Create a time control playing the effect at the given rate.
+    pub fn new(rate: f32) -> Self {
+        Self {
+            rate,
+            ..Default::default()
+        }
+    }
+
+    /// Get the current effect time, in seconds.
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// Check if the effect is being re-simulated toward a scrubbed time.
+    pub fn is_scrubbing(&self) -> bool {
+        self.target.is_some()
+    }
+
+    /// Scrub the effect to the given time, in seconds.
+    ///
+    /// If the time is before the current effect time, the effect restarts and re-simulates
+    /// from the start. The effect time then advances by steps of at most `catch_up_step`
+    /// each frame until it reaches the requested time, before resuming at its rate.
+    pub fn scrub(&mut self, time: f32) {
+        let time = time.max(0.);
+        if time < self.time {
+            self.restart = true;
+            self.time = 0.;
+        }
+        self.target = Some(time);
+    }
+
+    /// Set the effect time, in seconds. This is an alias of [`scrub()`].
+    ///
+    /// [`scrub()`]: EffectTimeControl::scrub
+    pub fn set_time(&mut self, time: f32) {
+        self.scrub(time);
+    }
+
+    /// Restart the effect, killing all its particles.
+    pub fn restart(&mut self) {
+        self.scrub(0.);
+    }
+
+    /// Advance the effect time for a frame of `dt` seconds of simulation time.
+    ///
+    /// Returns the time step of the effect for this frame, and whether the effect restarts.
+    pub(crate) fn step(&mut self, dt: f32) -> (f32, bool) {
+        let mut restart = std::mem::take(&mut self.restart);
+        let step = if let Some(target) = self.target {
+            let step = (target - self.time).min(self.catch_up_step.max(1e-3));
+            if step <= 0. || self.time + step >= target {
+                self.target = None;
+            }
+            step.max(0.)
+        } else if self.rate < 0. {
+            // Re-simulate from the start up to the earlier time; catching up over several
+            // frames would never keep up with the reverse playback.
+            let time = (self.time + dt * self.rate).max(0.);
+            restart = true;
+            self.time = 0.;
+            time
+        } else {
+            dt * self.rate
+        };
+        self.time += step;
+        (step, restart)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate() {
+        let mut control = EffectTimeControl::new(0.5);
+        assert_eq!(control.step(1.), (0.5, false));
+        assert_eq!(control.time(), 0.5);
+
+        // Paused
+        control.rate = 0.;
+        assert_eq!(control.step(1.), (0., false));
+
+        // Reverse restarts and re-simulates up to the earlier time
+        control.rate = -1.;
+        assert_eq!(control.step(0.25), (0.25, true));
+        assert_eq!(control.time(), 0.25);
+        assert_eq!(control.step(1.), (0., true));
+        assert_eq!(control.time(), 0.);
+    }
+
+    #[test]
+    fn scrub() {
+        let mut control = EffectTimeControl {
+            catch_up_step: 0.25,
+            ..Default::default()
+        };
+        control.step(1.);
+
+        // Forward scrub catches up without restarting
+        control.scrub(1.5);
+        assert!(control.is_scrubbing());
+        assert_eq!(control.step(0.01), (0.25, false));
+        assert_eq!(control.step(0.01), (0.25, false));
+        assert!(!control.is_scrubbing());
+        assert_eq!(control.time(), 1.5);
+
+        // Backward scrub restarts, then re-simulates from the start
+        control.scrub(0.5);
+        assert_eq!(control.step(0.01), (0.25, true));
+        assert_eq!(control.step(0.01), (0.25, false));
+        assert!(!control.is_scrubbing());
+        assert_eq!(control.time(), 0.5);
+
+        // Resume at the normal rate
+        assert_eq!(control.step(0.1), (0.1, false));
+    }
+}
@@ -0,0 +1,88 @@
+use bevy::math::Vec4;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::{Gradient, Value};
+
+/// Randomizable parameters of an [`EffectAsset`], resolved once per effect instance.
+///
+/// Each [`ParticleEffect`] instance picks its own [`EffectVariant`] from these parameters the
+/// first time it is extracted for rendering, and keeps it for its entire lifetime. This
+/// makes repeated uses of the same asset look slightly different, without any change to the
+/// instances at runtime.
+///
+/// The variant of an instance is not baked into its shaders; all the instances of an asset
+/// share the same render pipeline, which selects the gradient and size multiplier of each
+/// batch from its emitter properties.
+///
+/// [`EffectAsset`]: crate::EffectAsset
+/// [`ParticleEffect`]: crate::ParticleEffect
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EffectVariants {
+    /// Alternative color gradients over the particle lifetime, of which each instance picks
+    /// one at random. If empty, all instances use the color of the effect asset.
+    pub color_gradients: Vec<Gradient<Vec4>>,
+    /// Multiplier of the particle size of each instance.
+    pub size_multiplier: Value<f32>,
+}
+
+impl Default for EffectVariants {
+    fn default() -> Self {
+        EffectVariants {
+            color_gradients: vec![],
+            size_multiplier: Value::Single(1.),
+        }
+    }
+}
+
+impl EffectVariants {
+    /// Pick a random variant.
+    pub fn resolve(&self, rng: &mut impl RngCore) -> EffectVariant {
+        let color_gradient = if self.color_gradients.is_empty() {
+            None
+        } else {
+            Some(rng.gen_range(0..self.color_gradients.len()))
+        };
+        EffectVariant {
+            color_gradient,
+            size_multiplier: self.size_multiplier.sample(rng),
+        }
+    }
+}
+
+/// Variant of an [`EffectAsset`] picked by a single effect instance.
+///
+/// [`EffectAsset`]: crate::EffectAsset
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EffectVariant {
+    /// Index of the color gradient in [`EffectVariants::color_gradients`], if any.
+    pub color_gradient: Option<usize>,
+    /// Multiplier of the particle size.
+    pub size_multiplier: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spawn::new_rng;
+
+    #[test]
+    fn resolve() {
+        let mut rng = new_rng();
+
+        let variant = EffectVariants::default().resolve(&mut rng);
+        assert_eq!(variant.color_gradient, None);
+        assert_eq!(variant.size_multiplier, 1.);
+
+        let variants = EffectVariants {
+            color_gradients: vec![Gradient::new(), Gradient::new(), Gradient::new()],
+            size_multiplier: Value::Uniform((0.5, 2.)),
+        };
+        for _ in 0..100 {
+            let variant = variants.resolve(&mut rng);
+            assert!(variant.color_gradient.unwrap() < 3);
+            assert!(variant.size_multiplier >= 0.5 && variant.size_multiplier <= 2.);
+        }
+    }
+}